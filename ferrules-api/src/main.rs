@@ -1,10 +1,13 @@
 use axum::{
-    extract::{DefaultBodyLimit, Multipart, State},
+    extract::{DefaultBodyLimit, Multipart, Query, State},
     http::{
         header::{ACCEPT, CONTENT_TYPE},
         HeaderMap, Response, StatusCode,
     },
-    response::IntoResponse,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
     routing::{get, post},
     Json, Router,
 };
@@ -12,16 +15,37 @@ use axum_tracing_opentelemetry::middleware::OtelAxumLayer;
 use clap::Parser;
 use ferrules_api::init_tracing;
 use ferrules_core::{
-    layout::model::{ORTConfig, OrtExecutionProvider},
-    render::markdown::to_markdown,
-    FerrulesParseConfig, FerrulesParser,
+    entities::{Element, PageID, StructuredPage},
+    error::FerrulesError,
+    fetch::{fetch_document, DEFAULT_FETCH_TIMEOUT},
+    layout::{
+        model::{
+            AutoCount, AutoTune, CudaOptions, ORTConfig, ORTLayoutParser, OrtExecutionProvider,
+            Precision, TrtOptions,
+        },
+        LayoutMode,
+    },
+    metrics::PageMetrics,
+    ocr::{
+        OcrAccuracy, TextSource, DEFAULT_OCR_COVERAGE_THRESHOLD, DEFAULT_OCR_MIN_CHARS,
+        DEFAULT_OCR_MIN_CONFIDENCE,
+    },
+    render::markdown::to_markdown_document,
+    CaptionConfig, FerrulesParseConfig, FerrulesParser, MergeThresholds, PageRange,
+    ParseStreamEvent, ReadingOrder, TextNormalization, TitleLevelStrategy,
 };
+use futures::{Stream, StreamExt};
 use memmap2::Mmap;
 use mimalloc::MiMalloc;
 use serde::{Deserialize, Serialize};
-use std::io::{Seek, Write};
+use std::{
+    convert::Infallible,
+    io::{Seek, Write},
+    time::Duration,
+};
 use tempfile::NamedTempFile;
 use tokio::{fs::File, net::TcpListener};
+use tokio_util::sync::{CancellationToken, DropGuard};
 use uuid::Uuid;
 
 #[global_allocator]
@@ -74,6 +98,23 @@ struct Args {
     )]
     pub trt: bool,
 
+    #[arg(
+        long,
+        env = "FERRULES_TRT_CACHE_DIR",
+        help = "Directory caching the TensorRT engines between runs"
+    )]
+    pub trt_cache_dir: Option<std::path::PathBuf>,
+
+    #[arg(long, default_value_t = false, help = "Build fp16 TensorRT engines")]
+    pub trt_fp16: bool,
+
+    #[arg(
+        long,
+        value_name = "MIB",
+        help = "Maximum GPU memory in MiB used by CUDA and to build TensorRT engines"
+    )]
+    pub gpu_mem_limit: Option<usize>,
+
     #[arg(
         long,
         default_value_t = false,
@@ -93,22 +134,90 @@ struct Args {
     #[arg(
         long,
         short = 'j',
-        help = "Number of threads to use for parallel processing within operations",
-        default_value = "16"
+        help = "Number of threads to use for parallel processing within operations, `auto` derives it from the CPU count",
+        default_value = "auto"
     )]
-    intra_threads: usize,
+    intra_threads: AutoCount,
 
     /// Number of threads to use for parallel operation execution
     #[arg(
         long,
-        help = "Number of threads to use for executing operations in parallel",
-        default_value = "4"
+        help = "Number of threads to use for executing operations in parallel, `auto` derives it from the CPU count",
+        default_value = "auto"
     )]
-    inter_threads: usize,
+    inter_threads: AutoCount,
 
     #[arg(long, short = 'O', help = "Ort graph optimization level")]
     graph_opt_level: Option<usize>,
 
+    /// Custom ONNX layout model replacing the bundled one
+    #[arg(
+        long,
+        env = "FERRULES_LAYOUT_MODEL_PATH",
+        help = "Path to a custom ONNX layout model with DocLayNet compatible outputs"
+    )]
+    layout_model: Option<std::path::PathBuf>,
+
+    /// Input size of a custom layout model exported with dynamic spatial dimensions
+    #[arg(
+        long,
+        env = "FERRULES_LAYOUT_INPUT_SIZE",
+        value_parser = parse_input_size,
+        help = "Input size of the custom layout model as WIDTHxHEIGHT (e.g. 640x640)"
+    )]
+    layout_input_size: Option<(u32, u32)>,
+
+    /// Precision of the layout model, quantized models are loaded with `--layout-model`
+    #[arg(
+        long,
+        env = "FERRULES_LAYOUT_PRECISION",
+        default_value = "fp32",
+        help = "Precision of the layout model: fp32, fp16 or int8 (requires --layout-model)"
+    )]
+    layout_precision: Precision,
+
+    /// Maximum number of pages run in a single layout inference call
+    #[arg(
+        long,
+        default_value = "auto",
+        help = "Maximum number of pages batched in a single layout inference call, `auto` derives it from the CPU count"
+    )]
+    layout_batch_size: AutoCount,
+
+    /// Time to wait for a layout batch to fill up once several pages are queued
+    #[arg(
+        long,
+        default_value_t = ORTLayoutParser::DEFAULT_BATCH_MAX_WAIT.as_millis() as u64,
+        help = "Maximum time in milliseconds to wait for a layout batch to fill up"
+    )]
+    layout_batch_wait_ms: u64,
+
+    /// Number of retries of a page whose layout inference failed
+    #[arg(
+        long,
+        default_value_t = ORTLayoutParser::DEFAULT_LAYOUT_RETRIES,
+        help = "Number of times a failed layout inference is retried, on the CPU when running on an accelerator"
+    )]
+    layout_retries: usize,
+
+    /// Minimum confidence of the layout detections
+    #[arg(
+        long,
+        default_value_t = ORTLayoutParser::CONF_THRESHOLD,
+        value_parser = parse_threshold,
+        help = "Confidence threshold for layout detections, in [0, 1]"
+    )]
+    layout_conf: f32,
+
+    /// IoU above which overlapping layout detections are suppressed
+    #[arg(
+        long,
+        default_value_t = ORTLayoutParser::IOU_THRESHOLD,
+        value_parser = parse_threshold,
+        help = "IoU threshold for layout non-maximum suppression, in [0, 1]"
+    )]
+    layout_nms: f32,
+
     /// Enable profiling for layout model
     #[arg(long, help = "Enable profiling for the layout model (saved as .json)")]
     profile_layout: bool,
@@ -119,15 +228,67 @@ struct Args {
         help = "Enable profiling for the table transformer model (saved as .json)"
     )]
     profile_table: bool,
+
+    /// Number of native parsing processes, 0 parses every document in the server process
+    #[arg(
+        long,
+        env = "FERRULES_NATIVE_WORKERS",
+        default_value_t = 0,
+        help = "Number of pdfium worker processes parsing documents, and the pages of large documents, in parallel"
+    )]
+    native_workers: usize,
+
+    /// Accept the `url` field in the parse requests
+    #[arg(
+        long,
+        env = "FERRULES_ALLOW_URL_FETCH",
+        default_value_t = false,
+        help = "Let the parse requests pass an http(s) URL the server downloads the document from, instead of uploading the file. Only enable it when the server can't reach internal services"
+    )]
+    allow_url_fetch: bool,
+}
+
+fn parse_threshold(value: &str) -> Result<f32, String> {
+    let threshold: f32 = value
+        .parse()
+        .map_err(|_| format!("`{value}` is not a number"))?;
+    if (0f32..=1f32).contains(&threshold) {
+        Ok(threshold)
+    } else {
+        Err(format!("{threshold} is not in [0, 1]"))
+    }
+}
+
+fn parse_input_size(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or_else(|| format!("`{value}` is not formatted as WIDTHxHEIGHT"))?;
+    match (width.trim().parse::<u32>(), height.trim().parse::<u32>()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("`{value}` is not a valid WIDTHxHEIGHT size")),
+    }
 }
 
 fn parse_ep_args(args: &Args) -> Vec<OrtExecutionProvider> {
     let mut providers = Vec::new();
+    let gpu_mem_limit = args.gpu_mem_limit.map(|mib| mib * 1024 * 1024);
     if args.trt {
-        providers.push(OrtExecutionProvider::Trt(args.device_id));
+        providers.push(OrtExecutionProvider::Trt(
+            args.device_id,
+            TrtOptions {
+                engine_cache_dir: args.trt_cache_dir.clone(),
+                max_workspace_size: gpu_mem_limit,
+                fp16: args.trt_fp16,
+            },
+        ));
     }
     if args.cuda {
-        providers.push(OrtExecutionProvider::CUDA(args.device_id));
+        providers.push(OrtExecutionProvider::CUDA(
+            args.device_id,
+            CudaOptions {
+                memory_limit: gpu_mem_limit,
+            },
+        ));
     }
 
     if args.coreml {
@@ -150,15 +311,84 @@ struct ApiResponse<T> {
 struct ParseOptions {
     page_range: Option<String>,
     _save_images: Option<bool>,
+    ocr_accuracy: Option<OcrAccuracy>,
+    text_source: Option<TextSource>,
+    ocr_min_confidence: Option<f32>,
+    ocr_coverage_threshold: Option<f32>,
+    ocr_min_chars: Option<usize>,
+    force_ocr: Option<bool>,
+    raw_text: Option<bool>,
+    text_normalization: Option<TextNormalization>,
+    dehyphenate: Option<bool>,
+    merge_paragraphs: Option<bool>,
+    layout_timeout_ms: Option<u64>,
+    layout_timeout_fallback: Option<bool>,
+    timeout_secs: Option<u64>,
+    include_layout: Option<bool>,
+    layout_mode: Option<LayoutMode>,
+    reading_order: Option<ReadingOrder>,
+    title_levels: Option<TitleLevelStrategy>,
+    promote_large_text_titles: Option<bool>,
+    layout_class_filter: Option<Vec<String>>,
+    layout_class_exclude: Option<Vec<String>>,
+    caption_prefixes: Option<Vec<String>>,
+    caption_max_distance: Option<f32>,
+    line_gap: Option<f32>,
+    paragraph_gap: Option<f32>,
+    span_gap: Option<f32>,
+    strip_running_headers: Option<bool>,
+    extract_embedded_images: Option<bool>,
+    prefer_tagged_structure: Option<bool>,
+    fail_fast: Option<bool>,
+    raster_dpi: Option<f32>,
+    max_raster_dim: Option<u32>,
+}
+
+/// Page of the `page` events of a streamed parse
+#[derive(Debug, Serialize)]
+struct PageEvent<'a> {
+    id: PageID,
+    width: f32,
+    height: f32,
+    rotation: u16,
+    need_ocr: bool,
+    elements: &'a [Element],
+    metrics: &'a PageMetrics,
+}
+
+impl<'a> From<&'a StructuredPage> for PageEvent<'a> {
+    fn from(page: &'a StructuredPage) -> Self {
+        Self {
+            id: page.id,
+            width: page.width,
+            height: page.height,
+            rotation: page.rotation,
+            need_ocr: page.need_ocr,
+            elements: &page.elements,
+            metrics: &page.metrics,
+        }
+    }
+}
+
+/// Query parameters of `POST /parse`, the other parsing options are only read from the
+/// `options` form field
+#[derive(Debug, Deserialize)]
+struct ParseQuery {
+    page_range: Option<String>,
+    flatten: Option<bool>,
 }
 
 #[derive(Clone)]
 struct AppState {
     parser: FerrulesParser,
+    metrics_handle: metrics_exporter_prometheus::PrometheusHandle,
+    allow_url_fetch: bool,
 }
 
 #[tokio::main]
 async fn main() {
+    // NOTE: must run before anything is written to stdout
+    ferrules_core::run_native_worker_if_requested();
     let args = Args::parse();
 
     // Check providers
@@ -207,12 +437,25 @@ async fn main() {
         .install_recorder()
         .expect("failed to install Prometheus recorder");
 
-    let ort_config = ORTConfig {
+    let auto_tune = AutoTune::detect();
+    let inter_threads = args.inter_threads.resolve(auto_tune.inter_threads);
+    let mut ort_config = ORTConfig {
         execution_providers: providers,
-        intra_threads: args.intra_threads,
-        inter_threads: args.inter_threads,
+        intra_threads: args.intra_threads.resolve(auto_tune.intra_threads),
+        inter_threads,
         opt_level: args.graph_opt_level.map(|v| v.try_into().unwrap()),
-        warmup: true,
+        layout_batch_size: args.layout_batch_size.resolve(auto_tune.layout_batch_size),
+        layout_batch_max_wait: Duration::from_millis(args.layout_batch_wait_ms),
+        conf_threshold: args.layout_conf,
+        nms_iou: args.layout_nms,
+        layout_model_path: args.layout_model.clone(),
+        layout_labels: None,
+        layout_input_size: args.layout_input_size,
+        buffer_pool_size: inter_threads,
+        model_precision: args.layout_precision,
+        layout_retries: args.layout_retries,
+        // The parser is warmed up through its layout queue before serving requests
+        warmup: false,
         profile_layout: if args.profile_layout {
             Some(std::path::PathBuf::from("profile_layout_api"))
         } else {
@@ -224,22 +467,41 @@ async fn main() {
             None
         },
     };
+    for provider in ort_config.retain_available_providers() {
+        tracing::warn!(
+            "ONNX Runtime doesn't support {provider}, falling back to {}. {}",
+            ort_config.get_sorted_providers()[0],
+            provider.install_hint()
+        );
+    }
     // Initialize the layout model and queues
-    let parser = FerrulesParser::new(ort_config);
+    let parser = if args.native_workers > 0 {
+        FerrulesParser::with_native_workers(ort_config, args.native_workers)
+    } else {
+        FerrulesParser::new(ort_config)
+    };
+
+    // Only accept requests once the execution providers are initialized
+    parser.warmup().await.expect("layout model warmup failed");
+    tracing::info!("Layout model warmed up on {}", parser.layout_provider());
 
-    let app_state = AppState { parser };
+    let app_state = AppState {
+        parser,
+        metrics_handle: handle,
+        allow_url_fetch: args.allow_url_fetch,
+    };
 
     // Build our application with a route
     let app = Router::new()
         .route("/health", get(health_check))
         .route("/parse", post(parse_document_handler))
-        .route("/metrics", get(move || std::future::ready(handle.render())))
+        .route("/metrics", get(metrics_handler))
         .with_state(app_state)
         .layer(OtelAxumLayer::default())
         .layer(DefaultBodyLimit::max(MAX_SIZE_LIMIT));
 
     // Run it
-    let listener = TcpListener::bind("0.0.0.0:3002").await.unwrap();
+    let listener = TcpListener::bind(&args.listen_addr).await.unwrap();
     tracing::info!(
         "Starting ferrules service listening on {}",
         listener.local_addr().unwrap()
@@ -256,10 +518,23 @@ async fn health_check() -> impl IntoResponse {
     })
 }
 
+/// Prometheus metrics, with the current layout queue stats as gauges
+async fn metrics_handler(State(state): State<AppState>) -> String {
+    let stats = state.parser.layout_stats();
+    metrics::gauge!("layout_queue_depth").set(stats.queue_depth as f64);
+    metrics::gauge!("layout_in_flight").set(stats.in_flight as f64);
+    metrics::gauge!("layout_inferences_total").set(stats.inferences as f64);
+    metrics::gauge!("layout_inference_p50_ms").set(stats.inference_p50_ms);
+    metrics::gauge!("layout_inference_p95_ms").set(stats.inference_p95_ms);
+    metrics::gauge!("layout_queue_wait_total_ms").set(stats.total_queue_wait_ms);
+    state.metrics_handle.render()
+}
+
 #[tracing::instrument(skip_all)]
 async fn parse_document_handler(
     headers: HeaderMap,
     state: State<AppState>,
+    Query(query): Query<ParseQuery>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ApiResponse<()>>)> {
     // Extract the file from multipart form
@@ -276,6 +551,8 @@ async fn parse_document_handler(
     })?;
 
     let mut options = None;
+    let mut password = None;
+    let mut url = None;
 
     while let Some(field) = multipart.next_field().await.map_err(|e| {
         (
@@ -359,65 +636,341 @@ async fn parse_document_handler(
                     },
                 )?);
             }
+            "url" => {
+                url = Some(field.text().await.map_err(|e| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(ApiResponse {
+                            success: false,
+                            data: None,
+                            error: Some(format!("Failed to read url: {}", e)),
+                        }),
+                    )
+                })?);
+            }
+            "password" => {
+                password = Some(field.text().await.map_err(|e| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(ApiResponse {
+                            success: false,
+                            data: None,
+                            error: Some(format!("Failed to read password: {}", e)),
+                        }),
+                    )
+                })?);
+            }
             _ => continue,
         }
     }
 
-    let file = File::open(temp_file.path()).await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ApiResponse {
-                success: false,
-                data: None,
-                error: Some(format!("Failed to open temp file: {}", e)),
-            }),
-        )
-    })?;
-
-    let mmap = unsafe {
-        Mmap::map(&file).map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
+    // The document given by URL is downloaded in memory instead of the uploaded file
+    let fetched_doc = match url {
+        Some(_) if !state.allow_url_fetch => {
+            return Err((
+                StatusCode::FORBIDDEN,
                 Json(ApiResponse {
                     success: false,
                     data: None,
-                    error: Some(format!("Failed to memory map file: {}", e)),
+                    error: Some(
+                        "fetching documents from a URL is disabled on this server".to_string(),
+                    ),
                 }),
-            )
-        })?
+            ));
+        }
+        Some(url) => Some(
+            fetch_document(url.trim(), MAX_SIZE_LIMIT, DEFAULT_FETCH_TIMEOUT)
+                .await
+                .map_err(|e| {
+                    (
+                        StatusCode::BAD_GATEWAY,
+                        Json(ApiResponse {
+                            success: false,
+                            data: None,
+                            error: Some(e.to_string()),
+                        }),
+                    )
+                })?,
+        ),
+        None => None,
     };
-    let page_range = if let Some(options) = options {
-        if let Some(range_str) = options.page_range {
-            Some(parse_page_range(&range_str).map_err(|e| {
+
+    let mmap;
+    let doc: &[u8] = match &fetched_doc {
+        Some(doc) => doc,
+        None => {
+            let file = File::open(temp_file.path()).await.map_err(|e| {
                 (
-                    StatusCode::BAD_REQUEST,
+                    StatusCode::INTERNAL_SERVER_ERROR,
                     Json(ApiResponse {
                         success: false,
                         data: None,
-                        error: Some(e.to_string()),
+                        error: Some(format!("Failed to open temp file: {}", e)),
                     }),
                 )
-            })?)
-        } else {
-            None
+            })?;
+
+            mmap = unsafe {
+                Mmap::map(&file).map_err(|e| {
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(ApiResponse {
+                            success: false,
+                            data: None,
+                            error: Some(format!("Failed to memory map file: {}", e)),
+                        }),
+                    )
+                })?
+            };
+            &mmap
+        }
+    };
+    let ocr_accuracy = options
+        .as_ref()
+        .and_then(|o| o.ocr_accuracy)
+        .unwrap_or_default();
+    let text_source = options
+        .as_ref()
+        .and_then(|o| o.text_source)
+        .unwrap_or_default();
+    let ocr_min_confidence = options
+        .as_ref()
+        .and_then(|o| o.ocr_min_confidence)
+        .unwrap_or(DEFAULT_OCR_MIN_CONFIDENCE);
+    if !(0f32..=1f32).contains(&ocr_min_confidence) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse {
+                success: false,
+                data: None,
+                error: Some(format!(
+                    "ocr_min_confidence must be in [0, 1], got {ocr_min_confidence}"
+                )),
+            }),
+        ));
+    }
+    let ocr_coverage_threshold = options
+        .as_ref()
+        .and_then(|o| o.ocr_coverage_threshold)
+        .unwrap_or(DEFAULT_OCR_COVERAGE_THRESHOLD);
+    if !(0f32..=1f32).contains(&ocr_coverage_threshold) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse {
+                success: false,
+                data: None,
+                error: Some(format!(
+                    "ocr_coverage_threshold must be in [0, 1], got {ocr_coverage_threshold}"
+                )),
+            }),
+        ));
+    }
+    let ocr_min_chars = options
+        .as_ref()
+        .and_then(|o| o.ocr_min_chars)
+        .unwrap_or(DEFAULT_OCR_MIN_CHARS);
+    let force_ocr = options
+        .as_ref()
+        .and_then(|o| o.force_ocr)
+        .unwrap_or_default();
+    let text_normalization = if options.as_ref().and_then(|o| o.raw_text) == Some(true) {
+        None
+    } else {
+        Some(
+            options
+                .as_ref()
+                .and_then(|o| o.text_normalization)
+                .unwrap_or_default(),
+        )
+    };
+    let dehyphenate = options.as_ref().and_then(|o| o.dehyphenate).unwrap_or(true);
+    let merge_paragraphs = options
+        .as_ref()
+        .and_then(|o| o.merge_paragraphs)
+        .unwrap_or(true);
+    let layout_timeout = options
+        .as_ref()
+        .and_then(|o| o.layout_timeout_ms)
+        .map(Duration::from_millis);
+    let layout_timeout_fallback = options
+        .as_ref()
+        .and_then(|o| o.layout_timeout_fallback)
+        .unwrap_or_default();
+    let timeout = options
+        .as_ref()
+        .and_then(|o| o.timeout_secs)
+        .map(Duration::from_secs);
+    let include_layout = options
+        .as_ref()
+        .and_then(|o| o.include_layout)
+        .unwrap_or_default();
+    let layout_mode = options
+        .as_ref()
+        .and_then(|o| o.layout_mode)
+        .unwrap_or_default();
+    let reading_order = options
+        .as_ref()
+        .and_then(|o| o.reading_order)
+        .unwrap_or_default();
+    let title_level_strategy = options
+        .as_ref()
+        .and_then(|o| o.title_levels)
+        .unwrap_or_default();
+    let promote_large_text_titles = options
+        .as_ref()
+        .and_then(|o| o.promote_large_text_titles)
+        .unwrap_or(false);
+    let layout_class_filter = options.as_ref().and_then(|o| o.layout_class_filter.clone());
+    let layout_class_exclude = options
+        .as_ref()
+        .and_then(|o| o.layout_class_exclude.clone())
+        .unwrap_or_default();
+    let strip_running_headers = options
+        .as_ref()
+        .and_then(|o| o.strip_running_headers)
+        .unwrap_or(false);
+    let extract_embedded_images = options
+        .as_ref()
+        .and_then(|o| o.extract_embedded_images)
+        .unwrap_or(true);
+    let prefer_tagged_structure = options
+        .as_ref()
+        .and_then(|o| o.prefer_tagged_structure)
+        .unwrap_or(true);
+    let fail_fast = options
+        .as_ref()
+        .and_then(|o| o.fail_fast)
+        .unwrap_or_default();
+    let raster_dpi = options.as_ref().and_then(|o| o.raster_dpi);
+    if raster_dpi.is_some_and(|dpi| !dpi.is_finite() || dpi <= 0.0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse {
+                success: false,
+                data: None,
+                error: Some("raster_dpi must be a positive number".to_string()),
+            }),
+        ));
+    }
+    let max_raster_dim = options.as_ref().and_then(|o| o.max_raster_dim);
+    let mut caption = CaptionConfig::default();
+    if let Some(prefixes) = options.as_ref().and_then(|o| o.caption_prefixes.clone()) {
+        caption.prefixes = prefixes;
+    }
+    if let Some(max_distance) = options.as_ref().and_then(|o| o.caption_max_distance) {
+        caption.max_distance = max_distance;
+    }
+    let mut merge_thresholds = MergeThresholds::default();
+    if let Some(line_gap) = options.as_ref().and_then(|o| o.line_gap) {
+        merge_thresholds.line_gap = line_gap;
+    }
+    if let Some(paragraph_gap) = options.as_ref().and_then(|o| o.paragraph_gap) {
+        merge_thresholds.paragraph_gap = paragraph_gap;
+    }
+    merge_thresholds.span_gap = options.as_ref().and_then(|o| o.span_gap);
+    let page_range = match (options.and_then(|o| o.page_range), query.page_range) {
+        (Some(_), Some(_)) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some(
+                        "page_range is set both in the options field and as a query parameter"
+                            .to_string(),
+                    ),
+                }),
+            ));
         }
+        (range, query_range) => range.or(query_range),
+    };
+    let page_range = if let Some(range_str) = page_range {
+        Some(range_str.parse::<PageRange>().map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some(e.to_string()),
+                }),
+            )
+        })?)
     } else {
         None
     };
 
+    // Parsing stops when the client disconnects and the request future is dropped
+    let cancellation = CancellationToken::new();
+    let cancel_on_drop = cancellation.clone().drop_guard();
     let config = FerrulesParseConfig {
-        password: None,
-        flatten_pdf: true,
+        // NOTE: only read from the form, a query parameter would end up in the access logs and
+        // the traced request urls
+        password,
+        flatten_pdf: query.flatten.unwrap_or(true),
+        text_normalization,
+        dehyphenate,
+        merge_paragraphs,
         page_range,
         debug_dir: None,
+        ocr_accuracy,
+        text_source,
+        ocr_min_confidence,
+        ocr_coverage_threshold,
+        ocr_min_chars,
+        force_ocr,
+        layout_timeout,
+        layout_timeout_fallback,
+        timeout,
+        include_layout,
+        layout_mode,
+        reading_order,
+        title_level_strategy,
+        promote_large_text_titles,
+        layout_class_filter,
+        layout_class_exclude,
+        caption,
+        merge_thresholds,
+        strip_running_headers,
+        extract_embedded_images,
+        prefer_tagged_structure,
+        fail_fast,
+        raster_dpi,
+        max_raster_dim,
+        // Responses don't include any image
+        keep_page_images: false,
+        cancellation,
+        ..Default::default()
     };
+    let accept_header = headers.get(ACCEPT).and_then(|h| h.to_str().ok());
+
+    if accept_header == Some("text/event-stream") {
+        let events = state
+            .parser
+            .parse_document_streaming(doc, Uuid::new_v4().to_string(), config);
+        return Ok(Sse::new(parse_events(events, cancel_on_drop))
+            .keep_alive(KeepAlive::default())
+            .into_response());
+    }
+
     let doc = state
         .parser
-        .parse_document(&mmap, Uuid::new_v4().to_string(), config, Some(|_| {}))
+        .parse_document(doc, Uuid::new_v4().to_string(), config, Some(|_| {}))
         .await
         .map_err(|e| {
+            let status = match e {
+                // The document couldn't be opened: invalid PDF, wrong password or page range
+                FerrulesError::ParseNativeError
+                | FerrulesError::IncorrectPassword
+                | FerrulesError::EncryptedDocument
+                | FerrulesError::UnsupportedFormat(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                FerrulesError::LayoutTimeout { .. } | FerrulesError::Timeout(_) => {
+                    StatusCode::GATEWAY_TIMEOUT
+                }
+                FerrulesError::UnknownLayoutClass { .. } => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                status,
                 Json(ApiResponse {
                     success: false,
                     data: None,
@@ -426,11 +979,9 @@ async fn parse_document_handler(
             )
         })?;
 
-    let accept_header = headers.get(ACCEPT).and_then(|h| h.to_str().ok());
-
     match accept_header {
         Some("text/markdown") => {
-            let markdown = to_markdown(&doc, &doc.doc_name, None).map_err(|e| {
+            let markdown = to_markdown_document(&doc, None).map_err(|e| {
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
                     Json(ApiResponse {
@@ -445,7 +996,8 @@ async fn parse_document_handler(
                 .status(StatusCode::OK)
                 .header(CONTENT_TYPE, "text/markdown")
                 .body::<String>(markdown)
-                .unwrap())
+                .unwrap()
+                .into_response())
         }
         _ => {
             // NOTE: Default to JSON
@@ -460,33 +1012,45 @@ async fn parse_document_handler(
                     })
                     .unwrap(),
                 )
-                .unwrap())
+                .unwrap()
+                .into_response())
         }
     }
 }
 
-fn parse_page_range(range_str: &str) -> anyhow::Result<std::ops::Range<usize>> {
-    if let Some((start, end)) = range_str.split_once('-') {
-        let start: usize = start.trim().parse()?;
-        let end: usize = end.trim().parse()?;
-        if start > 0 && end >= start {
-            Ok(std::ops::Range {
-                start: start - 1,
-                end,
-            })
-        } else {
-            anyhow::bail!("Invalid page range: start must be > 0 and end must be >= start")
-        }
-    } else {
-        // Single page
-        let page: usize = range_str.trim().parse()?;
-        if page > 0 {
-            Ok(std::ops::Range {
-                start: page - 1,
-                end: page,
-            })
-        } else {
-            anyhow::bail!("Page number must be greater than 0")
-        }
-    }
+/// Server-sent events of a streamed parse: a `page` event for each page as soon as it is
+/// parsed, in completion order, then a `done` event with the document or an `error` event.
+/// Dropping the stream cancels the parsing.
+fn parse_events(
+    events: impl Stream<Item = ParseStreamEvent> + Send + Unpin,
+    cancel_on_drop: DropGuard,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    futures::stream::unfold(
+        (events, cancel_on_drop),
+        |(mut events, cancel_on_drop)| async move {
+            let event = match events.next().await? {
+                ParseStreamEvent::Page(page) => Event::default()
+                    .event("page")
+                    .json_data(PageEvent::from(page.as_ref())),
+                ParseStreamEvent::Done(Ok(doc)) => {
+                    Event::default().event("done").json_data(ApiResponse {
+                        success: true,
+                        data: Some(doc),
+                        error: None,
+                    })
+                }
+                ParseStreamEvent::Done(Err(e)) => {
+                    Event::default()
+                        .event("error")
+                        .json_data(ApiResponse::<()> {
+                            success: false,
+                            data: None,
+                            error: Some(e.to_string()),
+                        })
+                }
+            }
+            .unwrap_or_else(|e| Event::default().event("error").data(e.to_string()));
+            Some((Ok(event), (events, cancel_on_drop)))
+        },
+    )
 }
@@ -27,4 +27,20 @@ pub fn format_error(error_type: &str, message: &str, details: Vec<(&str, String)
     // Print footer with suggestion
     eprintln!("\n{DIM}For more information, try running with --debug flag{RESET}");
     eprintln!("{RED}{BOLD}═══════════════════════════════════════════════════════════════════{RESET}\n");
-}
\ No newline at end of file
+}
+
+pub fn format_warning(warning_type: &str, message: &str, details: Vec<(&str, String)>) {
+    eprintln!("\n{YELLOW}{BOLD}╭─────────────────────────────────────────────────────────────────╮{RESET}");
+    eprintln!("{YELLOW}{BOLD}│ ⚠ WARNING: {:<52}│{RESET}", warning_type);
+    eprintln!("{YELLOW}{BOLD}╰─────────────────────────────────────────────────────────────────╯{RESET}");
+
+    eprintln!("\n{WHITE}{message}{RESET}");
+
+    if !details.is_empty() {
+        eprintln!("\n{CYAN}{BOLD}Details:{RESET}");
+        for (label, value) in details {
+            eprintln!("  {DIM}•{RESET} {YELLOW}{label}:{RESET} {value}");
+        }
+    }
+    eprintln!();
+}
@@ -1,33 +1,91 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 mod error_formatter;
-use error_formatter::format_error;
+use error_formatter::{format_error, format_warning};
 
 use ferrules_core::{
-    layout::model::{ORTConfig, OrtExecutionProvider},
-    utils::{create_dirs, get_doc_length, save_parsed_document},
-    FerrulesParseConfig, FerrulesParser,
+    count_document, default_page_cache_dir,
+    fetch::{fetch_document, is_url, DEFAULT_FETCH_MAX_BYTES, DEFAULT_FETCH_TIMEOUT},
+    layout::{
+        model::{
+            AutoCount, AutoTune, CudaOptions, ORTConfig, ORTLayoutParser, OrtExecutionProvider,
+            Precision, TrtOptions,
+        },
+        LayoutMode,
+    },
+    metrics::{BenchStats, LayoutQueueStats, ParsingMetrics},
+    ocr::{
+        default_ocr_cache_dir, OcrAccuracy, TextSource, DEFAULT_OCR_COVERAGE_THRESHOLD,
+        DEFAULT_OCR_MIN_CHARS, DEFAULT_OCR_MIN_CONFIDENCE,
+    },
+    pdfium::{load_pdfium, PDFIUM_PATH_ENV},
+    render::txt::DEFAULT_PAGE_BREAK,
+    utils::{
+        create_dirs, get_doc_length, get_doc_length_from_bytes, save_parsed_document,
+        write_parsed_document, SaveOptions, DEFAULT_IMAGE_PADDING,
+    },
+    CaptionConfig, FerrulesParseConfig, FerrulesParser, MergeThresholds, PageRange,
+    ParseStreamEvent, ReadingOrder, TextNormalization, TitleLevelStrategy,
+    DEFAULT_CAPTION_MAX_DISTANCE, DEFAULT_PARAGRAPH_GAP,
 };
-use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+use futures::StreamExt;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
 use memmap2::Mmap;
 use std::{
     fmt::Write,
-    ops::Range,
     path::{Path, PathBuf},
+    time::Duration,
 };
 use tokio::fs::File;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 #[derive(Parser, Debug)]
 #[command(
     version,
     about = "Ferrules - High-performance document parsing library",
-    long_about = "Ferrules is an opinionated high-performance document parsing library designed to generate LLM-ready documents efficiently. Built with Rust for seamless deployment across various platforms."
+    long_about = "Ferrules is an opinionated high-performance document parsing library designed to generate LLM-ready documents efficiently. Built with Rust for seamless deployment across various platforms.",
+    args_conflicts_with_subcommands = true
 )]
-struct Args {
-    /// Path to the PDF file to be parsed
-    file_path: PathBuf,
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path or http(s) URL of the PDF file to be parsed, required without a subcommand
+    #[arg(required = true)]
+    file_path: Option<PathBuf>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Parse the document several times, without saving the results, and print the stage timings
+    Bench {
+        /// Path or http(s) URL of the PDF file to be parsed
+        file_path: PathBuf,
 
+        #[command(flatten)]
+        args: Args,
+
+        /// Parsing runs of the benchmark
+        #[arg(
+            long,
+            value_name = "RUNS",
+            default_value_t = 5,
+            value_parser = clap::value_parser!(u32).range(1..),
+            help = "Number of times the document is parsed before printing the stage timings"
+        )]
+        bench_runs: u32,
+    },
+    /// Print the JSON schema of the JSON output
+    #[cfg(feature = "schema")]
+    Schema,
+}
+
+#[derive(clap::Args, Debug)]
+struct Args {
     // /// Process directory instead of single file
     // #[arg(
     //     long,
@@ -38,10 +96,68 @@ struct Args {
     #[arg(
         long,
         short('r'),
-        help = "Specify pages to parse (e.g., '1-5' or '1' for single page)"
+        help = "Specify pages to parse (e.g., '1-5', '1' for single page or '1,3,5-7,10-' for an ascending list, '10-' meaning from page 10 to the end)"
     )]
     page_range: Option<String>,
 
+    /// Password of an encrypted PDF
+    #[arg(
+        long,
+        env = "FERRULES_PDF_PASSWORD",
+        hide_env_values = true,
+        help = "Password to open an encrypted PDF"
+    )]
+    password: Option<String>,
+
+    /// Parse the pages without flattening their form fields and annotations
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Don't flatten the form fields and annotations into the pages before parsing. Their text then only comes from the form fields and OCR"
+    )]
+    no_flatten: bool,
+
+    /// Keep the text exactly as extracted from the PDF
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["keep_soft_hyphens", "nfkc"],
+        help = "Keep the extracted text as is, without expanding the ligatures or removing the non-breaking spaces, soft hyphens, zero-width and control characters"
+    )]
+    raw_text: bool,
+
+    /// Keep the soft hyphens in the extracted text
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Keep the soft hyphens marking the hyphenation points in the extracted text"
+    )]
+    keep_soft_hyphens: bool,
+
+    /// NFKC normalize the extracted text
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Apply the NFKC normalization to the extracted text, e.g. superscript digits and full-width forms become plain characters"
+    )]
+    nfkc: bool,
+
+    /// Keep the words hyphenated at the end of a line split
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Keep the words hyphenated at the end of a line split instead of joining them with their continuation on the next line"
+    )]
+    no_dehyphenate: bool,
+
+    /// Keep the paragraphs split by a page break as one block per page
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Keep the paragraphs split by a page break as one block per page instead of merging the text block starting the next page in the middle of a sentence"
+    )]
+    no_merge_paragraphs: bool,
+
     /// Specifies the target directory where parsing results will be saved
     ///
     /// If not specified, defaults to the current working directory.
@@ -53,9 +169,32 @@ struct Args {
     )]
     output_dir: Option<PathBuf>,
 
+    /// Only read the native text layer of the document to triage it
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Print the page count, estimated block count, OCR need and language of the document as json, from its native text layer only. The layout model isn't run and no output is written"
+    )]
+    count: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "save_images",
+        help = "Write the selected output format (json by default) to stdout instead of a results directory"
+    )]
+    stdout: bool,
+
     #[arg(long, default_value_t = false, help = "Output the document as html")]
     html: bool,
 
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Output the document as a single html file with the images inlined as base64"
+    )]
+    html_inline: bool,
+
     #[arg(
         long,
         default_value_t = false,
@@ -63,6 +202,43 @@ struct Args {
     )]
     md: bool,
 
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Output the markdown of each page to its own file: page_0001.md, page_0002.md..."
+    )]
+    md_per_page: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Output the document in hOCR format"
+    )]
+    hocr: bool,
+
+    #[arg(long, default_value_t = false, help = "Output the document as docx")]
+    docx: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Output one json object per block and line (JSON Lines) instead of the json document"
+    )]
+    jsonl: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Output the text of the document blocks as plain text"
+    )]
+    txt: bool,
+
+    #[arg(
+        long,
+        help = "Marker written between pages in the plain text output [default: form feed]"
+    )]
+    txt_page_break: Option<String>,
+
     #[arg(
         long,
         default_value_t = false,
@@ -70,6 +246,13 @@ struct Args {
     )]
     save_images: bool,
 
+    #[arg(
+        long,
+        default_value_t = DEFAULT_IMAGE_PADDING,
+        help = "Margin in points kept around the saved image and table crops"
+    )]
+    image_padding: u32,
+
     /// Use CoreML for layout inference (default: true)
     #[arg(
         long,
@@ -92,6 +275,23 @@ struct Args {
     )]
     trt: bool,
 
+    #[arg(
+        long,
+        env = "FERRULES_TRT_CACHE_DIR",
+        help = "Directory caching the TensorRT engines between runs"
+    )]
+    trt_cache_dir: Option<PathBuf>,
+
+    #[arg(long, default_value_t = false, help = "Build fp16 TensorRT engines")]
+    trt_fp16: bool,
+
+    #[arg(
+        long,
+        value_name = "MIB",
+        help = "Maximum GPU memory in MiB used by CUDA and to build TensorRT engines"
+    )]
+    gpu_mem_limit: Option<usize>,
+
     #[arg(
         long,
         default_value_t = false,
@@ -99,6 +299,14 @@ struct Args {
     )]
     cuda: bool,
 
+    #[cfg(feature = "directml")]
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Enable or disable the use of DirectML for layout inference (Windows)"
+    )]
+    directml: bool,
+
     /// CUDA device ID to use for GPU acceleration (e.g. 0 for first GPU)
     #[arg(
         long,
@@ -111,22 +319,331 @@ struct Args {
     #[arg(
         long,
         short = 'j',
-        help = "Number of threads to use for parallel processing within operations",
-        default_value = "16"
+        help = "Number of threads to use for parallel processing within operations, `auto` derives it from the CPU count",
+        default_value = "auto"
     )]
-    intra_threads: usize,
+    intra_threads: AutoCount,
 
     /// Number of threads to use for parallel operation execution
     #[arg(
         long,
-        help = "Number of threads to use for executing operations in parallel",
-        default_value = "4"
+        help = "Number of threads to use for executing operations in parallel, `auto` derives it from the CPU count",
+        default_value = "auto"
     )]
-    inter_threads: usize,
+    inter_threads: AutoCount,
 
     #[arg(long, short = 'O', help = "Ort graph optimization level")]
     graph_opt_level: Option<usize>,
 
+    /// Custom ONNX layout model replacing the bundled one
+    #[arg(
+        long,
+        env = "FERRULES_LAYOUT_MODEL_PATH",
+        help = "Path to a custom ONNX layout model with DocLayNet compatible outputs"
+    )]
+    layout_model: Option<PathBuf>,
+
+    /// Input size of a custom layout model exported with dynamic spatial dimensions
+    #[arg(
+        long,
+        env = "FERRULES_LAYOUT_INPUT_SIZE",
+        value_parser = parse_input_size,
+        help = "Input size of the custom layout model as WIDTHxHEIGHT (e.g. 640x640)"
+    )]
+    layout_input_size: Option<(u32, u32)>,
+
+    /// Precision of the layout model, quantized models are loaded with `--layout-model`
+    #[arg(
+        long,
+        env = "FERRULES_LAYOUT_PRECISION",
+        default_value = "fp32",
+        help = "Precision of the layout model: fp32, fp16 or int8 (requires --layout-model)"
+    )]
+    layout_precision: Precision,
+
+    /// Maximum number of pages run in a single layout inference call
+    #[arg(
+        long,
+        default_value = "auto",
+        help = "Maximum number of pages batched in a single layout inference call, `auto` derives it from the CPU count"
+    )]
+    layout_batch_size: AutoCount,
+
+    /// Time to wait for a layout batch to fill up once several pages are queued
+    #[arg(
+        long,
+        default_value_t = ORTLayoutParser::DEFAULT_BATCH_MAX_WAIT.as_millis() as u64,
+        help = "Maximum time in milliseconds to wait for a layout batch to fill up"
+    )]
+    layout_batch_wait_ms: u64,
+
+    /// Number of retries of a page whose layout inference failed
+    #[arg(
+        long,
+        default_value_t = ORTLayoutParser::DEFAULT_LAYOUT_RETRIES,
+        help = "Number of times a failed layout inference is retried, on the CPU when running on an accelerator"
+    )]
+    layout_retries: usize,
+
+    /// Number of native parsing processes, 0 parses the document in the CLI process
+    #[arg(
+        long,
+        env = "FERRULES_NATIVE_WORKERS",
+        default_value_t = 0,
+        help = "Number of pdfium worker processes parsing the pages of the document in parallel"
+    )]
+    native_workers: usize,
+
+    /// Minimum confidence of the layout detections
+    #[arg(
+        long,
+        default_value_t = ORTLayoutParser::CONF_THRESHOLD,
+        value_parser = parse_threshold,
+        help = "Confidence threshold for layout detections, in [0, 1]"
+    )]
+    layout_conf: f32,
+
+    /// IoU above which overlapping layout detections are suppressed
+    #[arg(
+        long,
+        default_value_t = ORTLayoutParser::IOU_THRESHOLD,
+        value_parser = parse_threshold,
+        help = "IoU threshold for layout non-maximum suppression, in [0, 1]"
+    )]
+    layout_nms: f32,
+
+    /// Use the fast OCR recognition level instead of the accurate one
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Use fast OCR recognition (quicker but less accurate)"
+    )]
+    ocr_fast: bool,
+
+    /// How native text and OCR results are combined on pages with a partial text layer
+    #[arg(
+        long,
+        default_value = "prefer-native",
+        help = "Text source for pages with a partial text layer: prefer-native, prefer-ocr, native-only or ocr-only"
+    )]
+    text_source: TextSource,
+
+    /// Minimum confidence of the OCR lines kept on a page
+    #[arg(
+        long,
+        default_value_t = DEFAULT_OCR_MIN_CONFIDENCE,
+        value_parser = parse_threshold,
+        help = "Drop OCR lines recognized with a lower confidence, in [0, 1]"
+    )]
+    ocr_min_confidence: f32,
+
+    /// Minimum ratio of the text regions covered by the native text lines
+    #[arg(
+        long,
+        default_value_t = DEFAULT_OCR_COVERAGE_THRESHOLD,
+        value_parser = parse_threshold,
+        help = "OCR the pages whose native text lines cover less than this ratio of their text regions, in [0, 1]"
+    )]
+    ocr_coverage_threshold: f32,
+
+    /// Minimum number of characters of the native text layer
+    #[arg(
+        long,
+        default_value_t = DEFAULT_OCR_MIN_CHARS,
+        help = "OCR the pages whose native text layer has less non-whitespace characters"
+    )]
+    ocr_min_chars: usize,
+
+    /// OCR every page
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "OCR every page, whatever its text layer. Has no effect with --text-source native-only"
+    )]
+    force_ocr: bool,
+
+    /// Maximum time for getting the layout of a page
+    #[arg(
+        long,
+        help = "Fail a page when its layout takes longer than this many milliseconds, queueing included"
+    )]
+    layout_timeout_ms: Option<u64>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "layout_timeout_ms",
+        help = "Segment the pages whose layout timed out from their text lines instead of failing"
+    )]
+    layout_timeout_fallback: bool,
+
+    /// Maximum time for parsing the document
+    #[arg(
+        long,
+        help = "Fail when parsing the pages of the document takes longer than this many seconds"
+    )]
+    timeout_secs: Option<u64>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Include the raw layout detections of each page in the json output"
+    )]
+    emit_layout: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Skip layout inference and group the text lines into paragraphs only (no titles, lists, tables or figures)"
+    )]
+    no_layout: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Keep the elements in the layout order instead of detecting the columns of the pages, for layouts where the column detection misfires, e.g. newspapers"
+    )]
+    single_column: bool,
+
+    #[arg(
+        long,
+        value_name = "STRATEGY",
+        default_value = "outline",
+        help = "How the title levels are assigned: outline (the depth of the PDF bookmarks, then the font size clusters), kmeans[:<k>] (at most k font size clusters, 6 by default) or font-size (one level per font size)"
+    )]
+    title_levels: TitleLevelStrategy,
+
+    /// Read the short text in a large font as subtitles
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Turn into subtitles the short paragraphs set in a font noticeably larger than the body text, for the headings the layout model misses"
+    )]
+    promote_large_text: bool,
+
+    #[arg(
+        long,
+        value_name = "CLASSES",
+        value_delimiter = ',',
+        help = "Layout classes to ignore, e.g. page-header,page-footer. Their text is kept as plain text"
+    )]
+    exclude_layout_class: Vec<String>,
+
+    /// Prefixes of the text blocks assigned as figure and table captions
+    #[arg(
+        long,
+        value_name = "PREFIXES",
+        value_delimiter = ',',
+        help = "Prefixes of the caption text of figures and tables, e.g. Abbildung,Tabelle (defaults to Figure,Fig.,Table)"
+    )]
+    caption_prefixes: Option<Vec<String>>,
+
+    /// Maximum vertical gap between a caption and its figure or table
+    #[arg(
+        long,
+        value_name = "POINTS",
+        default_value_t = DEFAULT_CAPTION_MAX_DISTANCE,
+        help = "Maximum vertical distance, in page points, between a caption and its figure or table"
+    )]
+    caption_max_distance: f32,
+
+    #[arg(
+        long,
+        value_name = "POINTS",
+        default_value_t = 0.0,
+        help = "Gap, in page points, below the bottom of a line within which the next span still joins the line"
+    )]
+    line_gap: f32,
+
+    #[arg(
+        long,
+        value_name = "POINTS",
+        default_value_t = DEFAULT_PARAGRAPH_GAP,
+        help = "Maximum distance, in page points, between the centres of two consecutive text elements merged into one paragraph"
+    )]
+    paragraph_gap: f32,
+
+    #[arg(
+        long,
+        value_name = "POINTS",
+        help = "Maximum horizontal gap, in page points, between the end of a line and the next span joining it (unbounded by default)"
+    )]
+    span_gap: Option<f32>,
+
+    /// Drop the headers and footers repeated across the pages from the text outputs
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Detect the headers, footers and text repeated on most pages, e.g. page numbers, and drop them from the markdown, html and text outputs. They are listed once in the document metadata and flagged `repeated` in the json output"
+    )]
+    strip_running_headers: bool,
+
+    /// Crop the images from the page raster instead of extracting the embedded bitmaps
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Crop the figures from the rendered pages instead of exporting the images embedded in the PDF at their original resolution and format"
+    )]
+    crop_images: bool,
+
+    /// Segment tagged PDFs with the layout model instead of their structure tree
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Segment and order the pages of tagged PDFs with the layout model instead of the structure tree written by the authoring tool"
+    )]
+    ignore_tagged_structure: bool,
+
+    /// Resolution of the page rasters used for the image crops and OCR
+    #[arg(
+        long,
+        value_name = "DPI",
+        value_parser = parse_raster_dpi,
+        help = "Render the pages used for the image crops and OCR at this resolution (defaults to 72 DPI). Bboxes stay in PDF points"
+    )]
+    raster_dpi: Option<f32>,
+
+    /// Maximum width and height of the page rasters
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Maximum width and height in pixels of the rendered pages, lowering the resolution of large pages"
+    )]
+    max_raster_dim: Option<u32>,
+
+    /// Output the bboxes in [0, 1] page-relative coordinates
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Divide the bboxes of the json and jsonl outputs by their page width and height, giving resolution independent coordinates in [0, 1]"
+    )]
+    normalize_bboxes: bool,
+
+    /// Abort on the first page that fails instead of skipping it
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Stop parsing on the first page that fails. By default failed pages are skipped and reported at the end"
+    )]
+    fail_fast: bool,
+
+    /// Cache OCR results on disk, optionally in the given directory
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Cache OCR results on disk to speed up re-parsing (defaults to the user cache directory)"
+    )]
+    ocr_cache: Option<Option<PathBuf>>,
+
+    /// Cache the parsed pages on disk, optionally in the given directory
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Cache each parsed page on disk so an interrupted parse resumes where it stopped (defaults to the user cache directory)"
+    )]
+    page_cache: Option<Option<PathBuf>>,
+
     /// Enable debug mode to output additional information
     #[arg(
         long,
@@ -136,6 +653,28 @@ struct Args {
     )]
     debug: bool,
 
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Print a summary of the layout queue and inference timings, and the time breakdown of the slowest page, at the end"
+    )]
+    timings: bool,
+
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        help = "Only print errors, without the progress bar and the results path"
+    )]
+    quiet: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Run a dummy layout inference before parsing, so that the execution provider initialization isn't counted in the parsing time"
+    )]
+    warmup: bool,
+
     /// Directory for debug output files
     #[arg(
         long,
@@ -156,117 +695,384 @@ struct Args {
     profile_table: bool,
 }
 
-fn parse_page_range(range_str: &str) -> anyhow::Result<Range<usize>> {
-    if let Some((start, end)) = range_str.split_once('-') {
-        let start: usize = start.trim().parse()?;
-        let end: usize = end.trim().parse()?;
-        if start > 0 && end >= start {
-            Ok(Range {
-                start: start - 1,
-                end,
-            })
+fn format_incorrect_password(file_path: &Path, password: Option<&str>) {
+    format_error(
+        "Incorrect Password",
+        if password.is_some() {
+            "The password doesn't open the encrypted PDF."
         } else {
-            anyhow::bail!("Invalid page range: start must be > 0 and end must be >= start")
+            "The PDF is encrypted and requires a password."
+        },
+        vec![
+            ("File", file_path.display().to_string()),
+            (
+                "Suggestion",
+                "Pass the document password with --password or FERRULES_PDF_PASSWORD".to_string(),
+            ),
+        ],
+    );
+}
+
+fn format_encrypted_document(file_path: &Path) {
+    format_error(
+        "Encrypted Document",
+        "The PDF is encrypted and no password was given.",
+        vec![
+            ("File", file_path.display().to_string()),
+            (
+                "Suggestion",
+                "Pass the document password with --password or FERRULES_PDF_PASSWORD".to_string(),
+            ),
+        ],
+    );
+}
+
+fn format_unsupported_format(file_path: &Path, reason: &str) {
+    format_error(
+        "Unsupported Document Format",
+        "The file can't be opened as a PDF document.",
+        vec![
+            ("File", file_path.display().to_string()),
+            ("Reason", reason.to_string()),
+            (
+                "Suggestion",
+                "Check that the file is a valid PDF, or convert it to PDF first".to_string(),
+            ),
+        ],
+    );
+}
+
+fn format_pdfium_load_error(reason: &str) {
+    format_error(
+        "PDFium Library Load Failed",
+        "Failed to load the pdfium library.",
+        vec![
+            ("Error", reason.to_string()),
+            (
+                "Suggestion",
+                format!(
+                    "Point {PDFIUM_PATH_ENV} to the pdfium library or the directory containing it"
+                ),
+            ),
+        ],
+    );
+}
+
+/// Formats the errors of a document that can't be opened: encrypted, not a PDF or pdfium
+/// missing. Returns whether `e` is one of them
+fn format_document_error(file_path: &Path, password: Option<&str>, e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<ferrules_core::error::FerrulesError>() {
+        Some(ferrules_core::error::FerrulesError::IncorrectPassword) => {
+            format_incorrect_password(file_path, password)
         }
-    } else {
-        // Single page
-        let page: usize = range_str.trim().parse()?;
-        if page > 0 {
-            Ok(Range {
-                start: page - 1,
-                end: page,
-            })
-        } else {
-            anyhow::bail!("Page number must be greater than 0")
+        Some(ferrules_core::error::FerrulesError::EncryptedDocument) => {
+            format_encrypted_document(file_path)
         }
+        Some(ferrules_core::error::FerrulesError::UnsupportedFormat(reason)) => {
+            format_unsupported_format(file_path, reason)
+        }
+        Some(ferrules_core::error::FerrulesError::PdfiumLoad(reason)) => {
+            format_pdfium_load_error(reason)
+        }
+        _ => return false,
     }
+    true
 }
 
 fn setup_progress_bar(
     file_path: &Path,
+    fetched_doc: Option<&[u8]>,
     password: Option<&str>,
-    page_range: Option<Range<usize>>,
+    page_range: Option<&PageRange>,
+    quiet: bool,
 ) -> ProgressBar {
-    let length_pages = match get_doc_length(file_path, password, page_range.clone()) {
+    let length_pages = match fetched_doc {
+        Some(doc) => get_doc_length_from_bytes(doc, password, page_range),
+        None => get_doc_length(file_path, password, page_range),
+    };
+    let length_pages = match length_pages {
         Ok(pages) => pages,
+        Err(e) => {
+            if format_document_error(file_path, password, &e) {
+                std::process::exit(1);
+            }
+            format_error(
+                "Document Length Detection Failed",
+                "Failed to determine the number of pages in the document.",
+                vec![
+                    ("File", file_path.display().to_string()),
+                    ("Error", format!("{e:#}")),
+                    (
+                        "Suggestion",
+                        "Check if the file exists and is a valid PDF".to_string(),
+                    ),
+                ],
+            );
+            std::process::exit(1);
+        }
+    };
+    let pb = ProgressBar::new(length_pages as u64);
+    pb.set_style(
+        ProgressStyle::with_template(
+            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] [{prefix}] {msg}",
+        )
+        .unwrap()
+        .with_key("eta", |state: &ProgressState, w: &mut dyn Write| {
+            write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap()
+        })
+        .progress_chars("#>-"),
+    );
+    if quiet {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    pb
+}
+
+fn parse_threshold(value: &str) -> Result<f32, String> {
+    let threshold: f32 = value
+        .parse()
+        .map_err(|_| format!("`{value}` is not a number"))?;
+    if (0f32..=1f32).contains(&threshold) {
+        Ok(threshold)
+    } else {
+        Err(format!("{threshold} is not in [0, 1]"))
+    }
+}
+
+fn parse_raster_dpi(value: &str) -> Result<f32, String> {
+    let dpi: f32 = value
+        .parse()
+        .map_err(|_| format!("`{value}` is not a number"))?;
+    if dpi.is_finite() && dpi > 0f32 {
+        Ok(dpi)
+    } else {
+        Err(format!("{dpi} is not a positive resolution"))
+    }
+}
+
+fn parse_input_size(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or_else(|| format!("`{value}` is not formatted as WIDTHxHEIGHT"))?;
+    match (width.trim().parse::<u32>(), height.trim().parse::<u32>()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("`{value}` is not a valid WIDTHxHEIGHT size")),
+    }
+}
+
+fn parse_ep_args(args: &Args) -> Vec<OrtExecutionProvider> {
+    let mut providers = Vec::new();
+    let gpu_mem_limit = args.gpu_mem_limit.map(|mib| mib * 1024 * 1024);
+    if args.trt {
+        providers.push(OrtExecutionProvider::Trt(
+            args.device_id,
+            TrtOptions {
+                engine_cache_dir: args.trt_cache_dir.clone(),
+                max_workspace_size: gpu_mem_limit,
+                fp16: args.trt_fp16,
+            },
+        ));
+    }
+    if args.cuda {
+        providers.push(OrtExecutionProvider::CUDA(
+            args.device_id,
+            CudaOptions {
+                memory_limit: gpu_mem_limit,
+            },
+        ));
+    }
+
+    #[cfg(feature = "directml")]
+    if args.directml {
+        providers.push(OrtExecutionProvider::DirectML(args.device_id));
+    }
+
+    if args.coreml {
+        providers.push(OrtExecutionProvider::CoreML {
+            ane_only: args.use_ane,
+        });
+    }
+    providers.push(OrtExecutionProvider::CPU);
+    providers
+}
+
+/// Output formats selected by the arguments, besides the json document
+fn output_formats(args: &Args) -> Vec<&'static str> {
+    let mut formats = vec![];
+    if args.html || args.html_inline {
+        formats.push("HTML");
+    }
+    if args.md {
+        formats.push("Markdown");
+    }
+    if args.md_per_page {
+        formats.push("Markdown pages");
+    }
+    if args.hocr {
+        formats.push("hOCR");
+    }
+    if args.docx {
+        formats.push("DOCX");
+    }
+    if args.jsonl {
+        formats.push("JSON Lines");
+    }
+    if args.txt {
+        formats.push("Text");
+    }
+    formats
+}
+
+fn print_bench(stats: &BenchStats, provider: &str) {
+    println!("Benchmark: {} runs on {provider}", stats.runs);
+    println!(
+        "  {:<18} {:>8} {:>10} {:>10} {:>10}",
+        "stage", "samples", "min", "median", "p95"
+    );
+    let document = [("document".to_owned(), stats.document.clone())];
+    for (stage, durations) in document.iter().chain(&stats.stages) {
+        println!(
+            "  {:<18} {:>8} {:>8.1}ms {:>8.1}ms {:>8.1}ms",
+            stage, durations.samples, durations.min_ms, durations.p50_ms, durations.p95_ms
+        );
+    }
+    println!("  pages/sec: {:.2}", stats.pages_per_sec);
+}
+
+fn print_timings(stats: &LayoutQueueStats, metrics: &ParsingMetrics) {
+    eprintln!("Layout timings:");
+    eprintln!("  inferences:          {}", stats.inferences);
+    eprintln!(
+        "  inference p50/p95:   {:.1}ms / {:.1}ms",
+        stats.inference_p50_ms, stats.inference_p95_ms
+    );
+    eprintln!("  total queue wait:    {:.1}ms", stats.total_queue_wait_ms);
+    eprintln!(
+        "  queue depth:         {} ({} in flight)",
+        stats.queue_depth, stats.in_flight
+    );
+    if let Some(page) = metrics.slowest_page() {
+        eprintln!(
+            "Slowest page: {} ({:.1}ms)",
+            page.page_id + 1,
+            page.total_duration_ms
+        );
+        eprintln!(
+            "  native:              {:.1}ms",
+            page.native_step.execution_time_ms
+        );
+        eprintln!(
+            "  layout:              {:.1}ms (queued {:.1}ms)",
+            page.layout_step.execution_time_ms, page.layout_step.queue_time_ms
+        );
+        if let Some(ocr) = &page.ocr_step {
+            eprintln!(
+                "  ocr:                 {:.1}ms",
+                ocr.step_metrics.execution_time_ms
+            );
+        }
+        if !page.table_steps.is_empty() {
+            eprintln!(
+                "  tables:              {:.1}ms ({} tables)",
+                page.table_duration_ms(),
+                page.table_steps.len()
+            );
+        }
+    }
+}
+
+/// Memory maps the PDF file, exits when it can't be opened
+async fn map_file(file_path: &Path) -> Mmap {
+    let file = match File::open(file_path).await {
+        Ok(f) => f,
         Err(e) => {
             format_error(
-                "Document Length Detection Failed",
-                "Failed to determine the number of pages in the document.",
+                "File Open Failed",
+                "Failed to open the PDF file for processing.",
                 vec![
                     ("File", file_path.display().to_string()),
                     ("Error", e.to_string()),
                     (
                         "Suggestion",
-                        "Check if the file exists and is a valid PDF".to_string(),
+                        "Check file permissions and ensure the file exists".to_string(),
                     ),
                 ],
             );
             std::process::exit(1);
         }
     };
-    let pb = ProgressBar::new(length_pages as u64);
-    pb.set_style(
-        ProgressStyle::with_template(
-            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {msg}",
-        )
-        .unwrap()
-        .with_key("eta", |state: &ProgressState, w: &mut dyn Write| {
-            write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap()
-        })
-        .progress_chars("#>-"),
-    );
-    pb
-}
-
-fn parse_ep_args(args: &Args) -> Vec<OrtExecutionProvider> {
-    let mut providers = Vec::new();
-    if args.trt {
-        providers.push(OrtExecutionProvider::Trt(args.device_id));
-    }
-    if args.cuda {
-        providers.push(OrtExecutionProvider::CUDA(args.device_id));
-    }
-
-    if args.coreml {
-        providers.push(OrtExecutionProvider::CoreML {
-            ane_only: args.use_ane,
-        });
+    match unsafe { Mmap::map(&file) } {
+        Ok(m) => m,
+        Err(e) => {
+            format_error(
+                "Memory Mapping Failed",
+                "Failed to memory-map the PDF file.",
+                vec![
+                    ("File", file_path.display().to_string()),
+                    ("Error", e.to_string()),
+                    ("Suggestion", "Check available system memory".to_string()),
+                ],
+            );
+            std::process::exit(1);
+        }
     }
-    providers.push(OrtExecutionProvider::CPU);
-    providers
 }
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() {
-    let args = Args::parse();
+    // NOTE: must run before anything is written to stdout
+    ferrules_core::run_native_worker_if_requested();
+    let cli = Cli::parse();
+    let (file_path, args, bench_runs) = match cli.command {
+        Some(Command::Bench {
+            file_path,
+            args,
+            bench_runs,
+        }) => (file_path, args, Some(bench_runs)),
+        #[cfg(feature = "schema")]
+        Some(Command::Schema) => {
+            let schema = ferrules_core::entities::parsed_document_schema();
+            println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+            return;
+        }
+        // Clap requires the file path without a subcommand
+        None => (cli.file_path.expect("missing file path"), cli.args, None),
+    };
+    let bench = bench_runs.is_some();
     if args.debug || std::env::var("RUST_LOG").is_ok() {
-        tracing_subscriber::fmt::init();
+        // Logs never go to stdout, which can carry the parsed document
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .init();
+    }
+    if args.debug {
+        match load_pdfium() {
+            Ok((_, binding)) => eprintln!("pdfium: {binding}"),
+            Err(ferrules_core::error::FerrulesError::PdfiumLoad(reason)) => {
+                format_pdfium_load_error(&reason);
+                std::process::exit(1);
+            }
+            Err(e) => eprintln!("pdfium: {e}"),
+        }
+    }
+    if args.stdout && (output_formats(&args).len() > 1 || args.docx || args.md_per_page) {
+        format_error(
+            "Invalid Output Format",
+            "Only a single text output format can be written to stdout.",
+            vec![
+                ("Formats", output_formats(&args).join(", ")),
+                (
+                    "Suggestion",
+                    "Select one of --md, --html, --html-inline, --txt, --hocr or --jsonl"
+                        .to_string(),
+                ),
+            ],
+        );
+        std::process::exit(1);
     }
-
-    // Check providers
-    let providers = parse_ep_args(&args);
-
-    let ort_config = ORTConfig {
-        execution_providers: providers,
-        intra_threads: args.intra_threads,
-        inter_threads: args.inter_threads,
-        opt_level: args.graph_opt_level.map(|v| v.try_into().unwrap()),
-        warmup: false,
-        profile_layout: if args.profile_layout {
-            Some(PathBuf::from("profile_layout"))
-        } else {
-            None
-        },
-        profile_table: if args.profile_table {
-            Some(PathBuf::from("profile_table"))
-        } else {
-            None
-        },
-    };
 
     let page_range = match args.page_range {
-        Some(ref page_range_str) => match parse_page_range(page_range_str) {
+        Some(ref page_range_str) => match page_range_str.parse::<PageRange>() {
             Ok(range) => Some(range),
             Err(e) => {
                 format_error(
@@ -276,7 +1082,8 @@ async fn main() {
                         ("Input", page_range_str.clone()),
                         (
                             "Format",
-                            "Use '1-5' for range or '1' for single page".to_string(),
+                            "Use '1-5' for range, '1' for single page or '1,3,5-7,10-' for a list"
+                                .to_string(),
                         ),
                         ("Note", "Page numbers start from 1".to_string()),
                     ],
@@ -286,14 +1093,146 @@ async fn main() {
         },
         None => None,
     };
-    let pb = setup_progress_bar(&args.file_path, None, page_range.clone());
-    let pbc = pb.clone();
+    // Documents given by URL are downloaded in memory, never written to disk
+    let fetched_doc = match file_path.to_str().filter(|path| is_url(path)) {
+        Some(url) => {
+            match fetch_document(url, DEFAULT_FETCH_MAX_BYTES, DEFAULT_FETCH_TIMEOUT).await {
+                Ok(doc) => Some(doc),
+                Err(e) => {
+                    format_error(
+                        "Document Download Failed",
+                        "Failed to download the PDF file.",
+                        vec![
+                            ("URL", url.to_string()),
+                            ("Error", e.to_string()),
+                            (
+                                "Suggestion",
+                                "Check the URL and the network connection, or download the file and pass its path".to_string(),
+                            ),
+                        ],
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+    if args.count {
+        let count = match &fetched_doc {
+            Some(doc) => count_document(
+                doc,
+                args.password.as_deref(),
+                page_range.as_ref(),
+                args.ocr_min_chars,
+            ),
+            None => count_document(
+                &map_file(&file_path).await,
+                args.password.as_deref(),
+                page_range.as_ref(),
+                args.ocr_min_chars,
+            ),
+        };
+        match count {
+            Ok(count) => println!("{}", serde_json::to_string_pretty(&count).unwrap()),
+            Err(e) => {
+                if !format_document_error(&file_path, args.password.as_deref(), &e) {
+                    format_error(
+                        "Document Count Failed",
+                        "Failed to read the native text layer of the document.",
+                        vec![
+                            ("File", file_path.display().to_string()),
+                            ("Error", format!("{e:#}")),
+                        ],
+                    );
+                }
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Check providers
+    let providers = parse_ep_args(&args);
+
+    let auto_tune = AutoTune::detect();
+    let inter_threads = args.inter_threads.resolve(auto_tune.inter_threads);
+    let mut ort_config = ORTConfig {
+        execution_providers: providers,
+        intra_threads: args.intra_threads.resolve(auto_tune.intra_threads),
+        inter_threads,
+        opt_level: args.graph_opt_level.map(|v| v.try_into().unwrap()),
+        layout_batch_size: args.layout_batch_size.resolve(auto_tune.layout_batch_size),
+        layout_batch_max_wait: Duration::from_millis(args.layout_batch_wait_ms),
+        conf_threshold: args.layout_conf,
+        nms_iou: args.layout_nms,
+        layout_model_path: args.layout_model.clone(),
+        layout_labels: None,
+        layout_input_size: args.layout_input_size,
+        buffer_pool_size: inter_threads,
+        model_precision: args.layout_precision,
+        layout_retries: args.layout_retries,
+        warmup: false,
+        profile_layout: if args.profile_layout {
+            Some(PathBuf::from("profile_layout"))
+        } else {
+            None
+        },
+        profile_table: if args.profile_table {
+            Some(PathBuf::from("profile_table"))
+        } else {
+            None
+        },
+    };
+    for provider in ort_config.retain_available_providers() {
+        let fallback = ort_config.get_sorted_providers()[0].clone();
+        format_warning(
+            "Execution Provider Unavailable",
+            &format!(
+                "The ONNX Runtime library doesn't support {provider}, the layout model runs on {fallback} instead."
+            ),
+            vec![
+                ("Requested", provider.to_string()),
+                ("Fallback", fallback.to_string()),
+                ("Suggestion", provider.install_hint().to_string()),
+            ],
+        );
+    }
+
+    let pb = setup_progress_bar(
+        &file_path,
+        fetched_doc.as_deref(),
+        args.password.as_deref(),
+        page_range.as_ref(),
+        args.quiet,
+    );
 
     // Global tasks
-    let parser = FerrulesParser::new(ort_config);
+    let parser = if args.native_workers > 0 {
+        FerrulesParser::with_native_workers(ort_config, args.native_workers)
+    } else {
+        FerrulesParser::new(ort_config)
+    };
+    pb.set_prefix(parser.layout_provider().to_string());
+    // The execution provider initialization would skew the first benchmark run
+    if args.warmup || bench {
+        if let Err(e) = parser.warmup().await {
+            format_error(
+                "Layout Warmup Failed",
+                "The dummy layout inference run before parsing failed.",
+                vec![
+                    ("Error", e.to_string()),
+                    ("Provider", parser.layout_provider().to_string()),
+                    (
+                        "Suggestion",
+                        "Check that the execution provider is installed and the layout model is valid".to_string(),
+                    ),
+                ],
+            );
+            std::process::exit(1);
+        }
+    }
 
-    let doc_name = args
-        .file_path
+    let doc_name = file_path
         .file_name()
         .and_then(|name| name.to_str())
         .and_then(|name| name.split('.').next().map(|s| s.to_owned()))
@@ -301,10 +1240,13 @@ async fn main() {
 
     // Create all dirs
     // TODO: refac this
-    let save_figs = args.html | args.save_images;
-    let (output_dir_path, debug_path) =
+    let save_figs =
+        !args.stdout && ((args.html && !args.html_inline) | args.docx | args.save_images);
+    let (output_dir_path, debug_path) = if (args.stdout || bench) && !args.debug {
+        (None, None)
+    } else {
         match create_dirs(args.output_dir.as_ref(), &doc_name, args.debug, save_figs) {
-            Ok(paths) => paths,
+            Ok((output_dir_path, debug_path)) => (Some(output_dir_path), debug_path),
             Err(e) => {
                 format_error(
                     "Directory Creation Failed",
@@ -324,60 +1266,160 @@ async fn main() {
                 );
                 std::process::exit(1);
             }
-        };
-    // TODO : refac memap
-    let file = match File::open(&args.file_path).await {
-        Ok(f) => f,
-        Err(e) => {
-            format_error(
-                "File Open Failed",
-                "Failed to open the PDF file for processing.",
-                vec![
-                    ("File", args.file_path.display().to_string()),
-                    ("Error", e.to_string()),
-                    (
-                        "Suggestion",
-                        "Check file permissions and ensure the file exists".to_string(),
-                    ),
-                ],
-            );
-            std::process::exit(1);
         }
     };
-    let mmap = match unsafe { Mmap::map(&file) } {
-        Ok(m) => m,
-        Err(e) => {
-            format_error(
-                "Memory Mapping Failed",
-                "Failed to memory-map the PDF file.",
-                vec![
-                    ("File", args.file_path.display().to_string()),
-                    ("Error", e.to_string()),
-                    ("Suggestion", "Check available system memory".to_string()),
-                ],
-            );
-            std::process::exit(1);
+    let mmap;
+    let doc: &[u8] = match &fetched_doc {
+        Some(doc) => doc,
+        None => {
+            mmap = map_file(&file_path).await;
+            &mmap
         }
     };
 
+    // Ctrl-C stops parsing, the pages parsed so far are still saved
+    let cancellation = CancellationToken::new();
+    tokio::spawn({
+        let cancellation = cancellation.clone();
+        let pb = pb.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                pb.println("⚠ Interrupted, saving the pages parsed so far");
+                cancellation.cancel();
+            }
+        }
+    });
+
     let config = FerrulesParseConfig {
-        password: None,
-        flatten_pdf: true,
+        password: args.password.clone(),
+        flatten_pdf: !args.no_flatten,
+        text_normalization: (!args.raw_text).then_some(TextNormalization {
+            soft_hyphens: !args.keep_soft_hyphens,
+            nfkc: args.nfkc,
+            ..Default::default()
+        }),
+        dehyphenate: !args.no_dehyphenate,
+        merge_paragraphs: !args.no_merge_paragraphs,
         page_range,
         debug_dir: debug_path,
+        ocr_accuracy: if args.ocr_fast {
+            OcrAccuracy::Fast
+        } else {
+            OcrAccuracy::Accurate
+        },
+        text_source: args.text_source,
+        ocr_min_confidence: args.ocr_min_confidence,
+        ocr_coverage_threshold: args.ocr_coverage_threshold,
+        ocr_min_chars: args.ocr_min_chars,
+        force_ocr: args.force_ocr,
+        layout_timeout: args.layout_timeout_ms.map(Duration::from_millis),
+        layout_timeout_fallback: args.layout_timeout_fallback,
+        timeout: args.timeout_secs.map(Duration::from_secs),
+        include_layout: args.emit_layout,
+        reading_order: if args.single_column {
+            ReadingOrder::SingleColumn
+        } else {
+            ReadingOrder::Auto
+        },
+        title_level_strategy: args.title_levels,
+        promote_large_text_titles: args.promote_large_text,
+        layout_mode: if args.no_layout {
+            LayoutMode::Skip
+        } else {
+            LayoutMode::Model
+        },
+        layout_class_exclude: args.exclude_layout_class.clone(),
+        caption: CaptionConfig {
+            prefixes: args
+                .caption_prefixes
+                .clone()
+                .unwrap_or_else(|| CaptionConfig::default().prefixes),
+            max_distance: args.caption_max_distance,
+        },
+        merge_thresholds: MergeThresholds {
+            line_gap: args.line_gap,
+            paragraph_gap: args.paragraph_gap,
+            span_gap: args.span_gap,
+        },
+        strip_running_headers: args.strip_running_headers,
+        extract_embedded_images: !args.crop_images,
+        prefer_tagged_structure: !args.ignore_tagged_structure,
+        fail_fast: args.fail_fast,
+        raster_dpi: args.raster_dpi,
+        max_raster_dim: args.max_raster_dim,
+        // The page rasters are only needed to crop the saved or inlined images
+        keep_page_images: save_figs || args.html_inline,
+        ocr_cache_dir: args
+            .ocr_cache
+            .map(|dir| dir.unwrap_or_else(default_ocr_cache_dir)),
+        page_cache_dir: args
+            .page_cache
+            .map(|dir| dir.unwrap_or_else(default_page_cache_dir)),
+        cancellation: cancellation.clone(),
+        ..Default::default()
     };
-    let doc = match parser
-        .parse_document(
-            &mmap,
-            doc_name,
-            config,
-            Some(move |page_id| {
-                pbc.set_message(format!("Page #{}", page_id + 1));
-                pbc.inc(1u64);
-            }),
-        )
-        .await
-    {
+    if let Some(runs) = bench_runs {
+        pb.set_length(pb.length().unwrap_or_default() * runs as u64);
+        let mut metrics = Vec::with_capacity(runs as usize);
+        for _ in 0..runs {
+            // The run interrupted by Ctrl-C isn't counted
+            if cancellation.is_cancelled() {
+                break;
+            }
+            let page_pb = pb.clone();
+            let result = parser
+                .parse_document(
+                    doc,
+                    doc_name.clone(),
+                    config.clone(),
+                    Some(move |_| page_pb.inc(1)),
+                )
+                .await;
+            match result {
+                Ok(doc) if !cancellation.is_cancelled() => metrics.push(doc.metrics),
+                Ok(_) => break,
+                Err(ferrules_core::error::FerrulesError::Cancelled) => break,
+                Err(e) => {
+                    pb.finish_and_clear();
+                    format_error(
+                        "Benchmark Failed",
+                        "Failed to parse the document during the benchmark.",
+                        vec![
+                            ("File", file_path.display().to_string()),
+                            ("Error", e.to_string()),
+                        ],
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+        pb.finish_and_clear();
+        match BenchStats::from_runs(&metrics) {
+            Some(stats) => print_bench(&stats, &parser.layout_provider().to_string()),
+            None => eprintln!("No benchmark run completed"),
+        }
+        return;
+    }
+
+    // Pages are reported as they complete, with their own parsing time
+    let mut events = parser.parse_document_streaming(doc, doc_name, config);
+    let mut parse_result = None;
+    while let Some(event) = events.next().await {
+        match event {
+            ParseStreamEvent::Page(page) => {
+                pb.set_message(format!(
+                    "Page #{} ({:.0}ms)",
+                    page.id + 1,
+                    page.metrics.total_duration_ms
+                ));
+                pb.inc(1u64);
+            }
+            ParseStreamEvent::Done(result) => parse_result = Some(result),
+        }
+    }
+    let parse_result =
+        parse_result.unwrap_or(Err(ferrules_core::error::FerrulesError::ParseNativeError));
+    let doc = match parse_result {
         Ok(result) => result,
         Err(e) => {
             match e {
@@ -386,7 +1428,7 @@ async fn main() {
                         "Native PDF Parsing Failed",
                         "Failed to parse the PDF file using the native parser.",
                         vec![
-                            ("File", args.file_path.display().to_string()),
+                            ("File", file_path.display().to_string()),
                             (
                                 "Suggestion",
                                 "Check if the PDF file is valid and not corrupted".to_string(),
@@ -394,12 +1436,24 @@ async fn main() {
                         ],
                     );
                 }
+                ferrules_core::error::FerrulesError::IncorrectPassword => {
+                    format_incorrect_password(&file_path, args.password.as_deref());
+                }
+                ferrules_core::error::FerrulesError::EncryptedDocument => {
+                    format_encrypted_document(&file_path);
+                }
+                ferrules_core::error::FerrulesError::UnsupportedFormat(reason) => {
+                    format_unsupported_format(&file_path, &reason);
+                }
+                ferrules_core::error::FerrulesError::PdfiumLoad(reason) => {
+                    format_pdfium_load_error(&reason);
+                }
                 ferrules_core::error::FerrulesError::LayoutParsingError => {
                     format_error(
                         "Layout Detection Failed",
                         "Failed to detect document layout structure.",
                         vec![
-                            ("File", args.file_path.display().to_string()),
+                            ("File", file_path.display().to_string()),
                             (
                                 "Suggestion",
                                 "Try using a different execution provider (--cuda, --coreml)"
@@ -408,12 +1462,47 @@ async fn main() {
                         ],
                     );
                 }
+                ferrules_core::error::FerrulesError::LayoutTimeout {
+                    page_id,
+                    elapsed,
+                    queue_time_ms,
+                } => {
+                    format_error(
+                        "Layout Detection Timed Out",
+                        "Layout inference took longer than the configured timeout.",
+                        vec![
+                            ("File", file_path.display().to_string()),
+                            ("Page", (page_id + 1).to_string()),
+                            ("Elapsed", format!("{}ms", elapsed.as_millis())),
+                            ("Queue Time", format!("{queue_time_ms:.1}ms")),
+                            (
+                                "Suggestion",
+                                "Increase --layout-timeout-ms or use --layout-timeout-fallback"
+                                    .to_string(),
+                            ),
+                        ],
+                    );
+                }
+                ferrules_core::error::FerrulesError::UnknownLayoutClass { class, valid } => {
+                    format_error(
+                        "Unknown Layout Class",
+                        "A layout class to exclude is not a class of the layout model.",
+                        vec![
+                            ("Class", class),
+                            ("Valid Classes", valid),
+                            (
+                                "Suggestion",
+                                "Fix the --exclude-layout-class argument".to_string(),
+                            ),
+                        ],
+                    );
+                }
                 ferrules_core::error::FerrulesError::LineMergeError => {
                     format_error(
                         "Line Merging Failed",
                         "Failed to merge text lines during document processing.",
                         vec![
-                            ("File", args.file_path.display().to_string()),
+                            ("File", file_path.display().to_string()),
                             (
                                 "Suggestion",
                                 "This might indicate complex text layout in the PDF".to_string(),
@@ -434,7 +1523,7 @@ async fn main() {
                             ("Block Type", kind.to_string()),
                             ("Page Number", element.page_id.to_string()),
                             ("Element", format!("{}-{}", element.id, element.kind)),
-                            ("File", args.file_path.display().to_string()),
+                            ("File", file_path.display().to_string()),
                         ],
                     );
                 }
@@ -445,7 +1534,7 @@ async fn main() {
                         vec![
                             ("Page", format!("#{}", page_idx + 1)),
                             ("Debug Directory", tmp_dir.display().to_string()),
-                            ("File", args.file_path.display().to_string()),
+                            ("File", file_path.display().to_string()),
                         ],
                     );
                 }
@@ -456,7 +1545,7 @@ async fn main() {
                         vec![
                             ("Page", format!("#{}", page_idx + 1)),
                             ("Temp Directory", tmp_dir.display().to_string()),
-                            ("File", args.file_path.display().to_string()),
+                            ("File", file_path.display().to_string()),
                             (
                                 "Suggestion",
                                 "Try processing a different page range with --page-range"
@@ -471,7 +1560,7 @@ async fn main() {
                         "Failed to process table using the vision model.",
                         vec![
                             ("Error", e),
-                            ("File", args.file_path.display().to_string()),
+                            ("File", file_path.display().to_string()),
                             (
                                 "Suggestion",
                                 "Check if the model files are present and valid.".to_string(),
@@ -485,7 +1574,7 @@ async fn main() {
                         "Failed to parse table using the vision model.",
                         vec![
                             ("Error", e),
-                            ("File", args.file_path.display().to_string()),
+                            ("File", file_path.display().to_string()),
                             (
                                 "Suggestion",
                                 "Check if the model files are present and valid.".to_string(),
@@ -499,7 +1588,7 @@ async fn main() {
                         "Failed to extract text using OCR.",
                         vec![
                             ("Error", e),
-                            ("File", args.file_path.display().to_string()),
+                            ("File", file_path.display().to_string()),
                             (
                                 "Suggestion",
                                 "This might indicate an issue with Apple Vision or stitched image size".to_string(),
@@ -507,36 +1596,131 @@ async fn main() {
                         ],
                     );
                 }
+                ferrules_core::error::FerrulesError::NativePageError { page_id, message } => {
+                    format_error(
+                        "Page Parsing Failed",
+                        "Failed to parse a page using the native parser.",
+                        vec![
+                            ("Page", (page_id + 1).to_string()),
+                            ("Error", message),
+                            ("File", file_path.display().to_string()),
+                            (
+                                "Suggestion",
+                                "Run without --fail-fast to skip the failing pages".to_string(),
+                            ),
+                        ],
+                    );
+                }
+                ferrules_core::error::FerrulesError::Cancelled => {
+                    format_error(
+                        "Parsing Interrupted",
+                        "Parsing was cancelled before any page was parsed.",
+                        vec![("File", file_path.display().to_string())],
+                    );
+                }
+                ferrules_core::error::FerrulesError::Timeout(timeout) => {
+                    format_error(
+                        "Parsing Timed Out",
+                        "Parsing the document took longer than the configured timeout.",
+                        vec![
+                            ("File", file_path.display().to_string()),
+                            ("Timeout", format!("{}s", timeout.as_secs())),
+                            (
+                                "Suggestion",
+                                "Increase --timeout-secs or parse fewer pages with --page-range"
+                                    .to_string(),
+                            ),
+                        ],
+                    );
+                }
+                ferrules_core::error::FerrulesError::FetchError { url, reason } => {
+                    format_error(
+                        "Document Download Failed",
+                        "Failed to download the PDF file.",
+                        vec![("URL", url), ("Error", reason)],
+                    );
+                }
+                ferrules_core::error::FerrulesError::PagePanic { page_id } => {
+                    format_error(
+                        "Page Parsing Failed",
+                        "Parsing a page panicked.",
+                        vec![
+                            ("Page", (page_id + 1).to_string()),
+                            ("File", file_path.display().to_string()),
+                            (
+                                "Suggestion",
+                                "Run without --fail-fast to skip the failing pages".to_string(),
+                            ),
+                        ],
+                    );
+                }
             }
             std::process::exit(1);
         }
     };
 
+    let language = doc
+        .metadata
+        .language
+        .as_ref()
+        .map(|language| format!(" (language: {})", language.code))
+        .unwrap_or_default();
     pb.finish_with_message(format!(
-        "Parsed document in {}ms",
+        "Parsed document in {}ms{language}",
         doc.metadata.parsing_duration.as_millis()
     ));
-    if let Err(e) = save_parsed_document(
-        &doc,
-        output_dir_path.clone(),
-        args.save_images,
-        args.html,
-        args.md,
-    ) {
+    if !doc.failed_pages.is_empty() {
+        eprintln!(
+            "⚠ {} page(s) failed and were skipped: {}",
+            doc.failed_pages.len(),
+            doc.failed_pages
+                .iter()
+                .map(|failure| format!("#{} ({})", failure.page_id + 1, failure.error))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    for warning in &doc.warnings {
+        eprintln!("⚠ {}", warning.message);
+    }
+    let save_options = SaveOptions {
+        save_imgs: args.save_images,
+        save_html: args.html || args.html_inline,
+        html_inline: args.html_inline,
+        save_markdown: args.md,
+        save_markdown_pages: args.md_per_page,
+        save_hocr: args.hocr,
+        save_docx: args.docx,
+        save_jsonl: args.jsonl,
+        txt_page_break: args.txt.then(|| {
+            args.txt_page_break
+                .clone()
+                .unwrap_or_else(|| DEFAULT_PAGE_BREAK.to_owned())
+        }),
+        image_padding: args.image_padding,
+        quiet: args.quiet,
+        normalize_bboxes: args.normalize_bboxes,
+    };
+    let saved = match &output_dir_path {
+        Some(output_dir_path) if !args.stdout => {
+            save_parsed_document(&doc, output_dir_path.clone(), &save_options)
+        }
+        _ => write_parsed_document(&doc, std::io::stdout().lock(), &save_options),
+    };
+    if let Err(e) = saved {
         format_error(
             "Document Save Failed",
             "Failed to save the parsed document.",
             vec![
-                ("Output Directory", output_dir_path.display().to_string()),
+                (
+                    "Output Directory",
+                    output_dir_path
+                        .filter(|_| !args.stdout)
+                        .map_or("stdout".to_string(), |p| p.display().to_string()),
+                ),
                 ("Error", e.to_string()),
                 ("Formats", {
-                    let mut formats = vec![];
-                    if args.html {
-                        formats.push("HTML");
-                    }
-                    if args.md {
-                        formats.push("Markdown");
-                    }
+                    let mut formats = output_formats(&args);
                     if args.save_images {
                         formats.push("Images");
                     }
@@ -549,4 +1733,12 @@ async fn main() {
         );
         std::process::exit(1);
     }
+
+    if args.timings {
+        print_timings(&parser.layout_stats(), &doc.metrics);
+    }
+    if cancellation.is_cancelled() {
+        // Conventional exit code of a process interrupted by SIGINT
+        std::process::exit(130);
+    }
 }
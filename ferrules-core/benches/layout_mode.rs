@@ -0,0 +1,43 @@
+use std::{hint::black_box, time::Duration};
+
+use criterion::{criterion_main, Criterion};
+use ferrules_core::{entities::PageID, layout::LayoutMode, FerrulesParseConfig, FerrulesParser};
+
+/// Document parsed by the benchmark, defaults to the tables example of the repository
+fn bench_document() -> Vec<u8> {
+    let path = std::env::var("FERRULES_BENCH_PDF")
+        .unwrap_or_else(|_| "../examples/sample-tables.pdf".to_string());
+    std::fs::read(&path).unwrap_or_else(|e| panic!("can't read {path}: {e}"))
+}
+
+fn bench_layout_mode(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let parser = rt.block_on(async { FerrulesParser::new(Default::default()) });
+    let doc = bench_document();
+
+    let mut group = c.benchmark_group("layout_mode");
+    for (name, layout_mode) in [("model", LayoutMode::Model), ("skip", LayoutMode::Skip)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                rt.block_on(parser.parse_document(
+                    black_box(&doc),
+                    "bench".to_string(),
+                    FerrulesParseConfig {
+                        layout_mode,
+                        ..Default::default()
+                    },
+                    None::<fn(PageID)>,
+                ))
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion::criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10).measurement_time(Duration::from_secs(30));
+    targets = bench_layout_mode
+}
+criterion_main!(benches);
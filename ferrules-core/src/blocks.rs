@@ -1,5 +1,5 @@
 use crate::{
-    entities::{BBox, Element, ElementType, PageID},
+    entities::{BBox, Element, ElementType, FormField, FormFieldKind, LinkSpan, PageID, StyleSpan},
     error::FerrulesError,
 };
 use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
@@ -10,20 +10,85 @@ pub type TitleLevel = u8;
 #[derive(
     Clone, Debug, Default, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct ImageBlock {
     pub(crate) id: usize,
     pub(crate) caption: Option<String>,
+    /// Set when the image is exported from the bitmap embedded in the PDF rather than cropped
+    /// from the page raster
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) embedded: Option<EmbeddedImageInfo>,
+}
+
+/// Embedded image object matched to an image block
+#[derive(
+    Clone, Debug, Default, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct EmbeddedImageInfo {
+    /// Index of the image object in the `embedded_images` of its page
+    #[serde(skip)]
+    pub(crate) index: usize,
+    /// Original width of the bitmap in pixels
+    pub width: u32,
+    /// Original height of the bitmap in pixels
+    pub height: u32,
+    /// Encoding of the image in the PDF, the exported file uses the same format
+    #[serde(default)]
+    pub format: EmbeddedImageFormat,
+}
+
+/// File format of the exported images, JPEG for the images stored as JPEG (`DCTDecode`) in
+/// the PDF and PNG for all the others
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    Archive,
+    RkyvDeserialize,
+    RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddedImageFormat {
+    #[default]
+    Png,
+    Jpeg,
+}
+
+impl EmbeddedImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            EmbeddedImageFormat::Png => "png",
+            EmbeddedImageFormat::Jpeg => "jpg",
+        }
+    }
 }
 
 impl ImageBlock {
+    /// Format of the exported file, PNG for the images cropped from the page raster
+    pub(crate) fn format(&self) -> EmbeddedImageFormat {
+        self.embedded
+            .as_ref()
+            .map(|embedded| embedded.format)
+            .unwrap_or_default()
+    }
+
     pub(crate) fn path(&self) -> String {
-        format!("img_{}.png", self.id)
+        format!("img_{}.{}", self.id, self.format().extension())
     }
 }
 
 #[derive(
     Clone, Debug, Default, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct TextBlock {
     pub text: String,
 }
@@ -31,13 +96,132 @@ pub struct TextBlock {
 #[derive(
     Clone, Debug, Default, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct List {
-    pub items: Vec<String>,
+    pub items: Vec<ListItem>,
+}
+
+impl List {
+    /// Items one per line, indented by two spaces per nesting level
+    pub fn text(&self) -> String {
+        self.items
+            .iter()
+            .map(|item| format!("{}{}", "  ".repeat(item.level as usize), item.text))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Items indented by less than this distance from a previous item are at its level
+const LIST_INDENT_TOLERANCE: f32 = 4.0;
+
+/// Bullets starting the items of unordered lists
+const LIST_BULLETS: [char; 13] = [
+    '•', '●', '○', '◦', '■', '□', '▪', '▫', '‣', '–', '—', '-', '*',
+];
+
+#[derive(Clone, Debug, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ListItem {
+    /// Text of the item, starting with its bullet or number as in the document
+    pub text: String,
+    /// Nesting depth of the item, 0 for the items at the left of the list
+    pub level: u8,
+    pub marker: ListMarker,
+    /// Bounds of the item on its page
+    pub bbox: BBox,
+}
+
+/// Kind of marker starting a list item, items without a bullet or number are unordered
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    Archive,
+    RkyvDeserialize,
+    RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ListMarker {
+    /// Bullet or dash
+    #[default]
+    Unordered,
+    /// Number, letter or roman numeral followed by `.` or `)`, e.g. `2.`, `b)` or `(iv)`
+    Ordered,
+}
+
+/// Number, letter or roman numeral of an ordered list item, without its punctuation
+pub(crate) fn ordered_marker(text: &str) -> Option<&str> {
+    let marker = text.split_whitespace().next()?;
+    let marker = marker.strip_suffix(['.', ')'])?.trim_start_matches('(');
+    let is_number = marker
+        .split('.')
+        .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()));
+    let is_letter = marker.chars().count() == 1 && marker.chars().all(char::is_alphabetic);
+    let is_roman =
+        !marker.is_empty() && marker.len() <= 6 && marker.chars().all(|c| "ivxlcIVXLC".contains(c));
+    (is_number || is_letter || is_roman).then_some(marker)
+}
+
+impl ListItem {
+    /// Item at the left of the list, its marker is read from the start of its text
+    pub(crate) fn new(text: &str, bbox: BBox) -> Self {
+        let text = text.trim().to_owned();
+        let marker = if ordered_marker(&text).is_some() {
+            ListMarker::Ordered
+        } else {
+            ListMarker::Unordered
+        };
+        Self {
+            text,
+            level: 0,
+            marker,
+            bbox,
+        }
+    }
+
+    /// Text of the item without its bullet or number
+    pub fn content(&self) -> &str {
+        let marker = match self.marker {
+            ListMarker::Ordered => self.text.split_whitespace().next(),
+            ListMarker::Unordered => self
+                .text
+                .split_whitespace()
+                .next()
+                .filter(|token| token.chars().all(|c| LIST_BULLETS.contains(&c))),
+        };
+        marker
+            .and_then(|marker| self.text.strip_prefix(marker))
+            .map_or(self.text.as_str(), str::trim_start)
+    }
+
+    /// Nesting level of the item following `previous`: the level of the closest previous
+    /// item starting at the same position, one more than the closest one starting to its
+    /// left, 0 when it starts left of all of them
+    pub(crate) fn level_after(&self, previous: &[ListItem]) -> u8 {
+        for item in previous.iter().rev() {
+            if (self.bbox.x0 - item.bbox.x0).abs() <= LIST_INDENT_TOLERANCE {
+                return item.level;
+            }
+            if self.bbox.x0 > item.bbox.x0 {
+                return item.level.saturating_add(1);
+            }
+        }
+        0
+    }
 }
 
 #[derive(
     Clone, Debug, Default, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
 pub enum TableAlgorithm {
     #[default]
     Unknown,
@@ -49,6 +233,8 @@ pub enum TableAlgorithm {
 #[derive(
     Clone, Debug, Default, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
 pub struct TableBlock {
     pub(crate) id: usize,
     pub(crate) caption: Option<String>,
@@ -63,9 +249,42 @@ impl TableBlock {
     }
 }
 
+/// Display equation. Its native text is rarely readable, the region is exported as the
+/// `formula_{page}_{n}.png` image instead
+#[derive(
+    Clone, Debug, Default, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FormulaBlock {
+    /// Index of the formula on its page
+    pub(crate) id: usize,
+    pub(crate) page_id: PageID,
+    /// Text extracted from the region, kept for a math OCR model to improve on
+    pub raw_text: String,
+}
+
+impl FormulaBlock {
+    pub(crate) fn path(&self) -> String {
+        format!("formula_{}_{}.png", self.page_id, self.id)
+    }
+}
+
+/// Code listing, its lines and indentation are kept as on the page
 #[derive(
     Clone, Debug, Default, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct CodeBlock {
+    pub text: String,
+    /// Programming language of the listing, when known
+    pub language: Option<String>,
+}
+
+#[derive(
+    Clone, Debug, Default, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
 pub struct TableRow {
     pub cells: Vec<TableCell>,
     pub is_header: bool,
@@ -75,6 +294,8 @@ pub struct TableRow {
 #[derive(
     Clone, Debug, Default, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
 pub struct TableCell {
     /// IDs of blocks contained within this cell.
     /// This avoids recursion in serializable structures.
@@ -88,12 +309,67 @@ pub struct TableCell {
 #[derive(
     Clone, Debug, Default, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Title {
     pub level: TitleLevel,
     pub text: String,
 }
 
+/// Field of a fillable PDF form (AcroForm widget)
 #[derive(Clone, Debug, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
+pub struct FormFieldBlock {
+    /// Fully qualified name of the field, e.g. `applicant.last_name`
+    pub name: Option<String>,
+    pub field_type: FormFieldKind,
+    /// Value of a text field or selected option of a combo box
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// State of a checkbox or radio button
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checked: Option<bool>,
+}
+
+impl From<&FormField> for FormFieldBlock {
+    fn from(form_field: &FormField) -> Self {
+        Self {
+            name: form_field.name.clone(),
+            field_type: form_field.kind,
+            value: form_field.value.clone(),
+            checked: form_field.checked,
+        }
+    }
+}
+
+impl FormFieldBlock {
+    /// Text of the field in the text outputs: `name: value` for text fields and combo boxes,
+    /// `[x] name` or `[ ] name` for checkboxes and radio buttons
+    pub fn text(&self) -> String {
+        let name = self.name.as_deref().unwrap_or_default();
+        match self.field_type {
+            FormFieldKind::Text | FormFieldKind::ComboBox => {
+                let value = self.value.as_deref().unwrap_or_default();
+                if name.is_empty() {
+                    value.to_owned()
+                } else {
+                    format!("{name}: {value}").trim_end().to_owned()
+                }
+            }
+            FormFieldKind::Checkbox | FormFieldKind::RadioButton => {
+                let mark = if self.checked.unwrap_or_default() {
+                    "[x]"
+                } else {
+                    "[ ]"
+                };
+                format!("{mark} {name}").trim_end().to_owned()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(tag = "block_type")]
 pub enum BlockType {
     Header(TextBlock),
@@ -103,6 +379,9 @@ pub enum BlockType {
     TextBlock(TextBlock),
     Image(ImageBlock),
     Table(TableBlock),
+    FormField(FormFieldBlock),
+    Formula(FormulaBlock),
+    Code(CodeBlock),
 }
 
 impl std::fmt::Display for BlockType {
@@ -112,15 +391,153 @@ impl std::fmt::Display for BlockType {
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Block {
     pub id: usize,
     pub kind: BlockType,
     pub pages_id: Vec<PageID>,
     pub bbox: BBox,
+    /// Quality score in [0, 1]: the lowest layout detection score and OCR line confidence of
+    /// the merged elements. Native text lines count as fully confident
+    pub confidence: f32,
+    /// Link annotations over the text of the block, in reading order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<LinkSpan>,
+    /// Bold and italic runs of the text of the block, in reading order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub styles: Vec<StyleSpan>,
+    /// How the block was segmented, only serialized for the blocks of tagged pages
+    #[serde(default, skip_serializing_if = "BlockSource::is_layout")]
+    pub source: BlockSource,
+    /// Words of the text joined across a line break, with the boxes of their parts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hyphenations: Vec<Hyphenation>,
+    /// Header, footer or text repeated at the same position across the pages, listed in the
+    /// `running_headers` of the document metadata. The text outputs skip it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub repeated: bool,
+    /// Page and bbox of each part of a text or list block continued on the next pages, in
+    /// reading order. Empty for the blocks within a single bbox
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fragments: Vec<BlockFragment>,
+}
+
+/// Part of a block on one page
+#[derive(Clone, Debug, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BlockFragment {
+    pub page_id: PageID,
+    pub bbox: BBox,
+}
+
+/// Word hyphenated at the end of a line and joined with its continuation on the next line
+#[derive(Clone, Debug, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Hyphenation {
+    /// Word as it appears in the text of the block
+    pub text: String,
+    /// Part of the word ending the first line, hyphen included, then its continuation
+    pub fragments: Vec<WordFragment>,
+}
+
+/// Part of a word on one line
+#[derive(Clone, Debug, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct WordFragment {
+    pub text: String,
+    pub page_id: PageID,
+    pub bbox: BBox,
+}
+
+/// Where the segmentation of a block comes from
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    Archive,
+    RkyvDeserialize,
+    RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum BlockSource {
+    /// Layout model detections, or the text lines grouping without the model
+    #[default]
+    Layout,
+    /// Structure tree of a tagged PDF page
+    StructureTree,
+}
+
+impl BlockSource {
+    fn is_layout(&self) -> bool {
+        *self == BlockSource::Layout
+    }
 }
 
 impl Block {
+    /// Page and bbox of each part of the block, the block bbox on each of its pages when it
+    /// isn't split into fragments
+    pub(crate) fn page_fragments(&self) -> Vec<BlockFragment> {
+        if !self.fragments.is_empty() {
+            return self.fragments.clone();
+        }
+        self.pages_id
+            .iter()
+            .map(|&page_id| BlockFragment {
+                page_id,
+                bbox: self.bbox.clone(),
+            })
+            .collect()
+    }
+
+    /// Appends the text or list block `next` continuing this one on the next page. The bbox
+    /// of the first part is kept, the parts are listed in `fragments`.
+    pub(crate) fn append_continuation(&mut self, next: Block) {
+        let mut fragments = self.page_fragments();
+        fragments.extend(next.page_fragments());
+        self.fragments = fragments;
+        match (&mut self.kind, next.kind) {
+            (BlockType::ListBlock(list), BlockType::ListBlock(next_list)) => {
+                // The levels are computed again, the next part may start with nested items
+                for mut item in next_list.items {
+                    item.level = item.level_after(&list.items);
+                    list.items.push(item);
+                }
+            }
+            (BlockType::TextBlock(text), BlockType::TextBlock(next_text)) => {
+                let next_text = next_text.text.trim_start();
+                if !next_text.is_empty() {
+                    text.text.truncate(text.text.trim_end().len());
+                    text.text.push(' ');
+                    text.text.push_str(next_text);
+                }
+            }
+            _ => {}
+        }
+        self.pages_id.extend(next.pages_id);
+        self.confidence = self.confidence.min(next.confidence);
+        self.links.extend(next.links);
+        self.styles.extend(next.styles);
+        self.hyphenations.extend(next.hyphenations);
+    }
+
     pub(crate) fn merge(&mut self, element: Element) -> Result<(), FerrulesError> {
+        let confidence = element.confidence;
+        let links = element.text_block.links.clone();
+        let styles = element.text_block.styles.clone();
+        self.merge_element(element)?;
+        self.confidence = self.confidence.min(confidence);
+        self.links.extend(links);
+        self.styles.extend(styles);
+        Ok(())
+    }
+
+    fn merge_element(&mut self, element: Element) -> Result<(), FerrulesError> {
         match &mut self.kind {
             BlockType::TextBlock(text) => {
                 if let ElementType::Text = &element.kind {
@@ -141,8 +558,9 @@ impl Block {
             BlockType::ListBlock(list) => {
                 if let ElementType::ListItem = &element.kind {
                     self.bbox.merge(&element.bbox);
-                    let txt = element.text_block.text.trim();
-                    list.items.push(txt.to_owned());
+                    let mut item = ListItem::new(&element.text_block.text, element.bbox.clone());
+                    item.level = item.level_after(&list.items);
+                    list.items.push(item);
                     Ok(())
                 } else {
                     Err(FerrulesError::BlockMergeError {
@@ -178,8 +596,47 @@ impl Block {
                     })
                 }
             }
-            BlockType::Title(_title) => todo!(),
-            BlockType::Image(_image_block) => todo!(),
+            BlockType::Title(title) => {
+                if let ElementType::Title | ElementType::Subtitle = &element.kind {
+                    self.bbox.merge(&element.bbox);
+                    title.text.push(' ');
+                    title.text.push_str(element.text_block.text.trim());
+                    Ok(())
+                } else {
+                    Err(FerrulesError::BlockMergeError {
+                        element: Box::new(element),
+                        block_id: self.id,
+                        kind: self.kind.clone(),
+                    })
+                }
+            }
+            BlockType::Image(image_block) => match &element.kind {
+                ElementType::Caption | ElementType::FootNote => {
+                    self.bbox.merge(&element.bbox);
+                    let txt = element.text_block.text.trim();
+                    match &mut image_block.caption {
+                        Some(caption) => {
+                            caption.push(' ');
+                            caption.push_str(txt);
+                        }
+                        None => image_block.caption = Some(txt.to_owned()),
+                    }
+                    Ok(())
+                }
+                // Fragment of a multi-part figure
+                ElementType::Image => {
+                    self.bbox.merge(&element.bbox);
+                    if !self.pages_id.contains(&element.page_id) {
+                        self.pages_id.push(element.page_id);
+                    }
+                    Ok(())
+                }
+                _ => Err(FerrulesError::BlockMergeError {
+                    element: Box::new(element),
+                    block_id: self.id,
+                    kind: self.kind.clone(),
+                }),
+            },
             BlockType::Table(table) => {
                 if let ElementType::Table(incoming_table_opt) = &element.kind {
                     self.bbox.merge(&element.bbox);
@@ -195,11 +652,58 @@ impl Block {
                     })
                 }
             }
+            // Every widget, equation and code listing is its own block
+            BlockType::FormField(_) | BlockType::Formula(_) | BlockType::Code(_) => {
+                Err(FerrulesError::BlockMergeError {
+                    element: Box::new(element),
+                    block_id: self.id,
+                    kind: self.kind.clone(),
+                })
+            }
+        }
+    }
+
+    /// Merges a title continuation element, keeping the higher-priority (lower) level of the two
+    pub(crate) fn merge_title(
+        &mut self,
+        element: Element,
+        level: TitleLevel,
+    ) -> Result<(), FerrulesError> {
+        self.merge(element)?;
+        if let BlockType::Title(title) = &mut self.kind {
+            title.level = title.level.min(level);
         }
+        Ok(())
     }
 
     pub(crate) fn label(&self) -> &str {
-        match self.kind {
+        self.kind.label()
+    }
+}
+
+#[cfg(test)]
+impl Block {
+    /// Fully confident layout block on a single page, without annotations
+    pub(crate) fn for_test(id: usize, kind: BlockType, page_id: PageID, bbox: BBox) -> Self {
+        Self {
+            id,
+            kind,
+            pages_id: vec![page_id],
+            bbox,
+            confidence: 1.0,
+            links: vec![],
+            styles: vec![],
+            source: BlockSource::Layout,
+            hyphenations: vec![],
+            repeated: false,
+            fragments: vec![],
+        }
+    }
+}
+
+impl BlockType {
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
             BlockType::Header(_) => "HEADER",
             BlockType::Footer(_) => "FOOTER",
             BlockType::TextBlock(_) => "TEXT",
@@ -207,6 +711,9 @@ impl Block {
             BlockType::ListBlock(_) => "LIST",
             BlockType::Image(_) => "IMAGE",
             BlockType::Table(_) => "TABLE",
+            BlockType::FormField(_) => "FORM_FIELD",
+            BlockType::Formula(_) => "FORMULA",
+            BlockType::Code(_) => "CODE",
         }
     }
 }
@@ -0,0 +1,96 @@
+use std::path::Path;
+
+use rkyv::{
+    ser::serializers::AllocSerializer, validation::validators::DefaultValidator, AlignedVec,
+    Archive, CheckBytes, Deserialize as RkyvDeserialize, Infallible, Serialize as RkyvSerialize,
+};
+use xxhash_rust::xxh3::xxh3_64;
+
+// NOTE: entries of the on-disk caches are a little-endian xxh3 checksum of the archive
+// followed by the rkyv archive. The checksum rejects the corrupted entries whose archive is
+// still well-formed, the archive is validated before it is accessed: an entry written by
+// another build of ferrules, truncated or crafted is a miss, never a read out of bounds.
+pub(crate) fn encode_entry<T>(value: &T) -> anyhow::Result<Vec<u8>>
+where
+    T: RkyvSerialize<AllocSerializer<1024>>,
+{
+    let bytes = rkyv::to_bytes::<_, 1024>(value)?;
+    let mut entry = Vec::with_capacity(8 + bytes.len());
+    entry.extend_from_slice(&xxh3_64(&bytes).to_le_bytes());
+    entry.extend_from_slice(&bytes);
+    Ok(entry)
+}
+
+pub(crate) fn decode_entry<T>(entry: &[u8]) -> Option<T>
+where
+    T: Archive,
+    for<'a> T::Archived: CheckBytes<DefaultValidator<'a>> + RkyvDeserialize<T, Infallible>,
+{
+    let (checksum, bytes) = entry.split_first_chunk::<8>()?;
+    if u64::from_le_bytes(*checksum) != xxh3_64(bytes) {
+        return None;
+    }
+    let mut aligned = AlignedVec::with_capacity(bytes.len());
+    aligned.extend_from_slice(bytes);
+    let archived = rkyv::check_archived_root::<T>(&aligned).ok()?;
+    // Deserializing with Infallible can't fail
+    Some(archived.deserialize(&mut Infallible).unwrap())
+}
+
+/// Returns the entry at `path` decoded, if any. Unreadable entries are treated as misses.
+pub(crate) fn load_entry<T>(path: &Path) -> Option<T>
+where
+    T: Archive,
+    for<'a> T::Archived: CheckBytes<DefaultValidator<'a>> + RkyvDeserialize<T, Infallible>,
+{
+    let data = std::fs::read(path).ok()?;
+    let entry = decode_entry(&data);
+    if entry.is_none() {
+        tracing::warn!("invalid cache entry {}", path.display());
+    }
+    entry
+}
+
+/// Stores `value` at `path`. Failing to write the cache is not an error for the caller.
+pub(crate) fn store_entry<T>(path: &Path, value: &T)
+where
+    T: RkyvSerialize<AllocSerializer<1024>>,
+{
+    let store = || -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Write then rename so concurrent readers never see a partial entry
+        let tmp_path = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+        std::fs::write(&tmp_path, encode_entry(value)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    };
+    if let Err(e) = store() {
+        tracing::warn!("can't write cache entry {}: {e}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_invalid_entries() {
+        let entry = encode_entry(&vec!["cached".to_string()]).unwrap();
+        assert_eq!(
+            decode_entry::<Vec<String>>(&entry),
+            Some(vec!["cached".to_string()])
+        );
+
+        // Truncated
+        assert!(decode_entry::<Vec<String>>(&entry[..entry.len() - 2]).is_none());
+        assert!(decode_entry::<Vec<String>>(&entry[..4]).is_none());
+
+        // Garbage archive with a valid checksum
+        let garbage = [0xffu8; 32];
+        let mut crafted = xxh3_64(&garbage).to_le_bytes().to_vec();
+        crafted.extend_from_slice(&garbage);
+        assert!(decode_entry::<Vec<String>>(&crafted).is_none());
+    }
+}
@@ -25,6 +25,13 @@ pub struct DebugPage {
     pub image_data: Vec<u8>,
     pub width: f32,
     pub height: f32,
+    /// Whether the page was OCRed
+    pub need_ocr: bool,
+    /// Ratio between the area of the native text lines and the area of the text regions,
+    /// compared to the OCR coverage threshold
+    pub text_coverage: f32,
+    /// Non-whitespace characters of the native text lines
+    pub native_chars: usize,
 }
 
 #[derive(Archive, RkyvDeserialize, RkyvSerialize, Debug, Clone)]
@@ -384,17 +384,17 @@ mod tests {
         draw_table_structure(&table_block, &mut out_img);
 
         // Also test via draw_blocks
-        let block = crate::blocks::Block {
-            id: 1,
-            kind: crate::blocks::BlockType::Table(table_block),
-            pages_id: vec![0],
-            bbox: BBox {
+        let block = crate::blocks::Block::for_test(
+            1,
+            crate::blocks::BlockType::Table(table_block),
+            0,
+            BBox {
                 x0: 10.0,
                 y0: 10.0,
                 x1: 90.0,
                 y1: 50.0,
             },
-        };
+        );
 
         let result = draw_blocks(&[block], &page_img);
         assert!(result.is_ok());
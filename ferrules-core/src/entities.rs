@@ -3,14 +3,16 @@ use image::DynamicImage;
 use plsfix::fix_text;
 use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
 use serde::{Deserialize, Serialize};
-use std::{path::PathBuf, time::Duration};
+use std::{collections::HashSet, path::PathBuf, time::Duration};
 
 use pdfium_render::prelude::{PdfFontWeight, PdfPageTextChar, PdfRect};
 
 use crate::{
-    blocks::{Block, TableBlock},
+    blocks::{Block, EmbeddedImageFormat, FormFieldBlock, TableBlock, TitleLevel},
     layout::model::LayoutBBox,
     metrics::{PageMetrics, ParsingMetrics},
+    ocr::OCRLines,
+    parse::{merge::MergeThresholds, titles::TitleLevelStrategy},
 };
 
 pub type PageID = usize;
@@ -18,9 +20,23 @@ pub type ElementID = usize;
 
 const FERRULES_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Confidence of the text lines read from the PDF text layer, which are not recognized
+const NATIVE_TEXT_CONFIDENCE: f32 = 1.0;
+
+/// Superscripts and subscripts are set at most this fraction of the line font size
+const SCRIPT_FONT_SIZE_RATIO: f32 = 0.85;
+
+/// Superscripts end above the bottom of the line by this fraction of its height
+const SUPERSCRIPT_OFFSET_RATIO: f32 = 0.25;
+
+/// Subscripts end below the bottom of the line by this fraction of its height
+const SUBSCRIPT_OFFSET_RATIO: f32 = 0.1;
+
 #[derive(
     Debug, Default, Clone, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
 pub struct BBox {
     pub x0: f32,
     pub y0: f32,
@@ -29,7 +45,7 @@ pub struct BBox {
 }
 
 impl BBox {
-    fn from_pdfrect(
+    pub(crate) fn from_pdfrect(
         PdfRect {
             bottom,
             left,
@@ -71,6 +87,14 @@ impl BBox {
     pub fn size(&self) -> (f32, f32) {
         (self.width(), self.height())
     }
+    /// Divides the coordinates by the page `width` and `height`, giving a box in [0, 1]
+    pub fn normalize(&mut self, width: f32, height: f32) {
+        self.x0 /= width;
+        self.x1 /= width;
+        self.y0 /= height;
+        self.y1 /= height;
+    }
+
     #[inline(always)]
     pub(crate) fn merge(&mut self, other: &Self) {
         self.x0 = self.x0.min(other.x0);
@@ -136,8 +160,31 @@ impl BBox {
 #[derive(
     Debug, Clone, Default, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[archive(check_bytes)]
 pub struct ElementText {
     pub text: String,
+    /// Links of the element lines, in reading order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<LinkSpan>,
+    /// Bold and italic runs of the element lines, in reading order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub styles: Vec<StyleSpan>,
+    /// Largest font size of the element lines, `None` before the first line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f32>,
+    /// Lines of the element, for the blocks keeping their layout such as code listings
+    #[serde(skip)]
+    pub lines: Vec<ElementLine>,
+}
+
+/// Line of an element with its position on the page
+#[derive(Debug, Clone, Default, Archive, RkyvDeserialize, RkyvSerialize)]
+#[archive(check_bytes)]
+pub struct ElementLine {
+    pub text: String,
+    pub bbox: BBox,
+    /// Most of the line is set in a monospace font
+    pub monospace: bool,
 }
 
 impl ElementText {
@@ -154,6 +201,7 @@ impl ElementText {
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Archive, RkyvDeserialize, RkyvSerialize)]
+#[archive(check_bytes)]
 #[serde(tag = "element_type")]
 pub enum ElementType {
     Header,
@@ -166,6 +214,9 @@ pub enum ElementType {
     Caption,
     Image,
     Table(Option<TableBlock>),
+    FormField(FormFieldBlock),
+    Formula,
+    Code,
 }
 impl std::fmt::Display for ElementType {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -174,6 +225,7 @@ impl std::fmt::Display for ElementType {
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Archive, RkyvDeserialize, RkyvSerialize)]
+#[archive(check_bytes)]
 pub struct Element {
     pub id: ElementID,
     pub layout_block_id: i32,
@@ -181,13 +233,17 @@ pub struct Element {
     pub kind: ElementType,
     pub page_id: PageID,
     pub bbox: BBox,
+    /// Lowest of the layout detection score and the confidences of the element lines
+    pub confidence: f32,
 }
 
 impl Element {
     pub fn from_layout_block(id: usize, layout_block: &LayoutBBox, page_id: usize) -> Self {
         let kind = match layout_block.label.as_str() {
             "Caption" => ElementType::Caption,
-            "Formula" | "Text" => ElementType::Text,
+            "Text" => ElementType::Text,
+            "Formula" => ElementType::Formula,
+            "Code" => ElementType::Code,
             "List-item" => ElementType::ListItem,
             "Footnote" => ElementType::FootNote,
             "Page-footer" => ElementType::Footer,
@@ -210,14 +266,44 @@ impl Element {
             page_id,
             text_block: Default::default(),
             bbox: layout_block.bbox.to_owned(),
+            confidence: layout_block.proba,
+        }
+    }
+    /// Element of a form field widget, placed in the reading order like the layout elements
+    pub fn from_form_field(id: usize, form_field: &FormField) -> Self {
+        Self {
+            id,
+            kind: ElementType::FormField(FormFieldBlock::from(form_field)),
+            layout_block_id: -1,
+            page_id: form_field.page_id,
+            text_block: Default::default(),
+            bbox: form_field.bbox.clone(),
+            confidence: NATIVE_TEXT_CONFIDENCE,
         }
     }
     pub fn push_line(&mut self, line: &Line) {
+        self.confidence = self
+            .confidence
+            .min(line.confidence.unwrap_or(NATIVE_TEXT_CONFIDENCE));
         if self.text_block.is_empty() {
             self.text_block.push_first(&line.text);
         } else {
             self.text_block.append_line(&line.text);
         }
+        self.text_block.links.extend(line.links.iter().cloned());
+        self.text_block.styles.extend(line.style_spans());
+        self.text_block.lines.push(ElementLine {
+            text: line.text.clone(),
+            bbox: line.bbox.clone(),
+            monospace: line.is_monospace(),
+        });
+        if let Some(font_size) = line.font_size() {
+            self.text_block.font_size = Some(
+                self.text_block
+                    .font_size
+                    .map_or(font_size, |size| size.max(font_size)),
+            );
+        }
     }
 }
 
@@ -226,11 +312,22 @@ pub struct StructuredPage {
     pub id: PageID,
     pub width: f32,
     pub height: f32,
-    // pub rotation: PdfPageRenderRotation,
+    /// Clockwise rotation of the page `/Rotate` entry in degrees: 0, 90, 180 or 270
+    pub rotation: u16,
     pub need_ocr: bool,
+    /// Ratio between the area of the native text lines and the area of the text regions of
+    /// the page, compared to `FerrulesParseConfig::ocr_coverage_threshold`
+    pub text_coverage: f32,
+    /// Non-whitespace characters of the native text lines, compared to
+    /// `FerrulesParseConfig::ocr_min_chars`
+    pub native_chars: usize,
+    /// Segmented and ordered from the structure tree of the tagged PDF
+    pub tagged: bool,
     pub image: DynamicImage,
     pub elements: Vec<Element>,
     pub paths: Vec<PDFPath>,
+    pub form_fields: Vec<FormField>,
+    pub embedded_images: Vec<EmbeddedImage>,
     pub native_lines: Vec<Line>,
     pub layout: Vec<LayoutBBox>,
     pub ocr_lines: Vec<Line>,
@@ -238,6 +335,7 @@ pub struct StructuredPage {
 }
 
 #[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Page {
     pub id: PageID,
     pub width: f32,
@@ -245,15 +343,136 @@ pub struct Page {
 
     #[serde(skip_serializing, skip_deserializing)]
     pub image: DynamicImage,
-    // pub rotation: PdfPageRenderRotation,
+    /// Clockwise rotation of the page `/Rotate` entry in degrees: 0, 90, 180 or 270. The width,
+    /// height and all the coordinates are given in the rotated page, as displayed
+    #[serde(default)]
+    pub rotation: u16,
     pub need_ocr: bool,
+    /// Text lines of the page, from OCR when the page needed it
+    #[serde(skip_serializing, skip_deserializing)]
+    pub lines: Vec<Line>,
+    /// OCR lines of the page with their recognition confidence
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ocr_lines: Vec<OCRLines>,
+    /// Raw layout detections of the page in page coordinates, before merging into elements.
+    /// Only filled when `FerrulesParseConfig::include_layout` is set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub layout: Vec<LayoutBBox>,
+    /// Image objects of the page at their embedded resolution
+    #[serde(skip_serializing, skip_deserializing)]
+    pub embedded_images: Vec<EmbeddedImage>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Bitmap of an image object of the PDF page, at its original resolution
+#[derive(Debug, Clone)]
+pub struct EmbeddedImage {
+    /// Bounds of the image object in page coordinates
+    pub bbox: BBox,
+    pub image: DynamicImage,
+    pub format: EmbeddedImageFormat,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct DocumentMetadata {
     #[serde(with = "serde_millis")]
+    #[cfg_attr(feature = "schema", schemars(with = "u64"))]
     pub parsing_duration: Duration,
     pub ferrules_version: String,
+    /// Execution provider of the layout model, e.g. `cpu (requested: trt:0)` after a fallback
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout_provider: Option<String>,
+    /// Dominant language of the document text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<DocumentLanguage>,
+    /// Headers, footers and text repeated across the pages, their blocks are flagged `repeated`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub running_headers: Vec<RunningHeader>,
+    /// Strategy and size to level mapping of the title levels, for debugging
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_levels: Option<TitleLevels>,
+    /// Information dictionary of the PDF, missing entries are serialized as `null`
+    #[serde(flatten)]
+    pub info: DocumentInfo,
+}
+
+/// Entries of the PDF document information dictionary
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    Deserialize,
+    Serialize,
+    Archive,
+    RkyvDeserialize,
+    RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
+pub struct DocumentInfo {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+    pub creator: Option<String>,
+    pub producer: Option<String>,
+    /// ISO 8601 date, or the raw PDF date string when it is malformed
+    pub creation_date: Option<String>,
+    /// ISO 8601 date, or the raw PDF date string when it is malformed
+    pub modification_date: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DocumentLanguage {
+    /// ISO 639-3 code, e.g. `eng`
+    pub code: String,
+    /// Detection confidence in [0, 1]
+    pub confidence: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum RunningHeaderPosition {
+    Header,
+    Footer,
+    /// Text block repeated at the same position, e.g. a confidentiality notice
+    Body,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RunningHeader {
+    pub position: RunningHeaderPosition,
+    /// Text of the first occurrence, later pages can differ by their digits
+    pub text: String,
+    /// Pages the header or footer is repeated on
+    pub pages_id: Vec<PageID>,
+}
+
+/// How the title levels of a document were assigned
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TitleLevels {
+    pub strategy: TitleLevelStrategy,
+    /// The sizes are the title bbox heights instead of their font sizes, some titles have no
+    /// font size
+    pub by_height: bool,
+    /// Level of each title size, rounded to the half point, largest first. Outline levels
+    /// aren't included
+    pub sizes: Vec<TitleSizeLevel>,
+    /// Number of titles whose level is the depth of their outline entry
+    pub outline_titles: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TitleSizeLevel {
+    pub size: f32,
+    pub bold: bool,
+    pub level: TitleLevel,
 }
 
 impl DocumentMetadata {
@@ -261,11 +480,17 @@ impl DocumentMetadata {
         Self {
             parsing_duration,
             ferrules_version: FERRULES_VERSION.to_owned(),
+            layout_provider: None,
+            language: None,
+            running_headers: Vec::new(),
+            title_levels: None,
+            info: DocumentInfo::default(),
         }
     }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct ParsedDocument {
     pub doc_name: String,
     pub pages: Vec<Page>,
@@ -273,14 +498,274 @@ pub struct ParsedDocument {
     pub debug_path: Option<PathBuf>,
     pub metadata: DocumentMetadata,
     pub metrics: ParsingMetrics,
+    /// Interactive form fields (AcroForm widgets) of the pages with their values
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub form_fields: Vec<FormField>,
+    /// Outline (bookmarks) of the whole document in reading order, `None` if the PDF has none.
+    /// Entries can point at pages outside of the parsed page range
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outline: Option<Vec<OutlineEntry>>,
+    /// Pages that couldn't be parsed and were skipped, sorted by page id
+    #[serde(default)]
+    pub failed_pages: Vec<PageFailure>,
+    /// Problems recovered from while assembling the document, e.g. an element that couldn't
+    /// be merged into the block before it and started a new block
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ParseWarning>,
+    /// Section tree of the blocks nested by title level, the implicit root section first. See
+    /// [`ParsedDocument::sections`]
+    #[serde(default)]
+    pub sections: Vec<Section>,
+}
+
+impl ParsedDocument {
+    /// Every word of the document with its bbox in the page coordinates, page after page in
+    /// the order of their text lines. The words of native text are split from the font runs
+    /// of the lines, their boxes are approximated by `CharSpan::words`.
+    ///
+    /// The text lines aren't serialized, a deserialized document has no words.
+    pub fn words(&self) -> impl Iterator<Item = Word> + '_ {
+        self.pages.iter().flat_map(|page| {
+            page.lines.iter().flat_map(move |line| {
+                line.words().into_iter().map(move |word| Word {
+                    text: word.text,
+                    bbox: word.bbox,
+                    page_id: page.id,
+                    confidence: line.confidence,
+                })
+            })
+        })
+    }
+
+    /// Sections of the document, indexed by their id. The first one is the implicit root
+    /// section holding the blocks before the first title and the top-level sections, it's the
+    /// only one of a document without titles.
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+}
+
+/// Title block and the blocks after it up to the next title of the same or a higher level
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Section {
+    /// Index of the section in [`ParsedDocument::sections`]
+    pub id: usize,
+    /// Id of the title block, `None` for the root section
+    pub title_block_id: Option<usize>,
+    /// Level of the title, 0 for the root section
+    pub level: TitleLevel,
+    /// Ids of the subsections, in reading order
+    pub children: Vec<usize>,
+    /// Ids of the content blocks directly under the title, before the first subsection
+    pub blocks: Vec<usize>,
+}
+
+impl Section {
+    /// Title and content blocks of the section and of its subsections, in reading order
+    pub fn iter_blocks<'a>(&self, doc: &'a ParsedDocument) -> impl Iterator<Item = &'a Block> {
+        let mut ids = HashSet::new();
+        let mut stack = vec![self];
+        while let Some(section) = stack.pop() {
+            ids.extend(section.title_block_id);
+            ids.extend(section.blocks.iter().copied());
+            stack.extend(
+                section
+                    .children
+                    .iter()
+                    .filter_map(|&child| doc.sections.get(child)),
+            );
+        }
+        doc.blocks
+            .iter()
+            .filter(move |block| ids.contains(&block.id))
+    }
+}
+
+/// A word of the document text, see [`ParsedDocument::words`]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Word {
+    pub text: String,
+    /// Bounds of the word in the coordinates of its page
+    pub bbox: BBox,
+    pub page_id: PageID,
+    /// OCR recognition confidence of the line of the word, `None` for native text
+    pub confidence: Option<f32>,
+}
+
+/// JSON schema of the serialized [`ParsedDocument`], the JSON output of ferrules
+#[cfg(feature = "schema")]
+pub fn parsed_document_schema() -> schemars::schema::RootSchema {
+    schemars::schema_for!(ParsedDocument)
+}
+
+/// Problem the parsing recovered from, the document is still complete
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ParseWarning {
+    /// Page of the element the warning is about, if any
+    pub page_id: Option<PageID>,
+    pub message: String,
+}
+
+/// Page skipped during parsing with the error that caused it
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PageFailure {
+    pub page_id: PageID,
+    pub error: String,
+}
+
+#[derive(
+    Debug, Clone, PartialEq, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
+pub struct OutlineEntry {
+    pub title: String,
+    /// Depth of the entry in the outline tree, top-level entries are at level 1
+    pub level: u8,
+    /// Page the entry points to, `None` when it has no destination in the document
+    pub page_id: Option<PageID>,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    Archive,
+    RkyvDeserialize,
+    RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
+#[serde(rename_all = "snake_case")]
+pub enum FormFieldKind {
+    Text,
+    Checkbox,
+    RadioButton,
+    ComboBox,
+}
+
+/// Target of a link annotation
+#[derive(
+    Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkTarget {
+    /// External link, e.g. `https://example.com` or `mailto:` addresses
+    Uri(String),
+    /// Internal link to a page of the document
+    PageId(PageID),
+}
+
+/// Text of a line covered by a link annotation. A link spanning several lines is attached to
+/// each of them with the part of its text on the line
+#[derive(
+    Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
+pub struct LinkSpan {
+    pub text: String,
+    #[serde(flatten)]
+    pub target: LinkTarget,
+}
+
+/// Vertical position of a run set in a smaller font above or below the rest of its line
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    Archive,
+    RkyvDeserialize,
+    RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptPosition {
+    Superscript,
+    Subscript,
+}
+
+/// Reading direction of a line, from the script of the majority of its letters
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    Archive,
+    RkyvDeserialize,
+    RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
+#[serde(rename_all = "lowercase")]
+pub enum TextDirection {
+    #[default]
+    Ltr,
+    /// Arabic, Hebrew and the other right-to-left scripts
+    Rtl,
+}
+
+/// Run of bold, italic, superscript or subscript text of a line
+#[derive(
+    Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
+pub struct StyleSpan {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+    /// Set on superscripts and subscripts, e.g. footnote references and chemical formulas
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<ScriptPosition>,
 }
 
+/// Widget of a PDF form field
+#[derive(Debug, Clone, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
+pub struct FormField {
+    pub page_id: PageID,
+    /// Fully qualified name of the field, e.g. `applicant.last_name`
+    pub name: Option<String>,
+    pub kind: FormFieldKind,
+    /// Value of a text field or selected option of a combo box
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// State of a checkbox or radio button widget. Radio buttons of a group share their name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checked: Option<bool>,
+    /// Widget bbox in page coordinates
+    pub bbox: BBox,
+}
+
+/// Font weights from the lightest to the heaviest
 #[derive(
     Debug,
     Clone,
     Copy,
     PartialEq,
     Eq,
+    PartialOrd,
+    Ord,
     Deserialize,
     Serialize,
     Archive,
@@ -301,27 +786,86 @@ pub enum SerializableFontWeight {
 }
 
 impl From<PdfFontWeight> for SerializableFontWeight {
-    fn from(_weight: PdfFontWeight) -> Self {
-        // TODO: Map correctly once variants are known.
-        // For now, default to Normal to bypass compilation errors.
-        Self::Normal
-        /*
+    fn from(weight: PdfFontWeight) -> Self {
         match weight {
-            PdfFontWeight::Thin => Self::Thin,
-            PdfFontWeight::ExtraLight => Self::ExtraLight,
-            PdfFontWeight::Light => Self::Light,
-            PdfFontWeight::Normal => Self::Normal,
-            PdfFontWeight::Medium => Self::Medium,
-            PdfFontWeight::SemiBold => Self::SemiBold,
-            PdfFontWeight::Bold => Self::Bold,
-            PdfFontWeight::ExtraBold => Self::ExtraBold,
-            PdfFontWeight::Black => Self::Black,
+            PdfFontWeight::Weight100 => Self::Thin,
+            PdfFontWeight::Weight200 => Self::ExtraLight,
+            PdfFontWeight::Weight300 => Self::Light,
+            PdfFontWeight::Weight400Normal => Self::Normal,
+            PdfFontWeight::Weight500 => Self::Medium,
+            PdfFontWeight::Weight600 => Self::SemiBold,
+            PdfFontWeight::Weight700Bold => Self::Bold,
+            PdfFontWeight::Weight800 => Self::ExtraBold,
+            PdfFontWeight::Weight900 => Self::Black,
+            // Weights outside of 100-900, rounded to the closest one
+            PdfFontWeight::Custom(weight) => match weight {
+                0..=149 => Self::Thin,
+                150..=249 => Self::ExtraLight,
+                250..=349 => Self::Light,
+                350..=449 => Self::Normal,
+                450..=549 => Self::Medium,
+                550..=649 => Self::SemiBold,
+                650..=749 => Self::Bold,
+                750..=849 => Self::ExtraBold,
+                _ => Self::Black,
+            },
         }
-        */
     }
 }
 
+/// Standard 14 and embedded fonts often only carry their style in their name, e.g.
+/// `ABCDEF+Helvetica-BoldOblique`
+fn font_name_is_bold(font_name: &str) -> bool {
+    let font_name = font_name.to_lowercase();
+    ["bold", "black", "heavy", "demi"]
+        .iter()
+        .any(|style| font_name.contains(style))
+}
+
+fn font_name_is_italic(font_name: &str) -> bool {
+    let font_name = font_name.to_lowercase();
+    font_name.contains("italic") || font_name.contains("oblique")
+}
+
+/// Name parts of the common fixed-width fonts, e.g. `CourierNewPSMT` or the `CMTT10` TeX
+/// typewriter font
+const MONOSPACE_FONT_NAMES: [&str; 12] = [
+    "mono",
+    "courier",
+    "consolas",
+    "menlo",
+    "monaco",
+    "inconsolata",
+    "cmtt",
+    "typewriter",
+    "lucidaconsole",
+    "sourcecode",
+    "firacode",
+    "fixed",
+];
+
+fn font_name_is_monospace(font_name: &str) -> bool {
+    let font_name = font_name.to_lowercase();
+    MONOSPACE_FONT_NAMES
+        .iter()
+        .any(|name| font_name.contains(name))
+}
+
+/// Bold and italic flags of a char, from its font weight, descriptor flags and name
+fn char_style(
+    char: &PdfPageTextChar,
+    font_name: &str,
+    font_weight: Option<SerializableFontWeight>,
+) -> (bool, bool) {
+    let bold = font_weight.is_some_and(|weight| weight >= SerializableFontWeight::SemiBold)
+        || char.font_is_bold_reenforced()
+        || font_name_is_bold(font_name);
+    let italic = char.font_is_italic() || font_name_is_italic(font_name);
+    (bold, italic)
+}
+
 #[derive(Clone, Debug, Archive, RkyvDeserialize, RkyvSerialize)]
+#[archive(check_bytes)]
 pub struct CharSpan {
     pub bbox: BBox,
     pub text: String,
@@ -329,12 +873,19 @@ pub struct CharSpan {
     pub font_name: String,
     pub font_size: f32,
     pub font_weight: Option<SerializableFontWeight>,
+    pub bold: bool,
+    pub italic: bool,
+    /// Set by `Line::tag_scripts` on the spans raised or lowered from their line
+    pub script: Option<ScriptPosition>,
     pub char_start_idx: usize,
     pub char_end_idx: usize,
 }
 
 impl CharSpan {
     pub fn new_from_char(char: &PdfPageTextChar, page_bbox: &BBox) -> Self {
+        let font_name = char.font_name();
+        let font_weight = char.font_weight().map(SerializableFontWeight::from);
+        let (bold, italic) = char_style(char, &font_name, font_weight);
         Self {
             bbox: BBox::from_pdfrect(
                 char.tight_bounds()
@@ -342,8 +893,11 @@ impl CharSpan {
                 page_bbox.height(),
             ),
             text: char.unicode_char().unwrap_or_default().into(),
-            font_name: char.font_name(),
-            font_weight: char.font_weight().map(Into::into),
+            bold,
+            italic,
+            script: None,
+            font_name,
+            font_weight,
             font_size: char.unscaled_font_size().value,
             rotation: char.get_rotation_clockwise_degrees(),
             char_start_idx: char.index(),
@@ -352,10 +906,12 @@ impl CharSpan {
     }
     pub fn append(&mut self, char: &PdfPageTextChar, page_bbox: &BBox) -> Option<()> {
         let char_rotation = char.get_rotation_clockwise_degrees();
+        let char_font_name = char.font_name();
         let char_font_weight = char.font_weight().map(SerializableFontWeight::from);
         if char.unscaled_font_size().value != self.font_size
-            || char.font_name() != self.font_name
+            || char_font_name != self.font_name
             || char_font_weight != self.font_weight
+            || char_style(char, &char_font_name, char_font_weight) != (self.bold, self.italic)
             || char_rotation != self.rotation
         {
             None
@@ -370,13 +926,56 @@ impl CharSpan {
             Some(())
         }
     }
+
+    /// Splits the span into words on whitespace. Word boxes are approximated by distributing
+    /// the span width proportionally to the number of characters.
+    pub fn words(&self) -> Vec<CharSpan> {
+        let chars: Vec<char> = self.text.chars().collect();
+        if chars.is_empty() {
+            return vec![];
+        }
+        let char_width = self.bbox.width() / chars.len() as f32;
+
+        let mut words = Vec::new();
+        let mut word_start = None;
+        for idx in 0..=chars.len() {
+            let is_boundary = chars.get(idx).is_none_or(|c| c.is_whitespace());
+            match (is_boundary, word_start) {
+                (true, Some(start)) => {
+                    words.push(CharSpan {
+                        bbox: BBox {
+                            x0: self.bbox.x0 + start as f32 * char_width,
+                            y0: self.bbox.y0,
+                            x1: self.bbox.x0 + idx as f32 * char_width,
+                            y1: self.bbox.y1,
+                        },
+                        text: chars[start..idx].iter().collect(),
+                        char_start_idx: self.char_start_idx + start,
+                        char_end_idx: self.char_start_idx + idx - 1,
+                        ..self.clone()
+                    });
+                    word_start = None;
+                }
+                (false, None) => word_start = Some(idx),
+                _ => {}
+            }
+        }
+        words
+    }
 }
 #[derive(Clone, Default, Archive, RkyvDeserialize, RkyvSerialize)]
+#[archive(check_bytes)]
 pub struct Line {
     pub text: String,
     pub bbox: BBox,
     pub rotation: f32,
     pub spans: Vec<CharSpan>,
+    /// OCR recognition confidence, `None` for native text lines
+    pub confidence: Option<f32>,
+    /// Link annotations overlapping the line, empty for OCR lines
+    pub links: Vec<LinkSpan>,
+    /// The text and spans are in logical order, the order they are read in
+    pub direction: TextDirection,
 }
 
 impl std::fmt::Debug for Line {
@@ -402,20 +1001,148 @@ impl std::fmt::Debug for Line {
 }
 
 impl Line {
+    /// Whether most of the visible characters of the line are set in a monospace font. OCR
+    /// lines have no font and are never monospace
+    pub fn is_monospace(&self) -> bool {
+        let (chars, monospace_chars) =
+            self.spans
+                .iter()
+                .fold((0usize, 0usize), |(chars, monospace_chars), span| {
+                    let count = span.text.chars().filter(|c| !c.is_whitespace()).count();
+                    let monospace = font_name_is_monospace(&span.font_name) as usize;
+                    (chars + count, monospace_chars + count * monospace)
+                });
+        chars > 0 && monospace_chars * 2 > chars
+    }
+
+    /// Words of the line: native spans are font runs that can contain several words, a line
+    /// without spans is split as a single run
+    pub fn words(&self) -> Vec<CharSpan> {
+        if self.spans.is_empty() {
+            let line_span = CharSpan {
+                bbox: self.bbox.clone(),
+                text: self.text.clone(),
+                rotation: self.rotation,
+                font_name: String::new(),
+                font_size: self.bbox.height(),
+                font_weight: None,
+                bold: false,
+                italic: false,
+                script: None,
+                char_start_idx: 0,
+                char_end_idx: 0,
+            };
+            line_span.words()
+        } else {
+            self.spans.iter().flat_map(|s| s.words()).collect()
+        }
+    }
+
+    /// Runs of consecutive bold or italic spans, plain text is left out
+    pub fn style_spans(&self) -> Vec<StyleSpan> {
+        let mut styles: Vec<StyleSpan> = Vec::new();
+        let mut prev_style = None;
+        for span in &self.spans {
+            let style = (span.bold, span.italic, span.script);
+            if style == (false, false, None) {
+                prev_style = None;
+                continue;
+            }
+            match styles.last_mut() {
+                Some(run) if prev_style == Some(style) => run.text.push_str(&span.text),
+                _ => styles.push(StyleSpan {
+                    text: span.text.clone(),
+                    bold: span.bold,
+                    italic: span.italic,
+                    script: span.script,
+                }),
+            }
+            prev_style = Some(style);
+        }
+        styles
+            .into_iter()
+            .filter_map(|mut run| {
+                run.text = run.text.trim().to_owned();
+                (!run.text.is_empty()).then_some(run)
+            })
+            .collect()
+    }
+
+    /// Tags the spans set in a smaller font than the body of the line and raised above or
+    /// lowered below it as superscripts or subscripts. Only upright lines are tagged.
+    pub(crate) fn tag_scripts(&mut self) {
+        if self.rotation != 0f32 {
+            return;
+        }
+        let is_visible = |span: &CharSpan| {
+            span.font_size > 0f32 && span.bbox.height() > 0f32 && !span.text.trim().is_empty()
+        };
+        // The body of the line is set in the font size of its longest span
+        let Some(body_font_size) = self
+            .spans
+            .iter()
+            .filter(|span| is_visible(span))
+            .max_by_key(|span| span.text.trim().chars().count())
+            .map(|span| span.font_size)
+        else {
+            return;
+        };
+        let Some(body_bbox) = self
+            .spans
+            .iter()
+            .filter(|span| is_visible(span) && span.font_size == body_font_size)
+            .map(|span| span.bbox.clone())
+            .reduce(|mut bbox, span_bbox| {
+                bbox.merge(&span_bbox);
+                bbox
+            })
+        else {
+            return;
+        };
+        let body_height = body_bbox.height();
+        for span in self.spans.iter_mut() {
+            if !is_visible(span) || span.font_size > body_font_size * SCRIPT_FONT_SIZE_RATIO {
+                continue;
+            }
+            if span.bbox.y1 <= body_bbox.y1 - SUPERSCRIPT_OFFSET_RATIO * body_height {
+                span.script = Some(ScriptPosition::Superscript);
+            } else if span.bbox.y1 >= body_bbox.y1 + SUBSCRIPT_OFFSET_RATIO * body_height
+                && span.bbox.y0 >= body_bbox.y0 + SUPERSCRIPT_OFFSET_RATIO * body_height
+            {
+                span.script = Some(ScriptPosition::Subscript);
+            }
+        }
+    }
+
+    /// Largest font size of the line spans
+    pub fn font_size(&self) -> Option<f32> {
+        self.spans
+            .iter()
+            .map(|span| span.font_size)
+            .filter(|size| *size > 0f32)
+            .max_by(f32::total_cmp)
+    }
+
     pub fn new_from_span(span: CharSpan) -> Self {
         Self {
             bbox: span.bbox.clone(),
             text: span.text.clone(),
             rotation: span.rotation,
             spans: vec![span],
+            confidence: None,
+            links: Vec::new(),
+            direction: TextDirection::Ltr,
         }
     }
     // TODO: find a better pattern here
     // return Some if we fail to append the span-> not great
-    pub fn append(&mut self, span: CharSpan) -> Result<(), CharSpan> {
+    pub fn append(&mut self, span: CharSpan, thresholds: &MergeThresholds) -> Result<(), CharSpan> {
         if span.rotation != self.rotation
         // NOTE: sometimes pdfium doesn't inject a linebreak, so we check the span positions
-        || span.bbox.y0 > self.bbox.y1
+        || span.bbox.y0 > self.bbox.y1 + thresholds.line_gap
+        || thresholds
+            .span_gap
+            .is_some_and(|span_gap| span.bbox.x0 > self.bbox.x1 + span_gap)
         || span.text.ends_with("\n") || span.text.ends_with("\x02")
         {
             self.text = fix_text(&self.text, None);
@@ -435,12 +1162,14 @@ impl Line {
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize)]
+#[archive(check_bytes)]
 pub enum Segment {
     Line { start: (f32, f32), end: (f32, f32) },
     Rect { bbox: BBox },
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize)]
+#[archive(check_bytes)]
 pub struct PDFPath {
     pub segments: Vec<Segment>,
     pub is_stroke: bool,
@@ -452,6 +1181,80 @@ pub struct PDFPath {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parsed_document_words() {
+        let bbox = |x0: f32, x1: f32| BBox {
+            x0,
+            y0: 20.0,
+            x1,
+            y1: 30.0,
+        };
+        let span = |text: &str, x0: f32, x1: f32| CharSpan {
+            bbox: bbox(x0, x1),
+            text: text.to_string(),
+            rotation: 0.0,
+            font_name: "Helvetica".to_string(),
+            font_size: 10.0,
+            font_weight: None,
+            bold: false,
+            italic: false,
+            script: None,
+            char_start_idx: 0,
+            char_end_idx: 0,
+        };
+        let page = |id: PageID, line: Line| Page {
+            id,
+            width: 600.0,
+            height: 800.0,
+            image: DynamicImage::new_rgb8(1, 1),
+            rotation: 0,
+            need_ocr: line.confidence.is_some(),
+            lines: vec![line],
+            ocr_lines: vec![],
+            layout: vec![],
+            embedded_images: vec![],
+        };
+        let native_line = Line {
+            text: "Net income grew".to_string(),
+            bbox: bbox(10.0, 160.0),
+            spans: vec![span("Net income ", 10.0, 120.0), span("grew", 120.0, 160.0)],
+            ..Default::default()
+        };
+        let ocr_line = Line {
+            text: "Total".to_string(),
+            bbox: bbox(10.0, 60.0),
+            confidence: Some(0.8),
+            ..Default::default()
+        };
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![page(0, native_line), page(1, ocr_line)],
+            blocks: vec![],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let words: Vec<_> = doc
+            .words()
+            .map(|w| (w.text, w.bbox.x0, w.bbox.x1, w.page_id, w.confidence))
+            .collect();
+        assert_eq!(
+            words,
+            [
+                ("Net".to_string(), 10.0, 40.0, 0, None),
+                ("income".to_string(), 50.0, 110.0, 0, None),
+                ("grew".to_string(), 120.0, 160.0, 0, None),
+                ("Total".to_string(), 10.0, 60.0, 1, Some(0.8)),
+            ]
+        );
+    }
+
     #[test]
     fn test_intersection() {
         let bbox1 = BBox {
@@ -636,4 +1439,160 @@ mod tests {
         let distance = bbox1.distance(&bbox2, x_weight, y_weight);
         assert_eq!(distance, 45.0); // (3-1)^2 * 2 + (4-1)^2 * 3
     }
+
+    #[test]
+    fn test_line_append_thresholds() {
+        let span = |x0: f32, y0: f32| CharSpan {
+            bbox: BBox {
+                x0,
+                y0,
+                x1: x0 + 10.0,
+                y1: y0 + 8.0,
+            },
+            text: "word ".to_string(),
+            rotation: 0.0,
+            font_name: "Times".to_string(),
+            font_size: 10.0,
+            font_weight: None,
+            bold: false,
+            italic: false,
+            script: None,
+            char_start_idx: 0,
+            char_end_idx: 0,
+        };
+        let defaults = MergeThresholds::default();
+        let loose = MergeThresholds {
+            line_gap: 2.0,
+            span_gap: Some(20.0),
+            ..defaults
+        };
+
+        // A distant span on the same baseline joins the line unless the span gap is bounded
+        let mut line = Line::new_from_span(span(0.0, 100.0));
+        assert!(line.append(span(200.0, 100.0), &defaults).is_ok());
+        let mut line = Line::new_from_span(span(0.0, 100.0));
+        assert!(line.append(span(200.0, 100.0), &loose).is_err());
+        assert!(line.append(span(15.0, 100.0), &loose).is_ok());
+
+        // A span starting just below the line joins it within the line gap
+        let mut line = Line::new_from_span(span(0.0, 100.0));
+        assert!(line.append(span(10.0, 109.0), &defaults).is_err());
+        assert!(line.append(span(10.0, 109.0), &loose).is_ok());
+    }
+
+    #[test]
+    fn test_tag_scripts() {
+        let span = |text: &str, font_size, x0, y0, y1| CharSpan {
+            bbox: BBox {
+                x0,
+                y0,
+                x1: x0 + 10.0,
+                y1,
+            },
+            text: text.to_string(),
+            rotation: 0.0,
+            font_name: "Times".to_string(),
+            font_size,
+            font_weight: None,
+            bold: false,
+            italic: false,
+            script: None,
+            char_start_idx: 0,
+            char_end_idx: 0,
+        };
+        let mut line = Line::new_from_span(span("Water is H", 10.0, 0.0, 100.0, 108.0));
+        for s in [
+            span("2", 6.0, 10.0, 104.0, 110.0),
+            span("O as shown", 10.0, 20.0, 100.0, 108.0),
+            span("1", 6.0, 30.0, 98.0, 103.0),
+            // Small caps sit on the baseline
+            span("ABC", 8.0, 40.0, 102.0, 108.0),
+        ] {
+            line.append(s, &MergeThresholds::default()).unwrap();
+        }
+
+        line.tag_scripts();
+
+        let scripts = line.spans.iter().map(|s| s.script).collect::<Vec<_>>();
+        assert_eq!(
+            scripts,
+            vec![
+                None,
+                Some(ScriptPosition::Subscript),
+                None,
+                Some(ScriptPosition::Superscript),
+                None
+            ]
+        );
+        assert_eq!(
+            line.style_spans(),
+            vec![
+                StyleSpan {
+                    text: "2".to_string(),
+                    bold: false,
+                    italic: false,
+                    script: Some(ScriptPosition::Subscript),
+                },
+                StyleSpan {
+                    text: "1".to_string(),
+                    bold: false,
+                    italic: false,
+                    script: Some(ScriptPosition::Superscript),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_page_layout_serialization() {
+        let mut page = Page {
+            id: 0,
+            width: 600.0,
+            height: 800.0,
+            image: DynamicImage::new_rgb8(1, 1),
+            rotation: 0,
+            need_ocr: false,
+            lines: vec![],
+            ocr_lines: vec![],
+            layout: vec![],
+            embedded_images: vec![],
+        };
+        let json = serde_json::to_value(&page).unwrap();
+        assert!(json.get("layout").is_none());
+
+        page.layout = vec![LayoutBBox {
+            id: 0,
+            bbox: BBox {
+                x0: 10.0,
+                y0: 20.0,
+                x1: 300.0,
+                y1: 80.0,
+            },
+            label: "Section-header".to_string(),
+            proba: 0.75,
+        }];
+        let json = serde_json::to_value(&page).unwrap();
+        let detection = &json["layout"][0];
+        assert_eq!(detection["label"], "Section-header");
+        assert_eq!(detection["proba"], 0.75);
+        assert_eq!(detection["bbox"]["x1"], 300.0);
+    }
+
+    #[cfg(feature = "schema")]
+    #[test]
+    fn test_parsed_document_schema() {
+        let schema = serde_json::to_value(parsed_document_schema()).unwrap();
+        let properties = schema["properties"].as_object().unwrap();
+        for field in ["doc_name", "pages", "blocks", "metadata", "metrics"] {
+            assert!(properties.contains_key(field), "missing {field}");
+        }
+        let definitions = &schema["definitions"];
+        assert!(definitions["BlockType"].is_object());
+        // Fields that are never serialized are not part of the schema
+        assert!(definitions["Page"]["properties"].get("image").is_none());
+        assert_eq!(
+            definitions["DocumentMetadata"]["properties"]["parsing_duration"]["type"],
+            "integer"
+        );
+    }
 }
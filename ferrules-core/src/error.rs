@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use thiserror::Error;
 
@@ -11,8 +11,26 @@ use crate::{
 pub enum FerrulesError {
     #[error("error occured parsing document natively")]
     ParseNativeError,
+    #[error("incorrect password for the encrypted document")]
+    IncorrectPassword,
+    #[error("the document is encrypted and requires a password")]
+    EncryptedDocument,
+    #[error("unsupported document format: {0}")]
+    UnsupportedFormat(String),
+    #[error("can't load the pdfium library: {0}")]
+    PdfiumLoad(String),
+    #[error("can't fetch the document from {url}: {reason}")]
+    FetchError { url: String, reason: String },
     #[error("layout parsing error")]
     LayoutParsingError,
+    #[error("layout parsing of page {page_id} timed out after {elapsed:?}, queued for {queue_time_ms:.1}ms")]
+    LayoutTimeout {
+        page_id: PageID,
+        elapsed: Duration,
+        queue_time_ms: f64,
+    },
+    #[error("unknown layout class `{class}`, valid classes are: {valid}")]
+    UnknownLayoutClass { class: String, valid: String },
     #[error("merging line into block error")]
     LineMergeError,
     #[error("merging elements into block error")]
@@ -31,4 +49,12 @@ pub enum FerrulesError {
     TableParserError(String),
     #[error("ocr parser error: {0}")]
     OcrError(String),
+    #[error("native parsing of page {page_id} failed: {message}")]
+    NativePageError { page_id: PageID, message: String },
+    #[error("parsing page {page_id} panicked")]
+    PagePanic { page_id: PageID },
+    #[error("document parsing was cancelled")]
+    Cancelled,
+    #[error("document parsing timed out after {0:?}")]
+    Timeout(Duration),
 }
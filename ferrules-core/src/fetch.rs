@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use crate::error::FerrulesError;
+
+/// Default maximum size of a document fetched from a URL
+pub const DEFAULT_FETCH_MAX_BYTES: usize = 250 * 1024 * 1024;
+
+/// Default timeout of the whole download of a document fetched from a URL
+pub const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Inputs starting with `http://` or `https://` are fetched instead of read from disk
+pub fn is_url(input: &str) -> bool {
+    let scheme = input.split_once("://").map(|(scheme, _)| scheme);
+    scheme.is_some_and(|s| s.eq_ignore_ascii_case("http") || s.eq_ignore_ascii_case("https"))
+}
+
+/// Downloads the document at `url` in memory. The download fails when it takes longer than
+/// `timeout` or the document is larger than `max_bytes`, whether announced by the
+/// `Content-Length` header or not.
+#[tracing::instrument(skip(max_bytes, timeout))]
+pub async fn fetch_document(
+    url: &str,
+    max_bytes: usize,
+    timeout: Duration,
+) -> Result<Vec<u8>, FerrulesError> {
+    let fetch_error = |reason: String| FerrulesError::FetchError {
+        url: url.to_owned(),
+        reason,
+    };
+    if !is_url(url) {
+        return Err(fetch_error("only http and https URLs are supported".into()));
+    }
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| fetch_error(e.to_string()))?;
+    let mut response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| fetch_error(e.to_string()))?;
+    let too_large = || fetch_error(format!("the document is larger than {max_bytes} bytes"));
+    if response
+        .content_length()
+        .is_some_and(|len| len > max_bytes as u64)
+    {
+        return Err(too_large());
+    }
+
+    let mut doc = Vec::with_capacity(response.content_length().unwrap_or_default() as usize);
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| fetch_error(e.to_string()))?
+    {
+        if doc.len() + chunk.len() > max_bytes {
+            return Err(too_large());
+        }
+        doc.extend_from_slice(&chunk);
+    }
+    tracing::debug!("fetched {} bytes", doc.len());
+    Ok(doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://example.com/report.pdf"));
+        assert!(is_url("HTTP://example.com/report.pdf"));
+        assert!(!is_url("ftp://example.com/report.pdf"));
+        assert!(!is_url("reports/https.pdf"));
+        assert!(!is_url("/tmp/report.pdf"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_document_unsupported_scheme() {
+        let res = fetch_document("file:///etc/passwd", 1024, DEFAULT_FETCH_TIMEOUT).await;
+        assert!(matches!(res, Err(FerrulesError::FetchError { .. })));
+    }
+}
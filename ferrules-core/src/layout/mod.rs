@@ -1,23 +1,59 @@
-use std::{sync::Arc, time::Instant};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
+use futures::FutureExt;
 use image::DynamicImage;
-use model::{LayoutBBox, ORTLayoutParser};
+use model::{LayoutBBox, LayoutProvider, ORTLayoutParser, OrtExecutionProvider};
+use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::{self, Receiver, Sender};
-use tokio::sync::{oneshot, Semaphore};
+use tokio::sync::{oneshot, Mutex as AsyncMutex, Notify, Semaphore};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use tracing::{Instrument, Span};
 
-use crate::entities::PageID;
+use crate::entities::{BBox, PageID};
 use crate::error::FerrulesError;
-use crate::metrics::StepMetrics;
+use crate::metrics::{LayoutQueueStats, StepMetrics};
 
 pub mod model;
 
 const CONCURRENT_LAYOUT_REQUESTS: usize = 16;
 
+/// Number of recent inference durations the latency percentiles are computed on
+const INFERENCE_DURATION_WINDOW: usize = 1024;
+
 #[derive(Debug)]
 pub struct Metadata {
     pub(crate) response_tx: oneshot::Sender<anyhow::Result<ParseLayoutResponse>>,
     pub(crate) queue_time: Instant,
+    /// Maximum time between queueing the request and getting its layout
+    pub(crate) timeout: Option<Duration>,
+    /// Cancellation of the document parsing, checked before running the inference
+    pub(crate) cancellation: CancellationToken,
+}
+
+impl Metadata {
+    fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| self.queue_time + timeout)
+    }
+
+    fn timeout_error(&self, page_id: PageID, layout_queue_time_ms: f64) -> anyhow::Error {
+        let err = FerrulesError::LayoutTimeout {
+            page_id,
+            elapsed: self.queue_time.elapsed(),
+            queue_time_ms: layout_queue_time_ms,
+        };
+        tracing::error!("{err}");
+        err.into()
+    }
 }
 
 #[derive(Debug)]
@@ -28,28 +64,311 @@ pub(crate) struct ParseLayoutRequest {
     pub(crate) metadata: Metadata,
 }
 
+/// Layout region detected on an image by [`crate::FerrulesParser::detect_layout`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayoutRegion {
+    /// Layout class of the region, e.g. `Text` or `Table`
+    pub label: String,
+    /// Detection confidence in [0, 1]
+    pub score: f32,
+    /// Region in image pixel coordinates
+    pub bbox: BBox,
+}
+
+impl From<LayoutBBox> for LayoutRegion {
+    fn from(layout_bbox: LayoutBBox) -> Self {
+        Self {
+            label: layout_bbox.label,
+            score: layout_bbox.proba,
+            bbox: layout_bbox.bbox,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct ParseLayoutResponse {
     pub(crate) _page_id: PageID,
     pub(crate) layout_bbox: Vec<LayoutBBox>,
     pub(crate) step_metrics: StepMetrics,
+    /// Execution provider the layout of the page was inferred on
+    pub(crate) provider: OrtExecutionProvider,
+}
+
+/// Shutdown signal of the layout workers, shared by all the clones of a [`ParseLayoutQueue`].
+/// Dropping the last clone notifies the workers.
+#[derive(Debug)]
+struct LayoutShutdown {
+    notify: Arc<Notify>,
+    supervisor: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Drop for LayoutShutdown {
+    fn drop(&mut self) {
+        self.notify.notify_one();
+    }
+}
+
+/// Counters of the layout queue activity, updated by the layout workers
+#[derive(Debug, Default)]
+struct LayoutQueueMetrics {
+    in_flight: AtomicUsize,
+    inferences: AtomicU64,
+    total_queue_wait_us: AtomicU64,
+    inference_durations: Mutex<VecDeque<f64>>,
+}
+
+impl LayoutQueueMetrics {
+    /// Counts `n_pages` taken from the queue as in flight until the returned guard is dropped
+    fn dequeued(self: &Arc<Self>, n_pages: usize, queue_time_ms: f64) -> InFlightPages {
+        self.in_flight.fetch_add(n_pages, Ordering::Relaxed);
+        self.total_queue_wait_us
+            .fetch_add((queue_time_ms * 1000.0) as u64, Ordering::Relaxed);
+        InFlightPages {
+            metrics: self.clone(),
+            n_pages,
+        }
+    }
+
+    fn record_inference(&self, duration_ms: f64) {
+        self.inferences.fetch_add(1, Ordering::Relaxed);
+        let mut durations = self.inference_durations.lock().unwrap();
+        if durations.len() == INFERENCE_DURATION_WINDOW {
+            durations.pop_front();
+        }
+        durations.push_back(duration_ms);
+    }
+
+    fn snapshot(&self, queue_depth: usize) -> LayoutQueueStats {
+        let mut durations = self
+            .inference_durations
+            .lock()
+            .unwrap()
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        durations.sort_by(f64::total_cmp);
+        let percentile = |p: f64| {
+            let rank = (p * durations.len() as f64).ceil() as usize;
+            durations
+                .get(rank.saturating_sub(1))
+                .copied()
+                .unwrap_or_default()
+        };
+        LayoutQueueStats {
+            queue_depth,
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            inferences: self.inferences.load(Ordering::Relaxed),
+            inference_p50_ms: percentile(0.5),
+            inference_p95_ms: percentile(0.95),
+            total_queue_wait_ms: self.total_queue_wait_us.load(Ordering::Relaxed) as f64 / 1000.0,
+        }
+    }
+}
+
+/// Pages counted as in flight, whatever the way their request is answered
+#[derive(Debug)]
+struct InFlightPages {
+    metrics: Arc<LayoutQueueMetrics>,
+    n_pages: usize,
+}
+
+impl Drop for InFlightPages {
+    fn drop(&mut self) {
+        self.metrics
+            .in_flight
+            .fetch_sub(self.n_pages, Ordering::Relaxed);
+    }
+}
+
+/// How the page layout is obtained
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutMode {
+    /// Run the layout model on every page
+    #[default]
+    Model,
+    /// Never run the layout model: text lines are grouped into paragraphs from their vertical
+    /// gaps. Pages only contain text blocks, without titles, lists, tables or figures
+    Skip,
+}
+
+/// Layout classes kept on the pages, names are matched case insensitively
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LayoutClassFilter {
+    allow: Option<Vec<String>>,
+    exclude: Vec<String>,
+}
+
+impl LayoutClassFilter {
+    /// Resolves the class names against the `labels` of the layout model
+    pub(crate) fn new(
+        allow: Option<&[String]>,
+        exclude: &[String],
+        labels: &[String],
+    ) -> Result<Self, FerrulesError> {
+        let resolve = |names: &[String]| {
+            names
+                .iter()
+                .map(|name| {
+                    labels
+                        .iter()
+                        .find(|label| label.eq_ignore_ascii_case(name.trim()))
+                        .cloned()
+                        .ok_or_else(|| FerrulesError::UnknownLayoutClass {
+                            class: name.clone(),
+                            valid: labels.join(", "),
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            allow: allow.map(resolve).transpose()?,
+            exclude: resolve(exclude)?,
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.allow.is_none() && self.exclude.is_empty()
+    }
+
+    pub(crate) fn keeps(&self, label: &str) -> bool {
+        self.allow
+            .as_ref()
+            .is_none_or(|allow| allow.iter().any(|l| l == label))
+            && !self.exclude.iter().any(|l| l == label)
+    }
+}
+
+/// Capacity of the layout queue. It holds at least the pages the workers can have in flight,
+/// otherwise pushing pages blocks while the workers wait for their batches to fill up.
+fn queue_capacity(intra_threads: usize, max_batch_size: usize) -> usize {
+    let in_flight = CONCURRENT_LAYOUT_REQUESTS * max_batch_size.max(1);
+    if intra_threads < in_flight {
+        tracing::debug!(
+            "layout queue capacity raised from {intra_threads} to the {in_flight} pages in flight"
+        );
+    }
+    intra_threads.max(in_flight)
 }
 
 #[derive(Debug, Clone)]
 pub struct ParseLayoutQueue {
     queue: Sender<(ParseLayoutRequest, Span)>,
+    input_size: (u32, u32),
+    labels: Vec<String>,
+    provider: LayoutProvider,
+    fingerprint: String,
+    metrics: Arc<LayoutQueueMetrics>,
+    shutdown: Arc<LayoutShutdown>,
 }
 
 impl ParseLayoutQueue {
     pub fn new(layout_parser: Arc<ORTLayoutParser>) -> Self {
-        let (queue_sender, queue_receiver) = mpsc::channel(layout_parser.config.intra_threads);
+        let capacity = queue_capacity(
+            layout_parser.config.intra_threads,
+            layout_parser.max_batch_size(),
+        );
+        let (queue_sender, queue_receiver) = mpsc::channel(capacity);
+        let input_size = layout_parser.input_size();
+        let provider = layout_parser.provider().clone();
+        let labels = layout_parser.labels().to_vec();
+        let fingerprint = layout_parser.config.layout_fingerprint();
+        let notify = Arc::new(Notify::new());
+        let metrics = Arc::new(LayoutQueueMetrics::default());
 
-        tokio::task::spawn(start_layout_parser(layout_parser, queue_receiver));
+        let supervisor = tokio::task::spawn(start_layout_parser(
+            layout_parser,
+            queue_receiver,
+            notify.clone(),
+            metrics.clone(),
+        ));
         Self {
             queue: queue_sender,
+            input_size,
+            labels,
+            provider,
+            fingerprint,
+            metrics,
+            shutdown: Arc::new(LayoutShutdown {
+                notify,
+                supervisor: Mutex::new(Some(supervisor)),
+            }),
         }
     }
 
+    /// Stops the layout workers: queued requests are answered with an error, in-flight
+    /// inferences are awaited. Pushing to the queue afterwards fails.
+    pub async fn shutdown(&self) {
+        self.shutdown.notify.notify_one();
+        let supervisor = self.shutdown.supervisor.lock().unwrap().take();
+        if let Some(supervisor) = supervisor {
+            if let Err(e) = supervisor.await {
+                tracing::error!("layout workers shutdown failed: {e}");
+            }
+        }
+    }
+
+    /// Input `(width, height)` of the layout model
+    pub fn input_size(&self) -> (u32, u32) {
+        self.input_size
+    }
+
+    /// Labels of the layout model classes
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Settings of the layout model changing its detections, see `ORTConfig::layout_fingerprint`
+    pub(crate) fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// Execution provider of the layout model
+    pub fn provider(&self) -> &LayoutProvider {
+        &self.provider
+    }
+
+    /// Queue depth, in-flight pages and inference latencies of the layout workers
+    pub fn stats(&self) -> LayoutQueueStats {
+        self.metrics
+            .snapshot(self.queue.max_capacity() - self.queue.capacity())
+    }
+
+    /// Runs the layout of a blank page through the queue, so that the lazy initialization of
+    /// the execution provider (CUDA context, CoreML compilation...) happens before the first
+    /// document is parsed. Returns the warmup duration.
+    pub async fn warmup(&self) -> Result<Duration, FerrulesError> {
+        let (width, height) = self.input_size;
+        let start = Instant::now();
+        let (response_tx, response_rx) = oneshot::channel();
+        self.push(ParseLayoutRequest {
+            page_id: 0,
+            page_image: Arc::new(DynamicImage::new_rgb8(width, height)),
+            downscale_factor: 1f32,
+            metadata: Metadata {
+                response_tx,
+                queue_time: Instant::now(),
+                timeout: None,
+                cancellation: CancellationToken::new(),
+            },
+        })
+        .await?;
+        response_rx
+            .await
+            .map_err(|_| FerrulesError::LayoutParsingError)?
+            .map_err(|e| {
+                tracing::error!("Layout warmup failed: {e:?}");
+                FerrulesError::LayoutParsingError
+            })?;
+        let duration = start.elapsed();
+        tracing::debug!(
+            "layout worker warmup on {} took: {}ms",
+            self.provider,
+            duration.as_millis()
+        );
+        Ok(duration)
+    }
+
     pub(crate) async fn push(&self, req: ParseLayoutRequest) -> Result<(), FerrulesError> {
         let span = Span::current();
         self.queue
@@ -61,54 +380,393 @@ impl ParseLayoutQueue {
 
 async fn start_layout_parser(
     layout_parser: Arc<ORTLayoutParser>,
-    mut input_rx: Receiver<(ParseLayoutRequest, Span)>,
+    input_rx: Receiver<(ParseLayoutRequest, Span)>,
+    shutdown: Arc<Notify>,
+    metrics: Arc<LayoutQueueMetrics>,
 ) {
     let s = Arc::new(Semaphore::new(CONCURRENT_LAYOUT_REQUESTS));
-    while let Some((req, span)) = input_rx.recv().await {
+    // The receiver outlives a worker panic so that the respawned worker keeps the queue
+    let input_rx = Arc::new(AsyncMutex::new(input_rx));
+    supervise_worker(|| {
+        layout_worker(
+            layout_parser.clone(),
+            input_rx.clone(),
+            s.clone(),
+            shutdown.clone(),
+            metrics.clone(),
+        )
+    })
+    .await;
+
+    // Wait for the in-flight inferences to finish
+    let _ = s.acquire_many(CONCURRENT_LAYOUT_REQUESTS as u32).await;
+}
+
+/// Runs the worker returned by `spawn_worker` until it returns, spawning a new one each time
+/// the previous worker panics.
+async fn supervise_worker<F, Fut>(mut spawn_worker: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    loop {
+        match tokio::spawn(spawn_worker()).await {
+            Ok(()) => break,
+            Err(e) if e.is_panic() => {
+                tracing::error!("layout worker panicked, respawning it: {e}");
+            }
+            Err(e) => {
+                tracing::error!("layout worker was cancelled: {e}");
+                break;
+            }
+        }
+    }
+}
+
+/// Answers with an error every request still waiting in the closed queue.
+async fn reject_pending_requests(input_rx: &mut Receiver<(ParseLayoutRequest, Span)>) {
+    input_rx.close();
+    while let Some((req, _)) = input_rx.recv().await {
+        let _ = req
+            .metadata
+            .response_tx
+            .send(Err(anyhow::anyhow!("layout queue is shut down")));
+    }
+}
+
+async fn layout_worker(
+    layout_parser: Arc<ORTLayoutParser>,
+    input_rx: Arc<AsyncMutex<Receiver<(ParseLayoutRequest, Span)>>>,
+    s: Arc<Semaphore>,
+    shutdown: Arc<Notify>,
+    metrics: Arc<LayoutQueueMetrics>,
+) {
+    let mut input_rx = input_rx.lock().await;
+    let max_batch_size = layout_parser.max_batch_size();
+    let max_wait = layout_parser.config.layout_batch_max_wait;
+    loop {
+        let first = tokio::select! {
+            biased;
+            _ = shutdown.notified() => {
+                reject_pending_requests(&mut input_rx).await;
+                break;
+            }
+            first = input_rx.recv() => match first {
+                Some(first) => first,
+                None => break,
+            },
+        };
+        let mut batch = collect_batch(first, &mut input_rx, max_batch_size, max_wait).await;
+        if batch.len() > 1 {
+            tokio::spawn(handle_batch(
+                s.clone(),
+                layout_parser.clone(),
+                metrics.clone(),
+                batch,
+            ));
+            continue;
+        }
+
+        let (req, span) = batch.pop().unwrap();
         let queue_time = req.metadata.queue_time.elapsed().as_secs_f64() * 1000.0;
         let page_id = req.page_id;
         tracing::debug!("layout request queue time for page {page_id} took: {queue_time}ms");
         let _guard = span.enter();
         tokio::spawn(
-            handle_request(s.clone(), layout_parser.clone(), req, queue_time).in_current_span(),
+            handle_request(
+                s.clone(),
+                layout_parser.clone(),
+                metrics.clone(),
+                req,
+                queue_time,
+            )
+            .in_current_span(),
         );
     }
 }
 
+/// Drains the requests already waiting in the queue, up to `max_batch_size`.
+/// Once more than one request is pending, waits up to `max_wait` for the batch to fill up.
+/// A lone request is returned right away so that single page latency is unchanged.
+async fn collect_batch<T>(
+    first: T,
+    input_rx: &mut Receiver<T>,
+    max_batch_size: usize,
+    max_wait: Duration,
+) -> Vec<T> {
+    let mut batch = vec![first];
+    while batch.len() < max_batch_size {
+        match input_rx.try_recv() {
+            Ok(req) => batch.push(req),
+            Err(_) => break,
+        }
+    }
+
+    if batch.len() > 1 && !max_wait.is_zero() {
+        let deadline = tokio::time::Instant::now() + max_wait;
+        while batch.len() < max_batch_size {
+            match tokio::time::timeout_at(deadline, input_rx.recv()).await {
+                Ok(Some(req)) => batch.push(req),
+                _ => break,
+            }
+        }
+    }
+    batch
+}
+
+/// Answers the requests of cancelled documents with `FerrulesError::Cancelled`, returning the
+/// others
+fn reject_cancelled(batch: Vec<(ParseLayoutRequest, Span)>) -> Vec<(ParseLayoutRequest, Span)> {
+    let (batch, cancelled): (Vec<_>, Vec<_>) = batch
+        .into_iter()
+        .partition(|(req, _)| !req.metadata.cancellation.is_cancelled());
+    for (req, _) in cancelled {
+        tracing::debug!(
+            "skipping layout of page {}, parsing was cancelled",
+            req.page_id
+        );
+        let _ = req
+            .metadata
+            .response_tx
+            .send(Err(FerrulesError::Cancelled.into()));
+    }
+    batch
+}
+
+/// Runs `fut` to completion, or until `deadline` when there is one. Returns `None` once the
+/// deadline is exceeded.
+async fn until_deadline<T>(deadline: Option<Instant>, fut: impl Future<Output = T>) -> Option<T> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline.into(), fut).await.ok(),
+        None => Some(fut.await),
+    }
+}
+
+/// Turns a panic of the inference into an error, so that the caller always gets a response.
+async fn catch_inference_panic<T>(
+    inference: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    match AssertUnwindSafe(inference).catch_unwind().await {
+        Ok(result) => result,
+        Err(panic) => {
+            let reason = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(anyhow::anyhow!("layout inference panicked: {reason}"))
+        }
+    }
+}
+
+/// Retries the layout inference of a page up to `layout_retries` times, on the CPU. Returns the
+/// last error when every retry failed.
+async fn retry_layout(
+    parser: &ORTLayoutParser,
+    page_id: PageID,
+    page_image: &DynamicImage,
+    downscale_factor: f32,
+    mut err: anyhow::Error,
+) -> anyhow::Result<Vec<LayoutBBox>> {
+    let retries = parser.config.layout_retries;
+    for attempt in 1..=retries {
+        tracing::warn!(
+            "layout inference failed for page {page_id}, retrying on cpu ({attempt}/{retries}): {err:?}"
+        );
+        match catch_inference_panic(parser.parse_layout_cpu_async(page_image, downscale_factor))
+            .await
+        {
+            Ok(layout_bbox) => return Ok(layout_bbox),
+            Err(e) => err = e,
+        }
+    }
+    Err(err)
+}
+
+#[tracing::instrument(name = "layout_parse_batch", skip_all, fields(batch_size = batch.len()))]
+async fn handle_batch(
+    s: Arc<Semaphore>,
+    parser: Arc<ORTLayoutParser>,
+    metrics: Arc<LayoutQueueMetrics>,
+    batch: Vec<(ParseLayoutRequest, Span)>,
+) {
+    let batch = reject_cancelled(batch);
+    if batch.is_empty() {
+        return;
+    }
+    let queue_times: Vec<f64> = batch
+        .iter()
+        .map(|(req, _)| req.metadata.queue_time.elapsed().as_secs_f64() * 1000.0)
+        .collect();
+    let _in_flight = metrics.dequeued(batch.len(), queue_times.iter().sum());
+
+    // The batch runs until the latest deadline of its requests
+    let deadline = batch
+        .iter()
+        .map(|(req, _)| req.metadata.deadline())
+        .collect::<Option<Vec<_>>>()
+        .and_then(|deadlines| deadlines.into_iter().max());
+
+    let pages: Vec<(&DynamicImage, f32)> = batch
+        .iter()
+        .map(|(req, _)| (req.page_image.as_ref(), req.downscale_factor))
+        .collect();
+
+    let inference = async {
+        let start_wait = Instant::now();
+        let _permit = s.acquire().await.unwrap();
+        let idle_time_ms = start_wait.elapsed().as_secs_f64() * 1000.0;
+
+        let start = Instant::now();
+        let layout_result = catch_inference_panic(parser.parse_layout_batch_async(&pages)).await;
+        (
+            layout_result,
+            idle_time_ms,
+            start.elapsed().as_secs_f64() * 1000.0,
+        )
+    };
+    let Some((layout_result, idle_time_ms, inference_duration)) =
+        until_deadline(deadline, inference).await
+    else {
+        for ((req, _), layout_queue_time_ms) in batch.into_iter().zip(queue_times) {
+            let err = req
+                .metadata
+                .timeout_error(req.page_id, layout_queue_time_ms);
+            let _ = req.metadata.response_tx.send(Err(err));
+        }
+        return;
+    };
+    metrics.record_inference(inference_duration);
+    tracing::debug!(
+        "layout inference time for a batch of {} pages took: {inference_duration}ms",
+        pages.len()
+    );
+
+    match layout_result {
+        Ok(layouts) => {
+            for (((req, span), layout_bbox), layout_queue_time_ms) in
+                batch.into_iter().zip(layouts).zip(queue_times)
+            {
+                let _guard = span.enter();
+                let _ = req.metadata.response_tx.send(Ok(ParseLayoutResponse {
+                    _page_id: req.page_id,
+                    layout_bbox,
+                    step_metrics: StepMetrics {
+                        queue_time_ms: layout_queue_time_ms,
+                        execution_time_ms: inference_duration,
+                        idle_time_ms,
+                    },
+                    provider: parser.provider().effective.clone(),
+                }));
+            }
+        }
+        Err(e) => {
+            tracing::error!("Batched layout parsing failed: {:?}", e);
+            // The pages of the batch are retried one at a time
+            for ((req, span), layout_queue_time_ms) in batch.into_iter().zip(queue_times) {
+                let _guard = span.enter();
+                let start = Instant::now();
+                let err = anyhow::anyhow!("batched layout parsing failed: {e}");
+                let retry = retry_layout(
+                    &parser,
+                    req.page_id,
+                    &req.page_image,
+                    req.downscale_factor,
+                    err,
+                );
+                let Some(layout_result) = until_deadline(req.metadata.deadline(), retry).await
+                else {
+                    let err = req
+                        .metadata
+                        .timeout_error(req.page_id, layout_queue_time_ms);
+                    let _ = req.metadata.response_tx.send(Err(err));
+                    continue;
+                };
+                let retry_duration = start.elapsed().as_secs_f64() * 1000.0;
+                let _ = req
+                    .metadata
+                    .response_tx
+                    .send(layout_result.map(|layout_bbox| ParseLayoutResponse {
+                        _page_id: req.page_id,
+                        layout_bbox,
+                        step_metrics: StepMetrics {
+                            queue_time_ms: layout_queue_time_ms,
+                            execution_time_ms: inference_duration + retry_duration,
+                            idle_time_ms,
+                        },
+                        provider: OrtExecutionProvider::CPU,
+                    }));
+            }
+        }
+    }
+}
+
 #[tracing::instrument(name = "layout_parse", skip_all, fields(page_id = req.page_id, downscale_factor = req.downscale_factor))]
 async fn handle_request(
     s: Arc<Semaphore>,
     parser: Arc<ORTLayoutParser>,
+    metrics: Arc<LayoutQueueMetrics>,
     req: ParseLayoutRequest,
     layout_queue_time_ms: f64,
 ) {
-    let start_wait = Instant::now();
-    let _permit = s.acquire().await.unwrap();
-    let idle_time_ms = start_wait.elapsed().as_secs_f64() * 1000.0;
-
     let ParseLayoutRequest {
         page_id,
         page_image,
         downscale_factor,
         metadata,
     } = req;
+    let _in_flight = metrics.dequeued(1, layout_queue_time_ms);
 
-    let start = Instant::now();
-    let layout_result = parser
-        .parse_layout_async(&page_image, downscale_factor)
-        .await;
-    let inference_duration = start.elapsed().as_secs_f64() * 1000.0;
-    drop(_permit);
+    let inference = async {
+        let start_wait = Instant::now();
+        let _permit = s.acquire().await.unwrap();
+        let idle_time_ms = start_wait.elapsed().as_secs_f64() * 1000.0;
+        // The document parsing might have been cancelled while waiting for a permit
+        if metadata.cancellation.is_cancelled() {
+            return None;
+        }
+
+        let start = Instant::now();
+        let layout_result =
+            catch_inference_panic(parser.parse_layout_async(&page_image, downscale_factor))
+                .await
+                .map(|layout_bbox| (layout_bbox, parser.provider().effective.clone()));
+        let layout_result = match layout_result {
+            Err(err) => retry_layout(&parser, page_id, &page_image, downscale_factor, err)
+                .await
+                .map(|layout_bbox| (layout_bbox, OrtExecutionProvider::CPU)),
+            layout_result => layout_result,
+        };
+        Some((
+            layout_result,
+            idle_time_ms,
+            start.elapsed().as_secs_f64() * 1000.0,
+        ))
+    };
+    let Some(inference) = until_deadline(metadata.deadline(), inference).await else {
+        let err = metadata.timeout_error(page_id, layout_queue_time_ms);
+        let _ = metadata.response_tx.send(Err(err));
+        return;
+    };
+    let Some((layout_result, idle_time_ms, inference_duration)) = inference else {
+        tracing::debug!("skipping layout of page {page_id}, parsing was cancelled");
+        let _ = metadata
+            .response_tx
+            .send(Err(FerrulesError::Cancelled.into()));
+        return;
+    };
+    metrics.record_inference(inference_duration);
     tracing::debug!("layout inference time for page {page_id} took: {inference_duration}ms");
 
-    let layout_result = layout_result.map(|l| ParseLayoutResponse {
+    let layout_result = layout_result.map(|(layout_bbox, provider)| ParseLayoutResponse {
         _page_id: page_id,
-        layout_bbox: l,
+        layout_bbox,
         step_metrics: StepMetrics {
             queue_time_ms: layout_queue_time_ms,
             execution_time_ms: inference_duration,
             idle_time_ms,
         },
+        provider,
     });
     if let Err(e) = layout_result.as_ref() {
         tracing::error!("Layout parsing failed for page {page_id}: {:?}", e);
@@ -116,3 +774,329 @@ async fn handle_request(
 
     let _ = metadata.response_tx.send(layout_result);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::model::ORTConfig;
+    use image::{Rgb, RgbImage};
+
+    #[tokio::test]
+    async fn test_collect_batch_single_request_not_delayed() {
+        let (tx, mut rx) = mpsc::channel(16);
+        tx.send(1).await.unwrap();
+        let first = rx.recv().await.unwrap();
+
+        let start = Instant::now();
+        let batch = collect_batch(first, &mut rx, 8, Duration::from_secs(1)).await;
+        assert_eq!(batch, vec![1]);
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_collect_batch_drains_up_to_max_size() {
+        let (tx, mut rx) = mpsc::channel(16);
+        for i in 0..10 {
+            tx.send(i).await.unwrap();
+        }
+        let first = rx.recv().await.unwrap();
+
+        let batch = collect_batch(first, &mut rx, 8, Duration::from_millis(2)).await;
+        assert_eq!(batch, (0..8).collect::<Vec<_>>());
+        let batch = collect_batch(rx.recv().await.unwrap(), &mut rx, 8, Duration::ZERO).await;
+        assert_eq!(batch, vec![8, 9]);
+    }
+
+    fn layout_request(
+        page_id: PageID,
+    ) -> (
+        ParseLayoutRequest,
+        oneshot::Receiver<anyhow::Result<ParseLayoutResponse>>,
+    ) {
+        let (response_tx, response_rx) = oneshot::channel();
+        let req = ParseLayoutRequest {
+            page_id,
+            page_image: Arc::new(DynamicImage::new_rgb8(1, 1)),
+            downscale_factor: 1f32,
+            metadata: Metadata {
+                response_tx,
+                queue_time: Instant::now(),
+                timeout: None,
+                cancellation: CancellationToken::new(),
+            },
+        };
+        (req, response_rx)
+    }
+
+    async fn inference(poisoned: bool) -> anyhow::Result<usize> {
+        if poisoned {
+            panic!("malformed tensor");
+        }
+        Ok(1)
+    }
+
+    #[tokio::test]
+    async fn test_poisoned_inference_returns_error() {
+        let (response_tx, response_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let _ = response_tx.send(catch_inference_panic(inference(true)).await);
+        });
+
+        let response = tokio::time::timeout(Duration::from_secs(1), response_rx)
+            .await
+            .expect("caller should not hang")
+            .expect("response should be sent");
+        let err = response.unwrap_err().to_string();
+        assert!(err.contains("malformed tensor"), "{err}");
+        assert_eq!(catch_inference_panic(inference(false)).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_supervisor_respawns_panicked_worker() {
+        let (tx, rx) = mpsc::channel::<usize>(4);
+        let rx = Arc::new(AsyncMutex::new(rx));
+        let (done_tx, mut done_rx) = mpsc::channel(4);
+        let n_workers = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let spawned = n_workers.clone();
+        let supervisor = tokio::spawn(supervise_worker(move || {
+            let worker_id = spawned.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let rx = rx.clone();
+            let done_tx = done_tx.clone();
+            async move {
+                let mut rx = rx.lock().await;
+                while let Some(page_id) = rx.recv().await {
+                    if worker_id == 0 {
+                        panic!("poisoned request {page_id}");
+                    }
+                    done_tx.send(page_id).await.unwrap();
+                }
+            }
+        }));
+
+        tx.send(0).await.unwrap();
+        tx.send(1).await.unwrap();
+        // The first worker dies on the poisoned request, the respawned one serves the next
+        assert_eq!(done_rx.recv().await, Some(1));
+        drop(tx);
+        tokio::time::timeout(Duration::from_secs(1), supervisor)
+            .await
+            .expect("supervisor should stop once the queue is closed")
+            .unwrap();
+        assert_eq!(n_workers.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_layout_request_timeout() {
+        let (mut req, _response_rx) = layout_request(3);
+        req.metadata.timeout = Some(Duration::from_millis(10));
+
+        let stalled_inference = tokio::time::sleep(Duration::from_secs(5));
+        let start = Instant::now();
+        assert!(until_deadline(req.metadata.deadline(), stalled_inference)
+            .await
+            .is_none());
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let err = req.metadata.timeout_error(req.page_id, 4.0);
+        match err.downcast::<FerrulesError>() {
+            Ok(FerrulesError::LayoutTimeout {
+                page_id,
+                elapsed,
+                queue_time_ms,
+            }) => {
+                assert_eq!(page_id, 3);
+                assert!(elapsed >= Duration::from_millis(10));
+                assert_eq!(queue_time_ms, 4.0);
+            }
+            other => panic!("expected a layout timeout, got {other:?}"),
+        }
+        // Without a timeout the inference always completes
+        assert_eq!(until_deadline(None, async { 1 }).await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_reject_cancelled_requests() {
+        let cancellation = CancellationToken::new();
+        let (mut cancelled, cancelled_rx) = layout_request(0);
+        cancelled.metadata.cancellation = cancellation.clone();
+        let (kept, _kept_rx) = layout_request(1);
+        cancellation.cancel();
+
+        let batch = reject_cancelled(vec![(cancelled, Span::current()), (kept, Span::current())]);
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].0.page_id, 1);
+        assert!(matches!(
+            cancelled_rx
+                .await
+                .unwrap()
+                .unwrap_err()
+                .downcast::<FerrulesError>(),
+            Ok(FerrulesError::Cancelled)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_rejects_pending_requests() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let (req, response_rx) = layout_request(0);
+        tx.send((req, Span::current())).await.unwrap();
+
+        reject_pending_requests(&mut rx).await;
+        assert!(response_rx.await.unwrap().is_err());
+        let (req, _) = layout_request(1);
+        assert!(tx.send((req, Span::current())).await.is_err());
+    }
+
+    #[test]
+    fn test_queue_capacity_holds_in_flight_pages() {
+        assert_eq!(queue_capacity(1, 1), CONCURRENT_LAYOUT_REQUESTS);
+        assert_eq!(queue_capacity(4, 8), CONCURRENT_LAYOUT_REQUESTS * 8);
+        assert_eq!(queue_capacity(512, 8), 512);
+    }
+
+    #[test]
+    fn test_layout_queue_metrics() {
+        let metrics = Arc::new(LayoutQueueMetrics::default());
+        assert_eq!(metrics.snapshot(0).inference_p95_ms, 0.0);
+
+        let batch = metrics.dequeued(3, 4.5);
+        let single = metrics.dequeued(1, 0.5);
+        assert_eq!(metrics.snapshot(2).in_flight, 4);
+        drop(batch);
+        for duration_ms in 1..=100 {
+            metrics.record_inference(duration_ms as f64);
+        }
+
+        let stats = metrics.snapshot(2);
+        assert_eq!(stats.queue_depth, 2);
+        assert_eq!(stats.in_flight, 1);
+        assert_eq!(stats.inferences, 100);
+        assert_eq!(stats.inference_p50_ms, 50.0);
+        assert_eq!(stats.inference_p95_ms, 95.0);
+        assert_eq!(stats.total_queue_wait_ms, 5.0);
+        drop(single);
+        assert_eq!(metrics.snapshot(0).in_flight, 0);
+    }
+
+    #[tokio::test]
+    async fn test_retry_layout() {
+        let page = DynamicImage::ImageRgb8(RgbImage::from_pixel(612, 792, Rgb([255, 255, 255])));
+        let failure = || anyhow::anyhow!("inference failed");
+
+        let parser = ORTLayoutParser::new(ORTConfig {
+            execution_providers: vec![OrtExecutionProvider::CPU],
+            ..ORTConfig::default()
+        })
+        .expect("can't load layout model");
+        assert!(retry_layout(&parser, 0, &page, 1f32, failure())
+            .await
+            .is_ok());
+
+        let parser = ORTLayoutParser::new(ORTConfig {
+            execution_providers: vec![OrtExecutionProvider::CPU],
+            layout_retries: 0,
+            ..ORTConfig::default()
+        })
+        .expect("can't load layout model");
+        let err = retry_layout(&parser, 0, &page, 1f32, failure())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "inference failed");
+    }
+
+    async fn parse_pages(
+        config: ORTConfig,
+        page: Arc<DynamicImage>,
+        n_pages: usize,
+    ) -> (Duration, Vec<usize>) {
+        let parser = ORTLayoutParser::new(config).expect("can't load layout model");
+        let queue = ParseLayoutQueue::new(Arc::new(parser));
+
+        let start = Instant::now();
+        let mut responses = Vec::with_capacity(n_pages);
+        for page_id in 0..n_pages {
+            let (response_tx, response_rx) = oneshot::channel();
+            queue
+                .push(ParseLayoutRequest {
+                    page_id,
+                    page_image: page.clone(),
+                    downscale_factor: 1f32,
+                    metadata: Metadata {
+                        response_tx,
+                        queue_time: Instant::now(),
+                        timeout: None,
+                        cancellation: CancellationToken::new(),
+                    },
+                })
+                .await
+                .unwrap();
+            responses.push(response_rx);
+        }
+
+        let mut n_bboxes = Vec::with_capacity(n_pages);
+        for response_rx in responses {
+            let response = response_rx.await.unwrap().expect("layout parsing failed");
+            n_bboxes.push(response.layout_bbox.len());
+        }
+        (start.elapsed(), n_bboxes)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "runs layout inference on 50 pages"]
+    async fn test_batched_layout_throughput() {
+        const N_PAGES: usize = 50;
+        let mut img = RgbImage::from_pixel(1224, 1584, Rgb([255, 255, 255]));
+        for block in 0..12 {
+            let y0 = 100 + block * 110;
+            for y in y0..y0 + 60 {
+                for x in 100..1124 {
+                    if (x / 12 + y / 14) % 3 != 0 {
+                        img.put_pixel(x, y, Rgb([0, 0, 0]));
+                    }
+                }
+            }
+        }
+        let page = Arc::new(DynamicImage::ImageRgb8(img));
+
+        let (unbatched_duration, unbatched) = parse_pages(
+            ORTConfig {
+                layout_batch_size: 1,
+                ..ORTConfig::default()
+            },
+            page.clone(),
+            N_PAGES,
+        )
+        .await;
+        let (batched_duration, batched) = parse_pages(ORTConfig::default(), page, N_PAGES).await;
+
+        println!(
+            "unbatched: {:.1} pages/s, batched: {:.1} pages/s",
+            N_PAGES as f64 / unbatched_duration.as_secs_f64(),
+            N_PAGES as f64 / batched_duration.as_secs_f64()
+        );
+        assert_eq!(batched, unbatched);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "runs layout inference on 64 pages"]
+    async fn test_concurrent_requests_small_buffer_pool() {
+        const N_PAGES: usize = 64;
+        let page = Arc::new(DynamicImage::ImageRgb8(RgbImage::from_pixel(
+            612,
+            792,
+            Rgb([255, 255, 255]),
+        )));
+        let config = ORTConfig {
+            layout_batch_size: 1,
+            buffer_pool_size: 2,
+            ..ORTConfig::default()
+        };
+
+        let (_, n_bboxes) =
+            tokio::time::timeout(Duration::from_secs(300), parse_pages(config, page, N_PAGES))
+                .await
+                .expect("layout queue deadlocked");
+        assert_eq!(n_bboxes.len(), N_PAGES);
+    }
+}
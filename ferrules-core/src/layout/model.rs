@@ -1,16 +1,28 @@
 use anyhow::{bail, Context};
 use image::{imageops::FilterType, DynamicImage, GenericImageView};
 use lazy_static::lazy_static;
-use ndarray::{s, Array4, ArrayBase, Axis, Dim, OwnedRepr};
+use ndarray::{s, Array3, Array4, ArrayBase, ArrayView2, Axis, Dim, Ix3, OwnedRepr};
 use ort::{
     execution_providers::{
-        CPUExecutionProvider, CUDAExecutionProvider, CoreMLExecutionProvider,
-        TensorRTExecutionProvider,
+        CPUExecutionProvider, CUDAExecutionProvider, CoreMLExecutionProvider, ExecutionProvider,
+        ExecutionProviderDispatch, TensorRTExecutionProvider,
     },
-    session::{builder::GraphOptimizationLevel, Session},
+    session::{
+        builder::{GraphOptimizationLevel, SessionBuilder},
+        Session, SessionOutputs,
+    },
+    tensor::TensorElementType,
 };
 use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
-use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    path::PathBuf,
+    sync::Mutex,
+    time::Duration,
+};
+use tokio::sync::{OnceCell, Semaphore, SemaphorePermit};
 
 use crate::entities::BBox;
 
@@ -23,6 +35,114 @@ pub enum ORTGraphOptimizationLevel {
     Level3,
 }
 
+/// Numeric precision of the layout model weights
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Precision {
+    /// Full precision, like the bundled DocLayNet model
+    #[default]
+    Fp32,
+    /// Half precision model. Its inputs and outputs can be fp16 or, when exported keeping the
+    /// io types, fp32
+    Fp16,
+    /// Quantized model with fp32 inputs and outputs, e.g. from onnxruntime `quantize_dynamic`
+    Int8,
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Precision::Fp32 => write!(f, "fp32"),
+            Precision::Fp16 => write!(f, "fp16"),
+            Precision::Int8 => write!(f, "int8"),
+        }
+    }
+}
+
+impl std::str::FromStr for Precision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fp32" => Ok(Precision::Fp32),
+            "fp16" => Ok(Precision::Fp16),
+            "int8" => Ok(Precision::Int8),
+            _ => bail!("unknown layout model precision `{s}`, expected fp32, fp16 or int8"),
+        }
+    }
+}
+
+/// A thread count or batch size, either set or derived from the machine with `auto`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AutoCount {
+    #[default]
+    Auto,
+    Fixed(usize),
+}
+
+impl AutoCount {
+    /// The fixed count, or `auto` when it is derived from the machine
+    pub fn resolve(self, auto: usize) -> usize {
+        match self {
+            AutoCount::Auto => auto,
+            AutoCount::Fixed(count) => count,
+        }
+    }
+}
+
+impl fmt::Display for AutoCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AutoCount::Auto => write!(f, "auto"),
+            AutoCount::Fixed(count) => write!(f, "{count}"),
+        }
+    }
+}
+
+impl std::str::FromStr for AutoCount {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(AutoCount::Auto);
+        }
+        match s.parse::<usize>() {
+            Ok(0) => bail!("count must be at least 1"),
+            Ok(count) => Ok(AutoCount::Fixed(count)),
+            Err(_) => bail!("`{s}` is neither `auto` nor a number"),
+        }
+    }
+}
+
+/// ORT thread counts and layout batch size derived from the number of CPUs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoTune {
+    pub intra_threads: usize,
+    pub inter_threads: usize,
+    pub layout_batch_size: usize,
+}
+
+impl AutoTune {
+    /// Every CPU runs the operators, up to [`ORTLayoutParser::ORT_INTRATHREAD`]. A quarter of
+    /// them run the graph in parallel and half of them are batched together, within the
+    /// defaults.
+    pub fn for_cpus(cpus: usize) -> Self {
+        let cpus = cpus.max(1);
+        Self {
+            intra_threads: cpus.min(ORTLayoutParser::ORT_INTRATHREAD),
+            inter_threads: (cpus / 4).clamp(1, ORTLayoutParser::ORT_INTERTHREAD),
+            layout_batch_size: (cpus / 2).clamp(1, ORTLayoutParser::DEFAULT_BATCH_SIZE),
+        }
+    }
+
+    /// Tunes for the parallelism available to the process
+    pub fn detect() -> Self {
+        let cpus = std::thread::available_parallelism()
+            .map(|cpus| cpus.get())
+            .unwrap_or(1);
+        Self::for_cpus(cpus)
+    }
+}
+
 impl TryFrom<usize> for ORTGraphOptimizationLevel {
     type Error = anyhow::Error;
 
@@ -45,17 +165,96 @@ pub struct ORTConfig {
     pub warmup: bool,
     pub profile_layout: Option<std::path::PathBuf>,
     pub profile_table: Option<std::path::PathBuf>,
+    /// Maximum number of queued pages run in a single layout session call.
+    /// Only used when the layout model has a dynamic batch dimension.
+    pub layout_batch_size: usize,
+    /// How long the layout queue waits for a batch to fill up once several pages are pending.
+    pub layout_batch_max_wait: Duration,
+    /// Layout detections with a lower confidence are discarded. Must be in [0, 1].
+    pub conf_threshold: f32,
+    /// Overlapping layout detections with an IoU above this threshold are suppressed.
+    /// Must be in [0, 1].
+    pub nms_iou: f32,
+    /// ONNX layout detector loaded instead of the bundled DocLayNet model. It must output
+    /// YOLO-style predictions of shape `[batch, 4 + labels, anchors]`.
+    pub layout_model_path: Option<PathBuf>,
+    /// Labels of the layout model classes, in output order. Defaults to the DocLayNet labels.
+    pub layout_labels: Option<Vec<String>>,
+    /// Input `(width, height)` of the layout model. Required to differ from the default
+    /// 1024x1024 when the model was exported with dynamic spatial dimensions.
+    pub layout_input_size: Option<(u32, u32)>,
+    /// Number of single page input tensors preallocated for layout inference. Pages wait for
+    /// a free tensor when all of them are in use. Defaults to `inter_threads`.
+    pub buffer_pool_size: usize,
+    /// Precision of the layout model. Quantized models aren't bundled and are loaded from
+    /// `layout_model_path`.
+    pub model_precision: Precision,
+    /// Number of times the layout inference of a page is retried after a failure. When the
+    /// session runs on an accelerator, retries run on a CPU session built on the first failure.
+    pub layout_retries: usize,
 }
 
 impl ORTConfig {
+    /// Checks that the layout postprocessing thresholds are valid probabilities.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.intra_threads == 0 || self.inter_threads == 0 {
+            bail!("ORT intra and inter thread counts must be at least 1");
+        }
+        if self.layout_batch_size == 0 {
+            bail!("layout batch size must be at least 1");
+        }
+        if !(0f32..=1f32).contains(&self.conf_threshold) {
+            bail!(
+                "layout confidence threshold must be in [0, 1], got {}",
+                self.conf_threshold
+            );
+        }
+        if !(0f32..=1f32).contains(&self.nms_iou) {
+            bail!("layout NMS IoU must be in [0, 1], got {}", self.nms_iou);
+        }
+        if self.layout_labels.as_ref().is_some_and(|l| l.is_empty()) {
+            bail!("layout label map can't be empty");
+        }
+        if self.buffer_pool_size == 0 {
+            bail!("layout buffer pool size must be at least 1");
+        }
+        if self.model_precision != Precision::Fp32 && self.layout_model_path.is_none() {
+            bail!(
+                "the bundled layout model is fp32, a {} layout model must be loaded from a path",
+                self.model_precision
+            );
+        }
+        Ok(())
+    }
+
+    /// Settings of the layout model changing its detections, part of the page cache key. A
+    /// custom model file is identified by its path, size and modification time.
+    pub(crate) fn layout_fingerprint(&self) -> String {
+        let model_file = self.layout_model_path.as_ref().map(|path| {
+            let metadata = std::fs::metadata(path).ok();
+            let size = metadata.as_ref().map(|metadata| metadata.len());
+            let modified = metadata.and_then(|metadata| metadata.modified().ok());
+            (path, size, modified)
+        });
+        format!(
+            "{model_file:?}|{:?}|{}|{}|{}|{:?}",
+            self.layout_input_size,
+            self.model_precision,
+            self.conf_threshold,
+            self.nms_iou,
+            self.layout_labels
+        )
+    }
+
     /// Returns a new vector of execution providers sorted by priority (accelerators first).
     pub fn get_sorted_providers(&self) -> Vec<OrtExecutionProvider> {
         let mut providers = self.execution_providers.clone();
         providers.sort_by(|a, b| {
             let priority = |p: &OrtExecutionProvider| -> u8 {
                 match p {
-                    OrtExecutionProvider::Trt(_) => 4,
-                    OrtExecutionProvider::CUDA(_) => 3,
+                    OrtExecutionProvider::Trt(..) => 5,
+                    OrtExecutionProvider::CUDA(..) => 4,
+                    OrtExecutionProvider::DirectML(_) => 3,
                     OrtExecutionProvider::CoreML { .. } => 2,
                     OrtExecutionProvider::CPU => 1,
                 }
@@ -65,14 +264,169 @@ impl ORTConfig {
         });
         providers
     }
+
+    /// Removes the execution providers the ONNX Runtime library wasn't built with, which would
+    /// fail to register when the session is created. The session runs on the remaining ones,
+    /// CPU being always available. Returns the removed providers.
+    pub fn retain_available_providers(&mut self) -> Vec<OrtExecutionProvider> {
+        let (available, unavailable) = self
+            .execution_providers
+            .drain(..)
+            .partition(OrtExecutionProvider::is_available);
+        self.execution_providers = available;
+        if self.execution_providers.is_empty() {
+            self.execution_providers.push(OrtExecutionProvider::CPU);
+        }
+        unavailable
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum OrtExecutionProvider {
     CPU,
-    CUDA(i32),
-    Trt(i32),
-    CoreML { ane_only: bool },
+    CUDA(i32, CudaOptions),
+    Trt(i32, TrtOptions),
+    CoreML {
+        ane_only: bool,
+    },
+    /// DirectML device id, Windows only. Requires the `directml` feature
+    DirectML(i32),
+}
+
+/// Options of the CUDA execution provider
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CudaOptions {
+    /// Maximum size in bytes of the CUDA memory arena
+    pub memory_limit: Option<usize>,
+}
+
+/// Options of the TensorRT execution provider
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TrtOptions {
+    /// Directory where the built engines are cached. Later sessions load them from there
+    /// instead of building them again, which takes minutes.
+    pub engine_cache_dir: Option<PathBuf>,
+    /// Maximum GPU memory in bytes TensorRT can use when building an engine
+    pub max_workspace_size: Option<usize>,
+    /// Builds fp16 engines on GPUs supporting it
+    pub fp16: bool,
+}
+
+impl fmt::Display for OrtExecutionProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrtExecutionProvider::CPU => write!(f, "cpu"),
+            OrtExecutionProvider::CUDA(device_id, _) => write!(f, "cuda:{device_id}"),
+            OrtExecutionProvider::Trt(device_id, _) => write!(f, "trt:{device_id}"),
+            OrtExecutionProvider::CoreML { ane_only: false } => write!(f, "coreml"),
+            OrtExecutionProvider::CoreML { ane_only: true } => write!(f, "coreml-ane"),
+            OrtExecutionProvider::DirectML(device_id) => write!(f, "directml:{device_id}"),
+        }
+    }
+}
+
+impl OrtExecutionProvider {
+    /// Whether the loaded ONNX Runtime library was built with this provider. It doesn't check
+    /// that a device is present, the registration can still fail when the session is created.
+    pub fn is_available(&self) -> bool {
+        let available = match self {
+            OrtExecutionProvider::CPU => return true,
+            OrtExecutionProvider::CUDA(..) => CUDAExecutionProvider::default().is_available(),
+            OrtExecutionProvider::Trt(..) => TensorRTExecutionProvider::default().is_available(),
+            OrtExecutionProvider::CoreML { .. } => {
+                let provider = CoreMLExecutionProvider::default();
+                if !provider.supported_by_platform() {
+                    return false;
+                }
+                provider.is_available()
+            }
+            OrtExecutionProvider::DirectML(_) => return directml_available(),
+        };
+        available.unwrap_or_else(|e| {
+            tracing::warn!("can't list the ONNX Runtime execution providers: {e}");
+            false
+        })
+    }
+
+    /// How to get an ONNX Runtime library supporting this provider
+    pub fn install_hint(&self) -> &'static str {
+        match self {
+            OrtExecutionProvider::CPU => "CPU is supported by every ONNX Runtime build",
+            OrtExecutionProvider::CUDA(..) => {
+                "Install the CUDA and cuDNN libraries and point ORT_DYLIB_PATH to an onnxruntime-gpu build"
+            }
+            OrtExecutionProvider::Trt(..) => {
+                "Install TensorRT, CUDA and cuDNN and point ORT_DYLIB_PATH to an onnxruntime-gpu build"
+            }
+            OrtExecutionProvider::CoreML { .. } => "CoreML is only available on macOS",
+            OrtExecutionProvider::DirectML(_) => {
+                "Build ferrules with `--features directml` on Windows"
+            }
+        }
+    }
+}
+
+/// Execution provider the layout session actually runs on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutProvider {
+    /// First provider, in priority order, that registered successfully. CPU if none did.
+    pub effective: OrtExecutionProvider,
+    /// Highest priority provider of the config
+    pub requested: Option<OrtExecutionProvider>,
+}
+
+impl LayoutProvider {
+    /// Whether the session fell back to a lower priority provider than requested
+    pub fn is_fallback(&self) -> bool {
+        self.requested
+            .as_ref()
+            .is_some_and(|requested| requested != &self.effective)
+    }
+}
+
+impl fmt::Display for LayoutProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.requested {
+            Some(requested) if self.is_fallback() => {
+                write!(f, "{} (requested: {requested})", self.effective)
+            }
+            _ => write!(f, "{}", self.effective),
+        }
+    }
+}
+
+/// Whether the DirectML provider is compiled in and supported by the ONNX Runtime library
+fn directml_available() -> bool {
+    #[cfg(feature = "directml")]
+    {
+        ort::execution_providers::DirectMLExecutionProvider::default()
+            .is_available()
+            .unwrap_or(false)
+    }
+    #[cfg(not(feature = "directml"))]
+    {
+        false
+    }
+}
+
+/// DirectML provider for `device_id`. Without the `directml` feature it is skipped, the
+/// session falling back to the next providers of the chain.
+fn directml_provider(device_id: i32) -> Option<ExecutionProviderDispatch> {
+    #[cfg(feature = "directml")]
+    {
+        Some(
+            ort::execution_providers::DirectMLExecutionProvider::default()
+                .with_device_id(device_id)
+                .build(),
+        )
+    }
+    #[cfg(not(feature = "directml"))]
+    {
+        tracing::warn!(
+            "DirectML device {device_id} requested but ferrules was built without the `directml` feature, falling back to CPU"
+        );
+        None
+    }
 }
 
 impl Default for ORTConfig {
@@ -89,6 +443,16 @@ impl Default for ORTConfig {
             warmup: false,
             profile_layout: None,
             profile_table: None,
+            layout_batch_size: ORTLayoutParser::DEFAULT_BATCH_SIZE,
+            layout_batch_max_wait: ORTLayoutParser::DEFAULT_BATCH_MAX_WAIT,
+            conf_threshold: ORTLayoutParser::CONF_THRESHOLD,
+            nms_iou: ORTLayoutParser::IOU_THRESHOLD,
+            layout_model_path: None,
+            layout_labels: None,
+            layout_input_size: None,
+            buffer_pool_size: ORTLayoutParser::ORT_INTERTHREAD,
+            model_precision: Precision::Fp32,
+            layout_retries: ORTLayoutParser::DEFAULT_LAYOUT_RETRIES,
         }
     }
 }
@@ -109,7 +473,10 @@ lazy_static! {
     ];
 }
 
-#[derive(Debug, Default, Clone, Archive, RkyvDeserialize, RkyvSerialize)]
+#[derive(
+    Debug, Default, Clone, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[archive(check_bytes)]
 pub struct LayoutBBox {
     pub id: i32,
@@ -124,6 +491,7 @@ impl LayoutBBox {
             || self.label == "Caption"
             || self.label == "Footnote"
             || self.label == "Formula"
+            || self.label == "Code"
             || self.label == "List-item"
             || self.label == "Page-footer"
             || self.label == "Page-header"
@@ -137,7 +505,90 @@ pub struct ORTLayoutParser {
     session: Session,
     output_name: String,
     pub config: ORTConfig,
-    buffer_pool: Mutex<Vec<Array4<f32>>>,
+    labels: Vec<String>,
+    input_width: u32,
+    input_height: u32,
+    max_batch_size: usize,
+    provider: LayoutProvider,
+    /// CPU session the failed pages are retried on, built on the first retry
+    cpu_session: OnceCell<Session>,
+    buffer_pool: InputBufferPool,
+    /// The model takes fp16 inputs, the fp32 input tensors are converted
+    fp16_input: bool,
+    /// The model returns fp16 predictions, converted to fp32 before postprocessing
+    fp16_output: bool,
+}
+
+/// Preallocated single page input tensors. Acquiring a tensor waits asynchronously until one
+/// is free, so the pool never allocates more than its size.
+#[derive(Debug)]
+struct InputBufferPool {
+    buffers: Mutex<Vec<Array4<f32>>>,
+    available: Semaphore,
+}
+
+impl InputBufferPool {
+    fn new(size: usize, shape: [usize; 4]) -> Self {
+        let buffers = (0..size).map(|_| Array4::zeros(shape)).collect();
+        Self {
+            buffers: Mutex::new(buffers),
+            available: Semaphore::new(size),
+        }
+    }
+
+    async fn acquire(&self) -> PooledBuffer<'_> {
+        let permit = self
+            .available
+            .acquire()
+            .await
+            .expect("buffer pool semaphore is never closed");
+        let buffer = self
+            .buffers
+            .lock()
+            .expect("buffer pool lock poisoned")
+            .pop()
+            .expect("a permit always has a free buffer");
+        PooledBuffer {
+            pool: self,
+            buffer: Some(buffer),
+            _permit: permit,
+        }
+    }
+}
+
+/// Input tensor borrowed from the pool, returned to it on drop.
+struct PooledBuffer<'a> {
+    pool: &'a InputBufferPool,
+    buffer: Option<Array4<f32>>,
+    // Released after `drop` put the buffer back
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = Array4<f32>;
+
+    fn deref(&self) -> &Self::Target {
+        self.buffer.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.buffer.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            // Buffers are still returned when an inference panicked
+            let mut buffers = match self.pool.buffers.lock() {
+                Ok(buffers) => buffers,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            buffers.push(buffer);
+        }
+    }
 }
 
 impl ORTLayoutParser {
@@ -146,35 +597,129 @@ impl ORTLayoutParser {
         &self,
         page_img: &DynamicImage,
         bbox_rescale_factor: f32,
+    ) -> anyhow::Result<Vec<LayoutBBox>> {
+        self.parse_layout_session_async(&self.session, page_img, bbox_rescale_factor)
+            .await
+    }
+
+    /// Runs the layout model on the CPU, used to retry the pages whose inference failed on an
+    /// accelerator. Runs on the main session when it is already on the CPU.
+    #[tracing::instrument(skip_all)]
+    pub async fn parse_layout_cpu_async(
+        &self,
+        page_img: &DynamicImage,
+        bbox_rescale_factor: f32,
+    ) -> anyhow::Result<Vec<LayoutBBox>> {
+        let session = self.cpu_session().await?;
+        self.parse_layout_session_async(session, page_img, bbox_rescale_factor)
+            .await
+    }
+
+    async fn parse_layout_session_async(
+        &self,
+        session: &Session,
+        page_img: &DynamicImage,
+        bbox_rescale_factor: f32,
     ) -> anyhow::Result<Vec<LayoutBBox>> {
         let (img_width, img_height) = (page_img.width(), page_img.height());
-        let mut input = self.acquire_buffer();
+        let mut input = self.buffer_pool.acquire().await;
         self.preprocess_into(page_img, &mut input);
-        let output_tensor = self.run_async(&input).await?;
-        self.release_buffer(input);
-        let mut bboxes =
-            self.extract_bboxes(output_tensor, img_width, img_height, bbox_rescale_factor);
-        nms(&mut bboxes, Self::IOU_THRESHOLD);
-        Ok(bboxes)
+        let output_tensor = self.run_session_async(session, &input).await?;
+        drop(input);
+        Ok(self.postprocess(
+            output_tensor.slice(s![0, .., ..]),
+            img_width,
+            img_height,
+            bbox_rescale_factor,
+        ))
+    }
+
+    /// Runs the layout model on several pages in a single session call.
+    /// Each page comes with its own bbox rescale factor, results are returned in the same order.
+    #[tracing::instrument(skip_all, fields(batch_size = pages.len()))]
+    pub async fn parse_layout_batch_async(
+        &self,
+        pages: &[(&DynamicImage, f32)],
+    ) -> anyhow::Result<Vec<Vec<LayoutBBox>>> {
+        let mut input = Array4::zeros([
+            pages.len(),
+            3,
+            self.input_height as usize,
+            self.input_width as usize,
+        ]);
+        for (idx, (page_img, _)) in pages.iter().enumerate() {
+            self.preprocess_into_slot(page_img, &mut input, idx);
+        }
+        let output_tensor = self.run_batch_async(input).await?;
+
+        let layouts = pages
+            .iter()
+            .enumerate()
+            .map(|(idx, (page_img, bbox_rescale_factor))| {
+                self.postprocess(
+                    output_tensor.slice(s![idx, .., ..]),
+                    page_img.width(),
+                    page_img.height(),
+                    *bbox_rescale_factor,
+                )
+            })
+            .collect();
+        Ok(layouts)
+    }
+
+    /// Labels of the layout model classes, in output order
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Input `(width, height)` of the layout model, pages are rasterized to fit in it.
+    pub fn input_size(&self) -> (u32, u32) {
+        (self.input_width, self.input_height)
+    }
+
+    /// Execution provider the session runs on
+    pub fn provider(&self) -> &LayoutProvider {
+        &self.provider
+    }
+
+    async fn cpu_session(&self) -> anyhow::Result<&Session> {
+        if self.provider.effective == OrtExecutionProvider::CPU {
+            return Ok(&self.session);
+        }
+        self.cpu_session
+            .get_or_try_init(|| async {
+                tracing::info!("building a cpu layout session to retry the failed pages");
+                commit_session(&self.config, Session::builder()?)
+            })
+            .await
+    }
+
+    /// Maximum number of pages that can be run in a single session call.
+    /// Always 1 if the model was exported with a fixed batch dimension.
+    pub fn max_batch_size(&self) -> usize {
+        self.max_batch_size
     }
 
     pub async fn run_async(
         &self,
         input: &Array4<f32>,
     ) -> anyhow::Result<ArrayBase<OwnedRepr<f32>, Dim<[usize; 3]>>> {
-        let outputs = &self.session.run_async(ort::inputs![input.view()]?)?.await?;
-
-        let output_tensor = outputs
-            .get(&self.output_name)
-            .context("can't get the value of first output")?
-            .try_extract_tensor::<f32>()?;
+        self.run_session_async(&self.session, input).await
+    }
 
-        let output_tensor = output_tensor
-            .to_shape(Self::OUTPUT_SIZE)
-            .unwrap()
-            .to_owned();
+    async fn run_session_async(
+        &self,
+        session: &Session,
+        input: &Array4<f32>,
+    ) -> anyhow::Result<Array3<f32>> {
+        let outputs = if self.fp16_input {
+            let input_f16 = input.mapv(half::f16::from_f32);
+            session.run_async(ort::inputs![input_f16]?)?.await?
+        } else {
+            session.run_async(ort::inputs![input.view()]?)?.await?
+        };
 
-        Ok(output_tensor)
+        self.extract_output(&outputs)
     }
 
     #[tracing::instrument(skip_all)]
@@ -182,101 +727,61 @@ impl ORTLayoutParser {
         &self,
         input: Array4<f32>,
     ) -> anyhow::Result<ndarray::Array3<f32>> {
-        let batch_size = input.dim().0;
-        let outputs = &self.session.run_async(ort::inputs![input]?)?.await?;
-
-        let output_tensor = outputs
-            .get(&self.output_name)
-            .context("can't get the value of first output")?
-            .try_extract_tensor::<f32>()?;
-
-        // Adjust output shape to [batch_size, classes + bbox, candidate_boxes]
-        let output_tensor = output_tensor
-            .to_shape([batch_size, 15, 21504])
-            .unwrap()
-            .to_owned();
+        let outputs = if self.fp16_input {
+            let input_f16 = input.mapv(half::f16::from_f32);
+            self.session.run_async(ort::inputs![input_f16]?)?.await?
+        } else {
+            self.session.run_async(ort::inputs![input]?)?.await?
+        };
 
-        Ok(output_tensor)
+        // Output shape is [batch_size, classes + bbox, candidate_boxes]
+        self.extract_output(&outputs)
     }
 }
 
 impl ORTLayoutParser {
-    /// Required width of the input image for layout parsing.
+    /// Required width of the input image of the bundled layout model.
     pub const REQUIRED_WIDTH: u32 = 1024;
-    /// Required height of the input image for layout parsing.
+    /// Required height of the input image of the bundled layout model.
     pub const REQUIRED_HEIGHT: u32 = 1024;
 
-    // Output size of the tensor from the ONNX model.
-    // It has dimensions [batch_size = 1, classes + bbox = 15, candidate_boxes = 21504].
-    const OUTPUT_SIZE: [usize; 3] = [1, 15, 21504];
-
-    /// Confidence threshold for filtering out low probability bounding boxes.
+    /// Default confidence threshold for filtering out low probability bounding boxes.
     /// Bounding boxes with probability below this threshold will be ignored.
     pub const CONF_THRESHOLD: f32 = 0.1;
 
-    /// Intersection over Union (IOU) threshold for non-maximum suppression (NMS) algorithm.
+    /// Default Intersection over Union (IOU) threshold for non-maximum suppression (NMS) algorithm.
     /// It determines the overlap between bounding boxes before suppression.
     pub const IOU_THRESHOLD: f32 = 0.7;
 
     pub const ORT_INTRATHREAD: usize = 16;
     pub const ORT_INTERTHREAD: usize = 4;
 
+    pub const DEFAULT_BATCH_SIZE: usize = 8;
+    pub const DEFAULT_BATCH_MAX_WAIT: Duration = Duration::from_millis(2);
+
+    pub const DEFAULT_LAYOUT_RETRIES: usize = 1;
+
     pub fn new(config: ORTConfig) -> anyhow::Result<Self> {
-        let mut execution_providers = Vec::new();
+        config.validate()?;
 
         // Get providers sorted by priority: accelerators first
         let providers = config.get_sorted_providers();
-
-        // Providers
-        for provider in providers {
-            match provider {
-                OrtExecutionProvider::Trt(device_id) => {
-                    execution_providers.push(
-                        TensorRTExecutionProvider::default()
-                            .with_device_id(device_id)
-                            .build(),
-                    );
-                }
-                OrtExecutionProvider::CUDA(device_id) => {
-                    execution_providers.push(
-                        CUDAExecutionProvider::default()
-                            .with_device_id(device_id)
-                            .build(),
-                    );
-                }
-                OrtExecutionProvider::CoreML { ane_only } => {
-                    let provider = CoreMLExecutionProvider::default();
-                    let provider = if ane_only {
-                        provider.with_ane_only().build()
-                    } else {
-                        provider.build()
-                    };
-                    execution_providers.push(provider)
-                }
-                OrtExecutionProvider::CPU => {
-                    execution_providers.push(CPUExecutionProvider::default().build());
-                }
-            }
-        }
-
-        let opt_lvl = match config.opt_level {
-            Some(ORTGraphOptimizationLevel::Level1) => GraphOptimizationLevel::Level1,
-            Some(ORTGraphOptimizationLevel::Level2) => GraphOptimizationLevel::Level2,
-            Some(ORTGraphOptimizationLevel::Level3) => GraphOptimizationLevel::Level3,
-            None => GraphOptimizationLevel::Disable,
+        let (builder, effective) = register_providers(Session::builder()?, &providers);
+        let provider = LayoutProvider {
+            effective,
+            requested: providers.first().cloned(),
         };
-
-        let mut builder = Session::builder()?
-            .with_execution_providers(execution_providers)?
-            .with_optimization_level(opt_lvl)?
-            .with_intra_threads(config.intra_threads)?
-            .with_inter_threads(config.inter_threads)?;
-
-        if let Some(profile_path) = &config.profile_layout {
-            builder = builder.with_profiling(profile_path)?;
+        if provider.is_fallback() {
+            tracing::warn!("layout model running on fallback execution provider {provider}");
+        } else {
+            tracing::info!("layout model running on execution provider {provider}");
         }
 
-        let session = builder.commit_from_memory(LAYOUT_MODEL_BYTES)?;
+        let builder = match &config.profile_layout {
+            Some(profile_path) => builder.with_profiling(profile_path)?,
+            None => builder,
+        };
+        let session = commit_session(&config, builder)?;
 
         let output_name = session
             .outputs
@@ -285,12 +790,66 @@ impl ORTLayoutParser {
             .context("can't find name output input")?
             .to_owned();
 
+        let labels: Vec<String> = match &config.layout_labels {
+            Some(labels) => labels.clone(),
+            None => ID2LABEL.iter().map(|l| l.to_string()).collect(),
+        };
+        let input_dims = session
+            .inputs
+            .first()
+            .and_then(|i| i.input_type.tensor_dimensions())
+            .context("layout model has no tensor input")?;
+        let output_dims = session
+            .outputs
+            .first()
+            .and_then(|o| o.output_type.tensor_dimensions())
+            .context("layout model has no tensor output")?;
+        let (fp16_input, fp16_output) = check_model_precision(
+            config.model_precision,
+            session
+                .inputs
+                .first()
+                .and_then(|i| i.input_type.tensor_type()),
+            session
+                .outputs
+                .first()
+                .and_then(|o| o.output_type.tensor_type()),
+        )
+        .context("incompatible layout model")?;
+        let (input_width, input_height) = check_layout_signature(
+            input_dims,
+            output_dims,
+            labels.len(),
+            config.layout_input_size,
+        )
+        .context("incompatible layout model")?;
+
+        // Batching requires a dynamic batch dimension, exported as -1
+        let dynamic_batch = input_dims.first().is_some_and(|&dim| dim < 1);
+        let max_batch_size = if dynamic_batch {
+            config.layout_batch_size.max(1)
+        } else {
+            1
+        };
+
+        let buffer_pool = InputBufferPool::new(
+            config.buffer_pool_size,
+            [1, 3, input_height as usize, input_width as usize],
+        );
+
         let parser = Self {
             session,
             output_name,
             config,
-            // TODO: use ticket mutex instead of buffer pool to access resources
-            buffer_pool: Mutex::new(Vec::with_capacity(32)),
+            labels,
+            input_width,
+            input_height,
+            max_batch_size,
+            provider,
+            cpu_session: OnceCell::new(),
+            buffer_pool,
+            fp16_input,
+            fp16_output,
         };
 
         if parser.config.warmup {
@@ -302,12 +861,7 @@ impl ORTLayoutParser {
 
     #[tracing::instrument(skip(self))]
     fn warmup(&self) -> anyhow::Result<()> {
-        let input = Array4::zeros([
-            1,
-            3,
-            Self::REQUIRED_HEIGHT as usize,
-            Self::REQUIRED_WIDTH as usize,
-        ]);
+        let input = Array4::zeros([1, 3, self.input_height as usize, self.input_width as usize]);
         // We use the sync run method for warmup during initialization
         let _ = self.run(&input)?;
         tracing::info!("Layout model warmup complete");
@@ -318,37 +872,44 @@ impl ORTLayoutParser {
         &self,
         input: &Array4<f32>,
     ) -> anyhow::Result<ArrayBase<OwnedRepr<f32>, Dim<[usize; 3]>>> {
-        let outputs = &self.session.run(ort::inputs![input.view()]?)?;
-
-        let output_tensor = outputs
-            .get(&self.output_name)
-            .context("can't get the value of first output")?
-            .try_extract_tensor::<f32>()?;
-
-        let output_tensor = output_tensor
-            .to_shape(Self::OUTPUT_SIZE)
-            .unwrap()
-            .to_owned();
+        let outputs = if self.fp16_input {
+            let input_f16 = input.mapv(half::f16::from_f32);
+            self.session.run(ort::inputs![input_f16]?)?
+        } else {
+            self.session.run(ort::inputs![input.view()]?)?
+        };
 
-        Ok(output_tensor)
+        self.extract_output(&outputs)
     }
 
     #[tracing::instrument(skip_all)]
     pub fn run_batch(&self, input: Array4<f32>) -> anyhow::Result<ndarray::Array3<f32>> {
-        let batch_size = input.dim().0;
-        let outputs = &self.session.run(ort::inputs![input]?)?;
+        let outputs = if self.fp16_input {
+            let input_f16 = input.mapv(half::f16::from_f32);
+            self.session.run(ort::inputs![input_f16]?)?
+        } else {
+            self.session.run(ort::inputs![input]?)?
+        };
 
-        let output_tensor = outputs
-            .get(&self.output_name)
-            .context("can't get the value of first output")?
-            .try_extract_tensor::<f32>()?;
+        self.extract_output(&outputs)
+    }
 
-        let output_tensor = output_tensor
-            .to_shape([batch_size, 15, 21504])
-            .unwrap()
-            .to_owned();
+    /// Extracts the `[batch, 4 + labels, anchors]` predictions as fp32, whatever the model
+    /// output type, so quantized models share the fp32 postprocessing.
+    fn extract_output(&self, outputs: &SessionOutputs<'_, '_>) -> anyhow::Result<Array3<f32>> {
+        let output = outputs
+            .get(&self.output_name)
+            .context("can't get the value of first output")?;
+
+        let output_tensor = if self.fp16_output {
+            output
+                .try_extract_tensor::<half::f16>()?
+                .mapv(|x| x.to_f32())
+        } else {
+            output.try_extract_tensor::<f32>()?.to_owned()
+        };
 
-        Ok(output_tensor)
+        Ok(output_tensor.into_dimensionality::<Ix3>()?)
     }
 
     pub fn parse_layout(
@@ -357,128 +918,57 @@ impl ORTLayoutParser {
         bbox_rescale_factor: f32,
     ) -> anyhow::Result<Vec<LayoutBBox>> {
         let (img_width, img_height) = (page_img.width(), page_img.height());
-        let mut input = self.acquire_buffer();
-        self.preprocess_into(page_img, &mut input);
+        let input = self.preprocess(page_img);
         let output_tensor = self.run(&input)?;
-        self.release_buffer(input);
-        let mut bboxes =
-            self.extract_bboxes(output_tensor, img_width, img_height, bbox_rescale_factor);
-        nms(&mut bboxes, Self::IOU_THRESHOLD);
 
-        Ok(bboxes)
+        Ok(self.postprocess(
+            output_tensor.slice(s![0, .., ..]),
+            img_width,
+            img_height,
+            bbox_rescale_factor,
+        ))
     }
 
-    #[tracing::instrument(skip_all)]
-    fn extract_bboxes(
+    /// Filters the raw detections of a page with the configured confidence and NMS thresholds.
+    fn postprocess(
         &self,
-        output: ArrayBase<OwnedRepr<f32>, Dim<[usize; 3]>>,
+        output: ArrayView2<f32>,
         original_width: u32,
         original_height: u32,
         rescale_factor: f32,
     ) -> Vec<LayoutBBox> {
-        // Tensor shape: (bs, bbox(4) + classes(15), anchors )
-        let mut result = Vec::new();
-        let output = output.slice(s![0, .., ..]);
-        let mut bbox_id = 0;
-        for prediction in output.axis_iter(Axis(1)) {
-            // Prediction dim: (15,) -> (4 bbox, 11 labels)
-            const CXYWH_OFFSET: usize = 4;
-            let bbox = prediction.slice(s![0..CXYWH_OFFSET]);
-            let classes = prediction.slice(s![CXYWH_OFFSET..CXYWH_OFFSET + ID2LABEL.len()]);
-            let (max_prob_idx, &proba) = classes
-                .iter()
-                .enumerate()
-                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-                .unwrap();
-
-            if proba.is_nan() {
-                tracing::warn!(
-                    "Found NaN probability for label {} at idx {}",
-                    ID2LABEL[max_prob_idx],
-                    bbox_id
-                );
-                continue;
-            }
-
-            if proba < Self::CONF_THRESHOLD {
-                continue;
-            }
-            let label = ID2LABEL[max_prob_idx];
-            let ratio = (Self::REQUIRED_WIDTH as f32 / original_width as f32)
-                .min(Self::REQUIRED_HEIGHT as f32 / original_height as f32);
-            let xc = bbox[0_usize] / ratio;
-            let yc = bbox[1_usize] / ratio;
-            let w = bbox[2_usize] / ratio;
-            let h = bbox[3_usize] / ratio;
-            // Change to (upper-left, lower-right)
-            let x0 = (xc - (w / 2.0)).min(original_width as f32).max(0f32);
-            let y0 = (yc - (h / 2.0)).min(original_height as f32).max(0f32);
-            let x1 = (xc + (w / 2.0)).max(0f32).min(original_width as f32);
-            let y1 = (yc + (h / 2.0)).max(0f32).min(original_height as f32);
-
-            debug_assert!(x0 <= x1 && x1 <= original_width as f32);
-            debug_assert!(y0 <= y1 && y1 <= original_height as f32);
-
-            if x0 > x1 || y0 > y1 {
-                dbg!("bbox error: ({x0},{y1}), ({x1},{y1})");
-                continue;
-            }
-
-            result.push(LayoutBBox {
-                id: bbox_id,
-                bbox: BBox {
-                    x0: x0 * rescale_factor,
-                    y0: y0 * rescale_factor,
-                    x1: x1 * rescale_factor,
-                    y1: y1 * rescale_factor,
-                },
-                proba,
-                label: label.to_string(),
-            });
-            bbox_id += 1;
-        }
-
-        result
+        let mut bboxes = extract_bboxes(
+            output,
+            (original_width, original_height),
+            rescale_factor,
+            self.config.conf_threshold,
+            &self.labels,
+            (self.input_width, self.input_height),
+        );
+        nms(&mut bboxes, self.config.nms_iou);
+        bboxes
     }
+
     fn scale_wh(&self, w0: f32, h0: f32, w1: f32, h1: f32) -> (f32, f32, f32) {
         let r = (w1 / w0).min(h1 / h0);
         (r, (w0 * r).round(), (h0 * r).round())
     }
 
-    fn acquire_buffer(&self) -> Array4<f32> {
-        let mut pool = self.buffer_pool.lock().expect("buffer pool lock poisoned");
-        pool.pop().unwrap_or_else(|| {
-            Array4::ones([
-                1,
-                3,
-                Self::REQUIRED_HEIGHT as usize,
-                Self::REQUIRED_WIDTH as usize,
-            ])
-        })
-    }
-
-    fn release_buffer(&self, buffer: Array4<f32>) {
-        let mut pool = self.buffer_pool.lock().expect("buffer pool lock poisoned");
-        if pool.len() < 32 {
-            pool.push(buffer);
-        }
-    }
-
     #[tracing::instrument(skip_all)]
     pub fn preprocess_batch(&self, batch_imgs: &[DynamicImage]) -> Array4<f32> {
         let (w0, h0) = batch_imgs.first().unwrap().dimensions();
         let (_, w_new, h_new) = self.scale_wh(
             w0 as f32,
             h0 as f32,
-            Self::REQUIRED_WIDTH as f32,
-            Self::REQUIRED_HEIGHT as f32,
+            self.input_width as f32,
+            self.input_height as f32,
         ); // f32 round
 
         let mut input_tensor = Array4::ones([
             batch_imgs.len(),
             3,
-            Self::REQUIRED_HEIGHT as usize,
-            Self::REQUIRED_WIDTH as usize,
+            self.input_height as usize,
+            self.input_width as usize,
         ]);
 
         input_tensor.fill(144.0 / 255.0);
@@ -501,34 +991,284 @@ impl ORTLayoutParser {
 
     #[tracing::instrument(skip_all)]
     pub fn preprocess(&self, img: &DynamicImage) -> Array4<f32> {
-        let mut input_tensor = self.acquire_buffer();
+        // Pooled buffers are acquired asynchronously, synchronous callers allocate their own
+        let mut input_tensor =
+            Array4::zeros([1, 3, self.input_height as usize, self.input_width as usize]);
         self.preprocess_into(img, &mut input_tensor);
         input_tensor
     }
 
     #[tracing::instrument(skip_all)]
     pub fn preprocess_into(&self, img: &DynamicImage, input_tensor: &mut Array4<f32>) {
+        self.preprocess_into_slot(img, input_tensor, 0);
+    }
+
+    /// Preprocesses `img` into the `idx` entry of the batched `input_tensor`.
+    fn preprocess_into_slot(&self, img: &DynamicImage, input_tensor: &mut Array4<f32>, idx: usize) {
         let (w0, h0) = img.dimensions();
         let (_, w_new, h_new) = self.scale_wh(
             w0 as f32,
             h0 as f32,
-            Self::REQUIRED_WIDTH as f32,
-            Self::REQUIRED_HEIGHT as f32,
+            self.input_width as f32,
+            self.input_height as f32,
         );
         let resized_img = img.resize_exact(w_new as u32, h_new as u32, FilterType::Triangle);
 
-        input_tensor.fill(144.0 / 255.0);
+        input_tensor
+            .slice_mut(s![idx, .., .., ..])
+            .fill(144.0 / 255.0);
 
         let rgb = resized_img.to_rgb8();
         for (x, y, pixel) in rgb.enumerate_pixels() {
             let x = x as usize;
             let y = y as _;
             let [r, g, b] = pixel.0;
-            input_tensor[[0, 0, y, x]] = r as f32 / 255.0;
-            input_tensor[[0, 1, y, x]] = g as f32 / 255.0;
-            input_tensor[[0, 2, y, x]] = b as f32 / 255.0;
+            input_tensor[[idx, 0, y, x]] = r as f32 / 255.0;
+            input_tensor[[idx, 1, y, x]] = g as f32 / 255.0;
+            input_tensor[[idx, 2, y, x]] = b as f32 / 255.0;
+        }
+    }
+}
+
+/// Converts the raw model output of a page into bboxes in page coordinates.
+/// Detections with a confidence under `conf_threshold` are discarded.
+#[tracing::instrument(skip_all)]
+fn extract_bboxes(
+    output: ArrayView2<f32>,
+    (original_width, original_height): (u32, u32),
+    rescale_factor: f32,
+    conf_threshold: f32,
+    labels: &[String],
+    (input_width, input_height): (u32, u32),
+) -> Vec<LayoutBBox> {
+    // Tensor shape: (bbox(4) + classes(15), anchors )
+    let mut result = Vec::new();
+    let mut bbox_id = 0;
+    for prediction in output.axis_iter(Axis(1)) {
+        // Prediction dim: (4 bbox + labels,)
+        const CXYWH_OFFSET: usize = 4;
+        let bbox = prediction.slice(s![0..CXYWH_OFFSET]);
+        let classes = prediction.slice(s![CXYWH_OFFSET..CXYWH_OFFSET + labels.len()]);
+        let (max_prob_idx, &proba) = classes
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .unwrap();
+
+        if proba.is_nan() {
+            tracing::warn!(
+                "Found NaN probability for label {} at idx {}",
+                labels[max_prob_idx],
+                bbox_id
+            );
+            continue;
+        }
+
+        if proba < conf_threshold {
+            continue;
+        }
+        let label = &labels[max_prob_idx];
+        let ratio = (input_width as f32 / original_width as f32)
+            .min(input_height as f32 / original_height as f32);
+        let xc = bbox[0_usize] / ratio;
+        let yc = bbox[1_usize] / ratio;
+        let w = bbox[2_usize] / ratio;
+        let h = bbox[3_usize] / ratio;
+        // Change to (upper-left, lower-right)
+        let x0 = (xc - (w / 2.0)).min(original_width as f32).max(0f32);
+        let y0 = (yc - (h / 2.0)).min(original_height as f32).max(0f32);
+        let x1 = (xc + (w / 2.0)).max(0f32).min(original_width as f32);
+        let y1 = (yc + (h / 2.0)).max(0f32).min(original_height as f32);
+
+        debug_assert!(x0 <= x1 && x1 <= original_width as f32);
+        debug_assert!(y0 <= y1 && y1 <= original_height as f32);
+
+        if x0 > x1 || y0 > y1 {
+            dbg!("bbox error: ({x0},{y1}), ({x1},{y1})");
+            continue;
+        }
+
+        result.push(LayoutBBox {
+            id: bbox_id,
+            bbox: BBox {
+                x0: x0 * rescale_factor,
+                y0: y0 * rescale_factor,
+                x1: x1 * rescale_factor,
+                y1: y1 * rescale_factor,
+            },
+            proba,
+            label: label.clone(),
+        });
+        bbox_id += 1;
+    }
+
+    result
+}
+
+/// Checks that a layout model has a `[batch, 3, height, width]` image input and a
+/// `[batch, 4 + labels, anchors]` output. Returns the `(width, height)` of the model input.
+/// Dynamic dimensions, exported as -1, are accepted and spatial ones use `input_size_override`.
+pub(crate) fn provider_dispatch(
+    provider: &OrtExecutionProvider,
+) -> Option<ExecutionProviderDispatch> {
+    match provider {
+        OrtExecutionProvider::Trt(device_id, options) => {
+            let mut trt = TensorRTExecutionProvider::default()
+                .with_device_id(*device_id)
+                .with_fp16(options.fp16);
+            if let Some(cache_dir) = &options.engine_cache_dir {
+                if let Err(e) = std::fs::create_dir_all(cache_dir) {
+                    tracing::warn!(
+                        "can't create TensorRT engine cache {}: {e}",
+                        cache_dir.display()
+                    );
+                }
+                trt = trt
+                    .with_engine_cache(true)
+                    .with_engine_cache_path(cache_dir.display())
+                    .with_timing_cache(true)
+                    .with_timing_cache_path(cache_dir.display());
+            }
+            if let Some(max_workspace_size) = options.max_workspace_size {
+                trt = trt.with_max_workspace_size(max_workspace_size);
+            }
+            Some(trt.build())
+        }
+        OrtExecutionProvider::CUDA(device_id, options) => {
+            let mut cuda = CUDAExecutionProvider::default().with_device_id(*device_id);
+            if let Some(memory_limit) = options.memory_limit {
+                cuda = cuda.with_memory_limit(memory_limit);
+            }
+            Some(cuda.build())
+        }
+        OrtExecutionProvider::CoreML { ane_only } => {
+            let provider = CoreMLExecutionProvider::default();
+            Some(if *ane_only {
+                provider.with_ane_only().build()
+            } else {
+                provider.build()
+            })
+        }
+        OrtExecutionProvider::DirectML(device_id) => directml_provider(*device_id),
+        OrtExecutionProvider::CPU => Some(CPUExecutionProvider::default().build()),
+    }
+}
+
+/// Loads the layout model in a session built from `builder`, with the optimization level and
+/// threads of the config
+fn commit_session(config: &ORTConfig, builder: SessionBuilder) -> anyhow::Result<Session> {
+    let opt_lvl = match config.opt_level {
+        Some(ORTGraphOptimizationLevel::Level1) => GraphOptimizationLevel::Level1,
+        Some(ORTGraphOptimizationLevel::Level2) => GraphOptimizationLevel::Level2,
+        Some(ORTGraphOptimizationLevel::Level3) => GraphOptimizationLevel::Level3,
+        None => GraphOptimizationLevel::Disable,
+    };
+    let builder = builder
+        .with_optimization_level(opt_lvl)?
+        .with_intra_threads(config.intra_threads)?
+        .with_inter_threads(config.inter_threads)?;
+
+    let session = match &config.layout_model_path {
+        Some(model_path) => builder
+            .commit_from_file(model_path)
+            .with_context(|| format!("can't load layout model {}", model_path.display()))?,
+        None => builder.commit_from_memory(LAYOUT_MODEL_BYTES)?,
+    };
+    Ok(session)
+}
+
+/// Registers the providers one at a time so that we know which ones ORT accepted. Returns the
+/// builder and the first registered provider, the one running the session.
+fn register_providers(
+    mut builder: SessionBuilder,
+    providers: &[OrtExecutionProvider],
+) -> (SessionBuilder, OrtExecutionProvider) {
+    let mut effective = None;
+    for provider in providers {
+        let Some(dispatch) = provider_dispatch(provider) else {
+            continue;
+        };
+        // A failed registration consumes the builder
+        match builder
+            .clone()
+            .with_execution_providers([dispatch.error_on_failure()])
+        {
+            Ok(registered) => {
+                builder = registered;
+                effective.get_or_insert_with(|| provider.clone());
+            }
+            Err(e) => tracing::warn!("can't register execution provider {provider}: {e}"),
         }
     }
+    (builder, effective.unwrap_or(OrtExecutionProvider::CPU))
+}
+
+fn check_layout_signature(
+    input_dims: &[i64],
+    output_dims: &[i64],
+    n_labels: usize,
+    input_size_override: Option<(u32, u32)>,
+) -> anyhow::Result<(u32, u32)> {
+    let is_static = |dim: i64| dim > 0;
+    if input_dims.len() != 4 {
+        bail!("expected a [batch, 3, height, width] input, got dimensions {input_dims:?}");
+    }
+    if is_static(input_dims[1]) && input_dims[1] != 3 {
+        bail!(
+            "expected a 3 channels image input, got {} channels",
+            input_dims[1]
+        );
+    }
+
+    let (height, width) = (input_dims[2], input_dims[3]);
+    let input_size = match (is_static(width) && is_static(height), input_size_override) {
+        (true, Some((w, h))) if (w as i64, h as i64) != (width, height) => {
+            bail!("input size {w}x{h} doesn't match the model input size {width}x{height}")
+        }
+        (true, _) => (width as u32, height as u32),
+        (false, Some(size)) => size,
+        (false, None) => (
+            ORTLayoutParser::REQUIRED_WIDTH,
+            ORTLayoutParser::REQUIRED_HEIGHT,
+        ),
+    };
+
+    if output_dims.len() != 3 {
+        bail!("expected a [batch, 4 + labels, anchors] output, got dimensions {output_dims:?}");
+    }
+    if is_static(output_dims[1]) && output_dims[1] as usize != 4 + n_labels {
+        bail!(
+            "model predicts {} values per anchor, expected 4 bbox coordinates and {n_labels} labels",
+            output_dims[1]
+        );
+    }
+    Ok(input_size)
+}
+
+/// Checks the element types of the layout model input and output against the configured
+/// precision. Returns whether the input and the output are fp16 tensors.
+fn check_model_precision(
+    precision: Precision,
+    input_type: Option<TensorElementType>,
+    output_type: Option<TensorElementType>,
+) -> anyhow::Result<(bool, bool)> {
+    let is_fp16 = |ty: Option<TensorElementType>, name: &str| match ty {
+        Some(TensorElementType::Float32) => Ok(false),
+        Some(TensorElementType::Float16) => Ok(true),
+        Some(ty) => bail!("unsupported layout model {name} type {ty:?}"),
+        None => bail!("layout model has no tensor {name}"),
+    };
+    let fp16_input = is_fp16(input_type, "input")?;
+    let fp16_output = is_fp16(output_type, "output")?;
+
+    // int8 models are quantized internally and keep their fp32 inputs and outputs, fp16
+    // inputs and outputs only come from half precision exports
+    if precision != Precision::Fp16 && (fp16_input || fp16_output) {
+        bail!(
+            "layout model has fp16 inputs or outputs but the {precision} precision is configured"
+        );
+    }
+    Ok((fp16_input, fp16_output))
 }
 
 /// runs nms on without taking into account which class
@@ -563,6 +1303,216 @@ pub(crate) fn nms(raw_bboxes: &mut Vec<LayoutBBox>, iou_threshold: f32) {
 mod tests {
 
     use super::*;
+    use ndarray::Array2;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    /// Raw model output with one `Text` detection per anchor, in the model input coordinates.
+    fn raw_output(anchors: &[(f32, f32, f32)]) -> Array2<f32> {
+        let text_label = ID2LABEL.iter().position(|&l| l == "Text").unwrap();
+        let mut output = Array2::zeros([4 + ID2LABEL.len(), anchors.len()]);
+        for (idx, &(xc, yc, proba)) in anchors.iter().enumerate() {
+            output[[0, idx]] = xc;
+            output[[1, idx]] = yc;
+            output[[2, idx]] = 100.0;
+            output[[3, idx]] = 20.0;
+            output[[4 + text_label, idx]] = proba;
+        }
+        output
+    }
+
+    #[test]
+    fn test_lower_conf_threshold_superset() {
+        let output = raw_output(&[
+            (100.0, 100.0, 0.9),
+            (300.0, 100.0, 0.45),
+            (100.0, 300.0, 0.2),
+            (300.0, 300.0, 0.05),
+        ]);
+        let detections = |conf_threshold: f32| -> Vec<(f32, f32)> {
+            let labels: Vec<String> = ID2LABEL.iter().map(|l| l.to_string()).collect();
+            extract_bboxes(
+                output.view(),
+                (1024, 1024),
+                1.0,
+                conf_threshold,
+                &labels,
+                (1024, 1024),
+            )
+            .iter()
+            .map(|b| (b.bbox.x0, b.bbox.y0))
+            .collect()
+        };
+
+        let strict = detections(0.5);
+        let relaxed = detections(0.1);
+        assert_eq!(strict.len(), 1);
+        assert_eq!(relaxed.len(), 3);
+        assert!(strict.iter().all(|b| relaxed.contains(b)));
+    }
+
+    #[test]
+    fn test_auto_tune() {
+        assert_eq!(
+            AutoTune::for_cpus(1),
+            AutoTune {
+                intra_threads: 1,
+                inter_threads: 1,
+                layout_batch_size: 1,
+            }
+        );
+        assert_eq!(
+            AutoTune::for_cpus(8),
+            AutoTune {
+                intra_threads: 8,
+                inter_threads: 2,
+                layout_batch_size: 4,
+            }
+        );
+        assert_eq!(
+            AutoTune::for_cpus(64),
+            AutoTune {
+                intra_threads: ORTLayoutParser::ORT_INTRATHREAD,
+                inter_threads: ORTLayoutParser::ORT_INTERTHREAD,
+                layout_batch_size: ORTLayoutParser::DEFAULT_BATCH_SIZE,
+            }
+        );
+
+        assert_eq!("auto".parse::<AutoCount>().unwrap(), AutoCount::Auto);
+        assert_eq!("6".parse::<AutoCount>().unwrap().resolve(2), 6);
+        assert_eq!(AutoCount::Auto.resolve(2), 2);
+        assert!("0".parse::<AutoCount>().is_err());
+        assert!("many".parse::<AutoCount>().is_err());
+    }
+
+    #[test]
+    fn test_check_layout_signature() {
+        // Bundled model: static input and 11 labels
+        assert_eq!(
+            check_layout_signature(&[1, 3, 1024, 1024], &[1, 15, 21504], 11, None).unwrap(),
+            (1024, 1024)
+        );
+        // Dynamic spatial dims use the override
+        assert_eq!(
+            check_layout_signature(&[-1, 3, -1, -1], &[-1, 9, -1], 5, Some((640, 800))).unwrap(),
+            (640, 800)
+        );
+        // Override contradicting a static input size
+        assert!(
+            check_layout_signature(&[1, 3, 1024, 1024], &[1, 15, 21504], 11, Some((640, 640)))
+                .is_err()
+        );
+        // Label map not matching the model classes
+        assert!(check_layout_signature(&[1, 3, 1024, 1024], &[1, 15, 21504], 5, None).is_err());
+        // Not an image input
+        assert!(check_layout_signature(&[1, 1024, 1024], &[1, 15, 21504], 11, None).is_err());
+    }
+
+    #[test]
+    fn test_ort_config_rejects_invalid_thresholds() {
+        assert!(ORTConfig::default().validate().is_ok());
+        let config = ORTConfig {
+            conf_threshold: 1.5,
+            ..ORTConfig::default()
+        };
+        assert!(config.validate().is_err());
+        let config = ORTConfig {
+            nms_iou: -0.1,
+            ..ORTConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_model_precision() {
+        use TensorElementType::{Float16, Float32, Int8};
+
+        let config = ORTConfig {
+            model_precision: Precision::Int8,
+            ..ORTConfig::default()
+        };
+        // No quantized model is bundled
+        assert!(config.validate().is_err());
+        assert_eq!("INT8".parse::<Precision>().unwrap(), Precision::Int8);
+        assert!("int4".parse::<Precision>().is_err());
+
+        let check =
+            |precision, input, output| check_model_precision(precision, Some(input), Some(output));
+        assert_eq!(
+            check(Precision::Fp32, Float32, Float32).unwrap(),
+            (false, false)
+        );
+        assert_eq!(
+            check(Precision::Int8, Float32, Float32).unwrap(),
+            (false, false)
+        );
+        assert_eq!(
+            check(Precision::Fp16, Float16, Float16).unwrap(),
+            (true, true)
+        );
+        // fp16 model exported keeping the fp32 io types
+        assert_eq!(
+            check(Precision::Fp16, Float32, Float32).unwrap(),
+            (false, false)
+        );
+        assert!(check(Precision::Fp32, Float16, Float16).is_err());
+        assert!(check(Precision::Int8, Int8, Float32).is_err());
+    }
+
+    #[test]
+    #[ignore = "requires an int8 layout model in FERRULES_LAYOUT_MODEL_INT8"]
+    fn test_int8_layout_matches_fp32() {
+        let model_path = std::env::var("FERRULES_LAYOUT_MODEL_INT8")
+            .expect("FERRULES_LAYOUT_MODEL_INT8 is not set");
+
+        // Fixture page: a title and paragraphs of dark text-like stripes
+        let mut img = image::RgbImage::from_pixel(1224, 1584, image::Rgb([255, 255, 255]));
+        for (y0, x1, height) in [
+            (90, 900, 40),
+            (200, 1124, 180),
+            (450, 1124, 240),
+            (760, 1124, 120),
+        ] {
+            for y in y0..y0 + height {
+                for x in 100..x1 {
+                    if (y - y0) % 24 < 14 && (x / 12) % 7 != 0 {
+                        img.put_pixel(x, y, image::Rgb([0, 0, 0]));
+                    }
+                }
+            }
+        }
+        let page = DynamicImage::ImageRgb8(img);
+
+        let fp32 = ORTLayoutParser::new(ORTConfig::default())
+            .unwrap()
+            .parse_layout(&page, 1.0)
+            .unwrap();
+        let int8 = ORTLayoutParser::new(ORTConfig {
+            model_precision: Precision::Int8,
+            layout_model_path: Some(model_path.into()),
+            ..ORTConfig::default()
+        })
+        .unwrap()
+        .parse_layout(&page, 1.0)
+        .unwrap();
+
+        assert!(!fp32.is_empty());
+        for fp32_bbox in &fp32 {
+            let best_iou = int8
+                .iter()
+                .filter(|b| b.label == fp32_bbox.label)
+                .map(|b| b.bbox.iou(&fp32_bbox.bbox))
+                .fold(0f32, f32::max);
+            assert!(
+                best_iou > 0.7,
+                "{} box {:?} has no int8 match (best iou {best_iou})",
+                fp32_bbox.label,
+                fp32_bbox.bbox
+            );
+        }
+    }
 
     #[test]
     fn test_nms_high_overlap_contained_box() {
@@ -692,4 +1642,98 @@ mod tests {
         assert_eq!(raw_bboxes.len(), 1);
         assert_eq!(raw_bboxes[0].proba, 0.95);
     }
+
+    #[test]
+    fn test_directml_provider_priority() {
+        let config = ORTConfig {
+            execution_providers: vec![
+                OrtExecutionProvider::CPU,
+                OrtExecutionProvider::CoreML { ane_only: false },
+                OrtExecutionProvider::DirectML(0),
+                OrtExecutionProvider::CUDA(0, CudaOptions::default()),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            config.get_sorted_providers(),
+            vec![
+                OrtExecutionProvider::CUDA(0, CudaOptions::default()),
+                OrtExecutionProvider::DirectML(0),
+                OrtExecutionProvider::CoreML { ane_only: false },
+                OrtExecutionProvider::CPU,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_layout_provider_display() {
+        let provider = LayoutProvider {
+            effective: OrtExecutionProvider::CUDA(1, CudaOptions::default()),
+            requested: Some(OrtExecutionProvider::CUDA(1, CudaOptions::default())),
+        };
+        assert!(!provider.is_fallback());
+        assert_eq!(provider.to_string(), "cuda:1");
+
+        let provider = LayoutProvider {
+            effective: OrtExecutionProvider::CPU,
+            requested: Some(OrtExecutionProvider::Trt(0, TrtOptions::default())),
+        };
+        assert!(provider.is_fallback());
+        assert_eq!(provider.to_string(), "cpu (requested: trt:0)");
+    }
+
+    #[test]
+    fn test_retain_available_providers() {
+        let mut config = ORTConfig {
+            execution_providers: vec![OrtExecutionProvider::CPU],
+            ..Default::default()
+        };
+        assert!(config.retain_available_providers().is_empty());
+        assert_eq!(config.execution_providers, vec![OrtExecutionProvider::CPU]);
+
+        // Without the feature DirectML is never available and CPU takes over
+        if !cfg!(feature = "directml") {
+            config.execution_providers = vec![OrtExecutionProvider::DirectML(0)];
+            assert_eq!(
+                config.retain_available_providers(),
+                vec![OrtExecutionProvider::DirectML(0)]
+            );
+            assert_eq!(config.execution_providers, vec![OrtExecutionProvider::CPU]);
+        }
+    }
+
+    #[test]
+    fn test_register_providers_cpu() {
+        let (_, effective) =
+            register_providers(Session::builder().unwrap(), &[OrtExecutionProvider::CPU]);
+        assert_eq!(effective, OrtExecutionProvider::CPU);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_buffer_pool_bounds_concurrent_acquires() {
+        const POOL_SIZE: usize = 2;
+        let pool = Arc::new(InputBufferPool::new(POOL_SIZE, [1, 3, 4, 4]));
+        let in_use = Arc::new(AtomicUsize::new(0));
+        let max_in_use = Arc::new(AtomicUsize::new(0));
+
+        let tasks = (0..64)
+            .map(|_| {
+                let (pool, in_use, max_in_use) = (pool.clone(), in_use.clone(), max_in_use.clone());
+                tokio::spawn(async move {
+                    let mut buffer = pool.acquire().await;
+                    let current = in_use.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_use.fetch_max(current, Ordering::SeqCst);
+                    buffer.fill(1.0);
+                    tokio::task::yield_now().await;
+                    in_use.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect::<Vec<_>>();
+        tokio::time::timeout(Duration::from_secs(10), futures::future::join_all(tasks))
+            .await
+            .expect("buffer pool deadlocked");
+
+        assert!(max_in_use.load(Ordering::SeqCst) <= POOL_SIZE);
+        assert_eq!(pool.buffers.lock().unwrap().len(), POOL_SIZE);
+    }
 }
@@ -48,6 +48,10 @@
 //!         None::<fn(usize)>,          // No progress callback
 //!     ).await?;
 //!
+//!     // Render to any writer, e.g. a buffer for an HTTP response
+//!     let mut markdown = Vec::new();
+//!     ferrules_core::utils::write_markdown(&parsed_doc, &mut markdown, None)?;
+//!
 //!     Ok(())
 //! }
 //! ```
@@ -80,17 +84,29 @@
 #![feature(portable_simd)]
 #![recursion_limit = "256"]
 
+pub(crate) mod cache;
 pub(crate) mod draw;
 
 pub mod blocks;
 pub mod debug_info;
 pub mod entities;
 pub mod error;
+pub mod fetch;
 pub mod layout;
 pub mod metrics;
 pub mod ocr;
+pub mod pdfium;
 pub mod render;
 pub mod utils;
 
 mod parse;
-pub use parse::document::{FerrulesParseConfig, FerrulesParser};
+pub use parse::captions::{CaptionConfig, DEFAULT_CAPTION_MAX_DISTANCE, DEFAULT_CAPTION_PREFIXES};
+pub use parse::count::{count_document, DocumentCount};
+pub use parse::document::{FerrulesParseConfig, FerrulesParser, ParseStreamEvent};
+pub use parse::merge::{MergeThresholds, DEFAULT_PARAGRAPH_GAP};
+pub use parse::native_pool::run_native_worker_if_requested;
+pub use parse::normalize::TextNormalization;
+pub use parse::page_cache::default_page_cache_dir;
+pub use parse::page_range::PageRange;
+pub use parse::reading_order::ReadingOrder;
+pub use parse::titles::TitleLevelStrategy;
@@ -6,6 +6,8 @@ use crate::blocks::TableAlgorithm;
 #[derive(
     Debug, Clone, Default, Serialize, Deserialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
 pub struct StepMetrics {
     pub queue_time_ms: f64,
     pub execution_time_ms: f64,
@@ -26,6 +28,8 @@ impl StepMetrics {
 #[derive(
     Debug, Clone, Default, Serialize, Deserialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
 pub struct OCRMetrics {
     pub step_metrics: StepMetrics,
     pub lines_count: usize,
@@ -34,6 +38,8 @@ pub struct OCRMetrics {
 #[derive(
     Debug, Clone, Default, Serialize, Deserialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
 pub struct TableMetrics {
     pub step_metrics: StepMetrics,
     pub algorithm: TableAlgorithm,
@@ -42,16 +48,30 @@ pub struct TableMetrics {
 #[derive(
     Debug, Clone, Default, Serialize, Deserialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
 pub struct PageMetrics {
     pub page_id: usize,
     pub total_duration_ms: f64,
     pub native_step: StepMetrics,
     pub layout_step: StepMetrics,
+    /// Execution provider the layout model ran on for this page, `None` when the page was
+    /// segmented from its text lines
+    #[serde(default)]
+    pub layout_provider: Option<String>,
     pub table_steps: Vec<TableMetrics>,
     pub ocr_step: Option<OCRMetrics>,
 }
 
 impl PageMetrics {
+    /// Execution time of the table parsing of the page, summed over its tables
+    pub fn table_duration_ms(&self) -> f64 {
+        self.table_steps
+            .iter()
+            .map(|t| t.step_metrics.execution_time_ms)
+            .sum()
+    }
+
     #[cfg(feature = "metrics")]
     pub fn record(&self) {
         let ocr_label = if self.ocr_step.is_some() {
@@ -118,11 +138,7 @@ impl PageMetrics {
             );
         }
 
-        let total_table_duration: f64 = self
-            .table_steps
-            .iter()
-            .map(|t| t.step_metrics.execution_time_ms)
-            .sum();
+        let total_table_duration = self.table_duration_ms();
         let total_table_queue: f64 = self
             .table_steps
             .iter()
@@ -134,10 +150,206 @@ impl PageMetrics {
     }
 }
 
+/// Snapshot of the layout queue activity, aggregated over all the parsed documents
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LayoutQueueStats {
+    /// Requests waiting in the layout queue
+    pub queue_depth: usize,
+    /// Pages taken from the queue whose layout isn't answered yet
+    pub in_flight: usize,
+    /// Layout inferences run so far, a batch of pages counts as one inference
+    pub inferences: u64,
+    /// Median duration of the recent layout inferences
+    pub inference_p50_ms: f64,
+    /// 95th percentile duration of the recent layout inferences
+    pub inference_p95_ms: f64,
+    /// Time spent in the queue by all the layout requests so far
+    pub total_queue_wait_ms: f64,
+}
+
 #[derive(
     Debug, Clone, Default, Serialize, Deserialize, Archive, RkyvDeserialize, RkyvSerialize,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct ParsingMetrics {
     pub total_duration_ms: f64,
     pub pages: Vec<PageMetrics>,
 }
+
+impl ParsingMetrics {
+    /// Page with the longest processing time, native parsing excluded
+    pub fn slowest_page(&self) -> Option<&PageMetrics> {
+        self.pages
+            .iter()
+            .max_by(|a, b| a.total_duration_ms.total_cmp(&b.total_duration_ms))
+    }
+}
+
+/// Distribution of the durations of a parsing stage
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DurationStats {
+    pub samples: usize,
+    pub min_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+}
+
+impl DurationStats {
+    /// Nearest-rank percentiles of the durations, `None` without any
+    pub fn from_samples(mut samples: Vec<f64>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_by(f64::total_cmp);
+        let percentile = |p: f64| {
+            let rank = (p * samples.len() as f64).ceil() as usize;
+            samples[rank.saturating_sub(1)]
+        };
+        Some(Self {
+            samples: samples.len(),
+            min_ms: samples[0],
+            p50_ms: percentile(0.5),
+            p95_ms: percentile(0.95),
+        })
+    }
+}
+
+/// Stage timings aggregated over several parsing runs of the same document
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BenchStats {
+    pub runs: usize,
+    /// Parsing time of the whole document, one sample per run
+    pub document: DurationStats,
+    /// Per page durations of the native parsing, layout queue, layout inference, OCR and table
+    /// stages. The stages no page went through are left out
+    pub stages: Vec<(String, DurationStats)>,
+    /// Pages parsed per second, over all the runs
+    pub pages_per_sec: f64,
+}
+
+impl BenchStats {
+    pub fn from_runs(runs: &[ParsingMetrics]) -> Option<Self> {
+        let document =
+            DurationStats::from_samples(runs.iter().map(|run| run.total_duration_ms).collect())?;
+        let pages = || runs.iter().flat_map(|run| run.pages.iter());
+        let stages: [(&str, Vec<f64>); 5] = [
+            (
+                "native",
+                pages().map(|p| p.native_step.execution_time_ms).collect(),
+            ),
+            (
+                "layout queue",
+                pages().map(|p| p.layout_step.queue_time_ms).collect(),
+            ),
+            (
+                "layout inference",
+                pages().map(|p| p.layout_step.execution_time_ms).collect(),
+            ),
+            (
+                "ocr",
+                pages()
+                    .filter_map(|p| p.ocr_step.as_ref())
+                    .map(|ocr| ocr.step_metrics.execution_time_ms)
+                    .collect(),
+            ),
+            (
+                "tables",
+                pages()
+                    .filter(|p| !p.table_steps.is_empty())
+                    .map(PageMetrics::table_duration_ms)
+                    .collect(),
+            ),
+        ];
+        let total_secs = runs.iter().map(|run| run.total_duration_ms).sum::<f64>() / 1000.0;
+        Some(Self {
+            runs: runs.len(),
+            document,
+            stages: stages
+                .into_iter()
+                .filter_map(|(stage, samples)| {
+                    DurationStats::from_samples(samples).map(|stats| (stage.to_owned(), stats))
+                })
+                .collect(),
+            pages_per_sec: if total_secs > 0.0 {
+                pages().count() as f64 / total_secs
+            } else {
+                0.0
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(page_id: usize, total_duration_ms: f64, table_durations: &[f64]) -> PageMetrics {
+        PageMetrics {
+            page_id,
+            total_duration_ms,
+            table_steps: table_durations
+                .iter()
+                .map(|&execution_time_ms| TableMetrics {
+                    step_metrics: StepMetrics::new(execution_time_ms),
+                    algorithm: TableAlgorithm::Stream,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_slowest_page() {
+        let metrics = ParsingMetrics {
+            total_duration_ms: 500.0,
+            pages: vec![
+                page(0, 120.0, &[]),
+                page(1, 340.0, &[80.0, 45.5]),
+                page(2, 90.0, &[10.0]),
+            ],
+        };
+
+        let slowest = metrics.slowest_page().unwrap();
+        assert_eq!(slowest.page_id, 1);
+        assert_eq!(slowest.table_duration_ms(), 125.5);
+        assert!(ParsingMetrics::default().slowest_page().is_none());
+    }
+
+    #[test]
+    fn test_bench_stats() {
+        let run = |total_duration_ms: f64| ParsingMetrics {
+            total_duration_ms,
+            pages: vec![page(0, 120.0, &[]), page(1, 340.0, &[80.0, 45.5])],
+        };
+        let runs = vec![run(1000.0), run(500.0), run(1500.0)];
+
+        let stats = BenchStats::from_runs(&runs).unwrap();
+        assert_eq!(stats.runs, 3);
+        assert_eq!(
+            stats.document,
+            DurationStats {
+                samples: 3,
+                min_ms: 500.0,
+                p50_ms: 1000.0,
+                p95_ms: 1500.0,
+            }
+        );
+        // 6 pages in 3 seconds
+        assert_eq!(stats.pages_per_sec, 2.0);
+        let stages: Vec<_> = stats
+            .stages
+            .iter()
+            .map(|(s, d)| (s.as_str(), d.samples))
+            .collect();
+        assert_eq!(
+            stages,
+            [
+                ("native", 6),
+                ("layout queue", 6),
+                ("layout inference", 6),
+                ("tables", 3)
+            ]
+        );
+        assert!(BenchStats::from_runs(&[]).is_none());
+    }
+}
@@ -0,0 +1,118 @@
+use std::path::{Path, PathBuf};
+
+use image::DynamicImage;
+use xxhash_rust::xxh3::Xxh3;
+
+use super::{OCRLines, OcrAccuracy};
+use crate::cache::{load_entry, store_entry};
+
+/// Default location of the on-disk OCR cache: `<user cache dir>/ferrules/ocr`
+pub fn default_ocr_cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("ferrules")
+        .join("ocr")
+}
+
+/// Identifies the OCR result of a page raster.
+///
+/// Everything that changes the OCR output for the same raster must be part of the key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct OcrCacheKey {
+    image_hash: u64,
+    rescale_factor: f32,
+    accuracy: OcrAccuracy,
+}
+
+impl OcrCacheKey {
+    pub(crate) fn new(image: &DynamicImage, rescale_factor: f32, accuracy: OcrAccuracy) -> Self {
+        let mut hasher = Xxh3::new();
+        hasher.update(&image.width().to_le_bytes());
+        hasher.update(&image.height().to_le_bytes());
+        hasher.update(image.as_bytes());
+        Self {
+            image_hash: hasher.digest(),
+            rescale_factor,
+            accuracy,
+        }
+    }
+
+    fn file_name(&self) -> String {
+        format!(
+            "{:016x}_{:08x}_{:?}.ferr",
+            self.image_hash,
+            self.rescale_factor.to_bits(),
+            self.accuracy
+        )
+        .to_lowercase()
+    }
+}
+
+/// Returns the cached OCR lines for `key`, if any. Unreadable entries are treated as misses.
+pub(crate) fn load_ocr_cache(cache_dir: &Path, key: &OcrCacheKey) -> Option<Vec<OCRLines>> {
+    load_entry(&cache_dir.join(key.file_name()))
+}
+
+/// Stores the OCR lines for `key`. Failing to write the cache is not an error for the caller.
+pub(crate) fn store_ocr_cache(cache_dir: &Path, key: &OcrCacheKey, lines: &[OCRLines]) {
+    store_entry(&cache_dir.join(key.file_name()), &lines.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::BBox;
+    use image::{Rgb, RgbImage};
+
+    fn page_image(value: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(20, 10, Rgb([value, value, value])))
+    }
+
+    #[test]
+    fn test_ocr_cache_roundtrip() {
+        let cache_dir = std::env::temp_dir().join(format!("ferrules-ocr-{}", uuid::Uuid::new_v4()));
+        let key = OcrCacheKey::new(&page_image(255), 0.5, OcrAccuracy::Accurate);
+        assert!(load_ocr_cache(&cache_dir, &key).is_none());
+
+        let lines = vec![OCRLines {
+            text: "cached".to_string(),
+            confidence: 0.9,
+            bbox: BBox {
+                x0: 1.0,
+                y0: 2.0,
+                x1: 3.0,
+                y1: 4.0,
+            },
+            words: vec![],
+        }];
+        store_ocr_cache(&cache_dir, &key, &lines);
+
+        let cached = load_ocr_cache(&cache_dir, &key).expect("cache hit");
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].text, "cached");
+        assert_eq!(cached[0].bbox.x1, 3.0);
+
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+
+    #[test]
+    fn test_ocr_cache_key_invalidation() {
+        let key = OcrCacheKey::new(&page_image(255), 0.5, OcrAccuracy::Accurate);
+        assert_eq!(
+            key,
+            OcrCacheKey::new(&page_image(255), 0.5, OcrAccuracy::Accurate)
+        );
+        assert_ne!(
+            key.file_name(),
+            OcrCacheKey::new(&page_image(0), 0.5, OcrAccuracy::Accurate).file_name()
+        );
+        assert_ne!(
+            key.file_name(),
+            OcrCacheKey::new(&page_image(255), 0.25, OcrAccuracy::Accurate).file_name()
+        );
+        assert_ne!(
+            key.file_name(),
+            OcrCacheKey::new(&page_image(255), 0.5, OcrAccuracy::Fast).file_name()
+        );
+    }
+}
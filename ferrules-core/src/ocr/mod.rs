@@ -1,4 +1,6 @@
 use image::DynamicImage;
+use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
@@ -6,14 +8,74 @@ use tokio::sync::mpsc::{self, Receiver, Sender};
 use tokio::sync::{oneshot, Semaphore};
 use tracing::{Instrument, Span};
 
-use crate::entities::{BBox, Line, PageID};
+use crate::entities::{BBox, CharSpan, Line, PageID};
 use crate::error::FerrulesError;
 use crate::metrics::StepMetrics;
+use crate::parse::bidi::dominant_direction;
+
+mod cache;
+mod orientation;
+pub use cache::default_ocr_cache_dir;
+pub(crate) use cache::{load_ocr_cache, store_ocr_cache, OcrCacheKey};
+pub use orientation::{detect_page_rotation, PageRotation};
 
 const CONCURRENT_OCR_REQUESTS: usize = 32;
 const MAX_OCR_BATCH_SIZE: usize = 16;
 const OCR_BATCH_TIMEOUT_MS: u64 = 100;
 
+/// Default minimum recognition confidence of the OCR lines kept on a page
+pub const DEFAULT_OCR_MIN_CONFIDENCE: f32 = 0.3;
+
+/// Default minimum ratio between the area of the native text lines of a page and the area of
+/// its text regions. Pages below it are OCRed
+pub const DEFAULT_OCR_COVERAGE_THRESHOLD: f32 = 0.5;
+
+/// Default minimum number of non-whitespace characters in the text layer of a page. Pages
+/// below it, e.g. with a few invisible characters left by a scanner, are OCRed
+pub const DEFAULT_OCR_MIN_CHARS: usize = 10;
+
+/// Tradeoff between recognition speed and quality used by the OCR backend.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OcrAccuracy {
+    /// Fast recognition, roughly 3-5x quicker and usually good enough for body text triage
+    Fast,
+    /// Slower recognition with the best quality
+    #[default]
+    Accurate,
+}
+
+/// How native text lines and OCR lines are combined on a page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextSource {
+    /// OCR pages with a sparse text layer and keep native lines over overlapping OCR lines
+    #[default]
+    PreferNative,
+    /// OCR pages with a sparse text layer and keep OCR lines over overlapping native lines
+    PreferOcr,
+    /// Never run OCR, only use the native text layer
+    NativeOnly,
+    /// Always run OCR and ignore the native text layer
+    OcrOnly,
+}
+
+impl std::str::FromStr for TextSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "prefer-native" => Ok(TextSource::PreferNative),
+            "prefer-ocr" => Ok(TextSource::PreferOcr),
+            "native-only" => Ok(TextSource::NativeOnly),
+            "ocr-only" => Ok(TextSource::OcrOnly),
+            _ => anyhow::bail!(
+                "unknown text source `{s}`, expected prefer-native, prefer-ocr, native-only or ocr-only"
+            ),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct OCRMetadata {
     pub(crate) response_tx: oneshot::Sender<Result<ParseOCRResponse, FerrulesError>>,
@@ -25,6 +87,7 @@ pub(crate) struct ParseOCRRequest {
     pub(crate) page_id: PageID,
     pub(crate) page_image: Arc<DynamicImage>,
     pub(crate) rescale_factor: f32,
+    pub(crate) accuracy: OcrAccuracy,
     pub(crate) metadata: OCRMetadata,
 }
 
@@ -87,6 +150,7 @@ async fn handle_ocr_request(
         page_id,
         page_image,
         rescale_factor,
+        accuracy,
         metadata,
     } = req;
 
@@ -97,6 +161,7 @@ async fn handle_ocr_request(
         .send(OCRInferenceRequest {
             image: page_image,
             rescale_factor,
+            accuracy,
             response_tx: tx,
         })
         .await;
@@ -124,6 +189,7 @@ async fn handle_ocr_request(
 struct OCRInferenceRequest {
     image: Arc<DynamicImage>,
     rescale_factor: f32,
+    accuracy: OcrAccuracy,
     response_tx: oneshot::Sender<Result<Vec<OCRLines>, FerrulesError>>,
 }
 
@@ -168,7 +234,7 @@ impl BatchOCRRunner {
             let mut restxs = Vec::with_capacity(batch_size);
 
             for req in batch.drain(..) {
-                images.push((req.image, req.rescale_factor));
+                images.push((req.image, req.rescale_factor, req.accuracy));
                 restxs.push(req.response_tx);
             }
 
@@ -207,6 +273,7 @@ impl OCRParser {
         &self,
         image: &DynamicImage,
         rescale_factor: f32,
+        accuracy: OcrAccuracy,
     ) -> Result<Vec<OCRLines>, FerrulesError> {
         let (tx, rx) = oneshot::channel();
         let _ = self
@@ -214,6 +281,7 @@ impl OCRParser {
             .send(OCRInferenceRequest {
                 image: Arc::new(image.clone()),
                 rescale_factor,
+                accuracy,
                 response_tx: tx,
             })
             .await;
@@ -229,31 +297,146 @@ use ocr_linux::{parse_images_ocr_batch, parse_single_image_ocr};
 #[cfg(target_os = "macos")]
 use ocr_mac::{parse_images_ocr_batch, parse_single_image_ocr};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
+pub struct OCRWord {
+    pub text: String,
+    pub bbox: BBox,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Archive, RkyvDeserialize, RkyvSerialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[archive(check_bytes)]
 pub struct OCRLines {
     pub text: String,
     pub confidence: f32,
     pub bbox: BBox,
+    /// Words of the line with their own bbox, empty if the backend only returned the line box
+    #[serde(default)]
+    pub words: Vec<OCRWord>,
 }
 
 impl OCRLines {
+    fn word_spans(&self) -> Vec<CharSpan> {
+        let mut byte_idx = 0;
+        let mut spans = Vec::with_capacity(self.words.len());
+        for word in &self.words {
+            let Some(word_offset) = self.text[byte_idx..].find(&word.text) else {
+                continue;
+            };
+            let word_start = byte_idx + word_offset;
+            byte_idx = word_start + word.text.len();
+            let char_start_idx = self.text[..word_start].chars().count();
+            spans.push(CharSpan {
+                bbox: word.bbox.clone(),
+                text: word.text.clone(),
+                rotation: 0f32,
+                font_name: String::new(),
+                font_size: word.bbox.height(),
+                font_weight: None,
+                bold: false,
+                italic: false,
+                script: None,
+                char_start_idx,
+                char_end_idx: char_start_idx + word.text.chars().count().saturating_sub(1),
+            });
+        }
+        spans
+    }
+
     pub(crate) fn to_line(&self) -> Line {
+        let spans = if self.words.is_empty() {
+            // Words are approximated from the line box
+            CharSpan {
+                bbox: self.bbox.clone(),
+                text: self.text.to_string(),
+                rotation: 0f32,
+                font_name: String::new(),
+                font_size: self.bbox.height(),
+                font_weight: None,
+                bold: false,
+                italic: false,
+                script: None,
+                char_start_idx: 0,
+                char_end_idx: self.text.chars().count().saturating_sub(1),
+            }
+            .words()
+        } else {
+            self.word_spans()
+        };
         Line {
             text: self.text.to_string(),
             bbox: self.bbox.clone(),
             rotation: 0f32,
-            spans: vec![],
+            spans,
+            confidence: Some(self.confidence),
+            links: vec![],
+            direction: dominant_direction(&self.text),
+        }
+    }
+
+    /// Converts back an OCR line, once mapped to the page coordinates. Native lines have no
+    /// recognition confidence and return `None`.
+    pub(crate) fn from_line(line: &Line) -> Option<Self> {
+        Some(Self {
+            text: line.text.clone(),
+            confidence: line.confidence?,
+            bbox: line.bbox.clone(),
+            words: line
+                .spans
+                .iter()
+                .map(|span| OCRWord {
+                    text: span.text.clone(),
+                    bbox: span.bbox.clone(),
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Drops the OCR lines recognized with a confidence under `min_confidence`
+pub(crate) fn filter_ocr_lines(lines: Vec<OCRLines>, min_confidence: f32) -> Vec<OCRLines> {
+    lines
+        .into_iter()
+        .filter(|line| line.confidence >= min_confidence)
+        .collect()
+}
+
+/// Splits `text` into words on whitespace, returning each word with its range in UTF-16 code
+/// units, which is how Foundation strings are indexed.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn utf16_word_ranges(text: &str) -> Vec<(String, std::ops::Range<usize>)> {
+    let mut words = Vec::new();
+    let mut current: Option<(String, usize)> = None;
+    let mut utf16_idx = 0;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if let Some((word, start)) = current.take() {
+                words.push((word, start..utf16_idx));
+            }
+        } else {
+            current
+                .get_or_insert_with(|| (String::new(), utf16_idx))
+                .0
+                .push(c);
         }
+        utf16_idx += c.len_utf16();
+    }
+    if let Some((word, start)) = current {
+        words.push((word, start..utf16_idx));
     }
+    words
 }
 
 pub async fn parse_image_ocr(
     image: &DynamicImage,
     _debug_dir: Option<PathBuf>,
     rescale_factor: f32,
+    accuracy: OcrAccuracy,
 ) -> Result<(Vec<OCRLines>, StepMetrics), FerrulesError> {
     let start = Instant::now();
-    let ocr_result = parse_single_image_ocr(image, rescale_factor)
+    let ocr_result = parse_single_image_ocr(image, rescale_factor, accuracy)
         .map_err(|e| FerrulesError::OcrError(format!("OCR execution error: {}", e)))?;
     let execution_time_ms = start.elapsed().as_secs_f64() * 1000.0;
 
@@ -270,10 +453,20 @@ pub async fn parse_image_ocr(
 mod ocr_mac {
     use super::*;
     use objc2::ClassType;
-    use objc2_foundation::{CGRect, NSArray, NSData, NSDictionary};
-    use objc2_vision::{VNImageRequestHandler, VNRecognizeTextRequest, VNRequest};
+    use objc2_foundation::{CGRect, NSArray, NSData, NSDictionary, NSRange};
+    use objc2_vision::{
+        VNImageRequestHandler, VNRecognizeTextRequest, VNRecognizedText,
+        VNRecognizedTextObservation, VNRequest, VNRequestTextRecognitionLevel,
+    };
     const CONFIDENCE_THRESHOLD: f32 = 0f32;
 
+    fn recognition_level(accuracy: OcrAccuracy) -> VNRequestTextRecognitionLevel {
+        match accuracy {
+            OcrAccuracy::Fast => VNRequestTextRecognitionLevel::Fast,
+            OcrAccuracy::Accurate => VNRequestTextRecognitionLevel::Accurate,
+        }
+    }
+
     fn img_to_tiff(image: &DynamicImage) -> anyhow::Result<Vec<u8>> {
         let mut buffer = std::io::Cursor::new(Vec::new());
         image.write_to(&mut buffer, image::ImageFormat::Tiff)?;
@@ -324,22 +517,67 @@ mod ocr_mac {
         }
     }
 
+    /// Word boxes of a recognized line. Returns no words if Vision can't locate one of them,
+    /// the line box is then used to approximate them.
+    unsafe fn recognized_words(
+        rec_text: &VNRecognizedText,
+        text: &str,
+        img_width: u32,
+        img_height: u32,
+        rescale_factor: f32,
+    ) -> Vec<OCRWord> {
+        utf16_word_ranges(text)
+            .into_iter()
+            .map(|(word, range)| {
+                let observation = rec_text
+                    .boundingBoxForRange_error(NSRange::new(range.start, range.len()))
+                    .ok()?;
+                let bbox = observation.boundingBox();
+                Some(OCRWord {
+                    text: word,
+                    bbox: cgrect_to_bbox(&bbox, img_width, img_height, rescale_factor),
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default()
+    }
+
+    unsafe fn recognized_line(
+        recognized_text_region: &VNRecognizedTextObservation,
+        img_width: u32,
+        img_height: u32,
+        rescale_factor: f32,
+    ) -> Option<OCRLines> {
+        if recognized_text_region.confidence() <= CONFIDENCE_THRESHOLD {
+            return None;
+        }
+        let rec_text = recognized_text_region.topCandidates(1).first()?;
+        let text = rec_text.string().to_string();
+        let bbox = recognized_text_region.boundingBox();
+        Some(OCRLines {
+            words: recognized_words(&rec_text, &text, img_width, img_height, rescale_factor),
+            confidence: rec_text.confidence(),
+            bbox: cgrect_to_bbox(&bbox, img_width, img_height, rescale_factor),
+            text,
+        })
+    }
+
     pub(super) fn parse_images_ocr_batch(
-        inputs: Vec<(Arc<DynamicImage>, f32)>,
+        inputs: Vec<(Arc<DynamicImage>, f32, OcrAccuracy)>,
     ) -> Vec<anyhow::Result<Vec<OCRLines>>> {
         if inputs.is_empty() {
             return vec![];
         }
 
         if inputs.len() == 1 {
-            let (image, rescale_factor) = inputs.into_iter().next().unwrap();
-            return vec![parse_single_image_ocr(&image, rescale_factor)];
+            let (image, rescale_factor, accuracy) = inputs.into_iter().next().unwrap();
+            return vec![parse_single_image_ocr(&image, rescale_factor, accuracy)];
         }
 
         // Stitching logic for true batching
         let mut total_height = 0u32;
         let mut max_width = 0u32;
-        for (img, _) in &inputs {
+        for (img, _, _) in &inputs {
             total_height += img.height();
             max_width = max_width.max(img.width());
         }
@@ -348,7 +586,7 @@ mod ocr_mac {
         let mut offsets = Vec::with_capacity(inputs.len());
         let mut current_y = 0u32;
 
-        for (img, _) in &inputs {
+        for (img, _, _) in &inputs {
             offsets.push(current_y);
             image::imageops::overlay(&mut combined_image, img.as_ref(), 0, current_y as i64);
             current_y += img.height();
@@ -370,9 +608,9 @@ mod ocr_mac {
 
         unsafe {
             let mut requests = Vec::with_capacity(inputs.len());
-            for (i, (_, _)) in inputs.iter().enumerate() {
+            for (i, (_, _, accuracy)) in inputs.iter().enumerate() {
                 let request = VNRecognizeTextRequest::new();
-                request.setRecognitionLevel(objc2_vision::VNRequestTextRecognitionLevel::Accurate);
+                request.setRecognitionLevel(recognition_level(*accuracy));
                 request.setUsesLanguageCorrection(true);
 
                 // Set Region Of Interest for this specific image in the strip
@@ -416,23 +654,11 @@ mod ocr_mac {
                 let img_height = inputs[i].0.height();
 
                 if let Some(result) = request.results() {
-                    for recognized_text_region in result.to_vec() {
-                        if (*recognized_text_region).confidence() > CONFIDENCE_THRESHOLD {
-                            if let Some(rec_text) = recognized_text_region.topCandidates(1).first()
-                            {
-                                let bbox = (*recognized_text_region).boundingBox();
-                                // Note: bbox from Vision here is RELATIVE to ROI if we use ROI correctly?
-                                // Actually, Vision bboxes are typically relative to the WHOLE image if ROI is set on request?
-                                let bbox =
-                                    cgrect_to_bbox(&bbox, img_width, img_height, rescale_factor);
-                                final_results[i].push(OCRLines {
-                                    text: rec_text.string().to_string(),
-                                    confidence: rec_text.confidence(),
-                                    bbox,
-                                })
-                            }
-                        }
-                    }
+                    // Note: bbox from Vision here is RELATIVE to ROI if we use ROI correctly?
+                    // Actually, Vision bboxes are typically relative to the WHOLE image if ROI is set on request?
+                    final_results[i].extend(result.to_vec().iter().filter_map(|region| {
+                        recognized_line(region, img_width, img_height, rescale_factor)
+                    }));
                 }
             }
         }
@@ -443,6 +669,7 @@ mod ocr_mac {
     pub(super) fn parse_single_image_ocr(
         image: &DynamicImage,
         rescale_factor: f32,
+        accuracy: OcrAccuracy,
     ) -> anyhow::Result<Vec<OCRLines>> {
         let (img_width, img_height) = (image.width(), image.height());
         let raw_data = img_to_tiff(image)?;
@@ -450,7 +677,7 @@ mod ocr_mac {
         let mut ocr_result = Vec::new();
         unsafe {
             let request = VNRecognizeTextRequest::new();
-            request.setRecognitionLevel(objc2_vision::VNRequestTextRecognitionLevel::Accurate);
+            request.setRecognitionLevel(recognition_level(accuracy));
             request.setUsesLanguageCorrection(true);
 
             let handler = VNImageRequestHandler::initWithData_options(
@@ -463,19 +690,9 @@ mod ocr_mac {
             handler.performRequests_error(&requests)?;
 
             if let Some(result) = request.results() {
-                for recognized_text_region in result.to_vec() {
-                    if (*recognized_text_region).confidence() > CONFIDENCE_THRESHOLD {
-                        if let Some(rec_text) = recognized_text_region.topCandidates(1).first() {
-                            let bbox = (*recognized_text_region).boundingBox();
-                            let bbox = cgrect_to_bbox(&bbox, img_width, img_height, rescale_factor);
-                            ocr_result.push(OCRLines {
-                                text: rec_text.string().to_string(),
-                                confidence: rec_text.confidence(),
-                                bbox,
-                            })
-                        }
-                    }
-                }
+                ocr_result.extend(result.to_vec().iter().filter_map(|region| {
+                    recognized_line(region, img_width, img_height, rescale_factor)
+                }));
             }
         }
         Ok(ocr_result)
@@ -496,7 +713,7 @@ mod ocr_mac {
                     .unwrap();
 
                 let s = Instant::now();
-                let ocr_result = parse_image_ocr(&image, None, 1f32).await;
+                let ocr_result = parse_image_ocr(&image, None, 1f32, OcrAccuracy::Accurate).await;
                 assert!(ocr_result.is_ok());
 
                 println!(
@@ -506,6 +723,25 @@ mod ocr_mac {
             }
         }
 
+        #[tokio::test]
+        async fn test_ocr_noisy_image_confidence() {
+            let mut seed = 42u32;
+            let noise = image::RgbImage::from_fn(600, 400, |_, _| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                let v = (seed >> 16) as u8;
+                image::Rgb([v, v, v])
+            });
+            let image = DynamicImage::ImageRgb8(noise);
+
+            let lines = parse_image_ocr(&image, None, 1f32, OcrAccuracy::Accurate)
+                .await
+                .unwrap();
+            let kept = filter_ocr_lines(lines, DEFAULT_OCR_MIN_CONFIDENCE);
+            assert!(kept
+                .iter()
+                .all(|l| l.confidence >= DEFAULT_OCR_MIN_CONFIDENCE));
+        }
+
         #[test]
         fn test_ocr_batching_perf() {
             let image_path = if Path::new("./test_data/double_cols.jpg").exists() {
@@ -523,7 +759,7 @@ mod ocr_mac {
                 let img = image.clone();
                 handles.push(std::thread::spawn(move || {
                     let rt = tokio::runtime::Runtime::new().unwrap();
-                    let _ = rt.block_on(parse_image_ocr(&img, None, 1.0));
+                    let _ = rt.block_on(parse_image_ocr(&img, None, 1.0, OcrAccuracy::Accurate));
                 }));
             }
             for h in handles {
@@ -582,7 +818,7 @@ mod ocr_mac {
                 for _i in 0..n {
                     let img = image.clone();
                     let p = parser.clone();
-                    set.spawn(async move { p.parse(&img, 1.0).await });
+                    set.spawn(async move { p.parse(&img, 1.0, OcrAccuracy::Accurate).await });
                 }
 
                 let mut results = Vec::new();
@@ -605,8 +841,10 @@ mod ocr_linux {
 
     use super::*;
 
+    // NOTE: once a recognition model is available on linux, `OcrAccuracy::Fast` should map to
+    // the smaller recognition model and `OcrAccuracy::Accurate` to the full one.
     pub(super) fn parse_images_ocr_batch(
-        _inputs: Vec<(Arc<DynamicImage>, f32)>,
+        _inputs: Vec<(Arc<DynamicImage>, f32, OcrAccuracy)>,
     ) -> Vec<anyhow::Result<Vec<OCRLines>>> {
         vec![Err(anyhow::anyhow!("not implemented yet"))]
     }
@@ -614,7 +852,120 @@ mod ocr_linux {
     pub(super) fn parse_single_image_ocr(
         _image: &DynamicImage,
         _rescale_factor: f32,
+        _accuracy: OcrAccuracy,
     ) -> anyhow::Result<Vec<OCRLines>> {
         anyhow::bail!("not implemented yet")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_source_from_str() {
+        assert_eq!(
+            "ocr-only".parse::<TextSource>().unwrap(),
+            TextSource::OcrOnly
+        );
+        assert_eq!(
+            "Prefer-Native".parse::<TextSource>().unwrap(),
+            TextSource::PreferNative
+        );
+        assert!("ocr".parse::<TextSource>().is_err());
+    }
+
+    #[test]
+    fn test_utf16_word_ranges() {
+        let words = utf16_word_ranges(" caf\u{e9}  \u{1d11e}note end");
+        assert_eq!(
+            words,
+            vec![
+                ("caf\u{e9}".to_string(), 1..5),
+                // The musical symbol is a surrogate pair in UTF-16
+                ("\u{1d11e}note".to_string(), 7..13),
+                ("end".to_string(), 14..17),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ocr_words_to_spans() {
+        let word = |text: &str, x0: f32, x1: f32| OCRWord {
+            text: text.to_string(),
+            bbox: BBox {
+                x0,
+                y0: 10.0,
+                x1,
+                y1: 20.0,
+            },
+        };
+        let ocr_line = OCRLines {
+            text: "hello big world".to_string(),
+            confidence: 1.0,
+            bbox: BBox {
+                x0: 0.0,
+                y0: 10.0,
+                x1: 100.0,
+                y1: 20.0,
+            },
+            words: vec![
+                word("hello", 0.0, 30.0),
+                word("big", 35.0, 50.0),
+                word("world", 60.0, 100.0),
+            ],
+        };
+
+        let line = ocr_line.to_line();
+        let spans: Vec<_> = line
+            .spans
+            .iter()
+            .map(|s| (s.text.as_str(), s.bbox.x0, s.char_start_idx, s.char_end_idx))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                ("hello", 0.0, 0, 4),
+                ("big", 35.0, 6, 8),
+                ("world", 60.0, 10, 14)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter_ocr_lines_confidence() {
+        let ocr_line = |text: &str, confidence: f32| OCRLines {
+            text: text.to_string(),
+            confidence,
+            bbox: BBox {
+                x0: 0.0,
+                y0: 0.0,
+                x1: 10.0,
+                y1: 10.0,
+            },
+            words: vec![],
+        };
+        let lines = vec![
+            ocr_line("noise", 0.1),
+            ocr_line("edge", DEFAULT_OCR_MIN_CONFIDENCE),
+            ocr_line("text", 0.95),
+        ];
+
+        let kept = filter_ocr_lines(lines.clone(), DEFAULT_OCR_MIN_CONFIDENCE);
+        let texts: Vec<_> = kept.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, vec!["edge", "text"]);
+        assert_eq!(filter_ocr_lines(lines.clone(), 0.0).len(), 3);
+        assert!(filter_ocr_lines(lines, 1.0).is_empty());
+
+        // The confidence survives the conversion to a page line and back
+        let line = kept[1].to_line();
+        assert_eq!(line.confidence, Some(0.95));
+        let back = OCRLines::from_line(&line).expect("OCR line");
+        assert_eq!(back.confidence, 0.95);
+        let native = Line {
+            confidence: None,
+            ..line
+        };
+        assert!(OCRLines::from_line(&native).is_none());
+    }
+}
@@ -0,0 +1,326 @@
+use image::{DynamicImage, GrayImage};
+use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
+
+use crate::entities::BBox;
+
+/// Luma value under which a pixel is considered ink.
+const INK_LUMA_THRESHOLD: u8 = 128;
+
+/// Pages with less ink than this ratio of their pixels are considered blank and never rotated.
+const MIN_INK_RATIO: f32 = 0.005;
+
+/// The column profile variation must exceed the row profile variation by this factor for the
+/// page to be considered sideways.
+const SIDEWAYS_RATIO: f32 = 1.5;
+
+/// Ink below the text lines x-height band must exceed the ink above it by this factor for the
+/// page to be considered upside down.
+const UPSIDE_DOWN_RATIO: f32 = 1.2;
+
+/// Clockwise rotation that has to be applied to a page raster for its text to be upright.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Archive, RkyvDeserialize, RkyvSerialize)]
+#[archive(check_bytes)]
+pub enum PageRotation {
+    #[default]
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl PageRotation {
+    pub fn degrees(&self) -> f32 {
+        match self {
+            PageRotation::Deg0 => 0f32,
+            PageRotation::Deg90 => 90f32,
+            PageRotation::Deg180 => 180f32,
+            PageRotation::Deg270 => 270f32,
+        }
+    }
+
+    /// Clockwise rotation of the text as it appears on the original page, matching the
+    /// convention used for native `Line.rotation`.
+    pub fn text_rotation(&self) -> f32 {
+        (360f32 - self.degrees()) % 360f32
+    }
+
+    /// Returns the upright version of `image`.
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        match self {
+            PageRotation::Deg0 => image.clone(),
+            PageRotation::Deg90 => image.rotate90(),
+            PageRotation::Deg180 => image.rotate180(),
+            PageRotation::Deg270 => image.rotate270(),
+        }
+    }
+
+    /// Maps a point of the original page to the page rotated clockwise by `self`.
+    /// `width` and `height` are the dimensions of the original (unrotated) page.
+    pub fn rotate_point(&self, (x, y): (f32, f32), width: f32, height: f32) -> (f32, f32) {
+        match self {
+            PageRotation::Deg0 => (x, y),
+            PageRotation::Deg90 => (height - y, x),
+            PageRotation::Deg180 => (width - x, height - y),
+            PageRotation::Deg270 => (y, width - x),
+        }
+    }
+
+    /// Maps a bbox of the original page to the page rotated clockwise by `self`, the inverse
+    /// of [`PageRotation::unrotate_bbox`].
+    pub fn rotate_bbox(&self, bbox: &BBox, width: f32, height: f32) -> BBox {
+        let (ax, ay) = self.rotate_point((bbox.x0, bbox.y0), width, height);
+        let (bx, by) = self.rotate_point((bbox.x1, bbox.y1), width, height);
+        BBox {
+            x0: ax.min(bx),
+            y0: ay.min(by),
+            x1: ax.max(bx),
+            y1: ay.max(by),
+        }
+    }
+
+    /// Maps a bbox expressed in the upright raster back to the original page coordinates.
+    /// `width` and `height` are the dimensions of the original (unrotated) page.
+    pub fn unrotate_bbox(&self, bbox: &BBox, width: f32, height: f32) -> BBox {
+        let unrotate_point = |u: f32, v: f32| -> (f32, f32) {
+            match self {
+                PageRotation::Deg0 => (u, v),
+                PageRotation::Deg90 => (v, height - u),
+                PageRotation::Deg180 => (width - u, height - v),
+                PageRotation::Deg270 => (width - v, u),
+            }
+        };
+        let (ax, ay) = unrotate_point(bbox.x0, bbox.y0);
+        let (bx, by) = unrotate_point(bbox.x1, bbox.y1);
+        BBox {
+            x0: ax.min(bx),
+            y0: ay.min(by),
+            x1: ax.max(bx),
+            y1: ay.max(by),
+        }
+    }
+}
+
+fn binarize(image: &DynamicImage) -> GrayImage {
+    let mut gray = image.to_luma8();
+    for p in gray.pixels_mut() {
+        p.0[0] = if p.0[0] < INK_LUMA_THRESHOLD { 1 } else { 0 };
+    }
+    gray
+}
+
+fn row_profile(ink: &GrayImage) -> Vec<u32> {
+    ink.rows()
+        .map(|row| row.map(|p| p.0[0] as u32).sum())
+        .collect()
+}
+
+fn column_profile(ink: &GrayImage) -> Vec<u32> {
+    let mut profile = vec![0u32; ink.width() as usize];
+    for (x, _, p) in ink.enumerate_pixels() {
+        profile[x as usize] += p.0[0] as u32;
+    }
+    profile
+}
+
+/// Squared coefficient of variation of the inked span of a projection profile.
+/// Text lines produce an alternating full/empty profile, hence a high variation, in the
+/// direction orthogonal to the lines.
+fn profile_variation(profile: &[u32]) -> f32 {
+    let start = profile.iter().position(|&v| v > 0);
+    let end = profile.iter().rposition(|&v| v > 0);
+    let span = match (start, end) {
+        (Some(start), Some(end)) => &profile[start..=end],
+        _ => return 0f32,
+    };
+    let n = span.len() as f32;
+    let mean = span.iter().sum::<u32>() as f32 / n;
+    if mean == 0f32 {
+        return 0f32;
+    }
+    let var = span.iter().map(|&v| (v as f32 - mean).powi(2)).sum::<f32>() / n;
+    var / mean.powi(2)
+}
+
+/// Latin scripts have more ascenders than descenders: within each text line, more ink sits
+/// above the x-height band than below it. The opposite indicates an upside-down page.
+fn is_upside_down(ink: &GrayImage) -> bool {
+    let profile = row_profile(ink);
+    let min_row_ink = (ink.width() / 100).max(1);
+
+    let (mut above, mut below) = (0u32, 0u32);
+    let mut y = 0;
+    while y < profile.len() {
+        if profile[y] < min_row_ink {
+            y += 1;
+            continue;
+        }
+        let start = y;
+        while y < profile.len() && profile[y] >= min_row_ink {
+            y += 1;
+        }
+        let line = &profile[start..y];
+        let max_ink = *line.iter().max().unwrap_or(&0);
+        let first_core = line.iter().position(|&v| 2 * v >= max_ink).unwrap_or(0);
+        let last_core = line.iter().rposition(|&v| 2 * v >= max_ink).unwrap_or(0);
+        above += line[..first_core].iter().sum::<u32>();
+        below += line[last_core + 1..].iter().sum::<u32>();
+    }
+
+    below as f32 > above as f32 * UPSIDE_DOWN_RATIO
+}
+
+/// Detects the orientation of a scanned page using projection profiles.
+///
+/// This is a cheap heuristic meant for pages without a text layer: the dominant text line
+/// direction is found by comparing row and column projection profiles, then the
+/// ascender/descender asymmetry of the lines disambiguates between opposite orientations.
+pub fn detect_page_rotation(image: &DynamicImage) -> PageRotation {
+    let ink = binarize(image);
+    let total_ink = ink.pixels().map(|p| p.0[0] as u32).sum::<u32>();
+    if (total_ink as f32) < (ink.width() * ink.height()) as f32 * MIN_INK_RATIO {
+        return PageRotation::Deg0;
+    }
+
+    let row_variation = profile_variation(&row_profile(&ink));
+    let col_variation = profile_variation(&column_profile(&ink));
+    let sideways = col_variation > row_variation * SIDEWAYS_RATIO;
+
+    let candidate = if sideways {
+        image::imageops::rotate90(&ink)
+    } else {
+        ink
+    };
+
+    match (sideways, is_upside_down(&candidate)) {
+        (false, false) => PageRotation::Deg0,
+        (false, true) => PageRotation::Deg180,
+        (true, false) => PageRotation::Deg90,
+        (true, true) => PageRotation::Deg270,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// Draws lines of fake glyphs: an x-height body, frequent ascenders and rare descenders.
+    fn synthetic_text_page() -> DynamicImage {
+        let mut img = RgbImage::from_pixel(400, 300, Rgb([255, 255, 255]));
+        let mut seed = 7u32;
+        for line in 0..8 {
+            let y = 20 + line * 32;
+            let mut x = 20;
+            let mut glyph = 0;
+            while x < 370 {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                let width = 5 + (seed >> 16) % 6;
+                for gx in x..x + width {
+                    for gy in y + 6..y + 14 {
+                        img.put_pixel(gx, gy, Rgb([0, 0, 0]));
+                    }
+                }
+                if glyph % 3 == 0 {
+                    for gx in x..x + 2 {
+                        for gy in y..y + 6 {
+                            img.put_pixel(gx, gy, Rgb([0, 0, 0]));
+                        }
+                    }
+                }
+                if glyph % 7 == 0 {
+                    for gx in x + width - 2..x + width {
+                        for gy in y + 14..y + 18 {
+                            img.put_pixel(gx, gy, Rgb([0, 0, 0]));
+                        }
+                    }
+                }
+                x += width + 2 + (seed >> 24) % 3;
+                glyph += 1;
+            }
+        }
+        DynamicImage::ImageRgb8(img)
+    }
+
+    #[test]
+    fn test_detect_page_rotation() {
+        let upright = synthetic_text_page();
+        assert_eq!(detect_page_rotation(&upright), PageRotation::Deg0);
+        // A page whose content was rotated counter-clockwise needs a clockwise correction
+        assert_eq!(
+            detect_page_rotation(&upright.rotate270()),
+            PageRotation::Deg90
+        );
+        assert_eq!(
+            detect_page_rotation(&upright.rotate180()),
+            PageRotation::Deg180
+        );
+        assert_eq!(
+            detect_page_rotation(&upright.rotate90()),
+            PageRotation::Deg270
+        );
+    }
+
+    #[test]
+    fn test_blank_page_not_rotated() {
+        let blank = DynamicImage::ImageRgb8(RgbImage::from_pixel(200, 100, Rgb([255, 255, 255])));
+        assert_eq!(detect_page_rotation(&blank), PageRotation::Deg0);
+    }
+
+    #[test]
+    fn test_unrotate_bbox() {
+        let (width, height) = (200f32, 100f32);
+        // Box in the original page coordinates
+        let original = BBox {
+            x0: 10.0,
+            y0: 20.0,
+            x1: 50.0,
+            y1: 30.0,
+        };
+        let image = DynamicImage::ImageRgb8(RgbImage::new(width as u32, height as u32));
+        for rotation in [
+            PageRotation::Deg0,
+            PageRotation::Deg90,
+            PageRotation::Deg180,
+            PageRotation::Deg270,
+        ] {
+            let upright = rotation.apply(&image);
+            // Forward mapping of the box corners into the upright raster
+            let (uw, uh) = (upright.width() as f32, upright.height() as f32);
+            let rotated = match rotation {
+                PageRotation::Deg0 => original.clone(),
+                PageRotation::Deg90 => BBox {
+                    x0: uw - original.y1,
+                    y0: original.x0,
+                    x1: uw - original.y0,
+                    y1: original.x1,
+                },
+                PageRotation::Deg180 => BBox {
+                    x0: uw - original.x1,
+                    y0: uh - original.y1,
+                    x1: uw - original.x0,
+                    y1: uh - original.y0,
+                },
+                PageRotation::Deg270 => BBox {
+                    x0: original.y0,
+                    y0: uh - original.x1,
+                    x1: original.y1,
+                    y1: uh - original.x0,
+                },
+            };
+            let forward = rotation.rotate_bbox(&original, width, height);
+            assert_eq!(
+                (forward.x0, forward.y0, forward.x1, forward.y1),
+                (rotated.x0, rotated.y0, rotated.x1, rotated.y1),
+                "rotation {:?}",
+                rotation
+            );
+            let back = rotation.unrotate_bbox(&rotated, width, height);
+            assert_eq!(
+                (back.x0, back.y0, back.x1, back.y1),
+                (original.x0, original.y0, original.x1, original.y1),
+                "rotation {:?}",
+                rotation
+            );
+        }
+    }
+}
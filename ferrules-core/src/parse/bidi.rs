@@ -0,0 +1,218 @@
+use std::ops::Range;
+
+use unicode_bidi::{bidi_class, BidiClass, Level, ParagraphBidiInfo};
+
+use crate::entities::{BBox, CharSpan, Line, TextDirection};
+
+fn is_rtl_letter(c: char) -> bool {
+    matches!(bidi_class(c), BidiClass::R | BidiClass::AL)
+}
+
+/// Direction of the majority of the strong characters of `text`, left to right when it has
+/// none, e.g. a line of numbers
+pub(crate) fn dominant_direction(text: &str) -> TextDirection {
+    let (ltr, rtl) = text
+        .chars()
+        .fold((0usize, 0usize), |(ltr, rtl), c| match bidi_class(c) {
+            BidiClass::L => (ltr + 1, rtl),
+            BidiClass::R | BidiClass::AL => (ltr, rtl + 1),
+            _ => (ltr, rtl),
+        });
+    if rtl > ltr {
+        TextDirection::Rtl
+    } else {
+        TextDirection::Ltr
+    }
+}
+
+/// Byte ranges of the directional runs of `text`, in visual order, with whether they are
+/// right to left. The runs are resolved by the Unicode bidi algorithm, e.g. the numbers in an
+/// Arabic line are a left to right run.
+fn visual_runs(text: &str, direction: TextDirection) -> Vec<(Range<usize>, bool)> {
+    let level = match direction {
+        TextDirection::Ltr => Level::ltr(),
+        TextDirection::Rtl => Level::rtl(),
+    };
+    let bidi = ParagraphBidiInfo::new(text, Some(level));
+    let (levels, runs) = bidi.visual_runs(0..text.len());
+    runs.into_iter()
+        .map(|run| {
+            let rtl = levels[run.start].is_rtl();
+            (run, rtl)
+        })
+        .collect()
+}
+
+// NOTE: reordering the runs of a line displayed by the bidi algorithm gives back its logical
+// order, the runs of the visual text are the runs of the logical text in reverse. The right to
+// left runs are reversed and the runs are concatenated in visual order.
+fn visual_to_logical(text: &str, direction: TextDirection) -> String {
+    visual_runs(text, direction)
+        .into_iter()
+        .map(|(run, rtl)| {
+            if rtl {
+                text[run].chars().rev().collect()
+            } else {
+                text[run].to_owned()
+            }
+        })
+        .collect()
+}
+
+/// Part of `span` between the byte offsets `range`, reversed for a right to left run. Its bbox
+/// is approximated by distributing the span width over its characters.
+fn span_part(span: &CharSpan, range: Range<usize>, rtl: bool) -> CharSpan {
+    let char_width = span.bbox.width() / span.text.chars().count().max(1) as f32;
+    let start = span.text[..range.start].chars().count();
+    let len = span.text[range.clone()].chars().count();
+    let text = &span.text[range];
+    CharSpan {
+        bbox: BBox {
+            x0: span.bbox.x0 + start as f32 * char_width,
+            y0: span.bbox.y0,
+            x1: span.bbox.x0 + (start + len) as f32 * char_width,
+            y1: span.bbox.y1,
+        },
+        text: if rtl {
+            text.chars().rev().collect()
+        } else {
+            text.to_owned()
+        },
+        char_start_idx: span.char_start_idx + start,
+        char_end_idx: span.char_start_idx + start + len - 1,
+        ..span.clone()
+    }
+}
+
+/// Spans in logical order: the spans are split on the directional runs of the line, the parts
+/// of the right to left runs are reversed
+fn spans_to_logical(spans: &[CharSpan], direction: TextDirection) -> Vec<CharSpan> {
+    let text: String = spans.iter().map(|span| span.text.as_str()).collect();
+    let mut span_starts = Vec::with_capacity(spans.len());
+    let mut offset = 0;
+    for span in spans {
+        span_starts.push(offset);
+        offset += span.text.len();
+    }
+
+    let mut logical = Vec::with_capacity(spans.len());
+    for (run, rtl) in visual_runs(&text, direction) {
+        let mut parts: Vec<CharSpan> = spans
+            .iter()
+            .zip(&span_starts)
+            .filter_map(|(span, &span_start)| {
+                let start = run.start.max(span_start);
+                let end = run.end.min(span_start + span.text.len());
+                (start < end).then(|| span_part(span, start - span_start..end - span_start, rtl))
+            })
+            .collect();
+        if rtl {
+            parts.reverse();
+        }
+        logical.extend(parts);
+    }
+    logical
+}
+
+/// Whether the right to left spans of the line already follow each other from right to left,
+/// pdfium then returned them in logical order
+fn spans_in_logical_order(spans: &[CharSpan]) -> bool {
+    let mut rtl_spans = spans
+        .iter()
+        .filter(|span| span.text.chars().any(is_rtl_letter));
+    match (rtl_spans.next(), rtl_spans.next_back()) {
+        (Some(first), Some(last)) => first.bbox.x0 > last.bbox.x0,
+        _ => false,
+    }
+}
+
+/// Tags the direction of a native line and puts its text and spans in logical order. pdfium
+/// returns the characters in the order they are drawn, left to right, which reverses the
+/// words of the right to left scripts. Lines without right to left characters are unchanged.
+pub(crate) fn to_logical_order(line: &mut Line) {
+    line.direction = dominant_direction(&line.text);
+    if !line.text.chars().any(is_rtl_letter) || spans_in_logical_order(&line.spans) {
+        return;
+    }
+    line.text = visual_to_logical(&line.text, line.direction);
+    line.spans = spans_to_logical(&line.spans, line.direction);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str, x0: f32, x1: f32) -> CharSpan {
+        CharSpan {
+            bbox: BBox {
+                x0,
+                y0: 0.0,
+                x1,
+                y1: 10.0,
+            },
+            text: text.to_string(),
+            rotation: 0.0,
+            font_name: "Arial".to_string(),
+            font_size: 10.0,
+            font_weight: None,
+            bold: false,
+            italic: false,
+            script: None,
+            char_start_idx: 0,
+            char_end_idx: text.chars().count() - 1,
+        }
+    }
+
+    fn line(spans: Vec<CharSpan>) -> Line {
+        Line {
+            text: spans.iter().map(|span| span.text.as_str()).collect(),
+            spans,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_dominant_direction() {
+        assert_eq!(dominant_direction("שלום עולם"), TextDirection::Rtl);
+        assert_eq!(dominant_direction("مرحبا 2024"), TextDirection::Rtl);
+        assert_eq!(dominant_direction("The word שלום"), TextDirection::Ltr);
+        assert_eq!(dominant_direction("2024"), TextDirection::Ltr);
+    }
+
+    #[test]
+    fn test_rtl_line_logical_order() {
+        // "مرحبا 123 بالعالم" drawn from left to right
+        let mut rtl_line = line(vec![
+            span("ملاعلاب ", 0.0, 80.0),
+            span("123", 80.0, 110.0),
+            span(" ابحرم", 110.0, 170.0),
+        ]);
+        to_logical_order(&mut rtl_line);
+        assert_eq!(rtl_line.direction, TextDirection::Rtl);
+        assert_eq!(rtl_line.text, "مرحبا 123 بالعالم");
+        let spans: Vec<_> = rtl_line
+            .spans
+            .iter()
+            .map(|span| span.text.as_str())
+            .collect();
+        assert_eq!(spans, ["مرحبا ", "123", " بالعالم"]);
+        // The first word is at the right of the line
+        assert!(rtl_line.spans[0].bbox.x0 > rtl_line.spans[2].bbox.x0);
+
+        // A Hebrew word in an English line
+        let mut ltr_line = line(vec![span("The word םולש.", 0.0, 140.0)]);
+        to_logical_order(&mut ltr_line);
+        assert_eq!(ltr_line.direction, TextDirection::Ltr);
+        assert_eq!(ltr_line.text, "The word שלום.");
+        assert_eq!(ltr_line.spans[1].text, "שלום");
+        assert_eq!(ltr_line.spans[1].bbox.x0, 90.0);
+    }
+
+    #[test]
+    fn test_logical_rtl_spans_unchanged() {
+        let mut rtl_line = line(vec![span("שלום ", 60.0, 100.0), span("עולם", 0.0, 50.0)]);
+        to_logical_order(&mut rtl_line);
+        assert_eq!(rtl_line.direction, TextDirection::Rtl);
+        assert_eq!(rtl_line.text, "שלום עולם");
+    }
+}
@@ -0,0 +1,283 @@
+use tracing::instrument;
+
+use crate::{
+    blocks::{Block, BlockType},
+    entities::BBox,
+};
+
+/// Default maximum vertical gap, in page points, between a caption and its image or table
+pub const DEFAULT_CAPTION_MAX_DISTANCE: f32 = 20.0;
+
+/// Default prefixes of the caption text lines
+pub const DEFAULT_CAPTION_PREFIXES: [&str; 3] = ["Figure", "Fig.", "Table"];
+
+/// Minimum horizontal overlap between a caption and its image or table, as a fraction of the
+/// narrower of the two
+const MIN_CAPTION_OVERLAP_RATIO: f32 = 0.5;
+
+/// Maximum difference, in page points, between the gaps of the side-by-side images sharing a
+/// caption and the gap of the closest one
+const SHARED_CAPTION_GAP_TOLERANCE: f32 = 10.0;
+
+/// Configures how text blocks such as "Figure 3: ..." are assigned as the caption of the
+/// closest image or table block.
+#[derive(Debug, Clone)]
+pub struct CaptionConfig {
+    /// Text blocks starting with one of these prefixes, ignoring case, are caption candidates.
+    /// An empty list disables the caption association
+    pub prefixes: Vec<String>,
+
+    /// Maximum vertical gap, in page points, between a caption and the image or table it is
+    /// assigned to
+    pub max_distance: f32,
+}
+
+impl Default for CaptionConfig {
+    fn default() -> Self {
+        Self {
+            prefixes: DEFAULT_CAPTION_PREFIXES
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            max_distance: DEFAULT_CAPTION_MAX_DISTANCE,
+        }
+    }
+}
+
+impl CaptionConfig {
+    fn is_caption(&self, text: &str) -> bool {
+        let text = text.trim_start();
+        self.prefixes.iter().any(|prefix| {
+            let Some(head) = text.get(..prefix.len()) else {
+                return false;
+            };
+            // "Table 2" is a caption, "Tablet" is not
+            head.eq_ignore_ascii_case(prefix)
+                && text[prefix.len()..]
+                    .chars()
+                    .next()
+                    .is_none_or(|c| !c.is_alphabetic())
+        })
+    }
+}
+
+#[inline]
+fn vertical_gap(a: &BBox, b: &BBox) -> f32 {
+    f32::max(0f32, f32::max(a.y0, b.y0) - f32::min(a.y1, b.y1))
+}
+
+fn caption_mut(block: &mut Block) -> Option<&mut Option<String>> {
+    match &mut block.kind {
+        BlockType::Image(image_block) => Some(&mut image_block.caption),
+        BlockType::Table(table) => Some(&mut table.caption),
+        _ => None,
+    }
+}
+
+fn overlaps_horizontally(a: &BBox, b: &BBox) -> bool {
+    a.overlap_x(b) >= MIN_CAPTION_OVERLAP_RATIO * f32::min(a.width(), b.width())
+}
+
+/// Assigns the text blocks starting with a caption prefix to the closest image or table block
+/// of the same page without a caption, directly above or below them. Side-by-side subfigures
+/// under or over a shared caption row each get the caption. Assigned text blocks are removed
+/// from the blocks.
+#[instrument(skip_all)]
+pub(crate) fn associate_captions(mut blocks: Vec<Block>, config: &CaptionConfig) -> Vec<Block> {
+    if config.prefixes.is_empty() {
+        return blocks;
+    }
+
+    let mut assigned = vec![false; blocks.len()];
+    for text_idx in 0..blocks.len() {
+        let text_block = &blocks[text_idx];
+        let caption = match &text_block.kind {
+            BlockType::TextBlock(text)
+                if text_block.pages_id.len() == 1 && config.is_caption(&text.text) =>
+            {
+                text.text.split_whitespace().collect::<Vec<_>>().join(" ")
+            }
+            _ => continue,
+        };
+
+        // Captions are on the same page as their image, never on the next one
+        let candidates = blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| match &block.kind {
+                BlockType::Image(image_block) => image_block.caption.is_none(),
+                BlockType::Table(table) => table.caption.is_none(),
+                _ => false,
+            })
+            .filter(|(_, block)| {
+                block.pages_id == text_block.pages_id
+                    && overlaps_horizontally(&block.bbox, &text_block.bbox)
+            })
+            .map(|(idx, block)| {
+                let above = block.bbox.center().1 < text_block.bbox.center().1;
+                (idx, above, vertical_gap(&block.bbox, &text_block.bbox))
+            })
+            .filter(|(_, _, gap)| *gap <= config.max_distance)
+            .collect::<Vec<_>>();
+        let Some(&(_, closest_above, closest_gap)) = candidates
+            .iter()
+            .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+        else {
+            continue;
+        };
+
+        let targets = candidates
+            .into_iter()
+            .filter(|(_, above, gap)| {
+                *above == closest_above && *gap <= closest_gap + SHARED_CAPTION_GAP_TOLERANCE
+            })
+            .map(|(idx, _, _)| idx)
+            .collect::<Vec<_>>();
+        for idx in targets {
+            if let Some(target_caption) = caption_mut(&mut blocks[idx]) {
+                *target_caption = Some(caption.clone());
+                assigned[text_idx] = true;
+            }
+        }
+    }
+
+    blocks
+        .into_iter()
+        .zip(assigned)
+        .filter_map(|(block, assigned)| (!assigned).then_some(block))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{ImageBlock, TableBlock, TextBlock},
+        entities::PageID,
+    };
+
+    fn block(id: usize, kind: BlockType, y0: f32, y1: f32) -> Block {
+        block_at(id, kind, 0, 50.0, 500.0, y0, y1)
+    }
+
+    fn block_at(
+        id: usize,
+        kind: BlockType,
+        page: PageID,
+        x0: f32,
+        x1: f32,
+        y0: f32,
+        y1: f32,
+    ) -> Block {
+        Block::for_test(id, kind, page, BBox { x0, y0, x1, y1 })
+    }
+
+    fn text(id: usize, text: &str, y0: f32, y1: f32) -> Block {
+        block(
+            id,
+            BlockType::TextBlock(TextBlock {
+                text: text.to_string(),
+            }),
+            y0,
+            y1,
+        )
+    }
+
+    #[test]
+    fn test_associate_captions() {
+        let blocks = vec![
+            block(0, BlockType::Image(ImageBlock::default()), 100.0, 300.0),
+            text(1, "Figure 3: quarterly\nrevenue", 305.0, 320.0),
+            text(2, "Tablets are selling well.", 330.0, 345.0),
+            text(3, "TABLE 1. Results", 400.0, 412.0),
+            block(4, BlockType::Table(TableBlock::default()), 415.0, 600.0),
+            // Too far from any image or table
+            text(5, "Fig. 4 elsewhere", 700.0, 712.0),
+        ];
+
+        let blocks = associate_captions(blocks, &CaptionConfig::default());
+
+        let ids = blocks.iter().map(|b| b.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![0, 2, 4, 5]);
+        match &blocks[0].kind {
+            BlockType::Image(image) => {
+                assert_eq!(
+                    image.caption.as_deref(),
+                    Some("Figure 3: quarterly revenue")
+                )
+            }
+            _ => panic!("Expected Image block"),
+        }
+        match &blocks[2].kind {
+            BlockType::Table(table) => {
+                assert_eq!(table.caption.as_deref(), Some("TABLE 1. Results"))
+            }
+            _ => panic!("Expected Table block"),
+        }
+    }
+
+    #[test]
+    fn test_associate_captions_custom_prefixes() {
+        let blocks = vec![
+            block(0, BlockType::Image(ImageBlock::default()), 100.0, 300.0),
+            text(1, "Abbildung 2: Umsatz", 302.0, 314.0),
+        ];
+        let config = CaptionConfig {
+            prefixes: vec!["Abbildung".to_string()],
+            ..Default::default()
+        };
+
+        let blocks = associate_captions(blocks, &config);
+
+        assert_eq!(blocks.len(), 1);
+        match &blocks[0].kind {
+            BlockType::Image(image) => {
+                assert_eq!(image.caption.as_deref(), Some("Abbildung 2: Umsatz"))
+            }
+            _ => panic!("Expected Image block"),
+        }
+    }
+
+    fn image_caption(block: &Block) -> Option<&str> {
+        match &block.kind {
+            BlockType::Image(image) => image.caption.as_deref(),
+            _ => panic!("Expected Image block"),
+        }
+    }
+
+    #[test]
+    fn test_associate_shared_caption() {
+        let image = || BlockType::Image(ImageBlock::default());
+        let caption = || {
+            BlockType::TextBlock(TextBlock {
+                text: "Figure 2: (a) before (b) after".to_string(),
+            })
+        };
+        let blocks = vec![
+            // Side-by-side subfigures over a caption spanning both
+            block_at(0, image(), 0, 50.0, 270.0, 100.0, 300.0),
+            block_at(1, image(), 0, 280.0, 500.0, 110.0, 295.0),
+            block_at(2, caption(), 0, 50.0, 500.0, 305.0, 320.0),
+            // Image at the bottom of a page, the caption at the top of the next one
+            block_at(3, image(), 0, 50.0, 500.0, 500.0, 790.0),
+            block_at(4, caption(), 1, 50.0, 500.0, 10.0, 25.0),
+            // Barely overlapping the caption
+            block_at(5, image(), 1, 400.0, 700.0, 30.0, 200.0),
+        ];
+
+        let blocks = associate_captions(blocks, &CaptionConfig::default());
+
+        let ids = blocks.iter().map(|b| b.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![0, 1, 3, 4, 5]);
+        assert_eq!(
+            image_caption(&blocks[0]),
+            Some("Figure 2: (a) before (b) after")
+        );
+        assert_eq!(
+            image_caption(&blocks[1]),
+            Some("Figure 2: (a) before (b) after")
+        );
+        assert_eq!(image_caption(&blocks[2]), None);
+        assert_eq!(image_caption(&blocks[4]), None);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::entities::{Element, ElementLine, ElementType};
+
+/// Minimum fraction of the lines of a text element set in a monospace font for it to be a
+/// code listing
+const MIN_CODE_LINE_RATIO: f32 = 0.8;
+
+/// Turns the text elements mostly set in a monospace font into code listings. Tagged PDFs and
+/// layout models with a `Code` class already give code elements.
+pub(crate) fn detect_code_elements(elements: &mut [Element]) {
+    for element in elements
+        .iter_mut()
+        .filter(|element| matches!(element.kind, ElementType::Text))
+    {
+        let lines = &element.text_block.lines;
+        let monospace_lines = lines.iter().filter(|line| line.monospace).count();
+        if !lines.is_empty() && monospace_lines as f32 >= lines.len() as f32 * MIN_CODE_LINE_RATIO {
+            element.kind = ElementType::Code;
+        }
+    }
+}
+
+fn median(mut values: Vec<f32>) -> Option<f32> {
+    values.sort_by(f32::total_cmp);
+    values.get(values.len() / 2).copied()
+}
+
+/// Text of a code listing, one line per line of the page. pdfium drops the leading spaces of
+/// the lines: the indentation is rebuilt from the offset of each line to the left edge of the
+/// listing, in characters of the median width of the lines. Vertical gaps of a line height or
+/// more are blank lines.
+pub(crate) fn code_text(lines: &[ElementLine]) -> String {
+    let lines: Vec<&ElementLine> = lines
+        .iter()
+        .filter(|line| !line.text.trim().is_empty())
+        .collect();
+    let Some(left) = lines.iter().map(|line| line.bbox.x0).min_by(f32::total_cmp) else {
+        return String::new();
+    };
+    let char_width = median(
+        lines
+            .iter()
+            .map(|line| line.bbox.width() / line.text.trim().chars().count() as f32)
+            .collect(),
+    )
+    .filter(|width| *width > 0f32);
+
+    let mut text_lines: Vec<String> = Vec::with_capacity(lines.len());
+    let mut prev_bottom: Option<f32> = None;
+    for line in lines {
+        let height = line.bbox.height();
+        if let Some(prev_bottom) = prev_bottom.filter(|_| height > 0f32) {
+            let blank_lines = ((line.bbox.y0 - prev_bottom) / height).floor().max(0f32) as usize;
+            text_lines.extend(std::iter::repeat_n(String::new(), blank_lines));
+        }
+        prev_bottom = Some(line.bbox.y1);
+
+        let content = line.text.trim();
+        let leading_spaces = line.text.len() - line.text.trim_start().len();
+        let offset_spaces = char_width
+            .map(|width| ((line.bbox.x0 - left) / width).round() as usize)
+            .unwrap_or_default();
+        text_lines.push(format!(
+            "{}{content}",
+            " ".repeat(leading_spaces.max(offset_spaces))
+        ));
+    }
+    text_lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::{BBox, ElementText};
+
+    fn code_line(text: &str, x0: f32, y0: f32) -> ElementLine {
+        ElementLine {
+            text: text.to_owned(),
+            bbox: BBox {
+                x0,
+                y0,
+                x1: x0 + 6.0 * text.trim().chars().count() as f32,
+                y1: y0 + 10.0,
+            },
+            monospace: true,
+        }
+    }
+
+    #[test]
+    fn test_code_text_indentation() {
+        let lines = [
+            code_line("fn main() {", 50.0, 100.0),
+            code_line("let x = 1;", 74.0, 112.0),
+            code_line("if x > 0 {", 74.0, 124.0),
+            code_line("println!(\"{x}\");", 98.0, 136.0),
+            code_line("}", 74.0, 148.0),
+            // A blank line
+            code_line("}", 50.0, 172.0),
+        ];
+        assert_eq!(
+            code_text(&lines),
+            "fn main() {\n    let x = 1;\n    if x > 0 {\n        println!(\"{x}\");\n    }\n\n}"
+        );
+        assert_eq!(code_text(&[]), "");
+    }
+
+    #[test]
+    fn test_detect_code_elements() {
+        let element = |kind: ElementType, monospace: bool| Element {
+            id: 0,
+            layout_block_id: 0,
+            text_block: ElementText {
+                text: "return x".to_owned(),
+                lines: vec![ElementLine {
+                    monospace,
+                    ..code_line("return x", 0.0, 0.0)
+                }],
+                ..Default::default()
+            },
+            kind,
+            page_id: 0,
+            bbox: BBox::default(),
+            confidence: 1.0,
+        };
+        let mut elements = vec![
+            element(ElementType::Text, true),
+            element(ElementType::Text, false),
+            element(ElementType::Caption, true),
+        ];
+
+        detect_code_elements(&mut elements);
+
+        let kinds: Vec<_> = elements.iter().map(|e| e.kind.to_string()).collect();
+        assert_eq!(kinds, ["Code", "Text", "Caption"]);
+    }
+}
@@ -0,0 +1,149 @@
+use pdfium_render::prelude::*;
+use serde::Serialize;
+
+use crate::{
+    entities::{BBox, DocumentLanguage, Line, PageID},
+    error::FerrulesError,
+    ocr::PageRotation,
+    pdfium::load_pdfium,
+};
+
+use super::{
+    language::{detect_text_language, MAX_LANGUAGE_SAMPLE_LEN},
+    merge::{heuristic_text_layout, MergeThresholds},
+    native::{document_load_error, get_page_rotation, parse_text_lines, parse_text_spans},
+    page_range::PageRange,
+};
+
+/// Page count, estimated blocks, OCR need and language of a document, read from its native
+/// text layer only
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DocumentCount {
+    /// Pages of the document in the page range
+    pub pages: usize,
+    /// Paragraphs of the lines-only segmentation and image objects of the pages. The layout
+    /// model usually splits the pages in more blocks
+    pub estimated_blocks: usize,
+    /// At least one page has less native text than `ocr_min_chars` and would be OCRed
+    pub need_ocr: bool,
+    /// Pages that would be OCRed
+    pub ocr_pages: Vec<PageID>,
+    /// Dominant language of the native text, `None` without text
+    pub language: Option<DocumentLanguage>,
+}
+
+/// Native lines of the page, grouped top-down on the page as displayed
+fn page_lines(page: &PdfPage) -> Result<Vec<Line>, PdfiumError> {
+    let page_rotation = get_page_rotation(page);
+    let (width, height) = (page.width().value, page.height().value);
+    let (unrotated_width, unrotated_height) = match page_rotation {
+        PageRotation::Deg90 | PageRotation::Deg270 => (height, width),
+        PageRotation::Deg0 | PageRotation::Deg180 => (width, height),
+    };
+    let unrotated_bbox = BBox {
+        x0: 0f32,
+        y0: 0f32,
+        x1: unrotated_width,
+        y1: unrotated_height,
+    };
+    let mut text_spans = parse_text_spans(page.text()?.chars().iter(), &unrotated_bbox);
+    if page_rotation != PageRotation::Deg0 {
+        for span in text_spans.iter_mut() {
+            span.bbox = page_rotation.rotate_bbox(&span.bbox, unrotated_width, unrotated_height);
+            span.rotation = (span.rotation + page_rotation.degrees()) % 360f32;
+        }
+    }
+    Ok(parse_text_lines(text_spans, &MergeThresholds::default()))
+}
+
+/// Counts the pages of the document and estimates its blocks from the native text layer,
+/// without rendering the pages or running the layout model, e.g. to triage a batch of
+/// documents before parsing them. A page needs OCR when it has less than `ocr_min_chars`
+/// non-whitespace characters, the text coverage of the layout regions isn't checked.
+pub fn count_document(
+    doc: &[u8],
+    password: Option<&str>,
+    page_range: Option<&PageRange>,
+    ocr_min_chars: usize,
+) -> anyhow::Result<DocumentCount> {
+    let (pdfium, _) = load_pdfium()?;
+    let document = pdfium
+        .load_pdf_from_byte_slice(doc, password)
+        .map_err(|e| match document_load_error(&e, password) {
+            Some(err) => anyhow::Error::from(err),
+            None => anyhow::Error::from(e).context("can't open the document"),
+        })?;
+    let n_pages = document.pages().len() as usize;
+    let page_ids = match page_range {
+        Some(range) => match range.page_ids(n_pages) {
+            Some(page_ids) => page_ids,
+            None => anyhow::bail!("Page range exceeds document length ({n_pages})"),
+        },
+        None => (0..n_pages).collect(),
+    };
+
+    let mut count = DocumentCount {
+        pages: page_ids.len(),
+        ..Default::default()
+    };
+    let mut sample = String::new();
+    for (page_id, page) in document.pages().iter().enumerate() {
+        if page_ids.binary_search(&page_id).is_err() {
+            continue;
+        }
+        let lines = page_lines(&page).map_err(|e| FerrulesError::NativePageError {
+            page_id,
+            message: format!("{e:#}"),
+        })?;
+        let chars: usize = lines
+            .iter()
+            .map(|l| l.text.chars().filter(|c| !c.is_whitespace()).count())
+            .sum();
+        if chars < ocr_min_chars {
+            count.ocr_pages.push(page_id);
+        }
+        let images = page
+            .objects()
+            .iter()
+            .filter(|object| object.as_image_object().is_some())
+            .count();
+        count.estimated_blocks += heuristic_text_layout(&lines).len() + images;
+        for line in &lines {
+            if sample.len() >= MAX_LANGUAGE_SAMPLE_LEN {
+                break;
+            }
+            sample.push_str(&line.text);
+            sample.push('\n');
+        }
+    }
+    count.need_ocr = !count.ocr_pages.is_empty();
+    count.language = detect_text_language(&sample);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_document() -> anyhow::Result<()> {
+        let doc = std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/two_columns.pdf"
+        ))?;
+
+        let count = count_document(&doc, None, None, 10)?;
+        assert_eq!(count.pages, 1);
+        assert!(count.estimated_blocks >= 2);
+        assert!(!count.need_ocr);
+        assert_eq!(count.language.map(|l| l.code), Some("eng".to_string()));
+
+        // A page with less text than the threshold needs OCR
+        let count = count_document(&doc, None, None, 100_000)?;
+        assert!(count.need_ocr);
+        assert_eq!(count.ocr_pages, [0]);
+
+        assert!(count_document(b"not a pdf", None, None, 10).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,542 @@
+use std::collections::HashMap;
+
+use crate::{
+    blocks::{Block, BlockFragment, BlockType, Hyphenation, WordFragment},
+    entities::{Line, Page, PageID},
+};
+
+/// Hyphen, unicode hyphen, soft hyphen and the pdfium line end hyphen when the text isn't
+/// normalized
+const HYPHENS: [char; 4] = ['-', '\u{2010}', '\u{AD}', '\u{2}'];
+
+fn is_page_furniture(block: &Block) -> bool {
+    block.repeated || matches!(block.kind, BlockType::Header(_) | BlockType::Footer(_))
+}
+
+/// Part of the word before the hyphen, when `word` ends with a hyphen following a letter
+fn hyphen_prefix(word: &str) -> Option<&str> {
+    let prefix = word.strip_suffix(HYPHENS)?;
+    prefix
+        .chars()
+        .next_back()
+        .is_some_and(char::is_alphabetic)
+        .then_some(prefix)
+}
+
+fn starts_lowercase(word: &str) -> bool {
+    word.chars().next().is_some_and(char::is_lowercase)
+}
+
+/// Lowercased word without its surrounding punctuation
+fn word_key(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+/// Occurrences of the words of the text blocks, titles and lists
+fn word_frequencies(blocks: &[Block]) -> HashMap<String, usize> {
+    let mut frequencies = HashMap::new();
+    for block in blocks {
+        let texts: Vec<&str> = match &block.kind {
+            BlockType::TextBlock(text) => vec![&text.text],
+            BlockType::Title(title) => vec![&title.text],
+            BlockType::ListBlock(list) => {
+                list.items.iter().map(|item| item.text.as_str()).collect()
+            }
+            _ => continue,
+        };
+        for word in texts.into_iter().flat_map(str::split_whitespace) {
+            *frequencies.entry(word_key(word)).or_insert(0) += 1;
+        }
+    }
+    frequencies
+}
+
+/// Joins the hyphenated `head` with `tail`. The hyphen is kept when `head` is already part of
+/// a compound, e.g. `state-of-` and `the-art`, or when the hyphenated word is more frequent
+/// than the joined word in the document. Soft hyphens are always removed.
+fn join_word(head: &str, tail: &str, frequencies: &HashMap<String, usize>) -> String {
+    let prefix = hyphen_prefix(head).unwrap_or(head);
+    let joined = format!("{prefix}{tail}");
+    if head.ends_with('\u{AD}') {
+        return joined;
+    }
+    let hyphenated = format!("{prefix}-{tail}");
+    let count = |word: &str| frequencies.get(&word_key(word)).copied().unwrap_or(0);
+    if prefix.contains(HYPHENS) || count(&hyphenated) > count(&joined) {
+        hyphenated
+    } else {
+        joined
+    }
+}
+
+/// First or last word of the line, with the union of the word boxes of its spans
+fn edge_word(line: &Line, page_id: PageID, last: bool) -> Option<WordFragment> {
+    let text = if last {
+        line.text.split_whitespace().next_back()?
+    } else {
+        line.text.split_whitespace().next()?
+    };
+    let mut words: Vec<_> = line.spans.iter().flat_map(|span| span.words()).collect();
+    if last {
+        words.reverse();
+    }
+    let mut words = words.into_iter();
+    let first = words.next()?;
+    let mut bbox = first.bbox;
+    let mut len = first.text.len();
+    // A word set in several fonts is split across spans
+    for word in words {
+        if len >= text.len() {
+            break;
+        }
+        len += word.text.len();
+        bbox.merge(&word.bbox);
+    }
+    Some(WordFragment {
+        text: text.to_owned(),
+        page_id,
+        bbox,
+    })
+}
+
+/// Lines whose center is inside one of the parts of the block, in reading order
+fn block_lines<'a>(block: &Block, pages: &HashMap<PageID, &'a Page>) -> Vec<(PageID, &'a Line)> {
+    block
+        .page_fragments()
+        .into_iter()
+        .filter_map(|fragment| pages.get(&fragment.page_id).map(|page| (fragment, page)))
+        .flat_map(|(BlockFragment { page_id, bbox }, page)| {
+            page.lines
+                .iter()
+                .filter(move |line| {
+                    let (x, y) = line.bbox.center();
+                    x >= bbox.x0 && x <= bbox.x1 && y >= bbox.y0 && y <= bbox.y1
+                })
+                .map(move |line| (page_id, line))
+        })
+        .collect()
+}
+
+/// Hyphenated words ending a line of the block, continued by a lowercase word on the next one,
+/// which starts the next part of the block when it is split across pages
+fn line_breaks(lines: &[(PageID, &Line)]) -> Vec<(WordFragment, WordFragment)> {
+    lines
+        .windows(2)
+        .filter_map(|pair| {
+            let head = edge_word(pair[0].1, pair[0].0, true)?;
+            let tail = edge_word(pair[1].1, pair[1].0, false)?;
+            (hyphen_prefix(&head.text).is_some() && starts_lowercase(&tail.text))
+                .then_some((head, tail))
+        })
+        .collect()
+}
+
+/// Replaces the `head` and `tail` words separated by a single space or line break in `text`,
+/// starting at byte `from`. Returns the end of the joined word.
+fn replace_break(
+    text: &mut String,
+    from: usize,
+    head: &str,
+    tail: &str,
+    word: &str,
+) -> Option<usize> {
+    let is_boundary = |c: Option<char>| c.is_none_or(char::is_whitespace);
+    [' ', '\n'].into_iter().find_map(|separator| {
+        let pattern = format!("{head}{separator}{tail}");
+        let (offset, _) = text[from..].match_indices(&pattern).find(|(offset, _)| {
+            let start = from + offset;
+            is_boundary(text[..start].chars().next_back())
+                && is_boundary(text[start + pattern.len()..].chars().next())
+        })?;
+        let start = from + offset;
+        text.replace_range(start..start + pattern.len(), word);
+        Some(start + word.len())
+    })
+}
+
+/// Joins the words broken across the lines of the block, in the text of a text block and in
+/// each item of a list
+fn dehyphenate_block(
+    block: &mut Block,
+    pages: &HashMap<PageID, &Page>,
+    frequencies: &HashMap<String, usize>,
+) {
+    if !matches!(
+        block.kind,
+        BlockType::TextBlock(_) | BlockType::ListBlock(_)
+    ) {
+        return;
+    }
+    let breaks = line_breaks(&block_lines(block, pages));
+    if breaks.is_empty() {
+        return;
+    }
+    let mut texts: Vec<&mut String> = match &mut block.kind {
+        BlockType::TextBlock(text) => vec![&mut text.text],
+        BlockType::ListBlock(list) => list.items.iter_mut().map(|item| &mut item.text).collect(),
+        _ => return,
+    };
+    // The line breaks are in the reading order of the texts
+    let (mut text_idx, mut from) = (0, 0);
+    for (head, tail) in breaks {
+        let word = join_word(&head.text, &tail.text, frequencies);
+        let found = (text_idx..texts.len()).find_map(|idx| {
+            let start = if idx == text_idx { from } else { 0 };
+            replace_break(texts[idx], start, &head.text, &tail.text, &word).map(|end| (idx, end))
+        });
+        if let Some((idx, end)) = found {
+            (text_idx, from) = (idx, end);
+            block.hyphenations.push(Hyphenation {
+                text: word,
+                fragments: vec![head, tail],
+            });
+        }
+    }
+}
+
+/// Joins the word hyphenated at the end of the text block `block` with the first word of the
+/// following text block `next`, which may start the next page. Returns whether `next` is left
+/// empty.
+fn dehyphenate_across(
+    block: &mut Block,
+    next: &mut Block,
+    pages: &HashMap<PageID, &Page>,
+    frequencies: &HashMap<String, usize>,
+) -> bool {
+    let (BlockType::TextBlock(text), BlockType::TextBlock(next_text)) = (&block.kind, &next.kind)
+    else {
+        return false;
+    };
+    let (Some(head_text), Some(tail_text)) = (
+        text.text.split_whitespace().next_back(),
+        next_text.text.split_whitespace().next(),
+    ) else {
+        return false;
+    };
+    if hyphen_prefix(head_text).is_none() || !starts_lowercase(tail_text) {
+        return false;
+    }
+    // The words must end and start the lines of the blocks
+    let head = block_lines(block, pages)
+        .last()
+        .and_then(|(page_id, line)| edge_word(line, *page_id, true))
+        .filter(|head| head.text == head_text);
+    let tail = block_lines(next, pages)
+        .first()
+        .and_then(|(page_id, line)| edge_word(line, *page_id, false))
+        .filter(|tail| tail.text == tail_text);
+    let (Some(head), Some(tail)) = (head, tail) else {
+        return false;
+    };
+
+    let word = join_word(&head.text, &tail.text, frequencies);
+    let (BlockType::TextBlock(text), BlockType::TextBlock(next_text)) =
+        (&mut block.kind, &mut next.kind)
+    else {
+        return false;
+    };
+    let head_start = text.text.trim_end().len() - head.text.len();
+    text.text.truncate(head_start);
+    text.text.push_str(&word);
+    next_text.text = next_text.text.trim_start()[tail.text.len()..]
+        .trim_start()
+        .to_owned();
+    block.hyphenations.push(Hyphenation {
+        text: word,
+        fragments: vec![head, tail],
+    });
+    next_text.text.is_empty()
+}
+
+/// Joins the words hyphenated at the end of a line with their continuation, within the text
+/// and list blocks and between consecutive text blocks, skipping the headers, footers and
+/// repeated blocks between the end of a page and the start of the next one. The parts of each
+/// word are recorded in the `hyphenations` of the block.
+pub(crate) fn dehyphenate_blocks(blocks: Vec<Block>, pages: &[Page]) -> Vec<Block> {
+    let pages: HashMap<PageID, &Page> = pages.iter().map(|page| (page.id, page)).collect();
+    let frequencies = word_frequencies(&blocks);
+
+    let mut dehyphenated: Vec<Block> = Vec::with_capacity(blocks.len());
+    // Index in `dehyphenated` of the last text block, while only headers and footers follow it
+    let mut open_text: Option<usize> = None;
+    for mut block in blocks {
+        dehyphenate_block(&mut block, &pages, &frequencies);
+        if is_page_furniture(&block) {
+            dehyphenated.push(block);
+            continue;
+        }
+        if !matches!(block.kind, BlockType::TextBlock(_)) {
+            open_text = None;
+            dehyphenated.push(block);
+            continue;
+        }
+        if let Some(text_idx) = open_text {
+            if dehyphenate_across(
+                &mut dehyphenated[text_idx],
+                &mut block,
+                &pages,
+                &frequencies,
+            ) {
+                continue;
+            }
+        }
+        open_text = Some(dehyphenated.len());
+        dehyphenated.push(block);
+    }
+    dehyphenated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{List, ListItem, TextBlock},
+        entities::{BBox, CharSpan},
+    };
+    use image::DynamicImage;
+
+    fn bbox(x0: f32, y0: f32, x1: f32, y1: f32) -> BBox {
+        BBox { x0, y0, x1, y1 }
+    }
+
+    /// Line of a single span starting at `y`, 5 units per character
+    fn line(text: &str, y: f32) -> Line {
+        let width = 5.0 * text.chars().count() as f32;
+        Line::new_from_span(CharSpan {
+            bbox: bbox(50.0, y, 50.0 + width, y + 10.0),
+            text: text.to_string(),
+            rotation: 0.0,
+            font_name: "Helvetica".to_string(),
+            font_size: 10.0,
+            font_weight: None,
+            bold: false,
+            italic: false,
+            script: None,
+            char_start_idx: 0,
+            char_end_idx: text.chars().count().saturating_sub(1),
+        })
+    }
+
+    fn page(id: PageID, lines: Vec<Line>) -> Page {
+        Page {
+            id,
+            width: 600.0,
+            height: 800.0,
+            image: DynamicImage::new_rgb8(1, 1),
+            rotation: 0,
+            need_ocr: false,
+            lines,
+            ocr_lines: vec![],
+            layout: vec![],
+            embedded_images: vec![],
+        }
+    }
+
+    fn block(id: usize, kind: BlockType, page: PageID, y0: f32, y1: f32) -> Block {
+        Block::for_test(id, kind, page, bbox(40.0, y0, 560.0, y1))
+    }
+
+    fn text(text: &str) -> BlockType {
+        BlockType::TextBlock(TextBlock {
+            text: text.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_join_word() {
+        let frequencies = HashMap::from([("e-mail".to_string(), 3), ("email".to_string(), 1)]);
+        assert_eq!(join_word("informa-", "tion,", &frequencies), "information,");
+        // Split in the middle of a compound
+        assert_eq!(
+            join_word("state-of-", "the-art", &frequencies),
+            "state-of-the-art"
+        );
+        // The hyphenated form is more frequent in the document
+        assert_eq!(join_word("e-", "mail", &frequencies), "e-mail");
+        assert_eq!(join_word("hy\u{AD}", "phen", &frequencies), "hyphen");
+    }
+
+    #[test]
+    fn test_dehyphenate_within_block() {
+        let pages = [page(
+            0,
+            vec![
+                line("The retrieval of informa-", 100.0),
+                line("tion is a state-of-", 112.0),
+                line("the-art topic. Pre-", 124.0),
+                line("Processing is kept.", 136.0),
+            ],
+        )];
+        let blocks = vec![block(
+            0,
+            text(concat!(
+                "The retrieval of informa- tion is a state-of- the-art topic. ",
+                "Pre- Processing is kept."
+            )),
+            0,
+            95.0,
+            150.0,
+        )];
+        let blocks = dehyphenate_blocks(blocks, &pages);
+
+        let BlockType::TextBlock(ref text) = blocks[0].kind else {
+            panic!("expected a text block");
+        };
+        assert_eq!(
+            text.text,
+            "The retrieval of information is a state-of-the-art topic. Pre- Processing is kept."
+        );
+        let hyphenation = &blocks[0].hyphenations[0];
+        assert_eq!(hyphenation.text, "information");
+        let fragments: Vec<_> = hyphenation
+            .fragments
+            .iter()
+            .map(|f| (f.text.as_str(), f.bbox.y0))
+            .collect();
+        assert_eq!(fragments, [("informa-", 100.0), ("tion", 112.0)]);
+        // `informa-` ends the 25 characters line
+        assert_eq!(hyphenation.fragments[0].bbox.x1, 175.0);
+        assert_eq!(blocks[0].hyphenations.len(), 2);
+    }
+
+    #[test]
+    fn test_dehyphenate_list_items() {
+        let pages = [page(
+            0,
+            vec![
+                line("- Configure the connec-", 100.0),
+                line("tion pool", 112.0),
+            ],
+        )];
+        let blocks = vec![block(
+            0,
+            BlockType::ListBlock(List {
+                items: vec![ListItem::new(
+                    "- Configure the connec- tion pool",
+                    BBox::default(),
+                )],
+            }),
+            0,
+            95.0,
+            125.0,
+        )];
+        let blocks = dehyphenate_blocks(blocks, &pages);
+        let BlockType::ListBlock(ref list) = blocks[0].kind else {
+            panic!("expected a list block");
+        };
+        assert_eq!(list.items[0].text, "- Configure the connection pool");
+    }
+
+    #[test]
+    fn test_dehyphenate_across_pages() {
+        let pages = [
+            page(
+                0,
+                vec![
+                    line("The document ends with a hyphen-", 730.0),
+                    line("Page 1", 770.0),
+                ],
+            ),
+            page(
+                1,
+                vec![
+                    line("ated word. The paragraph goes on.", 60.0),
+                    line("A new paragraph.", 90.0),
+                ],
+            ),
+        ];
+        let footer = BlockType::Footer(TextBlock {
+            text: "Page 1".to_string(),
+        });
+        let blocks = vec![
+            block(0, text("The document ends with a hyphen-"), 0, 725.0, 745.0),
+            block(1, footer, 0, 765.0, 785.0),
+            block(2, text("ated word. The paragraph goes on."), 1, 55.0, 75.0),
+            block(3, text("A new paragraph."), 1, 85.0, 105.0),
+        ];
+        let blocks = dehyphenate_blocks(blocks, &pages);
+
+        assert_eq!(blocks.len(), 4);
+        let BlockType::TextBlock(ref first) = blocks[0].kind else {
+            panic!("expected a text block");
+        };
+        assert_eq!(first.text, "The document ends with a hyphenated");
+        let BlockType::TextBlock(ref next) = blocks[2].kind else {
+            panic!("expected a text block");
+        };
+        assert_eq!(next.text, "word. The paragraph goes on.");
+        let fragments: Vec<_> = blocks[0].hyphenations[0]
+            .fragments
+            .iter()
+            .map(|f| (f.text.as_str(), f.page_id))
+            .collect();
+        assert_eq!(fragments, [("hyphen-", 0), ("ated", 1)]);
+    }
+
+    #[test]
+    fn test_dehyphenate_paragraph_across_pages() {
+        let pages = [
+            page(
+                0,
+                vec![
+                    line("The document ends with a hyphen-", 730.0),
+                    line("Page 1", 770.0),
+                ],
+            ),
+            page(1, vec![line("ated word.", 60.0)]),
+        ];
+        // Paragraph merged across the page break, the footer isn't part of it
+        let mut paragraph = block(
+            0,
+            text("The document ends with a hyphen- ated word."),
+            0,
+            725.0,
+            745.0,
+        );
+        paragraph.append_continuation(block(1, text(""), 1, 55.0, 75.0));
+        let blocks = dehyphenate_blocks(vec![paragraph], &pages);
+
+        let BlockType::TextBlock(ref paragraph) = blocks[0].kind else {
+            panic!("expected a text block");
+        };
+        assert_eq!(paragraph.text, "The document ends with a hyphenated word.");
+        let fragments: Vec<_> = blocks[0].hyphenations[0]
+            .fragments
+            .iter()
+            .map(|f| (f.text.as_str(), f.page_id))
+            .collect();
+        assert_eq!(fragments, [("hyphen-", 0), ("ated", 1)]);
+    }
+
+    #[test]
+    fn test_dehyphenate_across_drops_empty_block() {
+        let pages = [
+            page(0, vec![line("A broken para-", 730.0)]),
+            page(1, vec![line("graph", 60.0), line("Next", 90.0)]),
+        ];
+        let blocks = vec![
+            block(0, text("A broken para-"), 0, 725.0, 745.0),
+            block(1, text("graph"), 1, 55.0, 75.0),
+            block(2, text("Next"), 1, 85.0, 105.0),
+        ];
+        let blocks = dehyphenate_blocks(blocks, &pages);
+        let ids: Vec<_> = blocks.iter().map(|b| b.id).collect();
+        assert_eq!(ids, [0, 2]);
+    }
+
+    #[test]
+    fn test_uppercase_continuation_is_kept() {
+        let pages = [
+            page(0, vec![line("Read the end-", 730.0)]),
+            page(1, vec![line("User guide", 60.0)]),
+        ];
+        let blocks = vec![
+            block(0, text("Read the end-"), 0, 725.0, 745.0),
+            block(1, text("User guide"), 1, 55.0, 75.0),
+        ];
+        let blocks = dehyphenate_blocks(blocks, &pages);
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks[0].hyphenations.is_empty());
+    }
+}
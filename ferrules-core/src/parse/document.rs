@@ -1,85 +1,326 @@
 use std::path::PathBuf;
-use std::{sync::Arc, time::Instant};
-
-use std::ops::Range;
+use std::{
+    panic::AssertUnwindSafe,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use tokio::{sync::mpsc, task::JoinSet};
+use futures::{FutureExt, Stream};
+use image::DynamicImage;
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinSet,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::sync::CancellationToken;
 use tracing::Instrument;
 
-use super::native::{ParseNativeQueue, ParseNativeRequest};
+use super::native::{ParseNativeDocument, ParseNativeQueue, ParseNativeRequest, RasterConfig};
 use super::{
-    merge::merge_elements_into_blocks, native::ParseNativePageResult, page::parse_page_full,
-    titles::title_levels_kmeans,
+    captions::{associate_captions, CaptionConfig},
+    dehyphenate::dehyphenate_blocks,
+    images::attach_embedded_images,
+    language::detect_document_language,
+    lists::merge_list_continuations,
+    merge::{merge_elements_into_blocks, MergeThresholds},
+    native::ParseNativePageResult,
+    normalize::TextNormalization,
+    page::{page_from_cache, parse_page_full, PageParseConfig},
+    page_cache::{CachedPage, PageCache, PageCacheKey},
+    page_range::PageRange,
+    paragraphs::merge_paragraph_continuations,
+    reading_order::{sort_elements_reading_order, ReadingOrder, DEFAULT_COLUMN_GAP_THRESHOLD},
+    running_headers,
+    sections::build_sections,
+    structure_tree::mark_structure_tree_blocks,
+    titles::{assign_title_levels, promote_large_text_titles, TitleLevelStrategy},
 };
 use crate::entities::DocumentMetadata;
 use crate::error::FerrulesError;
 use crate::{
     blocks::Block,
-    entities::{ElementType, Page, PageID, ParsedDocument, StructuredPage},
+    entities::{ElementType, Page, PageFailure, PageID, ParsedDocument, StructuredPage},
     layout::{
-        model::{ORTConfig, ORTLayoutParser},
-        ParseLayoutQueue,
+        model::{LayoutProvider, ORTConfig, ORTLayoutParser},
+        LayoutClassFilter, LayoutMode, LayoutRegion, ParseLayoutQueue,
+    },
+    metrics::{LayoutQueueStats, ParsingMetrics},
+    ocr::{
+        OCRLines, OCRParser, OCRQueue, OcrAccuracy, TextSource, DEFAULT_OCR_COVERAGE_THRESHOLD,
+        DEFAULT_OCR_MIN_CHARS, DEFAULT_OCR_MIN_CONFIDENCE,
     },
-    metrics::ParsingMetrics,
-    ocr::{OCRParser, OCRQueue},
     parse::table::{ParseTableQueue, TableParser, TableTransformer},
 };
 
+/// Number of messages buffered by [`FerrulesParser::parse_document_streaming`] before the
+/// parsing task waits for the consumer
+const STREAM_CHANNEL_SIZE: usize = 16;
+
 /// Configuration options for parsing documents with FerrulesParser
 #[derive(Debug, Clone)]
-pub struct FerrulesParseConfig<'a> {
+pub struct FerrulesParseConfig {
     /// Optional password for encrypted PDF documents
-    pub password: Option<&'a str>,
+    pub password: Option<String>,
 
     /// Whether to flatten the PDF before parsing. When true, flattens form fields and annotations
     /// into the document content for more consistent parsing results
     pub flatten_pdf: bool,
 
-    /// Optional range of pages to parse. When None, parses all pages
-    /// The range uses 0-based indexing (e.g., 0..5 parses first 5 pages)
-    pub page_range: Option<std::ops::Range<usize>>,
+    /// Normalization of the text extracted from the PDF: ligatures, non-breaking spaces,
+    /// soft hyphens, zero-width and control characters. `None` keeps the text as extracted by
+    /// pdfium. Defaults to `TextNormalization::default()`
+    pub text_normalization: Option<TextNormalization>,
+
+    /// Gaps, in page points, below which the characters are grouped into lines and the text
+    /// elements into paragraphs. Defaults to `MergeThresholds::default()`
+    pub merge_thresholds: MergeThresholds,
+
+    /// Join the words hyphenated at the end of a line with their continuation on the next
+    /// line, including across blocks and pages. The hyphen of compounds is kept and the parts
+    /// of each joined word are listed in the `hyphenations` of its block
+    pub dehyphenate: bool,
+
+    /// Merge the paragraphs split by a page break: a text block ending a page in the middle
+    /// of a sentence is joined with the text block starting the next page in lowercase. The
+    /// merged block lists both pages in `pages_id` and the bbox of each part in `fragments`.
+    /// Enabled by default
+    pub merge_paragraphs: bool,
+
+    /// Optional selection of pages to parse. When None, parses all pages.
+    /// Built from 0-based ranges (e.g., `PageRange::from(0..5)` parses the first 5 pages) or
+    /// parsed from 1-based segments like `"1,3,5-7,10-"`
+    pub page_range: Option<PageRange>,
 
     /// Optional directory path for debug output. When provided, saves intermediate parsing
     /// results and visualizations to this directory
     pub debug_dir: Option<std::path::PathBuf>,
+
+    /// Minimum horizontal gap (in page points) between elements for them to be considered
+    /// in separate columns when computing the reading order. Pages without such a gap keep
+    /// the single-column ordering
+    pub column_gap_threshold: f32,
+
+    /// Whether the columns of the pages are detected to order their elements. Defaults to
+    /// `ReadingOrder::Auto`
+    pub reading_order: ReadingOrder,
+
+    /// How the title levels are assigned. Defaults to `TitleLevelStrategy::OutlineFirst`
+    pub title_level_strategy: TitleLevelStrategy,
+
+    /// Turn into subtitles the short text elements set in a font noticeably larger than the
+    /// body text, for the headings the layout model reads as paragraphs. Disabled by default,
+    /// it can also promote pull quotes or large captions
+    pub promote_large_text_titles: bool,
+
+    /// Recognition level used for pages that need OCR. Defaults to `OcrAccuracy::Accurate`
+    pub ocr_accuracy: OcrAccuracy,
+
+    /// How native text and OCR results are combined on pages with a partial text layer.
+    /// Defaults to `TextSource::PreferNative`
+    pub text_source: TextSource,
+
+    /// Optional directory of the on-disk OCR cache. When provided, OCR results are stored
+    /// per page raster and reused when parsing the same pages again
+    pub ocr_cache_dir: Option<PathBuf>,
+
+    /// Optional directory of the on-disk page cache. When provided, the layout, OCR and
+    /// tables of each page are stored as soon as the page is parsed, and reused when parsing
+    /// the same document with the same options again, e.g. after a crash. The native parsing
+    /// of the cached pages still runs
+    pub page_cache_dir: Option<PathBuf>,
+
+    /// OCR lines recognized with a lower confidence are dropped. Defaults to
+    /// `DEFAULT_OCR_MIN_CONFIDENCE`
+    pub ocr_min_confidence: f32,
+
+    /// Pages whose native text lines cover less than this ratio of their text regions are
+    /// OCRed. Defaults to `DEFAULT_OCR_COVERAGE_THRESHOLD`
+    pub ocr_coverage_threshold: f32,
+
+    /// Pages whose native text lines have less non-whitespace characters are OCRed, e.g. scans
+    /// with a junk text layer. Defaults to `DEFAULT_OCR_MIN_CHARS`
+    pub ocr_min_chars: usize,
+
+    /// OCR every page, whatever its text layer. The OCR lines are combined with the native
+    /// ones according to `text_source`, it has no effect with `TextSource::NativeOnly`
+    pub force_ocr: bool,
+
+    /// Optional maximum time for getting the layout of a page, queueing included. When
+    /// exceeded, the page fails with `FerrulesError::LayoutTimeout`
+    pub layout_timeout: Option<Duration>,
+
+    /// Instead of failing, pages whose layout timed out are segmented from their text lines
+    /// only, grouping them into paragraphs
+    pub layout_timeout_fallback: bool,
+
+    /// Attach the raw layout detections of each page to the parsed `Page`s, serialized under
+    /// their `layout` key
+    pub include_layout: bool,
+
+    /// Whether pages are segmented by the layout model or from their text lines only.
+    /// Defaults to `LayoutMode::Model`
+    pub layout_mode: LayoutMode,
+
+    /// Optional allowlist of the layout classes kept on the pages, e.g. `["Table", "Text"]`
+    pub layout_class_filter: Option<Vec<String>>,
+
+    /// Layout classes ignored on the pages, e.g. `["Page-header", "Page-footer"]`. Ignored
+    /// regions overlapping text lines are parsed as plain text
+    pub layout_class_exclude: Vec<String>,
+
+    /// How text blocks such as "Figure 3: ..." are assigned as the caption of the closest
+    /// image or table
+    pub caption: CaptionConfig,
+
+    /// Detect the headers, footers and text repeated at the same position across the pages,
+    /// e.g. page numbers and document titles. They are listed once in the document metadata
+    /// and their blocks, flagged `repeated`, are skipped by the markdown, html and text outputs.
+    /// Disabled by default
+    pub strip_running_headers: bool,
+
+    /// Export the image blocks from the bitmaps embedded in the PDF, at their original
+    /// resolution and format, instead of cropping them from the page raster. Blocks without a
+    /// matching image object are still cropped
+    pub extract_embedded_images: bool,
+
+    /// Abort parsing on the first page that fails. By default failed pages are skipped and
+    /// listed in `ParsedDocument::failed_pages`
+    pub fail_fast: bool,
+
+    /// Resolution of the page rasters used for image crops, and for OCR when sharper than the
+    /// layout model input. Defaults to 72 DPI, one pixel per PDF point
+    pub raster_dpi: Option<f32>,
+
+    /// Optional maximum width and height of the page rasters, in pixels. Pages are also capped
+    /// to `RasterConfig::MAX_RASTER_PIXELS` pixels
+    pub max_raster_dim: Option<u32>,
+
+    /// Keep the page rasters in the parsed `Page`s, they are needed to crop the image and table
+    /// blocks from the pages. Otherwise the rasters are only rendered for the pages without a
+    /// text layer, to OCR them, and dropped once the page is parsed, which saves memory on
+    /// large documents. Always kept with a `debug_dir`. Defaults to true
+    pub keep_page_images: bool,
+
+    /// Segment and order the pages of tagged PDFs from their structure tree, the headings,
+    /// paragraphs, list items, tables and figures marked by the authoring tool, instead of the
+    /// layout model. Untagged pages are still segmented by the layout model. Defaults to true
+    pub prefer_tagged_structure: bool,
+
+    /// Optional maximum time for parsing the pages of the document. When exceeded, the pages
+    /// in flight are cancelled and parsing fails with `FerrulesError::Timeout`
+    pub timeout: Option<Duration>,
+
+    /// Stops parsing the document once cancelled: no new page is parsed and the pages in
+    /// flight fail with `FerrulesError::Cancelled`. The document is assembled from the pages
+    /// already parsed, the others are listed in `ParsedDocument::failed_pages`. Parsing fails
+    /// with `FerrulesError::Cancelled` when no page was parsed or with `fail_fast`
+    pub cancellation: CancellationToken,
 }
 
-impl Default for FerrulesParseConfig<'_> {
+impl Default for FerrulesParseConfig {
     fn default() -> Self {
         Self {
             password: None,
             flatten_pdf: true,
+            text_normalization: Some(TextNormalization::default()),
+            merge_thresholds: MergeThresholds::default(),
+            dehyphenate: true,
+            merge_paragraphs: true,
             page_range: None,
             debug_dir: None,
+            column_gap_threshold: DEFAULT_COLUMN_GAP_THRESHOLD,
+            reading_order: ReadingOrder::default(),
+            title_level_strategy: TitleLevelStrategy::default(),
+            promote_large_text_titles: false,
+            ocr_accuracy: OcrAccuracy::default(),
+            text_source: TextSource::default(),
+            ocr_cache_dir: None,
+            page_cache_dir: None,
+            ocr_min_confidence: DEFAULT_OCR_MIN_CONFIDENCE,
+            ocr_coverage_threshold: DEFAULT_OCR_COVERAGE_THRESHOLD,
+            ocr_min_chars: DEFAULT_OCR_MIN_CHARS,
+            force_ocr: false,
+            layout_timeout: None,
+            layout_timeout_fallback: false,
+            include_layout: false,
+            layout_mode: LayoutMode::default(),
+            layout_class_filter: None,
+            layout_class_exclude: Vec::new(),
+            caption: CaptionConfig::default(),
+            strip_running_headers: false,
+            extract_embedded_images: true,
+            fail_fast: false,
+            raster_dpi: None,
+            max_raster_dim: None,
+            keep_page_images: true,
+            prefer_tagged_structure: true,
+            timeout: None,
+            cancellation: CancellationToken::new(),
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn parse_task<F>(
     parse_native_result: ParseNativePageResult,
     layout_queue: ParseLayoutQueue,
     table_queue: ParseTableQueue,
     ocr_queue: OCRQueue,
+    page_config: PageParseConfig,
+    page_cache: Option<PageCache>,
     debug_dir: Option<PathBuf>,
     callback: Option<F>,
-) -> Result<StructuredPage, FerrulesError>
+) -> (PageID, Result<StructuredPage, FerrulesError>)
 where
     F: FnOnce(PageID) + Send + 'static + Clone,
 {
     let page_id = parse_native_result.page_id;
 
-    let result = parse_page_full(
+    if let Some(cached) = page_cache.as_ref().and_then(|cache| cache.load(page_id)) {
+        tracing::debug!("page {page_id} loaded from the page cache");
+        let page = page_from_cache(parse_native_result, cached, page_config.keep_page_image);
+        if let Some(callback) = callback {
+            callback(page_id)
+        }
+        return (page_id, Ok(page));
+    }
+    let layout_mode = page_config.layout_mode;
+    let result = AssertUnwindSafe(parse_page_full(
         parse_native_result,
         debug_dir,
         layout_queue.clone(),
         table_queue.clone(),
         ocr_queue.clone(),
-    )
-    .await;
+        page_config,
+    ))
+    .catch_unwind()
+    .await
+    .unwrap_or(Err(FerrulesError::PagePanic { page_id }));
+    // Pages segmented from their text lines after a layout timeout aren't cached, they are
+    // parsed again on the next run
+    let layout_fallback = |page: &StructuredPage| {
+        layout_mode == LayoutMode::Model && !page.tagged && page.metrics.layout_provider.is_none()
+    };
+    if let (Some(cache), Ok(page)) = (&page_cache, &result) {
+        if !layout_fallback(page) {
+            cache.store(page_id, &CachedPage::from(page));
+        }
+    }
     if let Some(callback) = callback {
         callback(page_id)
     }
-    result
+    (page_id, result)
+}
+
+/// Messages sent by [`FerrulesParser::parse_document_streaming`]
+#[derive(Debug)]
+pub enum ParseStreamEvent {
+    /// A page finished parsing. Pages are sent in completion order, not page order. The page is
+    /// shared with the document assembly, it's only copied if still held when the document is
+    /// assembled
+    Page(Arc<StructuredPage>),
+    /// Terminal message with the assembled document, after title levels and blocks are merged
+    Done(Result<ParsedDocument, FerrulesError>),
 }
 
 /// Core class Document parser that extracts structured content from PDF documents.
@@ -88,6 +329,7 @@ where
 /// layout analysis to extract text, structural elements, and content hierarchies from documents.
 #[derive(Clone)]
 pub struct FerrulesParser {
+    layout_model: Arc<ORTLayoutParser>,
     layout_queue: ParseLayoutQueue,
     native_queue: ParseNativeQueue,
     table_queue: ParseTableQueue,
@@ -106,22 +348,93 @@ impl FerrulesParser {
     /// # Panics
     /// Panics if the layout model cannot be loaded with the given configuration
     pub fn new(layout_config: ORTConfig) -> Self {
+        Self::with_native_queue(layout_config, ParseNativeQueue::new())
+    }
+
+    /// Creates a new FerrulesParser that parses documents natively in a pool of child processes
+    ///
+    /// pdfium is not thread-safe, so the default parser serializes native parsing of all
+    /// documents. Using `native_workers` processes lets a server parse several documents, and
+    /// the pages of a large document, in parallel. The binary must call [`crate::run_native_worker_if_requested`] at the very
+    /// start of its `main`.
+    ///
+    /// # Arguments
+    /// * `layout_config` - Configuration for the ONNX Runtime layout analysis model
+    /// * `native_workers` - Number of native parsing processes to spawn
+    ///
+    /// # Panics
+    /// Panics if the layout model cannot be loaded with the given configuration
+    pub fn with_native_workers(layout_config: ORTConfig, native_workers: usize) -> Self {
+        Self::with_native_queue(
+            layout_config,
+            ParseNativeQueue::new_process_pool(native_workers),
+        )
+    }
+
+    fn with_native_queue(layout_config: ORTConfig, native_queue: ParseNativeQueue) -> Self {
         let layout_model =
             Arc::new(ORTLayoutParser::new(layout_config.clone()).expect("can't load layout model"));
-        let native_queue = ParseNativeQueue::new();
-        let layout_queue = ParseLayoutQueue::new(layout_model);
+        let layout_queue = ParseLayoutQueue::new(layout_model.clone());
         let transformer = TableTransformer::new(&layout_config).ok();
         let table_parser = Arc::new(TableParser::new(transformer));
         let table_queue = ParseTableQueue::new(table_parser);
         let ocr_parser = Arc::new(OCRParser::new());
         let ocr_queue = OCRQueue::new(ocr_parser);
         Self {
+            layout_model,
             layout_queue,
             native_queue,
             table_queue,
             ocr_queue,
         }
     }
+
+    /// Execution provider the layout model runs on, after any registration fallback
+    pub fn layout_provider(&self) -> &LayoutProvider {
+        self.layout_queue.provider()
+    }
+
+    /// Runs a dummy layout inference so that the first parsed page doesn't pay for the
+    /// execution provider initialization. Returns once the layout workers are ready.
+    pub async fn warmup(&self) -> Result<(), FerrulesError> {
+        self.layout_queue.warmup().await?;
+        Ok(())
+    }
+
+    /// Queue depth, in-flight pages and inference latencies of the layout model
+    pub fn layout_stats(&self) -> LayoutQueueStats {
+        self.layout_queue.stats()
+    }
+
+    /// Runs the layout model on a single image, without the document parsing pipeline
+    ///
+    /// Inference runs on the calling thread and bypasses the layout queue. Call it from
+    /// `tokio::task::spawn_blocking` inside an async runtime.
+    ///
+    /// # Arguments
+    /// * `image` - Image of any size, resized to the model input size
+    ///
+    /// # Returns
+    /// The detected regions with their bbox in the image pixel coordinates
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use ferrules_core::{FerrulesParser, layout::model::ORTConfig};
+    ///
+    /// let parser = FerrulesParser::new(ORTConfig::default());
+    /// let image = image::open("form.png").unwrap();
+    /// for region in parser.detect_layout(&image).unwrap() {
+    ///     println!("{} ({:.2}): {:?}", region.label, region.score, region.bbox);
+    /// }
+    /// ```
+    pub fn detect_layout(&self, image: &DynamicImage) -> Result<Vec<LayoutRegion>, FerrulesError> {
+        let layout_bbox = self.layout_model.parse_layout(image, 1f32).map_err(|e| {
+            tracing::error!("Layout detection failed: {e:?}");
+            FerrulesError::LayoutParsingError
+        })?;
+        Ok(layout_bbox.into_iter().map(LayoutRegion::from).collect())
+    }
+
     /// Parses a document into a structured format with optional page-level progress callback
     ///
     /// # Arguments
@@ -150,59 +463,295 @@ impl FerrulesParser {
     ///     ).await.unwrap();
     /// }
     #[allow(clippy::too_many_arguments)]
-    #[tracing::instrument(skip(self, doc, page_callback), fields(doc_name = %doc_name))]
     pub async fn parse_document<F>(
         &self,
         doc: &[u8],
         doc_name: String,
-        config: FerrulesParseConfig<'_>,
+        config: FerrulesParseConfig,
         page_callback: Option<F>,
     ) -> Result<ParsedDocument, FerrulesError>
+    where
+        F: FnOnce(PageID) + Send + 'static + Clone,
+    {
+        self.parse_document_inner(doc, doc_name, config, page_callback, None)
+            .await
+    }
+
+    /// Parses a document, streaming each page as soon as it is parsed
+    ///
+    /// The returned stream yields a [`ParseStreamEvent::Page`] for every parsed page, followed
+    /// by a single [`ParseStreamEvent::Done`] containing the assembled document.
+    /// [`FerrulesParser::parse_document`] is the same parsing without the page events.
+    ///
+    /// # Arguments
+    /// * `doc` - Raw bytes of the document to parse
+    /// * `doc_name` - Name of the document
+    /// * `config` - Parsing configuration options
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use ferrules_core::{FerrulesParser, FerrulesParseConfig, ParseStreamEvent, layout::model::ORTConfig};
+    /// use futures::StreamExt;
+    ///
+    /// async fn parse() {
+    ///     let parser = FerrulesParser::new(ORTConfig::default());
+    ///     let doc_bytes = std::fs::read("document.pdf").unwrap();
+    ///     let mut events = parser.parse_document_streaming(
+    ///         &doc_bytes,
+    ///         "document.pdf".to_string(),
+    ///         FerrulesParseConfig::default(),
+    ///     );
+    ///     while let Some(event) = events.next().await {
+    ///         match event {
+    ///             ParseStreamEvent::Page(page) => println!("Parsed page {}", page.id),
+    ///             ParseStreamEvent::Done(doc) => println!("Parsed {} blocks", doc.unwrap().blocks.len()),
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn parse_document_streaming(
+        &self,
+        doc: &[u8],
+        doc_name: String,
+        config: FerrulesParseConfig,
+    ) -> impl Stream<Item = ParseStreamEvent> + Send + Unpin + 'static {
+        let (tx, rx) = mpsc::channel(STREAM_CHANNEL_SIZE);
+        let parser = self.clone();
+        let doc = doc.to_vec();
+
+        tokio::spawn(
+            async move {
+                let res = parser
+                    .parse_document_inner(
+                        &doc,
+                        doc_name,
+                        config,
+                        None::<fn(PageID)>,
+                        Some(tx.clone()),
+                    )
+                    .await;
+                let _ = tx.send(ParseStreamEvent::Done(res)).await;
+            }
+            .in_current_span(),
+        );
+        ReceiverStream::new(rx)
+    }
+
+    #[tracing::instrument(skip(self, doc, page_callback, page_tx), fields(doc_name = %doc_name))]
+    async fn parse_document_inner<F>(
+        &self,
+        doc: &[u8],
+        doc_name: String,
+        config: FerrulesParseConfig,
+        page_callback: Option<F>,
+        page_tx: Option<mpsc::Sender<ParseStreamEvent>>,
+    ) -> Result<ParsedDocument, FerrulesError>
     where
         F: FnOnce(PageID) + Send + 'static + Clone,
     {
         let FerrulesParseConfig {
             password,
             flatten_pdf,
+            text_normalization,
+            merge_thresholds,
+            dehyphenate,
+            merge_paragraphs,
             page_range,
             debug_dir,
+            column_gap_threshold,
+            reading_order,
+            title_level_strategy,
+            promote_large_text_titles: promote_large_text,
+            ocr_accuracy,
+            text_source,
+            ocr_cache_dir,
+            page_cache_dir,
+            ocr_min_confidence,
+            ocr_coverage_threshold,
+            ocr_min_chars,
+            force_ocr,
+            layout_timeout,
+            layout_timeout_fallback,
+            include_layout,
+            layout_mode,
+            layout_class_filter,
+            layout_class_exclude,
+            caption,
+            strip_running_headers,
+            extract_embedded_images,
+            fail_fast,
+            raster_dpi,
+            max_raster_dim,
+            keep_page_images,
+            prefer_tagged_structure,
+            timeout,
+            cancellation,
         } = config;
+        // Cancelled on timeout, without cancelling the token of the caller
+        let cancellation = cancellation.child_token();
+        // The debug output is drawn on the page rasters
+        let keep_page_image = keep_page_images || debug_dir.is_some();
+        let page_config = PageParseConfig {
+            ocr_accuracy,
+            text_source,
+            ocr_cache_dir,
+            ocr_min_confidence,
+            ocr_coverage_threshold,
+            ocr_min_chars,
+            force_ocr,
+            layout_timeout,
+            layout_timeout_fallback,
+            layout_mode,
+            layout_class_filter: LayoutClassFilter::new(
+                layout_class_filter.as_deref(),
+                &layout_class_exclude,
+                self.layout_queue.labels(),
+            )?,
+            cancellation: cancellation.clone(),
+            keep_page_image,
+            prefer_tagged_structure,
+        };
+        let raster = RasterConfig {
+            raster_dpi,
+            max_raster_dim,
+            skip_page_image: !keep_page_image,
+        };
+        let page_cache = page_cache_dir.map(|dir| {
+            let options = format!(
+                "{flatten_pdf}|{raster:?}|{text_normalization:?}|{merge_thresholds:?}|{}|{}",
+                page_config.cache_options(),
+                self.layout_queue.fingerprint()
+            );
+            PageCache {
+                dir,
+                key: PageCacheKey::new(doc, &options),
+            }
+        });
         let start_time = Instant::now();
-        let parsed_pages = self
-            .parse_doc_pages(
-                doc,
-                flatten_pdf,
-                password,
-                page_range,
-                debug_dir.clone(),
-                page_callback,
-            )
-            .await?;
+        let parse_pages = self.parse_doc_pages(
+            doc,
+            flatten_pdf,
+            password.as_deref(),
+            page_range,
+            raster,
+            text_normalization,
+            merge_thresholds,
+            page_config,
+            page_cache,
+            fail_fast,
+            cancellation.clone(),
+            debug_dir.clone(),
+            page_callback,
+            page_tx,
+        );
+        let (parsed_pages, native_document, failed_pages) = match timeout {
+            // Dropping the future aborts the page tasks of its `JoinSet`, the cancellation
+            // stops the native parsing and the OCR and layout requests in flight
+            Some(timeout) => tokio::time::timeout(timeout, parse_pages)
+                .await
+                .map_err(|_| {
+                    tracing::warn!("Parsing {doc_name} timed out after {timeout:?}");
+                    cancellation.cancel();
+                    FerrulesError::Timeout(timeout)
+                })??,
+            None => parse_pages.await?,
+        };
+        // Only the pages a stream consumer still holds are copied
+        let mut parsed_pages: Vec<StructuredPage> =
+            parsed_pages.into_iter().map(Arc::unwrap_or_clone).collect();
 
-        let all_elements = parsed_pages
+        let mut all_elements = parsed_pages
             .iter()
-            .flat_map(|p| p.elements.clone())
+            .flat_map(|p| {
+                // Tagged pages are already in the order of their structure tree
+                if p.tagged || reading_order == ReadingOrder::SingleColumn {
+                    p.elements.clone()
+                } else {
+                    sort_elements_reading_order(p.elements.clone(), p.width, column_gap_threshold)
+                }
+            })
             .collect::<Vec<_>>();
 
+        if promote_large_text {
+            promote_large_text_titles(&mut all_elements);
+        }
+
         let titles = all_elements
             .iter()
             .filter(|e| matches!(e.kind, ElementType::Title | ElementType::Subtitle))
             .collect::<Vec<_>>();
 
-        let title_level = title_levels_kmeans(&titles, 6);
+        let (title_level, title_levels) = assign_title_levels(
+            &titles,
+            title_level_strategy,
+            native_document.outline.as_deref(),
+        );
 
-        let doc_pages = parsed_pages
-            .iter()
+        let doc_pages: Vec<Page> = parsed_pages
+            .iter_mut()
             .map(|sp| Page {
                 id: sp.id,
                 width: sp.width,
                 height: sp.height,
+                rotation: sp.rotation,
                 need_ocr: sp.need_ocr,
-                image: sp.image.clone(),
+                // Moved instead of copied, unless the debug output still needs it
+                image: if debug_dir.is_some() {
+                    sp.image.clone()
+                } else {
+                    std::mem::take(&mut sp.image)
+                },
+                lines: if sp.need_ocr {
+                    sp.ocr_lines.clone()
+                } else {
+                    sp.native_lines.clone()
+                },
+                ocr_lines: sp
+                    .ocr_lines
+                    .iter()
+                    .filter_map(OCRLines::from_line)
+                    .collect(),
+                layout: if include_layout {
+                    sp.layout.clone()
+                } else {
+                    vec![]
+                },
+                embedded_images: if extract_embedded_images {
+                    sp.embedded_images.clone()
+                } else {
+                    vec![]
+                },
             })
             .collect();
 
-        let blocks = merge_elements_into_blocks(all_elements, title_level)?;
+        let (mut blocks, warnings) =
+            merge_elements_into_blocks(all_elements, title_level, &merge_thresholds);
+        let tagged_pages: Vec<PageID> = parsed_pages
+            .iter()
+            .filter(|p| p.tagged)
+            .map(|p| p.id)
+            .collect();
+        mark_structure_tree_blocks(&mut blocks, &tagged_pages);
+        let mut blocks = merge_list_continuations(blocks, &doc_pages);
+        attach_embedded_images(&mut blocks, &doc_pages);
+        let mut blocks = associate_captions(blocks, &caption);
+        let running_headers = if strip_running_headers {
+            running_headers::mark_running_headers(&mut blocks, parsed_pages.len())
+        } else {
+            Vec::new()
+        };
+        let blocks = if merge_paragraphs {
+            merge_paragraph_continuations(blocks, &doc_pages)
+        } else {
+            blocks
+        };
+        let blocks = if dehyphenate {
+            dehyphenate_blocks(blocks, &doc_pages)
+        } else {
+            blocks
+        };
+        let language = detect_document_language(&blocks);
+        let sections = build_sections(&blocks);
 
         if let Some(ref debug_dir) = debug_dir {
             self.save_debug_binary(debug_dir, &doc_name, &parsed_pages, &blocks);
@@ -220,8 +769,23 @@ impl FerrulesParser {
             pages: doc_pages,
             blocks,
             debug_path: debug_dir,
-            metadata: DocumentMetadata::new(duration),
+            metadata: DocumentMetadata {
+                layout_provider: Some(self.layout_queue.provider().to_string()),
+                language,
+                running_headers,
+                title_levels: Some(title_levels),
+                info: native_document.info,
+                ..DocumentMetadata::new(duration)
+            },
             metrics: parsing_metrics,
+            form_fields: parsed_pages
+                .iter()
+                .flat_map(|p| p.form_fields.iter().cloned())
+                .collect(),
+            outline: native_document.outline,
+            failed_pages,
+            warnings,
+            sections,
         })
     }
 
@@ -258,6 +822,9 @@ impl FerrulesParser {
                 image_data,
                 width: sp.width,
                 height: sp.height,
+                need_ocr: sp.need_ocr,
+                text_coverage: sp.text_coverage,
+                native_chars: sp.native_chars,
             });
         }
         let debug_doc = crate::debug_info::DebugDocument {
@@ -271,61 +838,338 @@ impl FerrulesParser {
     }
 
     #[allow(clippy::too_many_arguments)]
-    #[tracing::instrument(skip(self, data, callback), fields(flatten_pdf = flatten_pdf, page_range = ?page_range))]
+    #[tracing::instrument(skip(self, data, callback, page_tx), fields(flatten_pdf = flatten_pdf, page_range = ?page_range))]
     async fn parse_doc_pages<F>(
         &self,
         data: &[u8],
         flatten_pdf: bool,
         password: Option<&str>,
-        page_range: Option<Range<usize>>,
+        page_range: Option<PageRange>,
+        raster: RasterConfig,
+        text_normalization: Option<TextNormalization>,
+        merge_thresholds: MergeThresholds,
+        page_config: PageParseConfig,
+        page_cache: Option<PageCache>,
+        fail_fast: bool,
+        cancellation: CancellationToken,
         debug_dir: Option<PathBuf>,
         callback: Option<F>,
-    ) -> Result<Vec<StructuredPage>, FerrulesError>
+        page_tx: Option<mpsc::Sender<ParseStreamEvent>>,
+    ) -> Result<
+        (
+            Vec<Arc<StructuredPage>>,
+            ParseNativeDocument,
+            Vec<PageFailure>,
+        ),
+        FerrulesError,
+    >
     where
         F: FnOnce(PageID) + Send + 'static + Clone,
     {
         let mut set = JoinSet::new();
         let (native_tx, mut native_rx) = mpsc::channel(32);
-        let req = ParseNativeRequest::new(data, password, flatten_pdf, page_range, native_tx);
+        let (document_tx, document_rx) = oneshot::channel();
+        let req = ParseNativeRequest::new(
+            data,
+            password,
+            flatten_pdf,
+            page_range.clone(),
+            self.layout_queue.input_size(),
+            raster,
+            text_normalization,
+            merge_thresholds,
+            native_tx,
+            document_tx,
+            cancellation.clone(),
+        );
         self.native_queue.push(req).await?;
 
-        while let Some(native_page) = native_rx.recv().await {
-            match native_page {
-                Ok(parse_native_result) => {
-                    let tmp_dir = debug_dir.clone();
-                    let callback = callback.clone();
-                    set.spawn(
-                        parse_task(
-                            parse_native_result,
-                            self.layout_queue.clone(),
-                            self.table_queue.clone(),
-                            self.ocr_queue.clone(),
-                            tmp_dir,
-                            callback,
-                        )
-                        .in_current_span(),
-                    );
+        let mut native_error = None;
+        let mut native_done = false;
+        let mut native_pages = 0;
+        let mut failed_pages = Vec::new();
+        let mut parsed_pages = Vec::new();
+        // Pages are parsed as soon as they are natively parsed, and sent as soon as they finish
+        loop {
+            tokio::select! {
+                native_page = native_rx.recv(), if !native_done => {
+                    let Some(native_page) = native_page else {
+                        native_done = true;
+                        continue;
+                    };
+                    // Closing the receiver also stops the native parsing
+                    if cancellation.is_cancelled() {
+                        native_rx.close();
+                        native_done = true;
+                        continue;
+                    }
+                    match native_page {
+                        Ok(parse_native_result) => {
+                            native_pages += 1;
+                            let tmp_dir = debug_dir.clone();
+                            let callback = callback.clone();
+                            set.spawn(
+                                parse_task(
+                                    parse_native_result,
+                                    self.layout_queue.clone(),
+                                    self.table_queue.clone(),
+                                    self.ocr_queue.clone(),
+                                    page_config.clone(),
+                                    page_cache.clone(),
+                                    tmp_dir,
+                                    callback,
+                                )
+                                .in_current_span(),
+                            );
+                        }
+                        Err(e) => match e.downcast::<FerrulesError>() {
+                            Ok(FerrulesError::NativePageError { page_id, message }) => {
+                                tracing::warn!(
+                                    "Skipping page {page_id}, native parsing failed: {message}"
+                                );
+                                if fail_fast {
+                                    return Err(FerrulesError::NativePageError { page_id, message });
+                                }
+                                failed_pages.push(PageFailure {
+                                    page_id,
+                                    error: message,
+                                });
+                                if let Some(callback) = callback.clone() {
+                                    callback(page_id)
+                                }
+                            }
+                            Ok(e) => native_error = Some(e.into()),
+                            Err(e) => native_error = Some(e),
+                        },
+                    }
                 }
-                Err(_) => eprintln!("Error occured parsing page in doc"),
+                Some(result) = set.join_next(), if !set.is_empty() => match result {
+                    Ok((_, Ok(page))) => {
+                        let page = Arc::new(page);
+                        if let Some(page_tx) = &page_tx {
+                            // The consumer might have stopped listening, keep parsing the document
+                            let _ = page_tx.send(ParseStreamEvent::Page(Arc::clone(&page))).await;
+                        }
+                        parsed_pages.push(page);
+                    }
+                    Ok((page_id, Err(e))) => {
+                        tracing::warn!("Skipping page {page_id}, parsing failed: {e}");
+                        if fail_fast {
+                            return Err(e);
+                        }
+                        failed_pages.push(PageFailure {
+                            page_id,
+                            error: e.to_string(),
+                        });
+                    }
+                    Err(e) => {
+                        tracing::error!("Error Joining : {e:?}")
+                    }
+                },
+                else => break,
             }
         }
-
-        // Get results
-        let mut parsed_pages = Vec::new();
-        while let Some(result) = set.join_next().await {
-            match result {
-                Ok(Ok(page)) => {
-                    parsed_pages.push(page);
-                }
-                Ok(Err(e)) => {
-                    tracing::error!("Error parsing page : {e:?}")
-                }
-                Err(e) => {
-                    tracing::error!("Error Joining : {e:?}")
+        if native_pages == 0 {
+            if let Some(e) = native_error {
+                tracing::error!("Error parsing document natively: {e:?}");
+                return match e.downcast::<FerrulesError>() {
+                    Ok(
+                        err @ (FerrulesError::IncorrectPassword
+                        | FerrulesError::EncryptedDocument
+                        | FerrulesError::UnsupportedFormat(_)
+                        | FerrulesError::PdfiumLoad(_)
+                        | FerrulesError::Cancelled),
+                    ) => Err(err),
+                    _ => Err(FerrulesError::ParseNativeError),
+                };
+            }
+        }
+        // Sent before the pages, dropped without a value if the document couldn't be loaded
+        let native_document = document_rx.await.unwrap_or_default();
+        if cancellation.is_cancelled() {
+            if fail_fast || parsed_pages.is_empty() {
+                return Err(FerrulesError::Cancelled);
+            }
+            let page_ids = match page_range {
+                Some(range) => range.page_ids(native_document.page_count),
+                None => Some((0..native_document.page_count).collect()),
+            };
+            // Pages that were never parsed natively
+            for page_id in page_ids.unwrap_or_default() {
+                if !parsed_pages.iter().any(|p| p.id == page_id)
+                    && !failed_pages.iter().any(|f| f.page_id == page_id)
+                {
+                    failed_pages.push(PageFailure {
+                        page_id,
+                        error: FerrulesError::Cancelled.to_string(),
+                    });
                 }
             }
         }
         parsed_pages.sort_by(|p1, p2| p1.id.cmp(&p2.id));
-        Ok(parsed_pages)
+        failed_pages.sort_by_key(|failure| failure.page_id);
+        Ok((parsed_pages, native_document, failed_pages))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "runs the layout model"]
+    async fn test_detect_layout_image_coordinates() {
+        let mut img = RgbImage::from_pixel(800, 600, Rgb([255, 255, 255]));
+        for y0 in [80, 200, 320] {
+            for y in y0..y0 + 80 {
+                for x in 60..740 {
+                    if (y - y0) % 20 < 12 && (x / 10) % 6 != 0 {
+                        img.put_pixel(x, y, Rgb([0, 0, 0]));
+                    }
+                }
+            }
+        }
+        let image = DynamicImage::ImageRgb8(img);
+        let parser = FerrulesParser::new(ORTConfig::default());
+
+        let regions = tokio::task::spawn_blocking(move || parser.detect_layout(&image))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(!regions.is_empty());
+        for region in regions {
+            assert!((0.0..=1.0).contains(&region.score));
+            // Regions are in the 800x600 image coordinates, not the model input ones
+            assert!(region.bbox.x0 < 800.0 && region.bbox.x1 > 0.0);
+            assert!(region.bbox.y0 < 600.0 && region.bbox.y1 > 0.0);
+        }
+    }
+
+    fn fixture_document() -> Vec<u8> {
+        std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/rotated.pdf")).unwrap()
+    }
+
+    // Pages are segmented from their text lines, the layout model is not run
+    fn skip_layout_config() -> FerrulesParseConfig {
+        FerrulesParseConfig {
+            layout_mode: LayoutMode::Skip,
+            ..Default::default()
+        }
+    }
+
+    // OCR is not implemented on linux, forcing it fails every page
+    #[cfg(target_os = "linux")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_parse_document_skips_failed_pages() {
+        let parser = FerrulesParser::new(ORTConfig::default());
+        let config = FerrulesParseConfig {
+            force_ocr: true,
+            ..skip_layout_config()
+        };
+        let parsed_count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let callback_count = Arc::clone(&parsed_count);
+
+        let doc = parser
+            .parse_document(
+                &fixture_document(),
+                "rotated.pdf".to_string(),
+                config,
+                Some(move |_| {
+                    callback_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                }),
+            )
+            .await
+            .unwrap();
+
+        assert!(doc.pages.is_empty());
+        assert_eq!(
+            doc.failed_pages
+                .iter()
+                .map(|failure| failure.page_id)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+        assert!(doc.failed_pages.iter().all(|f| !f.error.is_empty()));
+        // The progress still advances on the failed pages
+        assert_eq!(parsed_count.load(std::sync::atomic::Ordering::SeqCst), 4);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_parse_document_fail_fast() {
+        let parser = FerrulesParser::new(ORTConfig::default());
+        let config = FerrulesParseConfig {
+            force_ocr: true,
+            fail_fast: true,
+            ..skip_layout_config()
+        };
+
+        let err = parser
+            .parse_document(
+                &fixture_document(),
+                "rotated.pdf".to_string(),
+                config,
+                None::<fn(PageID)>,
+            )
+            .await
+            .err()
+            .expect("the first failed page must abort parsing");
+
+        assert!(matches!(err, FerrulesError::OcrError(_)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_parse_document_timeout() {
+        let parser = FerrulesParser::new(ORTConfig::default());
+        let config = FerrulesParseConfig {
+            timeout: Some(Duration::ZERO),
+            ..skip_layout_config()
+        };
+
+        let err = parser
+            .parse_document(
+                &fixture_document(),
+                "rotated.pdf".to_string(),
+                config,
+                None::<fn(PageID)>,
+            )
+            .await
+            .err()
+            .expect("parsing must time out");
+
+        assert!(matches!(err, FerrulesError::Timeout(timeout) if timeout == Duration::ZERO));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_parse_document_streaming_pages_before_done() {
+        use futures::StreamExt;
+
+        let parser = FerrulesParser::new(ORTConfig::default());
+        let mut events = parser
+            .parse_document_streaming(
+                &fixture_document(),
+                "rotated.pdf".to_string(),
+                skip_layout_config(),
+            )
+            .collect::<Vec<_>>()
+            .await;
+
+        // The stream ends with the assembled document
+        let Some(ParseStreamEvent::Done(Ok(doc))) = events.pop() else {
+            panic!("the last event must be the parsed document");
+        };
+        let mut page_ids = events
+            .iter()
+            .map(|event| match event {
+                ParseStreamEvent::Page(page) => page.id,
+                ParseStreamEvent::Done(_) => panic!("`Done` must be the last event"),
+            })
+            .collect::<Vec<_>>();
+        page_ids.sort();
+        assert_eq!(page_ids, vec![0, 1, 2, 3]);
+        assert_eq!(doc.pages.len(), 4);
+        assert!(doc.failed_pages.is_empty());
     }
 }
@@ -0,0 +1,96 @@
+use crate::entities::{Element, ElementType};
+
+/// Minimum fraction of math symbols among the non-whitespace characters of a text element for
+/// it to be a display equation
+const MIN_FORMULA_SYMBOL_RATIO: f32 = 0.3;
+
+/// Text elements shorter than this are never equations, e.g. a lone `=` or a page number
+const MIN_FORMULA_CHARS: usize = 4;
+
+/// Operators, relations, arrows, math alphanumerics and super/subscripts. Greek letters and
+/// digits aren't counted, they are as common in the prose of the documents with equations
+fn is_math_symbol(c: char) -> bool {
+    matches!(
+        c,
+        '=' | '+' | '<' | '>' | '^' | '~' | '±' | '×' | '÷' | '·' | '¬' | '²' | '³' | '¹'
+            | '\u{2070}'..='\u{209F}' // Superscripts and subscripts
+            | '\u{2190}'..='\u{21FF}' // Arrows
+            | '\u{2200}'..='\u{22FF}' // Mathematical operators
+            | '\u{2308}'..='\u{230B}' // Ceiling and floor
+            | '\u{27C0}'..='\u{27EF}' // Miscellaneous mathematical symbols-A
+            | '\u{2980}'..='\u{2AFF}' // Miscellaneous mathematical symbols-B, operators
+            | '\u{1D400}'..='\u{1D7FF}' // Mathematical alphanumeric symbols
+    )
+}
+
+/// Fraction of math symbols among the non-whitespace characters of `text`
+fn math_symbol_ratio(text: &str) -> Option<f32> {
+    let (chars, symbols) = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .fold((0usize, 0usize), |(chars, symbols), c| {
+            (chars + 1, symbols + is_math_symbol(c) as usize)
+        });
+    (chars >= MIN_FORMULA_CHARS).then(|| symbols as f32 / chars as f32)
+}
+
+/// Turns the text elements dense in math symbols into formulas. The layout model misses the
+/// equations of some documents and their native text comes out as glyph soup in a paragraph.
+pub(crate) fn detect_formula_elements(elements: &mut [Element]) {
+    for element in elements
+        .iter_mut()
+        .filter(|element| matches!(element.kind, ElementType::Text))
+    {
+        if math_symbol_ratio(&element.text_block.text)
+            .is_some_and(|ratio| ratio >= MIN_FORMULA_SYMBOL_RATIO)
+        {
+            element.kind = ElementType::Formula;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::{BBox, ElementText};
+
+    fn text_element(id: usize, text: &str) -> Element {
+        Element {
+            id,
+            layout_block_id: id as i32,
+            text_block: ElementText {
+                text: text.to_owned(),
+                ..Default::default()
+            },
+            kind: ElementType::Text,
+            page_id: 0,
+            bbox: BBox::default(),
+            confidence: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_math_symbol_ratio() {
+        assert_eq!(math_symbol_ratio("∑ ∫ ≤ ≈"), Some(1.0));
+        assert_eq!(math_symbol_ratio("plain text"), Some(0.0));
+        assert_eq!(math_symbol_ratio("x="), None);
+    }
+
+    #[test]
+    fn test_detect_formula_elements() {
+        let mut elements = vec![
+            text_element(0, "The loss is minimized over the training set."),
+            text_element(1, "𝐿 = ∑ᵢ ‖𝑦ᵢ − 𝑓(𝑥ᵢ)‖²"),
+            text_element(2, "We set α = 0.5 in all the experiments."),
+        ];
+        elements.push(Element {
+            kind: ElementType::Caption,
+            ..text_element(3, "∀ε > 0 ∃δ > 0")
+        });
+
+        detect_formula_elements(&mut elements);
+
+        let kinds: Vec<_> = elements.iter().map(|e| e.kind.to_string()).collect();
+        assert_eq!(kinds, ["Text", "Formula", "Text", "Caption"]);
+    }
+}
@@ -0,0 +1,123 @@
+use image::GenericImageView;
+
+use crate::{
+    blocks::{Block, BlockType, EmbeddedImageInfo},
+    entities::Page,
+};
+
+/// Minimum IoU between an image block and an image object of its page to export the object
+const EMBEDDED_IMAGE_MIN_IOU: f32 = 0.5;
+
+/// Matches the single page image blocks to the embedded image object of their page with the
+/// highest overlap, so that the image is exported at its original resolution. Blocks without
+/// a matching object, e.g. vector drawings or multi-part figures, are cropped from the page.
+pub(crate) fn attach_embedded_images(blocks: &mut [Block], pages: &[Page]) {
+    for block in blocks.iter_mut() {
+        let BlockType::Image(image_block) = &mut block.kind else {
+            continue;
+        };
+        let [page_id] = block.pages_id[..] else {
+            continue;
+        };
+        let Some(page) = pages.iter().find(|p| p.id == page_id) else {
+            continue;
+        };
+
+        image_block.embedded = page
+            .embedded_images
+            .iter()
+            .enumerate()
+            .map(|(index, embedded)| (index, embedded, embedded.bbox.iou(&block.bbox)))
+            .filter(|(_, _, iou)| *iou >= EMBEDDED_IMAGE_MIN_IOU)
+            .max_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+            .map(|(index, embedded, _)| {
+                let (width, height) = embedded.image.dimensions();
+                EmbeddedImageInfo {
+                    index,
+                    width,
+                    height,
+                    format: embedded.format,
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{EmbeddedImageFormat, ImageBlock},
+        entities::{BBox, EmbeddedImage},
+    };
+    use image::DynamicImage;
+
+    fn bbox(x0: f32, y0: f32, x1: f32, y1: f32) -> BBox {
+        BBox { x0, y0, x1, y1 }
+    }
+
+    fn image_block(id: usize, bbox: BBox) -> Block {
+        Block::for_test(
+            id,
+            BlockType::Image(ImageBlock {
+                id,
+                ..Default::default()
+            }),
+            0,
+            bbox,
+        )
+    }
+
+    #[test]
+    fn test_attach_embedded_images() {
+        let page = Page {
+            id: 0,
+            width: 600.0,
+            height: 800.0,
+            image: DynamicImage::new_rgb8(600, 800),
+            rotation: 0,
+            need_ocr: false,
+            lines: vec![],
+            ocr_lines: vec![],
+            layout: vec![],
+            embedded_images: vec![
+                EmbeddedImage {
+                    bbox: bbox(300.0, 500.0, 500.0, 700.0),
+                    image: DynamicImage::new_rgb8(64, 64),
+                    format: EmbeddedImageFormat::Png,
+                },
+                EmbeddedImage {
+                    bbox: bbox(100.0, 100.0, 400.0, 300.0),
+                    image: DynamicImage::new_rgb8(1250, 833),
+                    format: EmbeddedImageFormat::Jpeg,
+                },
+            ],
+        };
+        let mut blocks = vec![
+            // Layout bbox slightly larger than the image object
+            image_block(0, bbox(95.0, 98.0, 405.0, 306.0)),
+            // Vector figure without an image object
+            image_block(1, bbox(50.0, 400.0, 250.0, 480.0)),
+        ];
+
+        attach_embedded_images(&mut blocks, &[page]);
+
+        let embedded = |block: &Block| match &block.kind {
+            BlockType::Image(image_block) => image_block
+                .embedded
+                .as_ref()
+                .map(|e| (e.index, e.width, e.height)),
+            _ => panic!("Expected Image block"),
+        };
+        assert_eq!(embedded(&blocks[0]), Some((1, 1250, 833)));
+        assert_eq!(embedded(&blocks[1]), None);
+
+        let paths = blocks
+            .iter()
+            .map(|block| match &block.kind {
+                BlockType::Image(image_block) => image_block.path(),
+                _ => panic!("Expected Image block"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["img_0.jpg", "img_1.png"]);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{
+    blocks::{Block, BlockType},
+    entities::DocumentLanguage,
+};
+
+/// Detection runs on the first bytes of text of the document, more text rarely changes the result
+pub(crate) const MAX_LANGUAGE_SAMPLE_LEN: usize = 20_000;
+
+/// Detects the dominant language over the text of the title, text and list blocks.
+/// Returns `None` when the document has no text or the language can't be detected.
+pub(crate) fn detect_document_language(blocks: &[Block]) -> Option<DocumentLanguage> {
+    let mut sample = String::new();
+    for block in blocks {
+        match &block.kind {
+            BlockType::TextBlock(text_block) => sample.push_str(&text_block.text),
+            BlockType::Title(title) => sample.push_str(&title.text),
+            BlockType::ListBlock(list) => sample.push_str(&list.text()),
+            _ => continue,
+        }
+        sample.push('\n');
+        if sample.len() >= MAX_LANGUAGE_SAMPLE_LEN {
+            break;
+        }
+    }
+    detect_text_language(&sample)
+}
+
+/// Dominant language of `sample`, `None` when it can't be detected
+pub(crate) fn detect_text_language(sample: &str) -> Option<DocumentLanguage> {
+    let info = whatlang::detect(sample)?;
+    Some(DocumentLanguage {
+        code: info.lang().code().to_owned(),
+        confidence: info.confidence(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{ImageBlock, TextBlock},
+        entities::BBox,
+    };
+
+    fn text_block(id: usize, text: &str) -> Block {
+        Block::for_test(
+            id,
+            BlockType::TextBlock(TextBlock {
+                text: text.to_string(),
+            }),
+            0,
+            BBox::default(),
+        )
+    }
+
+    #[test]
+    fn test_detect_document_language() {
+        let english = [text_block(
+            0,
+            "The quarterly report shows that revenue increased across all regions, driven by strong demand for our products.",
+        )];
+        let language = detect_document_language(&english).unwrap();
+        assert_eq!(language.code, "eng");
+        assert!(language.confidence > 0.5);
+
+        let french = [text_block(
+            0,
+            "Le rapport trimestriel montre que le chiffre d'affaires a augmenté dans toutes les régions, grâce à une forte demande.",
+        )];
+        assert_eq!(detect_document_language(&french).unwrap().code, "fra");
+
+        let no_text = [Block::for_test(
+            0,
+            BlockType::Image(ImageBlock {
+                id: 0,
+                caption: None,
+                embedded: None,
+            }),
+            0,
+            BBox::default(),
+        )];
+        assert!(detect_document_language(&no_text).is_none());
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use tracing::instrument;
+
+use crate::{
+    blocks::{ordered_marker, Block, BlockType},
+    entities::{Page, PageID},
+};
+
+/// Fraction of the page height, from the bottom for the first part and from the top for the
+/// second one, a list must reach to be continued on the next page
+const LIST_CONTINUATION_PAGE_MARGIN: f32 = 0.25;
+
+/// Markers of the first item of an ordered list, a list starting with one of them is a new list
+const FIRST_ITEM_MARKERS: [&str; 5] = ["1", "a", "A", "i", "I"];
+
+fn restarts_numbering(item: &str) -> bool {
+    ordered_marker(item).is_some_and(|marker| FIRST_ITEM_MARKERS.contains(&marker))
+}
+
+fn is_page_furniture(block: &Block) -> bool {
+    matches!(block.kind, BlockType::Header(_) | BlockType::Footer(_))
+}
+
+/// Whether the list block `next` continues `list` on the following page: `list` ends in the
+/// bottom margin of its page, `next` starts in the top margin of the next one and doesn't
+/// restart the numbering. A nested list restarting its numbering still continues `list`
+fn continues_list(list: &Block, next: &Block, page_heights: &HashMap<PageID, f32>) -> bool {
+    let (Some(&last_page), Some(&next_page)) = (list.pages_id.last(), next.pages_id.first()) else {
+        return false;
+    };
+    if next_page != last_page + 1 {
+        return false;
+    }
+    let (Some(&last_height), Some(&next_height)) =
+        (page_heights.get(&last_page), page_heights.get(&next_page))
+    else {
+        return false;
+    };
+    // The bbox of a continued list is the one of its first page
+    let ends_at_bottom = list.pages_id.len() > 1
+        || list.bbox.y1 >= last_height * (1.0 - LIST_CONTINUATION_PAGE_MARGIN);
+    let starts_at_top = next.bbox.y0 <= next_height * LIST_CONTINUATION_PAGE_MARGIN;
+    let restarts = match (&list.kind, &next.kind) {
+        (BlockType::ListBlock(list), BlockType::ListBlock(next_list)) => {
+            next_list.items.first().is_some_and(|item| {
+                item.level_after(&list.items) == 0 && restarts_numbering(&item.text)
+            })
+        }
+        _ => return false,
+    };
+    ends_at_bottom && starts_at_top && !restarts
+}
+
+/// Merges the list blocks split by a page break: a list ending at the bottom of a page is
+/// continued by the list starting the next page when only headers and footers are between
+/// them. A heading or any other block in between, or a new numbering starting at 1, keeps
+/// them separate. The continued list keeps the bbox of its first page, lists every page in
+/// `pages_id` and the bbox of each part in `fragments`.
+#[instrument(skip_all)]
+pub(crate) fn merge_list_continuations(blocks: Vec<Block>, pages: &[Page]) -> Vec<Block> {
+    let page_heights: HashMap<PageID, f32> =
+        pages.iter().map(|page| (page.id, page.height)).collect();
+
+    let mut merged: Vec<Block> = Vec::with_capacity(blocks.len());
+    // Index in `merged` of the last list block, while only headers and footers follow it
+    let mut open_list: Option<usize> = None;
+    for block in blocks {
+        if is_page_furniture(&block) {
+            merged.push(block);
+            continue;
+        }
+        if !matches!(block.kind, BlockType::ListBlock(_)) {
+            open_list = None;
+            merged.push(block);
+            continue;
+        }
+        if let Some(list_idx) =
+            open_list.filter(|&idx| continues_list(&merged[idx], &block, &page_heights))
+        {
+            merged[list_idx].append_continuation(block);
+            continue;
+        }
+        open_list = Some(merged.len());
+        merged.push(block);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{List, ListItem, ListMarker, TextBlock, Title},
+        entities::BBox,
+    };
+    use image::DynamicImage;
+
+    fn page(id: PageID) -> Page {
+        Page {
+            id,
+            width: 600.0,
+            height: 800.0,
+            image: DynamicImage::new_rgb8(1, 1),
+            rotation: 0,
+            need_ocr: false,
+            lines: vec![],
+            ocr_lines: vec![],
+            layout: vec![],
+            embedded_images: vec![],
+        }
+    }
+
+    fn block(id: usize, kind: BlockType, page: PageID, y0: f32, y1: f32) -> Block {
+        let bbox = BBox {
+            x0: 50.0,
+            y0,
+            x1: 550.0,
+            y1,
+        };
+        Block::for_test(id, kind, page, bbox)
+    }
+
+    /// List of the items, each leading space indents the item by 10 units
+    fn list(items: &[&str]) -> BlockType {
+        let mut list = List::default();
+        for text in items {
+            let indent = (text.len() - text.trim_start().len()) as f32;
+            let bbox = BBox {
+                x0: 50.0 + 10.0 * indent,
+                y0: 0.0,
+                x1: 550.0,
+                y1: 10.0,
+            };
+            let mut item = ListItem::new(text, bbox);
+            item.level = item.level_after(&list.items);
+            list.items.push(item);
+        }
+        BlockType::ListBlock(list)
+    }
+
+    fn levels(block: &Block) -> Vec<u8> {
+        match &block.kind {
+            BlockType::ListBlock(list) => list.items.iter().map(|item| item.level).collect(),
+            _ => panic!("expected a list block"),
+        }
+    }
+
+    fn footer(text: &str) -> BlockType {
+        BlockType::Footer(TextBlock {
+            text: text.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_merge_list_across_pages() {
+        let pages = [page(0), page(1)];
+        let blocks = vec![
+            block(0, list(&["1. Install", "2. Configure"]), 0, 500.0, 740.0),
+            block(1, footer("Page 1"), 0, 770.0, 780.0),
+            block(2, list(&["3. Run", "4. Check the logs"]), 1, 60.0, 120.0),
+            block(
+                3,
+                BlockType::TextBlock(TextBlock {
+                    text: "Done".to_string(),
+                }),
+                1,
+                140.0,
+                160.0,
+            ),
+        ];
+
+        let blocks = merge_list_continuations(blocks, &pages);
+
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].pages_id, vec![0, 1]);
+        let fragments: Vec<_> = blocks[0]
+            .fragments
+            .iter()
+            .map(|f| (f.page_id, f.bbox.y0))
+            .collect();
+        assert_eq!(fragments, [(0, 500.0), (1, 60.0)]);
+        match &blocks[0].kind {
+            BlockType::ListBlock(list) => assert_eq!(list.items.len(), 4),
+            _ => panic!("expected a list block"),
+        }
+        assert!(matches!(blocks[1].kind, BlockType::Footer(_)));
+    }
+
+    #[test]
+    fn test_list_item_marker() {
+        let item = ListItem::new("1. Install", BBox::default());
+        assert_eq!(
+            (item.marker, item.content()),
+            (ListMarker::Ordered, "Install")
+        );
+        let item = ListItem::new("(iv) Check", BBox::default());
+        assert_eq!(
+            (item.marker, item.content()),
+            (ListMarker::Ordered, "Check")
+        );
+        let item = ListItem::new(" • Bullet ", BBox::default());
+        assert_eq!(
+            (item.marker, item.content()),
+            (ListMarker::Unordered, "Bullet")
+        );
+        let item = ListItem::new("Plain item", BBox::default());
+        assert_eq!(
+            (item.marker, item.content()),
+            (ListMarker::Unordered, "Plain item")
+        );
+    }
+
+    #[test]
+    fn test_list_item_levels() {
+        let block = block(
+            0,
+            list(&[
+                "- alpha",
+                " - beta",
+                "  1. gamma",
+                "  2. delta",
+                " - epsilon",
+                "- zeta",
+            ]),
+            0,
+            100.0,
+            200.0,
+        );
+        assert_eq!(levels(&block), [0, 1, 2, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_merge_nested_list_across_pages() {
+        let pages = [page(0), page(1)];
+        // The nested list starting the next page restarts its own numbering
+        let blocks = vec![
+            block(0, list(&["1. Install", "2. Configure"]), 0, 600.0, 740.0),
+            block(1, list(&[" a. Set the port", "3. Run"]), 1, 60.0, 120.0),
+        ];
+
+        let blocks = merge_list_continuations(blocks, &pages);
+
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(levels(&blocks[0]), [0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_keep_separate_lists() {
+        let pages = [page(0), page(1)];
+        let title = BlockType::Title(Title {
+            level: 1,
+            text: "Next section".to_string(),
+        });
+
+        // A heading starts a new section on the next page
+        let blocks = vec![
+            block(0, list(&["- alpha"]), 0, 600.0, 740.0),
+            block(1, title, 1, 50.0, 70.0),
+            block(2, list(&["- beta"]), 1, 80.0, 120.0),
+        ];
+        assert_eq!(merge_list_continuations(blocks, &pages).len(), 3);
+
+        // The first list ends mid-page
+        let blocks = vec![
+            block(0, list(&["- alpha"]), 0, 100.0, 200.0),
+            block(1, list(&["- beta"]), 1, 60.0, 120.0),
+        ];
+        assert_eq!(merge_list_continuations(blocks, &pages).len(), 2);
+
+        // The numbering restarts
+        let blocks = vec![
+            block(0, list(&["1. alpha", "2. beta"]), 0, 600.0, 740.0),
+            block(1, list(&["1. gamma"]), 1, 60.0, 120.0),
+        ];
+        assert_eq!(merge_list_continuations(blocks, &pages).len(), 2);
+    }
+}
@@ -1,12 +1,18 @@
 use std::collections::HashMap;
 
+use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
 use tracing::instrument;
 
 use crate::{
-    blocks::{Block, BlockType, ImageBlock, List, TableBlock, TextBlock, Title, TitleLevel},
-    entities::{Element, ElementID, ElementType, Line, PageID},
+    blocks::{
+        Block, BlockSource, BlockType, CodeBlock, FormulaBlock, ImageBlock, List, ListItem,
+        TableBlock, TextBlock, Title, TitleLevel,
+    },
+    entities::{BBox, Element, ElementID, ElementType, Line, PageID, ParseWarning},
     error::FerrulesError,
-    layout::model::LayoutBBox,
+    layout::{model::LayoutBBox, LayoutClassFilter},
+    ocr::TextSource,
+    parse::code::code_text,
 };
 
 /// This constant defines the minimum required intersection ratio between the bounding box of an
@@ -27,6 +33,51 @@ const LAYOUT_DISTANCE_Y_WEIGHT: f32 = 1.0;
 /// This helps prevent incorrect assignments of text lines that are too far from layout blocks.
 const MAXIMUM_ASSIGNMENT_DISTANCE: f32 = 20.0;
 
+/// Default maximum distance, in page points, between the centres of two text elements merged
+/// into a paragraph, about 4.5pt (the square root of 20)
+pub const DEFAULT_PARAGRAPH_GAP: f32 = 4.472_136;
+
+/// Proximity thresholds, in page points, deciding when text is merged. The defaults keep the
+/// lines as pdfium breaks them.
+#[derive(Debug, Clone, Copy, PartialEq, Archive, RkyvDeserialize, RkyvSerialize)]
+#[archive(check_bytes)]
+pub struct MergeThresholds {
+    /// A span starting more than this below the bottom of the current line starts a new line.
+    /// Raise it for the documents whose lines are split by subscripts or uneven baselines
+    pub line_gap: f32,
+
+    /// Maximum distance between the centres of two consecutive text elements merged into one
+    /// paragraph block
+    pub paragraph_gap: f32,
+
+    /// A span starting more than this to the right of the end of the current line starts a
+    /// new line, e.g. to split the cells of a table written as a single line. Unbounded when
+    /// `None`
+    pub span_gap: Option<f32>,
+}
+
+impl Default for MergeThresholds {
+    fn default() -> Self {
+        Self {
+            line_gap: 0.0,
+            paragraph_gap: DEFAULT_PARAGRAPH_GAP,
+            span_gap: None,
+        }
+    }
+}
+
+/// Maximum vertical gap, relative to the line height, between two consecutive lines of the
+/// same paragraph in the lines-only segmentation.
+const HEURISTIC_PARAGRAPH_GAP_RATIO: f32 = 0.8;
+
+/// Maximum vertical gap, relative to the line height, between two parts of a code listing
+/// split by the layout model on its blank lines.
+const CODE_BLANK_LINES_GAP_RATIO: f32 = 2.5;
+
+/// Minimum IoU between a native text line and an OCR line for them to be considered
+/// the same line on the page. Only one of the two is kept when merging both sources.
+const MIN_NATIVE_OCR_IOU: f32 = 0.5;
+
 fn merge_or_create_elements(
     elements: &mut Vec<Element>,
     line: &Line,
@@ -164,6 +215,64 @@ pub(crate) fn merge_lines_layout(
     Ok(headers)
 }
 
+/// Lines-only segmentation, used when the layout model couldn't process a page.
+///
+/// Lines are read top to bottom and appended to the first paragraph they overlap horizontally
+/// and that ends right above them, otherwise they start a new paragraph. Every paragraph is
+/// returned as a `Text` layout box.
+pub(crate) fn heuristic_text_layout(lines: &[Line]) -> Vec<LayoutBBox> {
+    let mut sorted_lines: Vec<&Line> = lines.iter().filter(|l| !l.text.trim().is_empty()).collect();
+    sorted_lines.sort_by(|a, b| a.bbox.y0.total_cmp(&b.bbox.y0));
+
+    let mut layout: Vec<LayoutBBox> = Vec::new();
+    for line in sorted_lines {
+        let max_gap = line.bbox.height() * HEURISTIC_PARAGRAPH_GAP_RATIO;
+        let paragraph = layout.iter_mut().rev().find(|block| {
+            let gap = line.bbox.y0 - block.bbox.y1;
+            let overlap = line.bbox.x1.min(block.bbox.x1) - line.bbox.x0.max(block.bbox.x0);
+            gap <= max_gap && overlap > 0f32
+        });
+        match paragraph {
+            Some(block) => block.bbox.merge(&line.bbox),
+            None => layout.push(LayoutBBox {
+                id: layout.len() as i32,
+                bbox: line.bbox.clone(),
+                label: "Text".to_string(),
+                proba: 1f32,
+            }),
+        }
+    }
+    layout
+}
+
+/// Removes the layout boxes of the classes rejected by `filter`. Rejected boxes overlapping
+/// text lines are kept as `Text` boxes so that their lines still end up in a text block.
+pub(crate) fn filter_layout_classes(
+    layout: Vec<LayoutBBox>,
+    filter: &LayoutClassFilter,
+    lines: &[Line],
+) -> Vec<LayoutBBox> {
+    if filter.is_empty() {
+        return layout;
+    }
+    layout
+        .into_iter()
+        .filter_map(|mut layout_box| {
+            if filter.keeps(&layout_box.label) {
+                Some(layout_box)
+            } else if lines
+                .iter()
+                .any(|line| line.bbox.intersection(&layout_box.bbox) > 0f32)
+            {
+                layout_box.label = "Text".to_string();
+                Some(layout_box)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
 pub(crate) fn merge_remaining(
     elements: &mut Vec<Element>,
     remaining: &[&LayoutBBox],
@@ -196,17 +305,125 @@ pub(crate) fn merge_remaining(
     }
 }
 
+/// Combines the native text lines and the OCR lines of a page according to `text_source`.
+///
+/// When both sources are used, lines from the non-preferred source that overlap a line of the
+/// preferred source are dropped. Remaining lines are interleaved by vertical position.
+pub(crate) fn merge_native_ocr_lines(
+    native_lines: Vec<Line>,
+    ocr_lines: Vec<Line>,
+    text_source: TextSource,
+) -> Vec<Line> {
+    let (preferred, other) = match text_source {
+        TextSource::NativeOnly => return native_lines,
+        TextSource::OcrOnly => return ocr_lines,
+        TextSource::PreferNative => (native_lines, ocr_lines),
+        TextSource::PreferOcr => (ocr_lines, native_lines),
+    };
+
+    let kept = other
+        .into_iter()
+        .filter(|line| {
+            !preferred
+                .iter()
+                .any(|p| p.bbox.iou(&line.bbox) > MIN_NATIVE_OCR_IOU)
+        })
+        .collect::<Vec<_>>();
+
+    let mut lines = preferred;
+    lines.extend(kept);
+    lines.sort_by(|a, b| {
+        a.bbox
+            .y0
+            .total_cmp(&b.bbox.y0)
+            .then(a.bbox.x0.total_cmp(&b.bbox.x0))
+    });
+    lines
+}
+
+/// Turns a failed merge into a warning instead of aborting the document: the element is put
+/// back in `requeued` to start the next block. Returns whether the element was merged
+fn recover_merge(
+    merged: Result<(), FerrulesError>,
+    requeued: &mut Option<Element>,
+    warnings: &mut Vec<ParseWarning>,
+) -> bool {
+    let warning = match merged {
+        Ok(()) => return true,
+        Err(FerrulesError::BlockMergeError {
+            block_id,
+            kind,
+            element,
+        }) => {
+            let warning = ParseWarning {
+                page_id: Some(element.page_id),
+                message: format!(
+                    "element {} can't be merged into the {} block {block_id}, it starts a new block",
+                    element.id,
+                    kind.label()
+                ),
+            };
+            *requeued = Some(*element);
+            warning
+        }
+        Err(e) => ParseWarning {
+            page_id: None,
+            message: e.to_string(),
+        },
+    };
+    tracing::warn!("{}", warning.message);
+    warnings.push(warning);
+    false
+}
+
+/// Height of the first line of the element, or of the element without lines
+fn line_height(element: &Element) -> f32 {
+    element
+        .text_block
+        .lines
+        .first()
+        .map_or(element.bbox.height(), |line| line.bbox.height())
+}
+
+/// Whether `next` continues the title in `bbox`: the layout model can split a title line, or
+/// its lines, in several boxes. The boxes are less than a line height apart horizontally and
+/// vertically
+fn is_title_continuation(bbox: &BBox, next: &Element) -> bool {
+    let line_height = line_height(next);
+    let horizontal_gap = (next.bbox.x0 - bbox.x1).max(bbox.x0 - next.bbox.x1);
+    let vertical_gap = (next.bbox.y0 - bbox.y1).max(bbox.y0 - next.bbox.y1);
+    horizontal_gap < line_height && vertical_gap < line_height
+}
+
+/// Whether `next` is the part of the code listing in `bbox` after a few blank lines: it starts
+/// below it, within `CODE_BLANK_LINES_GAP_RATIO` line heights, and overlaps it horizontally
+fn is_code_continuation(bbox: &BBox, next: &Element) -> bool {
+    let line_height = line_height(next);
+    let vertical_gap = next.bbox.y0 - bbox.y1;
+    vertical_gap > -line_height
+        && vertical_gap < CODE_BLANK_LINES_GAP_RATIO * line_height
+        && bbox.overlap_x(&next.bbox) > 0.0
+}
+
+/// Groups the consecutive elements into blocks. An element that can't be merged into the
+/// block before it starts a new block and is reported in the returned warnings
 #[instrument(skip_all)]
 pub(crate) fn merge_elements_into_blocks(
     elements: Vec<Element>,
     title_level: HashMap<(PageID, ElementID), TitleLevel>,
-) -> Result<Vec<Block>, FerrulesError> {
+    thresholds: &MergeThresholds,
+) -> (Vec<Block>, Vec<ParseWarning>) {
     let mut element_it = elements.into_iter().peekable();
+    // Element that couldn't be merged into the previous block, it starts the next one
+    let mut requeued: Option<Element> = None;
+    let mut warnings = Vec::new();
 
     let mut blocks = Vec::new();
     let mut block_id = 0;
     let mut image_id = 0;
-    while let Some(mut curr_el) = element_it.next() {
+    // Formulas are numbered on each page for their `formula_{page}_{n}.png` image
+    let mut formula_ids: HashMap<PageID, usize> = HashMap::new();
+    while let Some(mut curr_el) = requeued.take().or_else(|| element_it.next()) {
         match &mut curr_el.kind {
             ElementType::Text => {
                 let mut text_block = Block {
@@ -216,16 +433,25 @@ pub(crate) fn merge_elements_into_blocks(
                     }),
                     pages_id: vec![curr_el.page_id],
                     bbox: curr_el.bbox,
+                    confidence: curr_el.confidence,
+                    links: curr_el.text_block.links,
+                    styles: curr_el.text_block.styles,
+                    source: BlockSource::Layout,
+                    hyphenations: vec![],
+                    repeated: false,
+                    fragments: vec![],
                 };
                 // TODO: This might be a bug here
                 // Check to see if we have another text block that is close
                 while let Some(next_el) = element_it.peek() {
                     if matches!(next_el.kind, crate::entities::ElementType::Text)
-                        && (text_block.bbox.distance(&next_el.bbox, 1.0, 1.0)
-                            < MAXIMUM_ASSIGNMENT_DISTANCE)
+                        && (text_block.bbox.distance(&next_el.bbox, 1.0, 1.0).sqrt()
+                            < thresholds.paragraph_gap)
                     {
                         let next_el = element_it.next().unwrap();
-                        text_block.merge(next_el)?;
+                        if !recover_merge(text_block.merge(next_el), &mut requeued, &mut warnings) {
+                            break;
+                        }
                     } else {
                         break;
                     }
@@ -237,17 +463,32 @@ pub(crate) fn merge_elements_into_blocks(
                 let mut list_block = Block {
                     id: block_id,
                     kind: BlockType::ListBlock(List {
-                        items: vec![curr_el.text_block.text],
+                        items: vec![ListItem::new(
+                            &curr_el.text_block.text,
+                            curr_el.bbox.clone(),
+                        )],
                     }),
                     pages_id: vec![curr_el.page_id],
                     bbox: curr_el.bbox,
+                    confidence: curr_el.confidence,
+                    links: curr_el.text_block.links,
+                    styles: curr_el.text_block.styles,
+                    source: BlockSource::Layout,
+                    hyphenations: vec![],
+                    repeated: false,
+                    fragments: vec![],
                 };
 
                 while let Some(next_el) = element_it.peek() {
                     // TODO: add constraint on gap between bounding boxes on all dimensions (l,r,b,t)
-                    if matches!(next_el.kind, crate::entities::ElementType::ListItem) {
+                    // Lists split by a page break are merged by `merge_list_continuations`
+                    if matches!(next_el.kind, crate::entities::ElementType::ListItem)
+                        && next_el.page_id == curr_el.page_id
+                    {
                         let next_el = element_it.next().unwrap();
-                        list_block.merge(next_el)?;
+                        if !recover_merge(list_block.merge(next_el), &mut requeued, &mut warnings) {
+                            break;
+                        }
                     } else {
                         break;
                     }
@@ -268,6 +509,13 @@ pub(crate) fn merge_elements_into_blocks(
                                 }),
                                 pages_id: vec![curr_el.page_id],
                                 bbox: curr_el.bbox,
+                                confidence: curr_el.confidence,
+                                links: curr_el.text_block.links,
+                                styles: curr_el.text_block.styles,
+                                source: BlockSource::Layout,
+                                hyphenations: vec![],
+                                repeated: false,
+                                fragments: vec![],
                             };
                             element_it.next();
                             block_id += 1;
@@ -280,18 +528,36 @@ pub(crate) fn merge_elements_into_blocks(
                                 | crate::entities::ElementType::Caption => {
                                     // Merge this with a the caption
                                     curr_el.text_block.append_line(&next_el.text_block.text);
+                                    curr_el
+                                        .text_block
+                                        .links
+                                        .extend(next_el.text_block.links.iter().cloned());
+                                    curr_el
+                                        .text_block
+                                        .styles
+                                        .extend(next_el.text_block.styles.iter().cloned());
+                                    curr_el.confidence = curr_el.confidence.min(next_el.confidence);
                                     element_it.next();
                                 }
                                 crate::entities::ElementType::Image => {
                                     curr_el.bbox.merge(&next_el.bbox);
+                                    let confidence = curr_el.confidence.min(next_el.confidence);
                                     let img_block = Block {
                                         id: block_id,
                                         kind: BlockType::Image(ImageBlock {
                                             id: image_id,
                                             caption: Some(curr_el.text_block.text),
+                                            embedded: None,
                                         }),
                                         pages_id: vec![next_el.page_id],
                                         bbox: curr_el.bbox,
+                                        confidence,
+                                        links: vec![],
+                                        styles: vec![],
+                                        source: BlockSource::Layout,
+                                        hyphenations: vec![],
+                                        repeated: false,
+                                        fragments: vec![],
                                     };
                                     image_id += 1;
                                     block_id += 1;
@@ -308,6 +574,13 @@ pub(crate) fn merge_elements_into_blocks(
                                         }),
                                         pages_id: vec![curr_el.page_id],
                                         bbox: curr_el.bbox,
+                                        confidence: curr_el.confidence,
+                                        links: curr_el.text_block.links,
+                                        styles: curr_el.text_block.styles,
+                                        source: BlockSource::Layout,
+                                        hyphenations: vec![],
+                                        repeated: false,
+                                        fragments: vec![],
                                     };
                                     block_id += 1;
                                     blocks.push(text_block);
@@ -319,59 +592,42 @@ pub(crate) fn merge_elements_into_blocks(
                 }
             }
             ElementType::Image => {
-                match element_it.peek() {
-                    None => {
-                        let block = Block {
-                            id: block_id,
-                            kind: crate::blocks::BlockType::Image(ImageBlock {
-                                id: image_id,
-                                caption: None,
-                            }),
-                            pages_id: vec![curr_el.page_id],
-                            bbox: curr_el.bbox,
-                        };
-                        element_it.next();
-                        image_id += 1;
-                        block_id += 1;
-                        blocks.push(block);
-                    }
-                    Some(next_el) => {
-                        match &next_el.kind {
-                            crate::entities::ElementType::FootNote
-                            | crate::entities::ElementType::Caption => {
-                                // TODO: check if there is a case where there is multiple caption associated with the same image
-                                let next_el = element_it.next().unwrap();
-                                curr_el.bbox.merge(&next_el.bbox);
-                                let block = Block {
-                                    id: block_id,
-                                    kind: crate::blocks::BlockType::Image(ImageBlock {
-                                        id: image_id,
-                                        caption: Some(next_el.text_block.text),
-                                    }),
-                                    pages_id: vec![curr_el.page_id],
-                                    bbox: curr_el.bbox,
-                                };
-                                image_id += 1;
-                                block_id += 1;
-                                blocks.push(block);
-                            }
-                            _ => {
-                                let block = Block {
-                                    id: block_id,
-                                    kind: crate::blocks::BlockType::Image(ImageBlock {
-                                        id: image_id,
-                                        caption: None,
-                                    }),
-                                    pages_id: vec![curr_el.page_id],
-                                    bbox: curr_el.bbox,
-                                };
-                                image_id += 1;
-                                block_id += 1;
-                                blocks.push(block);
-                            }
-                        }
+                let mut img_block = Block {
+                    id: block_id,
+                    kind: BlockType::Image(ImageBlock {
+                        id: image_id,
+                        caption: None,
+                        embedded: None,
+                    }),
+                    pages_id: vec![curr_el.page_id],
+                    bbox: curr_el.bbox,
+                    confidence: curr_el.confidence,
+                    links: vec![],
+                    styles: vec![],
+                    source: BlockSource::Layout,
+                    hyphenations: vec![],
+                    repeated: false,
+                    fragments: vec![],
+                };
+                // Multi-part figures are detected as several overlapping pictures
+                while let Some(next_el) = element_it.next_if(|next_el| {
+                    matches!(next_el.kind, ElementType::Image)
+                        && next_el.page_id == curr_el.page_id
+                        && img_block.bbox.intersection(&next_el.bbox) > 0.0
+                }) {
+                    if !recover_merge(img_block.merge(next_el), &mut requeued, &mut warnings) {
+                        break;
                     }
                 }
+                // TODO: check if there is a case where there is multiple caption associated with the same image
+                if let Some(caption_el) = element_it.next_if(|next_el| {
+                    matches!(next_el.kind, ElementType::FootNote | ElementType::Caption)
+                }) {
+                    recover_merge(img_block.merge(caption_el), &mut requeued, &mut warnings);
+                }
+                image_id += 1;
+                block_id += 1;
+                blocks.push(img_block);
             }
             ElementType::Header => {
                 let mut header_block = Block {
@@ -381,12 +637,22 @@ pub(crate) fn merge_elements_into_blocks(
                     }),
                     pages_id: vec![curr_el.page_id],
                     bbox: curr_el.bbox,
+                    confidence: curr_el.confidence,
+                    links: curr_el.text_block.links,
+                    styles: curr_el.text_block.styles,
+                    source: BlockSource::Layout,
+                    hyphenations: vec![],
+                    repeated: false,
+                    fragments: vec![],
                 };
 
                 while let Some(next_el) = element_it.peek() {
                     if matches!(next_el.kind, crate::entities::ElementType::Header) {
                         let next_el = element_it.next().unwrap();
-                        header_block.merge(next_el)?;
+                        if !recover_merge(header_block.merge(next_el), &mut requeued, &mut warnings)
+                        {
+                            break;
+                        }
                     } else {
                         break;
                     }
@@ -402,12 +668,22 @@ pub(crate) fn merge_elements_into_blocks(
                     }),
                     pages_id: vec![curr_el.page_id],
                     bbox: curr_el.bbox,
+                    confidence: curr_el.confidence,
+                    links: curr_el.text_block.links,
+                    styles: curr_el.text_block.styles,
+                    source: BlockSource::Layout,
+                    hyphenations: vec![],
+                    repeated: false,
+                    fragments: vec![],
                 };
 
                 while let Some(next_el) = element_it.peek() {
                     if matches!(next_el.kind, ElementType::Footer) {
                         let next_el = element_it.next().unwrap();
-                        footer_block.merge(next_el)?;
+                        if !recover_merge(footer_block.merge(next_el), &mut requeued, &mut warnings)
+                        {
+                            break;
+                        }
                     } else {
                         break;
                     }
@@ -419,7 +695,7 @@ pub(crate) fn merge_elements_into_blocks(
                 let lvl = title_level
                     .get(&(curr_el.page_id, curr_el.id))
                     .unwrap_or(&0u8);
-                let title = Block {
+                let mut title = Block {
                     id: block_id,
                     kind: BlockType::Title(Title {
                         level: *lvl,
@@ -427,7 +703,37 @@ pub(crate) fn merge_elements_into_blocks(
                     }),
                     pages_id: vec![curr_el.page_id],
                     bbox: curr_el.bbox,
+                    confidence: curr_el.confidence,
+                    links: curr_el.text_block.links,
+                    styles: curr_el.text_block.styles,
+                    source: BlockSource::Layout,
+                    hyphenations: vec![],
+                    repeated: false,
+                    fragments: vec![],
                 };
+                // Layout can split a single title line in multiple boxes
+                while let Some(next_el) = element_it.peek() {
+                    let same_kind = matches!(
+                        (&curr_el.kind, &next_el.kind),
+                        (ElementType::Title, ElementType::Title)
+                            | (ElementType::Subtitle, ElementType::Subtitle)
+                    );
+                    if same_kind
+                        && next_el.page_id == curr_el.page_id
+                        && is_title_continuation(&title.bbox, next_el)
+                    {
+                        let next_el = element_it.next().unwrap();
+                        let next_lvl = *title_level
+                            .get(&(next_el.page_id, next_el.id))
+                            .unwrap_or(&0u8);
+                        let merged = title.merge_title(next_el, next_lvl);
+                        if !recover_merge(merged, &mut requeued, &mut warnings) {
+                            break;
+                        }
+                    } else {
+                        break;
+                    }
+                }
                 block_id += 1;
                 blocks.push(title);
             }
@@ -443,21 +749,105 @@ pub(crate) fn merge_elements_into_blocks(
                     })),
                     pages_id: vec![curr_el.page_id],
                     bbox: curr_el.bbox,
+                    confidence: curr_el.confidence,
+                    links: vec![],
+                    styles: vec![],
+                    source: BlockSource::Layout,
+                    hyphenations: vec![],
+                    repeated: false,
+                    fragments: vec![],
                 };
                 block_id += 1;
                 blocks.push(table_block);
             }
+            ElementType::FormField(form_field) => {
+                blocks.push(Block {
+                    id: block_id,
+                    kind: BlockType::FormField(form_field.clone()),
+                    pages_id: vec![curr_el.page_id],
+                    bbox: curr_el.bbox,
+                    confidence: curr_el.confidence,
+                    links: vec![],
+                    styles: vec![],
+                    source: BlockSource::Layout,
+                    hyphenations: vec![],
+                    repeated: false,
+                    fragments: vec![],
+                });
+                block_id += 1;
+            }
+            ElementType::Formula => {
+                let formula_id = formula_ids.entry(curr_el.page_id).or_default();
+                blocks.push(Block {
+                    id: block_id,
+                    kind: BlockType::Formula(FormulaBlock {
+                        id: *formula_id,
+                        page_id: curr_el.page_id,
+                        raw_text: curr_el.text_block.text,
+                    }),
+                    pages_id: vec![curr_el.page_id],
+                    bbox: curr_el.bbox,
+                    confidence: curr_el.confidence,
+                    links: vec![],
+                    styles: vec![],
+                    source: BlockSource::Layout,
+                    hyphenations: vec![],
+                    repeated: false,
+                    fragments: vec![],
+                });
+                *formula_id += 1;
+                block_id += 1;
+            }
+            ElementType::Code => {
+                // The layout model can split a listing on its blank lines, the parts are
+                // joined before rebuilding the indentation from the position of the lines
+                let mut lines = std::mem::take(&mut curr_el.text_block.lines);
+                let mut bbox = curr_el.bbox.clone();
+                let mut confidence = curr_el.confidence;
+                while let Some(next_el) = element_it.next_if(|next_el| {
+                    matches!(next_el.kind, ElementType::Code)
+                        && next_el.page_id == curr_el.page_id
+                        && is_code_continuation(&bbox, next_el)
+                }) {
+                    lines.extend(next_el.text_block.lines);
+                    bbox.merge(&next_el.bbox);
+                    confidence = confidence.min(next_el.confidence);
+                }
+                let text = if lines.is_empty() {
+                    curr_el.text_block.text
+                } else {
+                    code_text(&lines)
+                };
+                blocks.push(Block {
+                    id: block_id,
+                    kind: BlockType::Code(CodeBlock {
+                        text,
+                        language: None,
+                    }),
+                    pages_id: vec![curr_el.page_id],
+                    bbox,
+                    confidence,
+                    links: vec![],
+                    styles: vec![],
+                    source: BlockSource::Layout,
+                    hyphenations: vec![],
+                    repeated: false,
+                    fragments: vec![],
+                });
+                block_id += 1;
+            }
         }
     }
-    Ok(blocks)
+    (blocks, warnings)
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
-    use crate::entities::BBox;
+    use crate::entities::ElementLine;
     use crate::entities::ElementText;
+    use crate::entities::TextDirection;
 
     fn create_text_element(id: usize, page_id: usize, text: &str, bbox: BBox) -> Element {
         Element {
@@ -466,9 +856,14 @@ mod tests {
             kind: ElementType::Text,
             text_block: ElementText {
                 text: text.to_owned(),
+                links: vec![],
+                styles: vec![],
+                font_size: None,
+                lines: vec![],
             },
             page_id,
             bbox,
+            confidence: 1.0,
         }
     }
 
@@ -479,9 +874,14 @@ mod tests {
             kind: ElementType::ListItem,
             text_block: ElementText {
                 text: text.to_string(),
+                links: vec![],
+                styles: vec![],
+                font_size: None,
+                lines: vec![],
             },
             page_id,
             bbox,
+            confidence: 1.0,
         }
     }
 
@@ -492,9 +892,14 @@ mod tests {
             kind: ElementType::Caption,
             text_block: ElementText {
                 text: text.to_string(),
+                links: vec![],
+                styles: vec![],
+                font_size: None,
+                lines: vec![],
             },
             page_id,
             bbox,
+            confidence: 1.0,
         }
     }
 
@@ -505,9 +910,14 @@ mod tests {
             kind: ElementType::FootNote,
             text_block: ElementText {
                 text: text.to_string(),
+                links: vec![],
+                styles: vec![],
+                font_size: None,
+                lines: vec![],
             },
             page_id,
             bbox,
+            confidence: 1.0,
         }
     }
     fn create_image_element(id: usize, page_id: usize, bbox: BBox) -> Element {
@@ -518,6 +928,7 @@ mod tests {
             text_block: ElementText::default(),
             page_id,
             bbox,
+            confidence: 1.0,
         }
     }
 
@@ -541,7 +952,8 @@ mod tests {
             create_text_element(1, 1, "Second paragraph", bbox2),
         ];
 
-        let blocks = merge_elements_into_blocks(elements, HashMap::new())?;
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
 
         assert_eq!(blocks.len(), 1);
         if let BlockType::TextBlock(text) = &blocks[0].kind {
@@ -574,13 +986,14 @@ mod tests {
             create_text_element(2, 1, "Random text", bbox2),
         ];
 
-        let blocks = merge_elements_into_blocks(elements, HashMap::new())?;
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
 
         assert_eq!(blocks.len(), 2);
         if let BlockType::ListBlock(list) = &blocks[0].kind {
             assert_eq!(list.items.len(), 2);
-            assert_eq!(list.items[0], "First item");
-            assert_eq!(list.items[1], "Second item");
+            assert_eq!(list.items[0].text, "First item");
+            assert_eq!(list.items[1].text, "Second item");
         } else {
             panic!("Expected ListItem");
         }
@@ -607,7 +1020,8 @@ mod tests {
             create_image_element(1, 1, image_bbox),
         ];
 
-        let blocks = merge_elements_into_blocks(elements, HashMap::new())?;
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
 
         assert_eq!(blocks.len(), 1);
         if let BlockType::Image(image) = &blocks[0].kind {
@@ -629,7 +1043,8 @@ mod tests {
 
         let elements = vec![create_caption_element(0, 1, "Orphan caption", caption_bbox)];
 
-        let blocks = merge_elements_into_blocks(elements, HashMap::new())?;
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
 
         assert_eq!(blocks.len(), 1);
         if let BlockType::TextBlock(text) = &blocks[0].kind {
@@ -660,7 +1075,8 @@ mod tests {
             create_text_element(1, 1, "Distant paragraph", bbox2),
         ];
 
-        let blocks = merge_elements_into_blocks(elements, HashMap::new())?;
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
 
         assert_eq!(blocks.len(), 2);
         Ok(())
@@ -677,7 +1093,8 @@ mod tests {
 
         let elements = vec![create_image_element(0, 1, image_bbox)];
 
-        let blocks = merge_elements_into_blocks(elements, HashMap::new())?;
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
 
         assert_eq!(blocks.len(), 1);
         if let BlockType::Image(image) = &blocks[0].kind {
@@ -708,7 +1125,8 @@ mod tests {
             create_caption_element(1, 1, "Image Description", caption_bbox),
         ];
 
-        let blocks = merge_elements_into_blocks(elements, HashMap::new())?;
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
 
         assert_eq!(blocks.len(), 1);
         if let BlockType::Image(image) = &blocks[0].kind {
@@ -739,7 +1157,8 @@ mod tests {
             create_text_element(1, 1, "Regular text", text_bbox),
         ];
 
-        let blocks = merge_elements_into_blocks(elements, HashMap::new())?;
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
 
         assert_eq!(blocks.len(), 2);
         if let BlockType::Image(image) = &blocks[0].kind {
@@ -776,7 +1195,8 @@ mod tests {
             create_footnote_element(1, 1, "Image Footnote", footnote_bbox),
         ];
 
-        let blocks = merge_elements_into_blocks(elements, HashMap::new())?;
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
 
         assert_eq!(blocks.len(), 1);
         if let BlockType::Image(image) = &blocks[0].kind {
@@ -810,6 +1230,7 @@ mod tests {
                 text_block: ElementText::default(),
                 page_id: 1,
                 bbox: table1_bbox,
+                confidence: 1.0,
             },
             Element {
                 id: 1,
@@ -818,14 +1239,596 @@ mod tests {
                 text_block: ElementText::default(),
                 page_id: 1,
                 bbox: table2_bbox,
+                confidence: 1.0,
             },
         ];
 
-        let blocks = merge_elements_into_blocks(elements, HashMap::new())?;
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
 
         assert_eq!(blocks.len(), 2);
         assert!(matches!(blocks[0].kind, BlockType::Table(_)));
         assert!(matches!(blocks[1].kind, BlockType::Table(_)));
         Ok(())
     }
+
+    #[test]
+    fn test_form_field_blocks() -> anyhow::Result<()> {
+        use crate::entities::{FormField, FormFieldKind};
+
+        let field = |name: &str, kind, value: Option<&str>, checked, y0| FormField {
+            page_id: 0,
+            name: Some(name.to_string()),
+            kind,
+            value: value.map(str::to_string),
+            checked,
+            bbox: BBox {
+                x0: 100.0,
+                y0,
+                x1: 300.0,
+                y1: y0 + 20.0,
+            },
+        };
+        let elements = vec![
+            Element::from_form_field(
+                0,
+                &field("Name", FormFieldKind::Text, Some("John Doe"), None, 0.0),
+            ),
+            Element::from_form_field(
+                1,
+                &field("I agree", FormFieldKind::Checkbox, None, Some(true), 30.0),
+            ),
+            Element::from_form_field(
+                2,
+                &field(
+                    "Newsletter",
+                    FormFieldKind::Checkbox,
+                    None,
+                    Some(false),
+                    60.0,
+                ),
+            ),
+        ];
+
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
+
+        let texts = blocks
+            .iter()
+            .map(|block| match &block.kind {
+                BlockType::FormField(form_field) => form_field.text(),
+                _ => panic!("Expected FormField block"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            vec!["Name: John Doe", "[x] I agree", "[ ] Newsletter"]
+        );
+        assert_eq!(blocks[1].bbox.y0, 30.0);
+        Ok(())
+    }
+
+    fn create_title_element(id: usize, page_id: usize, text: &str, bbox: BBox) -> Element {
+        Element {
+            id,
+            layout_block_id: 0,
+            kind: ElementType::Title,
+            text_block: ElementText {
+                text: text.to_string(),
+                links: vec![],
+                styles: vec![],
+                font_size: None,
+                lines: vec![],
+            },
+            page_id,
+            bbox,
+            confidence: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_formula_blocks() {
+        let formula = |id: usize, page_id: PageID, text: &str| Element {
+            kind: ElementType::Formula,
+            ..create_text_element(id, page_id, text, BBox::default())
+        };
+        let elements = vec![
+            formula(0, 0, "E = mc²"),
+            formula(1, 0, "a² + b² = c²"),
+            create_text_element(2, 1, "where c is the speed of light", BBox::default()),
+            formula(3, 1, "∇ · E = ρ / ε₀"),
+        ];
+
+        let (blocks, warnings) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
+
+        assert!(warnings.is_empty());
+        let formulas: Vec<_> = blocks
+            .iter()
+            .filter_map(|block| match &block.kind {
+                BlockType::Formula(formula) => Some((formula.path(), formula.raw_text.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            formulas,
+            [
+                ("formula_0_0.png".to_string(), "E = mc²"),
+                ("formula_0_1.png".to_string(), "a² + b² = c²"),
+                ("formula_1_0.png".to_string(), "∇ · E = ρ / ε₀"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_paragraph_gap() {
+        let text = |id: usize, y0: f32| {
+            create_text_element(
+                id,
+                0,
+                &format!("Line {id}"),
+                BBox {
+                    x0: 50.0,
+                    y0,
+                    x1: 300.0,
+                    y1: y0 + 10.0,
+                },
+            )
+        };
+        // The centre of the second element is 3pt below the first, the centre of the third
+        // 13.5pt below the first two merged
+        let elements = || vec![text(0, 100.0), text(1, 103.0), text(2, 115.0)];
+
+        let (blocks, _) =
+            merge_elements_into_blocks(elements(), HashMap::new(), &MergeThresholds::default());
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].bbox.y1, 113.0);
+
+        let thresholds = MergeThresholds {
+            paragraph_gap: 14.0,
+            ..Default::default()
+        };
+        let (blocks, _) = merge_elements_into_blocks(elements(), HashMap::new(), &thresholds);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].bbox.y1, 125.0);
+    }
+
+    #[test]
+    fn test_code_blocks() {
+        let code_line = |text: &str, x0: f32, y0: f32| ElementLine {
+            text: text.to_owned(),
+            bbox: BBox {
+                x0,
+                y0,
+                x1: x0 + 6.0 * text.chars().count() as f32,
+                y1: y0 + 10.0,
+            },
+            monospace: true,
+        };
+        let code = |id: usize, page_id: PageID, lines: Vec<ElementLine>| {
+            let mut bbox = lines[0].bbox.clone();
+            lines.iter().for_each(|line| bbox.merge(&line.bbox));
+            let mut element = create_text_element(id, page_id, "", bbox);
+            element.kind = ElementType::Code;
+            element.text_block.lines = lines;
+            element
+        };
+        let elements = vec![
+            code(
+                0,
+                0,
+                vec![
+                    code_line("def f(x):", 50.0, 100.0),
+                    code_line("return x", 74.0, 112.0),
+                ],
+            ),
+            code(1, 0, vec![code_line("print(f(1))", 50.0, 136.0)]),
+            code(2, 1, vec![code_line("exit()", 50.0, 100.0)]),
+            // Too far below to be the same listing
+            code(3, 1, vec![code_line("main()", 50.0, 200.0)]),
+        ];
+
+        let (blocks, warnings) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
+
+        assert!(warnings.is_empty());
+        let codes: Vec<_> = blocks
+            .iter()
+            .filter_map(|block| match &block.kind {
+                BlockType::Code(code) => Some(code.text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            codes,
+            ["def f(x):\n    return x\n\nprint(f(1))", "exit()", "main()"]
+        );
+        assert_eq!(blocks[0].bbox.y1, 146.0);
+    }
+
+    #[test]
+    fn test_merge_split_title() -> anyhow::Result<()> {
+        let bbox = |x0: f32, y0: f32, x1: f32| BBox {
+            x0,
+            y0,
+            x1,
+            y1: y0 + 24.0,
+        };
+
+        let elements = vec![
+            create_title_element(0, 1, "Attention is", bbox(72.0, 100.0, 200.0)),
+            create_title_element(1, 1, "all you need", bbox(205.0, 100.0, 330.0)),
+            // Second line of the title
+            create_title_element(2, 1, "for parsing", bbox(72.0, 128.0, 190.0)),
+            // Another title further down the page
+            create_title_element(3, 1, "Introduction", bbox(72.0, 200.0, 190.0)),
+        ];
+        let title_level =
+            HashMap::from([((1, 0), 2u8), ((1, 1), 1u8), ((1, 2), 1u8), ((1, 3), 1u8)]);
+
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, title_level, &MergeThresholds::default());
+
+        assert_eq!(blocks.len(), 2);
+        if let BlockType::Title(title) = &blocks[0].kind {
+            assert_eq!(title.text, "Attention is all you need for parsing");
+            assert_eq!(title.level, 1);
+        } else {
+            panic!("Expected Title block");
+        }
+        assert_eq!(blocks[0].bbox.x1, 330.0);
+        assert_eq!(blocks[0].bbox.y1, 152.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_image_fragments() -> anyhow::Result<()> {
+        let left_bbox = BBox {
+            x0: 0.0,
+            y0: 0.0,
+            x1: 2.0,
+            y1: 2.0,
+        };
+        let right_bbox = BBox {
+            x0: 1.5,
+            y0: 0.5,
+            x1: 4.0,
+            y1: 2.5,
+        };
+        let caption_bbox = BBox {
+            x0: 0.0,
+            y0: 2.6,
+            x1: 4.0,
+            y1: 3.0,
+        };
+
+        let elements = vec![
+            create_image_element(0, 1, left_bbox),
+            create_image_element(1, 1, right_bbox),
+            create_caption_element(2, 1, "Figure 1: two panels", caption_bbox),
+        ];
+
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
+
+        assert_eq!(blocks.len(), 1);
+        let bbox = &blocks[0].bbox;
+        assert_eq!((bbox.x0, bbox.y0, bbox.x1, bbox.y1), (0.0, 0.0, 4.0, 3.0));
+        if let BlockType::Image(image) = &blocks[0].kind {
+            assert_eq!(image.caption, Some("Figure 1: two panels".to_string()));
+        } else {
+            panic!("Expected Image block");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_text_into_image_errors() {
+        let bbox = BBox {
+            x0: 0.0,
+            y0: 0.0,
+            x1: 2.0,
+            y1: 2.0,
+        };
+        let mut block =
+            Block::for_test(0, BlockType::Image(ImageBlock::default()), 1, bbox.clone());
+
+        let res = block.merge(create_text_element(1, 1, "Body", bbox));
+        assert!(matches!(res, Err(FerrulesError::BlockMergeError { .. })));
+    }
+
+    #[test]
+    fn test_merge_text_into_title_errors() {
+        let bbox = BBox {
+            x0: 0.0,
+            y0: 0.0,
+            x1: 2.0,
+            y1: 2.0,
+        };
+        let mut block = Block::for_test(
+            0,
+            BlockType::Title(Title {
+                level: 0,
+                text: "Title".to_string(),
+            }),
+            1,
+            bbox.clone(),
+        );
+
+        let res = block.merge(create_text_element(1, 1, "Body", bbox));
+        assert!(matches!(res, Err(FerrulesError::BlockMergeError { .. })));
+    }
+
+    #[test]
+    fn test_recover_merge_requeues_element() {
+        let bbox = BBox {
+            x0: 0.0,
+            y0: 0.0,
+            x1: 2.0,
+            y1: 2.0,
+        };
+        let mut block =
+            Block::for_test(3, BlockType::Image(ImageBlock::default()), 1, bbox.clone());
+        let mut requeued = None;
+        let mut warnings = Vec::new();
+
+        let merged = block.merge(create_text_element(7, 1, "Body", bbox));
+        assert!(!recover_merge(merged, &mut requeued, &mut warnings));
+
+        assert_eq!(requeued.map(|el| el.id), Some(7));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].page_id, Some(1));
+        assert!(warnings[0].message.contains("IMAGE block 3"));
+    }
+
+    #[test]
+    fn test_merge_title_and_image_sequences() {
+        let bbox = |y0: f32| BBox {
+            x0: 0.0,
+            y0,
+            x1: 100.0,
+            y1: y0 + 10.0,
+        };
+        let elements = vec![
+            create_title_element(0, 0, "Introduction", bbox(0.0)),
+            // A second title further down starts its own block
+            create_title_element(1, 0, "Background", bbox(200.0)),
+            create_image_element(2, 0, bbox(300.0)),
+            // Images only merge with the overlapping parts of the same figure
+            create_image_element(3, 0, bbox(400.0)),
+            create_text_element(4, 0, "Body", bbox(500.0)),
+        ];
+
+        let (blocks, warnings) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
+
+        let labels: Vec<_> = blocks.iter().map(|b| b.label()).collect();
+        assert_eq!(labels, ["TITLE", "TITLE", "IMAGE", "IMAGE", "TEXT"]);
+        assert!(warnings.is_empty());
+    }
+
+    fn create_line(text: &str, y0: f32) -> Line {
+        Line {
+            text: text.to_string(),
+            bbox: BBox {
+                x0: 50.0,
+                y0,
+                x1: 500.0,
+                y1: y0 + 12.0,
+            },
+            rotation: 0.0,
+            spans: vec![],
+            confidence: None,
+            links: vec![],
+            direction: TextDirection::Ltr,
+        }
+    }
+
+    fn line_texts(lines: &[Line]) -> Vec<&str> {
+        lines.iter().map(|l| l.text.as_str()).collect()
+    }
+
+    /// Native text covers the top half of the page, OCR finds the whole page
+    fn half_native_page() -> (Vec<Line>, Vec<Line>) {
+        let native = vec![
+            create_line("native heading", 100.0),
+            create_line("native body", 200.0),
+        ];
+        let ocr = vec![
+            create_line("ocr heading", 101.0),
+            create_line("ocr body", 199.0),
+            create_line("ocr bottom 1", 500.0),
+            create_line("ocr bottom 2", 600.0),
+        ];
+        (native, ocr)
+    }
+
+    #[test]
+    fn test_merge_native_ocr_prefer_native() {
+        let (native, ocr) = half_native_page();
+        let lines = merge_native_ocr_lines(native, ocr, TextSource::PreferNative);
+        assert_eq!(
+            line_texts(&lines),
+            vec![
+                "native heading",
+                "native body",
+                "ocr bottom 1",
+                "ocr bottom 2"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_native_ocr_prefer_ocr() {
+        let (native, ocr) = half_native_page();
+        let lines = merge_native_ocr_lines(native, ocr, TextSource::PreferOcr);
+        assert_eq!(
+            line_texts(&lines),
+            vec!["ocr heading", "ocr body", "ocr bottom 1", "ocr bottom 2"]
+        );
+    }
+
+    #[test]
+    fn test_merge_native_ocr_single_source() {
+        let (native, ocr) = half_native_page();
+        let lines = merge_native_ocr_lines(native.clone(), ocr.clone(), TextSource::NativeOnly);
+        assert_eq!(line_texts(&lines), vec!["native heading", "native body"]);
+
+        let lines = merge_native_ocr_lines(native, ocr, TextSource::OcrOnly);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0].text, "ocr heading");
+    }
+    #[test]
+    fn test_heuristic_text_layout() {
+        let line = |text: &str, x0: f32, y0: f32| Line {
+            text: text.to_string(),
+            bbox: BBox {
+                x0,
+                y0,
+                x1: x0 + 200.0,
+                y1: y0 + 10.0,
+            },
+            rotation: 0.0,
+            spans: vec![],
+            confidence: None,
+            links: vec![],
+            direction: TextDirection::Ltr,
+        };
+        // Two columns, the left one has two paragraphs
+        let lines = vec![
+            line("left-1a", 50.0, 100.0),
+            line("right-1a", 300.0, 100.0),
+            line("left-1b", 50.0, 112.0),
+            line("right-1b", 300.0, 112.0),
+            line("left-2a", 50.0, 160.0),
+            line("", 50.0, 400.0),
+        ];
+
+        let layout = heuristic_text_layout(&lines);
+        let boxes: Vec<_> = layout
+            .iter()
+            .map(|b| (b.id, b.bbox.x0, b.bbox.y0, b.bbox.y1))
+            .collect();
+        assert_eq!(
+            boxes,
+            vec![
+                (0, 50.0, 100.0, 122.0),
+                (1, 300.0, 100.0, 122.0),
+                (2, 50.0, 160.0, 170.0)
+            ]
+        );
+        assert!(layout.iter().all(|b| b.is_text_block()));
+
+        let elements = merge_lines_layout(&layout, &lines, 0).unwrap();
+        assert_eq!(elements.len(), 3);
+    }
+
+    #[test]
+    fn test_filter_layout_classes() {
+        let labels: Vec<String> = ["Page-header", "Picture", "Table", "Text"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let layout_box = |id: i32, label: &str, y0: f32| LayoutBBox {
+            id,
+            bbox: BBox {
+                x0: 0.0,
+                y0,
+                x1: 100.0,
+                y1: y0 + 20.0,
+            },
+            label: label.to_string(),
+            proba: 0.9,
+        };
+        let lines = vec![Line {
+            text: "Revenue 2024".to_string(),
+            bbox: BBox {
+                x0: 10.0,
+                y0: 5.0,
+                x1: 90.0,
+                y1: 15.0,
+            },
+            rotation: 0.0,
+            spans: vec![],
+            confidence: None,
+            links: vec![],
+            direction: TextDirection::Ltr,
+        }];
+        let layout = vec![
+            layout_box(0, "Page-header", 0.0),
+            layout_box(1, "Page-header", 200.0),
+            layout_box(2, "Table", 400.0),
+        ];
+
+        let filter = LayoutClassFilter::new(None, &["page-header".to_string()], &labels).unwrap();
+        let filtered = filter_layout_classes(layout.clone(), &filter, &lines);
+        let kept: Vec<_> = filtered.iter().map(|b| (b.id, b.label.as_str())).collect();
+        // The header over a text line becomes text, the empty one is dropped
+        assert_eq!(kept, vec![(0, "Text"), (2, "Table")]);
+
+        let filter = LayoutClassFilter::new(Some(&["Table".to_string()]), &[], &labels).unwrap();
+        let filtered = filter_layout_classes(layout, &filter, &lines);
+        let kept: Vec<_> = filtered.iter().map(|b| (b.id, b.label.as_str())).collect();
+        assert_eq!(kept, vec![(0, "Text"), (2, "Table")]);
+
+        let err = LayoutClassFilter::new(None, &["header".to_string()], &labels).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("valid classes are: Page-header, Picture, Table, Text"));
+    }
+
+    #[test]
+    fn test_block_confidence() {
+        let layout_box = |id: i32, label: &str, y0: f32, proba: f32| LayoutBBox {
+            id,
+            bbox: BBox {
+                x0: 0.0,
+                y0,
+                x1: 200.0,
+                y1: y0 + 20.0,
+            },
+            label: label.to_string(),
+            proba,
+        };
+        let line = |text: &str, y0: f32, confidence: Option<f32>| Line {
+            text: text.to_string(),
+            bbox: BBox {
+                x0: 10.0,
+                y0: y0 + 5.0,
+                x1: 190.0,
+                y1: y0 + 15.0,
+            },
+            rotation: 0.0,
+            spans: vec![],
+            confidence,
+            links: vec![],
+            direction: TextDirection::Ltr,
+        };
+        let layout = vec![
+            layout_box(0, "Text", 0.0, 0.95),
+            layout_box(1, "Text", 22.0, 0.8),
+            layout_box(2, "Picture", 200.0, 0.7),
+        ];
+        let lines = vec![
+            line("native line", 0.0, None),
+            line("recognized line", 22.0, Some(0.6)),
+        ];
+
+        let mut elements = merge_lines_layout(&layout, &lines, 0).unwrap();
+        assert_eq!(elements[0].confidence, 0.95);
+        assert_eq!(elements[1].confidence, 0.6);
+        elements.push(Element::from_layout_block(2, &layout[2], 0));
+
+        let recognized = elements[1].clone();
+
+        let (blocks, _) =
+            merge_elements_into_blocks(elements, HashMap::new(), &MergeThresholds::default());
+        let confidences: Vec<_> = blocks.iter().map(|b| b.confidence).collect();
+        assert_eq!(confidences, vec![0.95, 0.6, 0.7]);
+
+        // Merged blocks keep the score of their least confident element
+        let mut block = blocks[0].clone();
+        block.merge(recognized).unwrap();
+        assert_eq!(block.confidence, 0.6);
+    }
 }
@@ -1,6 +1,25 @@
+pub(crate) mod bidi;
+pub(crate) mod captions;
+pub(crate) mod code;
+pub(crate) mod count;
+pub(crate) mod dehyphenate;
 pub mod document;
+pub(crate) mod formulas;
+pub(crate) mod images;
+pub(crate) mod language;
+pub(crate) mod lists;
 pub(crate) mod merge;
 pub mod native;
+pub(crate) mod native_pool;
+pub(crate) mod normalize;
+pub(crate) mod outline;
 mod page;
+pub(crate) mod page_cache;
+pub(crate) mod page_range;
+pub(crate) mod paragraphs;
+pub(crate) mod reading_order;
+pub(crate) mod running_headers;
+pub(crate) mod sections;
+pub(crate) mod structure_tree;
 pub mod table;
 pub mod titles;
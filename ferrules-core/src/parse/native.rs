@@ -1,4 +1,4 @@
-use std::{ops::Range, sync::Arc, time::Instant};
+use std::{sync::Arc, time::Instant};
 
 use image::DynamicImage;
 use pdfium_render::prelude::*;
@@ -6,14 +6,33 @@ use pdfium_render::prelude::*;
 use tracing::{instrument, Span};
 
 use crate::{
-    entities::{BBox, CharSpan, Line, PDFPath, PageID, Segment},
+    blocks::EmbeddedImageFormat,
+    entities::{
+        BBox, CharSpan, DocumentInfo, EmbeddedImage, FormField, FormFieldKind, Line, LinkSpan,
+        LinkTarget, OutlineEntry, PDFPath, PageID, Segment,
+    },
     error::FerrulesError,
-    layout::model::ORTLayoutParser,
+    layout::model::LayoutBBox,
+    ocr::{detect_page_rotation, PageRotation},
+    pdfium::load_pdfium,
+};
+use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
+use tokio::sync::{
+    mpsc::{self, Receiver, Sender},
+    oneshot,
+};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    bidi::to_logical_order, merge::MergeThresholds, native_pool::start_native_process_pool,
+    normalize::TextNormalization, page_range::PageRange, structure_tree::extract_structure_layout,
 };
-use tokio::sync::mpsc::{self, Receiver, Sender};
 
 const MAX_CONCURRENT_NATIVE_REQS: usize = 10;
 
+/// Bounds the outline traversal, pdfium doesn't protect against cyclic bookmark trees
+const MAX_OUTLINE_ENTRIES: usize = 10_000;
+
 pub(crate) fn parse_text_spans<'a>(
     chars: impl Iterator<Item = PdfPageTextChar<'a>>,
     page_bbox: &BBox,
@@ -39,7 +58,7 @@ pub(crate) fn parse_text_spans<'a>(
     spans
 }
 
-pub(crate) fn parse_text_lines(spans: Vec<CharSpan>) -> Vec<Line> {
+pub(crate) fn parse_text_lines(spans: Vec<CharSpan>, thresholds: &MergeThresholds) -> Vec<Line> {
     let mut lines = Vec::new();
     for span in spans {
         if lines.is_empty() {
@@ -47,48 +66,120 @@ pub(crate) fn parse_text_lines(spans: Vec<CharSpan>) -> Vec<Line> {
             lines.push(line);
         } else {
             let line = lines.last_mut().unwrap();
-            if let Err(span) = line.append(span) {
+            if let Err(span) = line.append(span, thresholds) {
                 let line = Line::new_from_span(span);
                 lines.push(line)
             }
         }
     }
 
+    for line in lines.iter_mut() {
+        to_logical_order(line);
+        line.tag_scripts();
+    }
     lines
 }
 
+/// Resolution of the page raster used for the image crops, and for OCR when it's sharper than
+/// the layout model input. Bboxes stay in PDF points whatever the resolution.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Archive, RkyvDeserialize, RkyvSerialize)]
+#[archive(check_bytes)]
+pub struct RasterConfig {
+    /// Rendering resolution, 72 DPI (one pixel per PDF point) when unset
+    pub raster_dpi: Option<f32>,
+    /// Optional maximum width and height of the raster, in pixels
+    pub max_raster_dim: Option<u32>,
+    /// Only render the raster of the pages without a text layer, which are OCRed from it
+    pub skip_page_image: bool,
+}
+
+impl RasterConfig {
+    /// PDF points per inch, the resolution of a raster with one pixel per point
+    pub const POINTS_PER_INCH: f32 = 72.0;
+    /// Upper bound on the pixel count of a page raster, about 256MB in RGBA
+    pub const MAX_RASTER_PIXELS: f32 = 64_000_000.0;
+
+    /// Pixels per PDF point of the raster of a `width` x `height` points page, and whether it
+    /// was lowered to stay under `MAX_RASTER_PIXELS`
+    pub(crate) fn scale(&self, width: f32, height: f32) -> (f32, bool) {
+        let mut scale = self
+            .raster_dpi
+            .filter(|dpi| dpi.is_finite() && *dpi > 0.0)
+            .map_or(1.0, |dpi| dpi / Self::POINTS_PER_INCH);
+        if let Some(max_dim) = self.max_raster_dim.filter(|&max_dim| max_dim > 0) {
+            scale = scale.min(max_dim as f32 / width.max(height));
+        }
+        let max_scale = (Self::MAX_RASTER_PIXELS / (width * height)).sqrt();
+        if scale > max_scale {
+            (max_scale, true)
+        } else {
+            (scale, false)
+        }
+    }
+}
+
 pub struct ParseNativeRequest {
     pub doc_data: std::sync::Arc<[u8]>,
     pub password: Option<String>,
     pub flatten: bool,
-    pub page_range: Option<Range<usize>>,
+    pub page_range: Option<PageRange>,
     pub required_raster_width: u32,
     pub required_raster_height: u32,
+    pub raster: RasterConfig,
+    /// Normalization of the extracted text, `None` keeps the text as extracted by pdfium
+    pub text_normalization: Option<TextNormalization>,
+    /// Proximity thresholds grouping the characters into lines
+    pub merge_thresholds: MergeThresholds,
     pub sender_tx: Sender<anyhow::Result<ParseNativePageResult>>,
+    /// Receives the document level data once the document is loaded, before its pages
+    pub document_tx: oneshot::Sender<ParseNativeDocument>,
+    /// Stops parsing the remaining pages once triggered
+    pub cancellation: CancellationToken,
     pub queue_time: Instant,
 }
 impl ParseNativeRequest {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         data: &[u8],
         password: Option<&str>,
         flatten: bool,
-        page_range: Option<Range<usize>>,
+        page_range: Option<PageRange>,
+        (required_raster_width, required_raster_height): (u32, u32),
+        raster: RasterConfig,
+        text_normalization: Option<TextNormalization>,
+        merge_thresholds: MergeThresholds,
         sender_tx: Sender<anyhow::Result<ParseNativePageResult>>,
+        document_tx: oneshot::Sender<ParseNativeDocument>,
+        cancellation: CancellationToken,
     ) -> Self {
         ParseNativeRequest {
             doc_data: Arc::from(data),
             password: password.map(|p| p.to_string()),
             flatten,
             page_range,
-            // TODO: should be global?
-            required_raster_width: ORTLayoutParser::REQUIRED_WIDTH,
-            required_raster_height: ORTLayoutParser::REQUIRED_HEIGHT,
+            required_raster_width,
+            required_raster_height,
+            raster,
+            text_normalization,
+            merge_thresholds,
             sender_tx,
+            document_tx,
+            cancellation,
             queue_time: Instant::now(),
         }
     }
 }
 
+/// Data of the whole document, read before its pages
+#[derive(Debug, Default, Archive, RkyvDeserialize, RkyvSerialize)]
+#[archive(check_bytes)]
+pub struct ParseNativeDocument {
+    pub outline: Option<Vec<OutlineEntry>>,
+    pub info: DocumentInfo,
+    /// Number of pages of the document, selected or not
+    pub page_count: usize,
+}
+
 #[derive(Debug)]
 pub struct ParseNativeMetadata {
     pub parse_native_duration_ms: f64,
@@ -96,14 +187,24 @@ pub struct ParseNativeMetadata {
 
 #[derive(Debug)]
 pub struct ParseNativePageResult {
-    // TODO: page_native_rotation
     pub page_id: PageID,
     pub text_lines: Vec<Line>,
     pub paths: Vec<PDFPath>,
+    pub form_fields: Vec<FormField>,
+    pub embedded_images: Vec<EmbeddedImage>,
     pub page_bbox: BBox,
     pub page_image: Arc<DynamicImage>,
-    pub page_image_scale1: DynamicImage,
+    /// Page raster at the resolution of the `RasterConfig`, one pixel per point by default.
+    /// Not rendered for the pages with a text layer with `RasterConfig::skip_page_image`
+    pub page_image_scale1: Option<DynamicImage>,
     pub downscale_factor: f32,
+    /// Rotation detected on the page raster, only computed for pages without a text layer
+    pub rotation: PageRotation,
+    /// `/Rotate` entry of the page, already applied to the rasters and all the coordinates
+    pub page_rotation: PageRotation,
+    /// Regions of the structure tree of a tagged page, in reading order. Empty for untagged
+    /// pages
+    pub structure_layout: Vec<LayoutBBox>,
     pub metadata: ParseNativeMetadata,
 }
 
@@ -128,6 +229,23 @@ impl ParseNativeQueue {
         }
     }
 
+    /// Distributes native parsing over `workers` child processes, each binding its own pdfium
+    /// instance, so that several documents can be parsed in parallel. The pages of a document
+    /// are split in chunks parsed by the idle workers. The pages of a crashed worker are retried
+    /// once on a respawned worker, then reported as failed pages. The document is written once
+    /// to a temporary file mapped by the workers, the requests only carry the pages to parse.
+    ///
+    /// The child processes re-execute the current binary, which must call
+    /// [`crate::run_native_worker_if_requested`] at the very start of its `main`.
+    pub fn new_process_pool(workers: usize) -> Self {
+        let (queue_sender, queue_receiver) = mpsc::channel(MAX_CONCURRENT_NATIVE_REQS);
+
+        tokio::task::spawn(start_native_process_pool(workers.max(1), queue_receiver));
+        Self {
+            queue: queue_sender,
+        }
+    }
+
     pub(crate) async fn push(&self, req: ParseNativeRequest) -> Result<(), FerrulesError> {
         let span = Span::current();
         self.queue
@@ -137,6 +255,16 @@ impl ParseNativeQueue {
     }
 }
 
+/// Render config of the page rasters. Annotations and form widgets are drawn by pdfium when
+/// the page isn't flattened, so that the rasters look the same either way
+fn page_render_config(scale: f32) -> PdfRenderConfig {
+    PdfRenderConfig::default()
+        .scale_page_by_factor(scale)
+        .render_annotations(true)
+        .render_form_data(true)
+}
+
+#[allow(clippy::too_many_arguments)]
 #[instrument(skip(page))]
 pub(crate) fn parse_page_native(
     page_id: PageID,
@@ -144,21 +272,69 @@ pub(crate) fn parse_page_native(
     flatten_page: bool,
     required_raster_width: u32,
     required_raster_height: u32,
+    raster: RasterConfig,
+    text_normalization: Option<TextNormalization>,
+    merge_thresholds: MergeThresholds,
 ) -> anyhow::Result<ParseNativePageResult> {
     let start_time = Instant::now();
 
+    // NOTE: pdfium renders the page and reports its size with the `/Rotate` entry applied,
+    // but the text, objects and annotations positions are in the unrotated page space.
+    // Everything is converted to the rotated page, as displayed.
     let page_bbox = BBox {
         x0: 0f32,
         y0: 0f32,
         x1: page.width().value,
         y1: page.height().value,
     };
+    let page_rotation = get_page_rotation(page);
+    let unrotated_bbox = match page_rotation {
+        PageRotation::Deg90 | PageRotation::Deg270 => BBox {
+            x0: 0f32,
+            y0: 0f32,
+            x1: page_bbox.height(),
+            y1: page_bbox.width(),
+        },
+        PageRotation::Deg0 | PageRotation::Deg180 => page_bbox.clone(),
+    };
+    let (unrotated_width, unrotated_height) = (unrotated_bbox.width(), unrotated_bbox.height());
+    let rotate_bbox =
+        |bbox: &BBox| page_rotation.rotate_bbox(bbox, unrotated_width, unrotated_height);
 
     // NOTE: Extract paths BEFORE flatten. `page.flatten()` merges annotations and
     // form fields into the page content stream, which invalidates pdfium's
     // internal page‐object list. Calling `page.objects()` after flatten
     // dereferences stale pointers and segfaults.
-    let paths = extract_page_paths(page, &page_bbox);
+    let mut paths = extract_page_paths(page, &unrotated_bbox);
+    let mut embedded_images = extract_embedded_images(page, &unrotated_bbox);
+    // Flattening also bakes the form widgets into the page content
+    let mut form_fields = extract_form_fields(page_id, page, &unrotated_bbox);
+    let mut links = extract_page_links(page, &unrotated_bbox);
+    let mut structure_layout = extract_structure_layout(page, unrotated_bbox.height());
+    if page_rotation != PageRotation::Deg0 {
+        for segment in paths.iter_mut().flat_map(|path| path.segments.iter_mut()) {
+            match segment {
+                Segment::Line { start, end } => {
+                    *start = page_rotation.rotate_point(*start, unrotated_width, unrotated_height);
+                    *end = page_rotation.rotate_point(*end, unrotated_width, unrotated_height);
+                }
+                Segment::Rect { bbox } => *bbox = rotate_bbox(bbox),
+            }
+        }
+        for embedded_image in embedded_images.iter_mut() {
+            embedded_image.bbox = rotate_bbox(&embedded_image.bbox);
+            embedded_image.image = page_rotation.apply(&embedded_image.image);
+        }
+        for form_field in form_fields.iter_mut() {
+            form_field.bbox = rotate_bbox(&form_field.bbox);
+        }
+        for link in links.iter_mut() {
+            link.bbox = rotate_bbox(&link.bbox);
+        }
+        for region in structure_layout.iter_mut() {
+            region.bbox = rotate_bbox(&region.bbox);
+        }
+    }
 
     if flatten_page {
         page.flatten()?;
@@ -171,16 +347,47 @@ pub(crate) fn parse_page_native(
     let downscale_factor = 1f32 / rescale_factor;
 
     let page_image = page
-        .render_with_config(&PdfRenderConfig::default().scale_page_by_factor(rescale_factor))
+        .render_with_config(&page_render_config(rescale_factor))
         .map(|bitmap| bitmap.as_image())?;
 
-    let page_image_scale1 = page
-        .render_with_config(&PdfRenderConfig::default().scale_page_by_factor(1f32))
-        .map(|bitmap| bitmap.as_image())?;
+    let mut text_spans = parse_text_spans(page.text()?.chars().iter(), &unrotated_bbox);
+    if page_rotation != PageRotation::Deg0 {
+        // Lines are grouped top-down, so spans are rotated before grouping
+        for span in text_spans.iter_mut() {
+            span.bbox = rotate_bbox(&span.bbox);
+            span.rotation = (span.rotation + page_rotation.degrees()) % 360f32;
+        }
+    }
+
+    let mut text_lines = parse_text_lines(text_spans, &merge_thresholds);
+    if let Some(normalization) = text_normalization {
+        normalization.normalize_lines(&mut text_lines);
+    }
+    attach_links(&mut text_lines, &links);
 
-    let text_spans = parse_text_spans(page.text()?.chars().iter(), &page_bbox);
+    let page_image_scale1 = if raster.skip_page_image && !text_lines.is_empty() {
+        None
+    } else {
+        let (raster_scale, capped) = raster.scale(page.width().value, page.height().value);
+        if capped {
+            tracing::warn!(
+                "page {page_id} raster capped to {:.0} DPI to stay under {} pixels",
+                raster_scale * RasterConfig::POINTS_PER_INCH,
+                RasterConfig::MAX_RASTER_PIXELS
+            );
+        }
+        let image = page
+            .render_with_config(&page_render_config(raster_scale))
+            .map(|bitmap| bitmap.as_image())?;
+        Some(image)
+    };
 
-    let text_lines = parse_text_lines(text_spans);
+    // NOTE: scanned pages have no text layer and are OCRed from the raster
+    let rotation = if text_lines.is_empty() {
+        detect_page_rotation(&page_image)
+    } else {
+        PageRotation::default()
+    };
 
     let parse_native_duration_ms = start_time.elapsed().as_secs_f64() * 1000.0;
     tracing::debug!("pdfium parsing for page {page_id} took: {parse_native_duration_ms}ms");
@@ -188,16 +395,34 @@ pub(crate) fn parse_page_native(
         page_id,
         text_lines,
         paths,
+        form_fields,
+        embedded_images,
         page_bbox,
         page_image: Arc::new(page_image),
         page_image_scale1,
         downscale_factor,
+        rotation,
+        page_rotation,
+        structure_layout,
         metadata: ParseNativeMetadata {
             parse_native_duration_ms,
         },
     })
 }
 
+pub(crate) fn get_page_rotation(page: &PdfPage) -> PageRotation {
+    match page.rotation() {
+        Ok(PdfPageRenderRotation::Degrees90) => PageRotation::Deg90,
+        Ok(PdfPageRenderRotation::Degrees180) => PageRotation::Deg180,
+        Ok(PdfPageRenderRotation::Degrees270) => PageRotation::Deg270,
+        Ok(PdfPageRenderRotation::None) => PageRotation::Deg0,
+        Err(e) => {
+            tracing::warn!("can't read the page rotation: {e:?}");
+            PageRotation::Deg0
+        }
+    }
+}
+
 fn extract_page_paths(page: &PdfPage, page_bbox: &BBox) -> Vec<PDFPath> {
     let mut paths = Vec::new();
 
@@ -252,6 +477,335 @@ fn extract_page_paths(page: &PdfPage, page_bbox: &BBox) -> Vec<PDFPath> {
     paths
 }
 
+/// Collects the bitmaps of the image objects of the page, without their transformation
+fn extract_embedded_images(page: &PdfPage, page_bbox: &BBox) -> Vec<EmbeddedImage> {
+    let mut images = Vec::new();
+
+    for object in page.objects().iter() {
+        let Some(image_object) = object.as_image_object() else {
+            continue;
+        };
+        let (Ok(bounds), Ok(image)) = (object.bounds(), image_object.get_raw_image()) else {
+            tracing::debug!("can't extract the bitmap of an image object");
+            continue;
+        };
+        let is_jpeg = image_object
+            .filters()
+            .iter()
+            .any(|filter| filter.name() == "DCTDecode");
+        images.push(EmbeddedImage {
+            bbox: BBox::from_pdfrect(bounds.to_rect(), page_bbox.height()),
+            image,
+            format: if is_jpeg {
+                EmbeddedImageFormat::Jpeg
+            } else {
+                EmbeddedImageFormat::Png
+            },
+        });
+    }
+    images
+}
+
+/// Collects the text, checkbox and radio button widgets of the page with their values
+fn extract_form_fields(page_id: PageID, page: &PdfPage, page_bbox: &BBox) -> Vec<FormField> {
+    let mut form_fields = Vec::new();
+
+    for annotation in page.annotations().iter() {
+        let Some(field) = annotation.as_form_field() else {
+            continue;
+        };
+        let (kind, value, checked) = if let Some(text_field) = field.as_text_field() {
+            (FormFieldKind::Text, text_field.value(), None)
+        } else if let Some(checkbox) = field.as_checkbox_field() {
+            (FormFieldKind::Checkbox, None, checkbox.is_checked().ok())
+        } else if let Some(radio_button) = field.as_radio_button_field() {
+            (
+                FormFieldKind::RadioButton,
+                None,
+                radio_button.is_checked().ok(),
+            )
+        } else if let Some(combo_box) = field.as_combo_box_field() {
+            (FormFieldKind::ComboBox, combo_box.value(), None)
+        } else {
+            continue;
+        };
+        let Ok(bounds) = annotation.bounds() else {
+            tracing::warn!("can't get the bounds of a form field on page {page_id}");
+            continue;
+        };
+
+        form_fields.push(FormField {
+            page_id,
+            name: field.name(),
+            kind,
+            value,
+            checked,
+            bbox: BBox::from_pdfrect(bounds, page_bbox.height()),
+        });
+    }
+    form_fields
+}
+
+/// Link annotation of a page
+#[derive(Debug, Clone)]
+struct PageLink {
+    bbox: BBox,
+    target: LinkTarget,
+}
+
+/// Collects the link annotations of the page pointing to a URI or to a page of the document
+fn extract_page_links(page: &PdfPage, page_bbox: &BBox) -> Vec<PageLink> {
+    let mut links = Vec::new();
+
+    for annotation in page.annotations().iter() {
+        let Some(link_annotation) = annotation.as_link_annotation() else {
+            continue;
+        };
+        let (Ok(bounds), Ok(link)) = (annotation.bounds(), link_annotation.link()) else {
+            continue;
+        };
+        let page_target = |destination: PdfDestination| {
+            destination
+                .page_index()
+                .ok()
+                .map(|page_index| LinkTarget::PageId(page_index as PageID))
+        };
+        let target = match link.action() {
+            Some(PdfAction::Uri(action)) => action.uri().ok().map(LinkTarget::Uri),
+            Some(PdfAction::LocalDestination(action)) => {
+                action.destination().ok().and_then(page_target)
+            }
+            _ => link.destination().and_then(page_target),
+        };
+        let Some(target) = target else {
+            tracing::debug!("skipping a link annotation without a supported target");
+            continue;
+        };
+        links.push(PageLink {
+            bbox: BBox::from_pdfrect(bounds, page_bbox.height()),
+            target,
+        });
+    }
+    links
+}
+
+/// Attaches the links to the lines they overlap, with the words of the line whose center is
+/// inside the link rect as text. A link rect spanning several lines is attached to each of them.
+fn attach_links(lines: &mut [Line], links: &[PageLink]) {
+    if links.is_empty() {
+        return;
+    }
+    for line in lines.iter_mut() {
+        for link in links {
+            if line.bbox.intersection(&link.bbox) <= 0.0 {
+                continue;
+            }
+            let words = line
+                .spans
+                .iter()
+                .flat_map(CharSpan::words)
+                .filter(|word| {
+                    let (x, y) = word.bbox.center();
+                    (link.bbox.x0..=link.bbox.x1).contains(&x)
+                        && (link.bbox.y0..=link.bbox.y1).contains(&y)
+                })
+                .map(|word| word.text)
+                .collect::<Vec<_>>();
+            if !words.is_empty() {
+                line.links.push(LinkSpan {
+                    text: words.join(" "),
+                    target: link.target.clone(),
+                });
+            }
+        }
+    }
+}
+
+/// Walks the bookmark tree depth first, so that entries are in reading order
+fn extract_outline(document: &PdfDocument) -> Option<Vec<OutlineEntry>> {
+    let mut entries = Vec::new();
+    let mut stack = vec![(document.bookmarks().root()?, 1u8)];
+    while let Some((bookmark, level)) = stack.pop() {
+        if entries.len() >= MAX_OUTLINE_ENTRIES {
+            tracing::warn!("outline truncated to {MAX_OUTLINE_ENTRIES} entries");
+            break;
+        }
+        // The children are visited before the next sibling
+        if let Some(sibling) = bookmark.next_sibling() {
+            stack.push((sibling, level));
+        }
+        if let Some(child) = bookmark.first_child() {
+            stack.push((child, level.saturating_add(1)));
+        }
+        entries.push(OutlineEntry {
+            title: bookmark.title().unwrap_or_default().trim().to_owned(),
+            level,
+            page_id: bookmark
+                .destination()
+                .and_then(|destination| destination.page_index().ok())
+                .map(|page_index| page_index as PageID),
+        });
+    }
+    (!entries.is_empty()).then_some(entries)
+}
+
+/// Converts a PDF date, `D:YYYYMMDDHHmmSSOHH'mm'` where every part after the year is
+/// optional, to ISO 8601. Returns `None` for malformed dates.
+fn pdf_date_to_iso8601(raw: &str) -> Option<String> {
+    let date = raw.trim();
+    let date = date.strip_prefix("D:").unwrap_or(date);
+    let digits = date
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(date.len());
+    let (fields, tz) = date.split_at(digits);
+    if fields.len() < 4 || fields.len() > 14 || fields.len() % 2 != 0 {
+        return None;
+    }
+    let field = |start: usize, default: u32| {
+        fields
+            .get(start..start + 2)
+            .map_or(Some(default), |f| f.parse::<u32>().ok())
+    };
+    let year = &fields[..4];
+    let (month, day) = (field(4, 1)?, field(6, 1)?);
+    let (hour, minute, second) = (field(8, 0)?, field(10, 0)?, field(12, 0)?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+
+    let offset = match tz.chars().next() {
+        None => String::new(),
+        Some('Z') => "Z".to_owned(),
+        Some(sign @ ('+' | '-')) => {
+            let offset = tz[1..].replace('\'', "");
+            if !matches!(offset.len(), 2 | 4) || !offset.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let (hours, minutes) = offset.split_at(2);
+            let minutes = if minutes.is_empty() { "00" } else { minutes };
+            format!("{sign}{hours}:{minutes}")
+        }
+        Some(_) => return None,
+    };
+    Some(format!(
+        "{year}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}{offset}"
+    ))
+}
+
+fn extract_document_info(document: &PdfDocument) -> DocumentInfo {
+    let metadata = document.metadata();
+    let tag = |tag_type: PdfDocumentMetadataTagType| {
+        metadata
+            .get(tag_type)
+            .map(|tag| tag.value().trim().to_owned())
+            .filter(|value| !value.is_empty())
+    };
+    // Malformed dates are kept as is rather than dropped
+    let date = |tag_type: PdfDocumentMetadataTagType| {
+        tag(tag_type).map(|raw| pdf_date_to_iso8601(&raw).unwrap_or(raw))
+    };
+    DocumentInfo {
+        title: tag(PdfDocumentMetadataTagType::Title),
+        author: tag(PdfDocumentMetadataTagType::Author),
+        subject: tag(PdfDocumentMetadataTagType::Subject),
+        keywords: tag(PdfDocumentMetadataTagType::Keywords),
+        creator: tag(PdfDocumentMetadataTagType::Creator),
+        producer: tag(PdfDocumentMetadataTagType::Producer),
+        creation_date: date(PdfDocumentMetadataTagType::CreationDate),
+        modification_date: date(PdfDocumentMetadataTagType::ModificationDate),
+    }
+}
+
+/// Tells the encrypted and unsupported documents apart from the other pdfium load errors. A
+/// password error is a missing password when none was given.
+pub(crate) fn document_load_error(
+    e: &PdfiumError,
+    password: Option<&str>,
+) -> Option<FerrulesError> {
+    match e {
+        PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::PasswordError) => {
+            Some(if password.is_some() {
+                FerrulesError::IncorrectPassword
+            } else {
+                FerrulesError::EncryptedDocument
+            })
+        }
+        PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::FormatError) => Some(
+            FerrulesError::UnsupportedFormat("not a PDF or the file is corrupted".to_string()),
+        ),
+        PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::SecurityError) => Some(
+            FerrulesError::UnsupportedFormat("unsupported encryption handler".to_string()),
+        ),
+        _ => None,
+    }
+}
+
+/// Loads the document and parses the requested pages natively, handing the document level
+/// data to `on_document` and then each page result to `on_page` as soon as it is ready.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn parse_native_pages(
+    pdfium: &Pdfium,
+    doc_data: &[u8],
+    password: Option<&str>,
+    flatten: bool,
+    page_range: Option<PageRange>,
+    required_raster_width: u32,
+    required_raster_height: u32,
+    raster: RasterConfig,
+    text_normalization: Option<TextNormalization>,
+    merge_thresholds: MergeThresholds,
+    on_document: impl FnOnce(ParseNativeDocument) -> Result<(), FerrulesError>,
+    mut on_page: impl FnMut(anyhow::Result<ParseNativePageResult>) -> Result<(), FerrulesError>,
+) -> Result<(), FerrulesError> {
+    let mut document = pdfium
+        .load_pdf_from_byte_slice(doc_data, password)
+        .map_err(|e| {
+            document_load_error(&e, password).unwrap_or(FerrulesError::ParseNativeError)
+        })?;
+    on_document(ParseNativeDocument {
+        outline: extract_outline(&document),
+        info: extract_document_info(&document),
+        page_count: document.pages().len() as usize,
+    })?;
+    let mut pages: Vec<_> = document.pages_mut().iter().enumerate().collect();
+    let pages = if let Some(range) = page_range {
+        let page_ids = range
+            .page_ids(pages.len())
+            .ok_or(FerrulesError::ParseNativeError)?;
+        pages
+            .into_iter()
+            .filter(|(page_id, _)| page_ids.binary_search(page_id).is_ok())
+            .collect()
+    } else {
+        pages
+    };
+    for (page_id, mut page) in pages {
+        let parsing_result = parse_page_native(
+            page_id,
+            &mut page,
+            flatten,
+            required_raster_width,
+            required_raster_height,
+            raster,
+            text_normalization,
+            merge_thresholds,
+        );
+        on_page(parsing_result.map_err(|e| {
+            FerrulesError::NativePageError {
+                page_id,
+                message: format!("{e:#}"),
+            }
+            .into()
+        }))?;
+    }
+    Ok(())
+}
+
 fn handle_parse_native_req(
     pdfium: &Pdfium,
     req: ParseNativeRequest,
@@ -266,46 +820,407 @@ fn handle_parse_native_req(
         page_range,
         required_raster_width,
         required_raster_height,
+        raster,
+        text_normalization,
+        merge_thresholds,
         sender_tx,
+        document_tx,
+        cancellation,
         queue_time: _,
     } = req;
-    let mut document = pdfium
-        .load_pdf_from_byte_slice(&doc_data, password.as_deref())
-        .map_err(|_| FerrulesError::ParseNativeError)?;
-    let mut pages: Vec<_> = document.pages_mut().iter().enumerate().collect();
-    let pages = if let Some(range) = page_range {
-        if range.end > pages.len() {
-            return Err(FerrulesError::ParseNativeError);
+    let check_cancelled = || {
+        if cancellation.is_cancelled() {
+            Err(FerrulesError::Cancelled)
+        } else {
+            Ok(())
         }
-        pages.drain(range).collect()
-    } else {
-        pages
     };
-    for (page_id, mut page) in pages {
-        let parsing_result = parse_page_native(
-            page_id,
-            &mut page,
-            flatten,
-            required_raster_width,
-            required_raster_height,
-        );
-        sender_tx
-            .blocking_send(parsing_result)
-            .map_err(|_| FerrulesError::ParseNativeError)?
+    let res = parse_native_pages(
+        pdfium,
+        &doc_data,
+        password.as_deref(),
+        flatten,
+        page_range,
+        required_raster_width,
+        required_raster_height,
+        raster,
+        text_normalization,
+        merge_thresholds,
+        |document| {
+            check_cancelled()?;
+            // The caller only waits for the document data once its pages are parsed
+            let _ = document_tx.send(document);
+            Ok(())
+        },
+        |parsing_result| {
+            let sent = sender_tx.blocking_send(parsing_result);
+            // Stops before parsing the next page, the caller might have stopped listening
+            check_cancelled()?;
+            sent.map_err(|_| FerrulesError::ParseNativeError)
+        },
+    );
+    if let Err(e) = &res {
+        // Let the caller know the document itself couldn't be parsed
+        let err = match e {
+            FerrulesError::IncorrectPassword => FerrulesError::IncorrectPassword.into(),
+            FerrulesError::EncryptedDocument => FerrulesError::EncryptedDocument.into(),
+            FerrulesError::UnsupportedFormat(reason) => {
+                FerrulesError::UnsupportedFormat(reason.clone()).into()
+            }
+            FerrulesError::Cancelled => FerrulesError::Cancelled.into(),
+            e => anyhow::anyhow!("{e}"),
+        };
+        let _ = sender_tx.blocking_send(Err(err));
     }
-    Ok(())
+    res
 }
 
 pub fn start_native_parser(mut input_rx: Receiver<(ParseNativeRequest, Span)>) {
-    let pdfium = Pdfium::new(
-        Pdfium::bind_to_statically_linked_library().expect("can't load pdfiurm bindings"),
-    );
+    let pdfium = match load_pdfium() {
+        Ok((pdfium, _)) => pdfium,
+        Err(e) => {
+            tracing::error!("{e}");
+            // Every document fails with the load error instead of a closed queue
+            while let Some((req, _)) = input_rx.blocking_recv() {
+                let _ = req
+                    .sender_tx
+                    .blocking_send(Err(FerrulesError::PdfiumLoad(e.to_string()).into()));
+            }
+            return;
+        }
+    };
     while let Some((req, parent_span)) = input_rx.blocking_recv() {
         let queue_duration = req.queue_time.elapsed();
         tracing::debug!(parent: &parent_span, "Native request dequeued after {:?} in queue", queue_duration);
         match handle_parse_native_req(&pdfium, req, parent_span) {
             Ok(_) => {}
+            Err(FerrulesError::Cancelled) => tracing::debug!("native parsing cancelled"),
             Err(e) => eprintln!("error parsing request natively : {:?}", e),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::StyleSpan;
+
+    #[test]
+    fn test_raster_scale() {
+        // A4 page
+        let (width, height) = (595.0, 842.0);
+        assert_eq!(RasterConfig::default().scale(width, height), (1.0, false));
+
+        let raster = RasterConfig {
+            raster_dpi: Some(144.0),
+            max_raster_dim: None,
+        };
+        assert_eq!(raster.scale(width, height), (2.0, false));
+
+        let raster = RasterConfig {
+            raster_dpi: Some(144.0),
+            max_raster_dim: Some(842),
+        };
+        assert_eq!(raster.scale(width, height), (1.0, false));
+
+        // A0 poster at 600 DPI is over the pixel cap
+        let raster = RasterConfig {
+            raster_dpi: Some(600.0),
+            max_raster_dim: None,
+        };
+        let (scale, capped) = raster.scale(2384.0, 3370.0);
+        assert!(capped);
+        assert!(scale * scale * 2384.0 * 3370.0 <= RasterConfig::MAX_RASTER_PIXELS * 1.001);
+    }
+
+    #[test]
+    fn test_parse_encrypted_document() {
+        let (pdfium, _) = load_pdfium().unwrap();
+        let doc = std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/encrypted.pdf"
+        ))
+        .unwrap();
+        let parse = |password| {
+            let mut pages = Vec::new();
+            parse_native_pages(
+                &pdfium,
+                &doc,
+                password,
+                true,
+                None,
+                1024,
+                1024,
+                RasterConfig::default(),
+                Some(TextNormalization::default()),
+                MergeThresholds::default(),
+                |_| Ok(()),
+                |page| {
+                    pages.push(page.unwrap());
+                    Ok(())
+                },
+            )
+            .map(|_| pages)
+        };
+
+        let pages = parse(Some("secret")).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0]
+            .text_lines
+            .iter()
+            .any(|l| l.text.contains("Encrypted fixture page")));
+        assert!(matches!(
+            parse(Some("wrong")),
+            Err(FerrulesError::IncorrectPassword)
+        ));
+        assert!(matches!(parse(None), Err(FerrulesError::EncryptedDocument)));
+    }
+
+    #[test]
+    fn test_parse_unsupported_format() {
+        let (pdfium, _) = load_pdfium().unwrap();
+        let res = parse_native_pages(
+            &pdfium,
+            b"PK\x03\x04 not a pdf document",
+            None,
+            true,
+            None,
+            1024,
+            1024,
+            RasterConfig::default(),
+            Some(TextNormalization::default()),
+            MergeThresholds::default(),
+            |_| Ok(()),
+            |_| Ok(()),
+        );
+        assert!(matches!(res, Err(FerrulesError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn test_parse_rotated_pages() {
+        let (pdfium, _) = load_pdfium().unwrap();
+        let doc =
+            std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/rotated.pdf")).unwrap();
+        let mut pages = Vec::new();
+        parse_native_pages(
+            &pdfium,
+            &doc,
+            None,
+            true,
+            None,
+            1024,
+            1024,
+            RasterConfig::default(),
+            Some(TextNormalization::default()),
+            MergeThresholds::default(),
+            |_| Ok(()),
+            |page| {
+                pages.push(page.unwrap());
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        // The same heading is written at the top left of each 612x792 page, pages are
+        // rotated by 0, 90, 180 and 270 degrees clockwise
+        let expected = [
+            (PageRotation::Deg0, (612.0, 792.0), (false, false)),
+            (PageRotation::Deg90, (792.0, 612.0), (true, false)),
+            (PageRotation::Deg180, (612.0, 792.0), (true, true)),
+            (PageRotation::Deg270, (792.0, 612.0), (false, true)),
+        ];
+        assert_eq!(pages.len(), expected.len());
+        for (page, (rotation, (width, height), (right, bottom))) in pages.iter().zip(expected) {
+            assert_eq!(page.page_rotation, rotation);
+            assert_eq!(
+                (page.page_bbox.width(), page.page_bbox.height()),
+                (width, height)
+            );
+            let page_image = page.page_image_scale1.as_ref().unwrap();
+            assert_eq!(page_image.width() > page_image.height(), width > height);
+
+            let heading = page
+                .text_lines
+                .iter()
+                .find(|l| l.text.contains("Rotated heading"))
+                .unwrap();
+            let (x, y) = heading.bbox.center();
+            assert_eq!(
+                (x > width / 2.0, y > height / 2.0),
+                (right, bottom),
+                "{rotation:?}"
+            );
+            assert!(heading.bbox.x1 <= width && heading.bbox.y1 <= height);
+        }
+    }
+
+    #[test]
+    fn test_extract_outline() {
+        let (pdfium, _) = load_pdfium().unwrap();
+        let doc =
+            std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/outline.pdf")).unwrap();
+        let mut outline = None;
+        let mut pages = Vec::new();
+        // The outline covers the whole document, whatever the page range
+        parse_native_pages(
+            &pdfium,
+            &doc,
+            None,
+            true,
+            Some(PageRange::from(1..2)),
+            1024,
+            1024,
+            RasterConfig::default(),
+            Some(TextNormalization::default()),
+            MergeThresholds::default(),
+            |document| {
+                outline = document.outline;
+                Ok(())
+            },
+            |page| {
+                pages.push(page.unwrap().page_id);
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(pages, vec![1]);
+        let outline = outline
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.title, e.level, e.page_id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outline,
+            vec![
+                ("Annual Report".to_string(), 1, Some(0)),
+                ("Results".to_string(), 2, Some(1)),
+                ("Outlook".to_string(), 2, Some(1)),
+                ("Appendix".to_string(), 1, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_links() {
+        let (pdfium, _) = load_pdfium().unwrap();
+        let doc =
+            std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/links.pdf")).unwrap();
+        let mut pages = Vec::new();
+        parse_native_pages(
+            &pdfium,
+            &doc,
+            None,
+            true,
+            None,
+            1024,
+            1024,
+            RasterConfig::default(),
+            Some(TextNormalization::default()),
+            MergeThresholds::default(),
+            |_| Ok(()),
+            |page| {
+                pages.push(page.unwrap());
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        let links = pages[0]
+            .text_lines
+            .iter()
+            .flat_map(|line| line.links.iter())
+            .map(|link| (link.text.as_str(), &link.target))
+            .collect::<Vec<_>>();
+        let uri = |uri: &str| LinkTarget::Uri(uri.to_string());
+        assert_eq!(
+            links,
+            vec![
+                ("https://example.com", &uri("https://example.com")),
+                // The link rect spans two lines
+                ("the annual", &uri("https://example.com/report")),
+                ("report", &uri("https://example.com/report")),
+                ("See page 2", &LinkTarget::PageId(1)),
+            ]
+        );
+        assert!(pages[1].text_lines.iter().all(|line| line.links.is_empty()));
+    }
+
+    #[test]
+    fn test_span_styles() {
+        let (pdfium, _) = load_pdfium().unwrap();
+        let doc =
+            std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/styles.pdf")).unwrap();
+        let mut pages = Vec::new();
+        parse_native_pages(
+            &pdfium,
+            &doc,
+            None,
+            true,
+            None,
+            1024,
+            1024,
+            RasterConfig::default(),
+            Some(TextNormalization::default()),
+            MergeThresholds::default(),
+            |_| Ok(()),
+            |page| {
+                pages.push(page.unwrap());
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        let lines = &pages[0].text_lines;
+        // Spans break where the font style changes, pdfium generated spaces are left out
+        let spans = lines[0]
+            .spans
+            .iter()
+            .filter(|span| !span.text.trim().is_empty())
+            .map(|span| (span.text.trim(), span.bold, span.italic))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                ("Regular text,", false, false),
+                ("bold words", true, false),
+                ("and", false, false),
+                ("italic words", false, true),
+            ]
+        );
+        assert_eq!(lines[0].font_size(), Some(11.0));
+
+        assert_eq!(
+            lines[1].style_spans(),
+            vec![StyleSpan {
+                text: "Bold italic heading".to_string(),
+                bold: true,
+                italic: true,
+                script: None,
+            }]
+        );
+        assert_eq!(lines[1].font_size(), Some(18.0));
+    }
+
+    #[test]
+    fn test_pdf_date_to_iso8601() {
+        assert_eq!(
+            pdf_date_to_iso8601("D:20230415093012+02'00'").as_deref(),
+            Some("2023-04-15T09:30:12+02:00")
+        );
+        assert_eq!(
+            pdf_date_to_iso8601("D:20230415093012Z").as_deref(),
+            Some("2023-04-15T09:30:12Z")
+        );
+        assert_eq!(
+            pdf_date_to_iso8601("D:2023").as_deref(),
+            Some("2023-01-01T00:00:00")
+        );
+        assert_eq!(
+            pdf_date_to_iso8601("20230415-05'30").as_deref(),
+            Some("2023-04-15T00:00:00-05:30")
+        );
+        assert_eq!(pdf_date_to_iso8601("D:20231345"), None);
+        assert_eq!(pdf_date_to_iso8601("April 15th, 2023"), None);
+    }
+}
@@ -0,0 +1,1022 @@
+use std::{
+    cell::RefCell,
+    io::{Read, Write},
+    path::Path,
+    process::Stdio,
+    sync::Arc,
+    time::Instant,
+};
+
+use image::{DynamicImage, RgbaImage};
+use memmap2::Mmap;
+use rkyv::{
+    ser::serializers::AllocSerializer, validation::validators::DefaultValidator, AlignedVec,
+    Archive, CheckBytes, Deserialize as RkyvDeserialize, Infallible, Serialize as RkyvSerialize,
+};
+use tempfile::TempPath;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    sync::{
+        mpsc::{self, error::SendError, Receiver, Sender, UnboundedReceiver, WeakUnboundedSender},
+        oneshot, Mutex, OnceCell,
+    },
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, Span};
+
+use crate::{
+    blocks::EmbeddedImageFormat,
+    entities::{BBox, EmbeddedImage, FormField, Line, PDFPath, PageID},
+    error::FerrulesError,
+    layout::model::LayoutBBox,
+    ocr::PageRotation,
+    pdfium::load_pdfium,
+};
+
+use super::{
+    merge::MergeThresholds,
+    native::{
+        parse_native_pages, ParseNativeDocument, ParseNativeMetadata, ParseNativePageResult,
+        ParseNativeRequest, RasterConfig,
+    },
+    normalize::TextNormalization,
+    page_range::PageRange,
+};
+
+/// Environment variable set on the child processes spawned by the native process pool.
+pub const NATIVE_WORKER_ENV: &str = "FERRULES_NATIVE_WORKER";
+
+/// Largest frame exchanged with a worker, a larger length is a corrupted stream
+const MAX_FRAME_LEN: u64 = 256 * 1024 * 1024;
+
+#[derive(Archive, RkyvSerialize, RkyvDeserialize)]
+#[archive(check_bytes)]
+struct WorkerRequest {
+    /// File of the document written by the parent, mapped by the worker
+    doc_path: String,
+    password: Option<String>,
+    flatten: bool,
+    /// 0-based `(start, end)` ranges of the selected pages
+    page_range: Option<Vec<(u64, u64)>>,
+    required_raster_width: u32,
+    required_raster_height: u32,
+    raster: RasterConfig,
+    text_normalization: Option<TextNormalization>,
+    merge_thresholds: MergeThresholds,
+}
+
+#[derive(Archive, RkyvSerialize, RkyvDeserialize)]
+#[archive(check_bytes)]
+struct WorkerImage {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl From<&DynamicImage> for WorkerImage {
+    fn from(image: &DynamicImage) -> Self {
+        let rgba = image.to_rgba8();
+        Self {
+            width: rgba.width(),
+            height: rgba.height(),
+            rgba: rgba.into_raw(),
+        }
+    }
+}
+
+impl WorkerImage {
+    fn into_image(self) -> anyhow::Result<DynamicImage> {
+        RgbaImage::from_raw(self.width, self.height, self.rgba)
+            .map(DynamicImage::ImageRgba8)
+            .ok_or_else(|| anyhow::anyhow!("invalid page image received from native worker"))
+    }
+}
+
+#[derive(Archive, RkyvSerialize, RkyvDeserialize)]
+#[archive(check_bytes)]
+struct WorkerEmbeddedImage {
+    bbox: BBox,
+    image: WorkerImage,
+    format: EmbeddedImageFormat,
+}
+
+#[derive(Archive, RkyvSerialize, RkyvDeserialize)]
+#[archive(check_bytes)]
+struct WorkerPage {
+    page_id: u64,
+    text_lines: Vec<Line>,
+    paths: Vec<PDFPath>,
+    form_fields: Vec<FormField>,
+    embedded_images: Vec<WorkerEmbeddedImage>,
+    page_bbox: BBox,
+    page_image: WorkerImage,
+    page_image_scale1: Option<WorkerImage>,
+    downscale_factor: f32,
+    rotation: PageRotation,
+    page_rotation: PageRotation,
+    structure_layout: Vec<LayoutBBox>,
+    parse_native_duration_ms: f64,
+}
+
+impl From<ParseNativePageResult> for WorkerPage {
+    fn from(result: ParseNativePageResult) -> Self {
+        Self {
+            page_id: result.page_id as u64,
+            page_image: WorkerImage::from(result.page_image.as_ref()),
+            page_image_scale1: result.page_image_scale1.as_ref().map(WorkerImage::from),
+            text_lines: result.text_lines,
+            paths: result.paths,
+            form_fields: result.form_fields,
+            embedded_images: result
+                .embedded_images
+                .into_iter()
+                .map(|embedded| WorkerEmbeddedImage {
+                    bbox: embedded.bbox,
+                    image: WorkerImage::from(&embedded.image),
+                    format: embedded.format,
+                })
+                .collect(),
+            page_bbox: result.page_bbox,
+            downscale_factor: result.downscale_factor,
+            rotation: result.rotation,
+            page_rotation: result.page_rotation,
+            structure_layout: result.structure_layout,
+            parse_native_duration_ms: result.metadata.parse_native_duration_ms,
+        }
+    }
+}
+
+impl WorkerPage {
+    fn into_result(self) -> anyhow::Result<ParseNativePageResult> {
+        Ok(ParseNativePageResult {
+            page_id: self.page_id as PageID,
+            text_lines: self.text_lines,
+            paths: self.paths,
+            form_fields: self.form_fields,
+            embedded_images: self
+                .embedded_images
+                .into_iter()
+                .map(|embedded| {
+                    Ok(EmbeddedImage {
+                        bbox: embedded.bbox,
+                        image: embedded.image.into_image()?,
+                        format: embedded.format,
+                    })
+                })
+                .collect::<anyhow::Result<_>>()?,
+            page_bbox: self.page_bbox,
+            page_image: Arc::new(self.page_image.into_image()?),
+            page_image_scale1: self
+                .page_image_scale1
+                .map(WorkerImage::into_image)
+                .transpose()?,
+            downscale_factor: self.downscale_factor,
+            rotation: self.rotation,
+            page_rotation: self.page_rotation,
+            structure_layout: self.structure_layout,
+            metadata: ParseNativeMetadata {
+                parse_native_duration_ms: self.parse_native_duration_ms,
+            },
+        })
+    }
+}
+
+#[derive(Archive, RkyvSerialize, RkyvDeserialize)]
+#[archive(check_bytes)]
+enum WorkerResponse {
+    /// Document level data, sent before the pages
+    Document(ParseNativeDocument),
+    Page(WorkerPage),
+    /// A single page couldn't be parsed, the following pages are still sent
+    PageError {
+        page_id: u64,
+        message: String,
+    },
+    /// All the requested pages were sent
+    Done,
+    /// The document couldn't be parsed
+    Error(String),
+    /// The document is encrypted and the password is wrong
+    IncorrectPassword,
+    /// The document is encrypted and no password was given
+    EncryptedDocument,
+    /// pdfium can't open the document format
+    UnsupportedFormat(String),
+}
+
+impl WorkerResponse {
+    /// The error opening the document, rebuilt on the parent side
+    fn document_error(self) -> Option<FerrulesError> {
+        match self {
+            WorkerResponse::IncorrectPassword => Some(FerrulesError::IncorrectPassword),
+            WorkerResponse::EncryptedDocument => Some(FerrulesError::EncryptedDocument),
+            WorkerResponse::UnsupportedFormat(reason) => {
+                Some(FerrulesError::UnsupportedFormat(reason))
+            }
+            _ => None,
+        }
+    }
+}
+
+// NOTE: frames are a little-endian u64 length followed by the rkyv archive. The archives are
+// validated before they are accessed and the lengths are capped: a worker that crashed
+// mid-frame or wrote anything else on its stdout is an error, not a read out of bounds.
+fn encode_frame<T>(value: &T) -> anyhow::Result<Vec<u8>>
+where
+    T: RkyvSerialize<AllocSerializer<1024>>,
+{
+    let bytes = rkyv::to_bytes::<_, 1024>(value)?;
+    let mut frame = Vec::with_capacity(8 + bytes.len());
+    frame.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    frame.extend_from_slice(&bytes);
+    Ok(frame)
+}
+
+fn decode_frame<T>(bytes: &[u8]) -> anyhow::Result<T>
+where
+    T: Archive,
+    for<'a> T::Archived: CheckBytes<DefaultValidator<'a>> + RkyvDeserialize<T, Infallible>,
+{
+    let mut aligned = AlignedVec::with_capacity(bytes.len());
+    aligned.extend_from_slice(bytes);
+    let archived = rkyv::check_archived_root::<T>(&aligned)
+        .map_err(|e| anyhow::anyhow!("invalid native worker frame: {e}"))?;
+    // Deserializing with Infallible can't fail
+    Ok(archived.deserialize(&mut Infallible).unwrap())
+}
+
+fn frame_len(len: u64) -> anyhow::Result<usize> {
+    if len > MAX_FRAME_LEN {
+        anyhow::bail!("native worker frame of {len} bytes exceeds {MAX_FRAME_LEN} bytes");
+    }
+    Ok(len as usize)
+}
+
+fn read_frame_blocking<T>(reader: &mut impl Read) -> anyhow::Result<Option<T>>
+where
+    T: Archive,
+    for<'a> T::Archived: CheckBytes<DefaultValidator<'a>> + RkyvDeserialize<T, Infallible>,
+{
+    let mut len = [0u8; 8];
+    match reader.read_exact(&mut len) {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let mut bytes = vec![0u8; frame_len(u64::from_le_bytes(len))?];
+    reader.read_exact(&mut bytes)?;
+    decode_frame(&bytes).map(Some)
+}
+
+async fn read_frame<T>(reader: &mut BufReader<ChildStdout>) -> anyhow::Result<T>
+where
+    T: Archive,
+    for<'a> T::Archived: CheckBytes<DefaultValidator<'a>> + RkyvDeserialize<T, Infallible>,
+{
+    let len = frame_len(reader.read_u64_le().await?)?;
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes).await?;
+    decode_frame(&bytes)
+}
+
+/// Output of the worker frames: the stdout the worker was spawned with. The process stdout is
+/// then redirected to stderr, so that the logs of pdfium or of a dependency writing to stdout
+/// can't end up in the middle of the frames.
+#[cfg(unix)]
+fn worker_output() -> anyhow::Result<Box<dyn Write>> {
+    use std::os::fd::AsFd;
+
+    std::io::stdout().flush()?;
+    let output = std::io::stdout().as_fd().try_clone_to_owned()?;
+    // SAFETY: replaces the stdout descriptor of the process with a copy of its stderr, the
+    // frames are written to the descriptor cloned above
+    if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(Box::new(std::fs::File::from(output)))
+}
+
+#[cfg(not(unix))]
+fn worker_output() -> anyhow::Result<Box<dyn Write>> {
+    Ok(Box::new(std::io::stdout().lock()))
+}
+
+/// Runs the native worker loop when the current process was spawned by the native
+/// process pool, exiting once the parent closes the pipe. Does nothing otherwise.
+///
+/// Binaries using [`super::native::ParseNativeQueue::new_process_pool`] must call this
+/// function at the very start of their `main`, before any output is written to stdout.
+pub fn run_native_worker_if_requested() {
+    if std::env::var_os(NATIVE_WORKER_ENV).is_none() {
+        return;
+    }
+    let code = match run_native_worker() {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("native worker error: {e:?}");
+            1
+        }
+    };
+    std::process::exit(code)
+}
+
+fn run_native_worker() -> anyhow::Result<()> {
+    // Shared by the document and page callbacks
+    let stdout = RefCell::new(worker_output()?);
+    let (pdfium, _) = load_pdfium()?;
+    let mut stdin = std::io::stdin().lock();
+
+    while let Some(req) = read_frame_blocking::<WorkerRequest>(&mut stdin)? {
+        let write_response = |response: WorkerResponse| -> Result<(), FerrulesError> {
+            encode_frame(&response)
+                .and_then(|frame| Ok(stdout.borrow_mut().write_all(&frame)?))
+                .map_err(|_| FerrulesError::ParseNativeError)
+        };
+        // SAFETY: the document file is written once by the parent before the first request and
+        // only removed after its last job
+        let doc_data =
+            match std::fs::File::open(&req.doc_path).and_then(|file| unsafe { Mmap::map(&file) }) {
+                Ok(doc_data) => doc_data,
+                Err(e) => {
+                    write_response(WorkerResponse::Error(format!(
+                        "can't read the document: {e}"
+                    )))?;
+                    stdout.borrow_mut().flush()?;
+                    continue;
+                }
+            };
+        let parsed = parse_native_pages(
+            &pdfium,
+            &doc_data,
+            req.password.as_deref(),
+            req.flatten,
+            req.page_range.map(|ranges| {
+                ranges
+                    .into_iter()
+                    .map(|(start, end)| start as usize..end as usize)
+                    .collect()
+            }),
+            req.required_raster_width,
+            req.required_raster_height,
+            req.raster,
+            req.text_normalization,
+            req.merge_thresholds,
+            |document| write_response(WorkerResponse::Document(document)),
+            |page| {
+                write_response(match page {
+                    Ok(page) => WorkerResponse::Page(page.into()),
+                    Err(e) => match e.downcast::<FerrulesError>() {
+                        Ok(FerrulesError::NativePageError { page_id, message }) => {
+                            WorkerResponse::PageError {
+                                page_id: page_id as u64,
+                                message,
+                            }
+                        }
+                        Ok(e) => WorkerResponse::Error(e.to_string()),
+                        Err(e) => WorkerResponse::Error(e.to_string()),
+                    },
+                })
+            },
+        );
+        match parsed {
+            Ok(_) => write_response(WorkerResponse::Done)?,
+            Err(FerrulesError::IncorrectPassword) => {
+                write_response(WorkerResponse::IncorrectPassword)?
+            }
+            Err(FerrulesError::EncryptedDocument) => {
+                write_response(WorkerResponse::EncryptedDocument)?
+            }
+            Err(FerrulesError::UnsupportedFormat(reason)) => {
+                write_response(WorkerResponse::UnsupportedFormat(reason))?
+            }
+            Err(e) => write_response(WorkerResponse::Error(e.to_string()))?,
+        }
+        stdout.borrow_mut().flush()?;
+    }
+    Ok(())
+}
+
+/// Retries of a request on a respawned worker after its worker process crashed
+const WORKER_RETRIES: usize = 1;
+
+/// Documents are only split in chunks of at least this many pages, smaller chunks would spend
+/// more time loading the document in each worker than parsing pages
+const MIN_CHUNK_PAGES: usize = 4;
+
+struct NativeWorker {
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl NativeWorker {
+    fn spawn() -> anyhow::Result<Self> {
+        let mut child = Command::new(std::env::current_exe()?)
+            .env(NATIVE_WORKER_ENV, "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("native worker stdin unavailable"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("native worker stdout unavailable"))?;
+        Ok(Self {
+            _child: child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        })
+    }
+
+    async fn send(&mut self, doc: &NativeDoc, page_ids: &[PageID]) -> anyhow::Result<()> {
+        let doc_path = doc.file().await?;
+        let frame = encode_frame(&WorkerRequest {
+            doc_path: doc_path
+                .to_str()
+                .ok_or_else(|| anyhow::anyhow!("non UTF-8 document path {}", doc_path.display()))?
+                .to_owned(),
+            password: doc.password.clone(),
+            flatten: doc.flatten,
+            page_range: Some(
+                page_ranges(page_ids)
+                    .ranges()
+                    .iter()
+                    .map(|r| (r.start as u64, r.end as u64))
+                    .collect(),
+            ),
+            required_raster_width: doc.required_raster_width,
+            required_raster_height: doc.required_raster_height,
+            raster: doc.raster,
+            text_normalization: doc.text_normalization,
+            merge_thresholds: doc.merge_thresholds,
+        })?;
+        self.stdin.write_all(&frame).await?;
+        self.stdin.flush().await?;
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> anyhow::Result<WorkerResponse> {
+        read_frame(&mut self.stdout).await
+    }
+}
+
+/// Worker process of a pool task, spawned on first use and after a crash
+struct WorkerSlot {
+    worker_id: usize,
+    worker: Option<NativeWorker>,
+}
+
+impl WorkerSlot {
+    fn get(&mut self) -> anyhow::Result<&mut NativeWorker> {
+        if self.worker.is_none() {
+            self.worker = Some(NativeWorker::spawn()?);
+        }
+        Ok(self.worker.as_mut().unwrap())
+    }
+
+    /// Drops the worker process, which is in an unknown state. The next request respawns it.
+    fn kill(&mut self, e: &anyhow::Error) {
+        tracing::error!("native worker {} error: {e:?}", self.worker_id);
+        self.worker = None;
+    }
+}
+
+/// Document shared by the jobs parsing its pages
+struct NativeDoc {
+    doc_data: Arc<[u8]>,
+    /// Copy of the document the workers map, written on the first request. The requests only
+    /// carry its path and the pages to parse, not the document. Removed with the last job.
+    doc_file: OnceCell<TempPath>,
+    password: Option<String>,
+    flatten: bool,
+    page_range: Option<PageRange>,
+    required_raster_width: u32,
+    required_raster_height: u32,
+    raster: RasterConfig,
+    text_normalization: Option<TextNormalization>,
+    merge_thresholds: MergeThresholds,
+}
+
+impl NativeDoc {
+    async fn file(&self) -> anyhow::Result<&Path> {
+        let doc_file = self
+            .doc_file
+            .get_or_try_init(|| async {
+                let doc_data = Arc::clone(&self.doc_data);
+                let doc_file = tokio::task::spawn_blocking(move || -> std::io::Result<TempPath> {
+                    let mut file = tempfile::Builder::new()
+                        .prefix("ferrules-native-")
+                        .suffix(".pdf")
+                        .tempfile()?;
+                    file.write_all(&doc_data)?;
+                    Ok(file.into_temp_path())
+                })
+                .await??;
+                anyhow::Ok(doc_file)
+            })
+            .await?;
+        Ok(doc_file)
+    }
+}
+
+/// Work item of the pool: a document to open and split, or a chunk of its pages
+struct NativeJob {
+    doc: Arc<NativeDoc>,
+    /// Pages parsed by the job, `None` for the job opening the document
+    page_ids: Option<Vec<PageID>>,
+    sender_tx: Sender<anyhow::Result<ParseNativePageResult>>,
+    document_tx: Option<oneshot::Sender<ParseNativeDocument>>,
+    /// The jobs of a cancelled document are skipped, pages already sent to a worker still
+    /// finish
+    cancellation: CancellationToken,
+    span: Span,
+}
+
+impl NativeJob {
+    fn new(req: ParseNativeRequest, span: Span) -> Self {
+        let ParseNativeRequest {
+            doc_data,
+            password,
+            flatten,
+            page_range,
+            required_raster_width,
+            required_raster_height,
+            raster,
+            text_normalization,
+            merge_thresholds,
+            sender_tx,
+            document_tx,
+            cancellation,
+            queue_time: _,
+        } = req;
+        Self {
+            doc: Arc::new(NativeDoc {
+                doc_data,
+                doc_file: OnceCell::new(),
+                password,
+                flatten,
+                page_range,
+                required_raster_width,
+                required_raster_height,
+                raster,
+                text_normalization,
+                merge_thresholds,
+            }),
+            page_ids: None,
+            sender_tx,
+            document_tx: Some(document_tx),
+            cancellation,
+            span,
+        }
+    }
+}
+
+/// Contiguous runs of `page_ids` as a page range
+fn page_ranges(page_ids: &[PageID]) -> PageRange {
+    page_ids
+        .iter()
+        .map(|&page_id| page_id..page_id + 1)
+        .collect()
+}
+
+/// Splits the pages of a document in one chunk per worker, of at least [`MIN_CHUNK_PAGES`]
+fn chunk_pages(page_ids: &[PageID], workers: usize) -> Vec<Vec<PageID>> {
+    let chunk_size = page_ids.len().div_ceil(workers.max(1)).max(MIN_CHUNK_PAGES);
+    page_ids.chunks(chunk_size).map(<[_]>::to_vec).collect()
+}
+
+/// Loads the document in a worker, without parsing any page, to get its document level data.
+/// The outer error is a worker failure, the inner one a document that can't be parsed.
+async fn open_document(
+    slot: &mut WorkerSlot,
+    doc: &NativeDoc,
+) -> anyhow::Result<anyhow::Result<ParseNativeDocument>> {
+    let worker = slot.get()?;
+    worker.send(doc, &[]).await?;
+    let mut document = None;
+    loop {
+        match worker.recv().await? {
+            WorkerResponse::Document(doc) => document = Some(doc),
+            WorkerResponse::Done => {
+                return Ok(document.ok_or_else(|| anyhow::anyhow!("native worker sent no document")))
+            }
+            WorkerResponse::Error(e) => return Ok(Err(anyhow::anyhow!(e))),
+            response @ (WorkerResponse::IncorrectPassword
+            | WorkerResponse::EncryptedDocument
+            | WorkerResponse::UnsupportedFormat(_)) => {
+                if let Some(err) = response.document_error() {
+                    return Ok(Err(err.into()));
+                }
+            }
+            WorkerResponse::Page(_) | WorkerResponse::PageError { .. } => {}
+        }
+    }
+}
+
+/// Opens the document of `job` and queues its pages in chunks, so that idle workers parse them
+/// in parallel. The chunks that can't be queued are parsed by this worker.
+async fn split_document(
+    slot: &mut WorkerSlot,
+    job: NativeJob,
+    jobs_tx: &WeakUnboundedSender<NativeJob>,
+    workers: usize,
+) {
+    let NativeJob {
+        doc,
+        sender_tx,
+        document_tx,
+        cancellation,
+        span,
+        ..
+    } = job;
+    let mut attempt = 0;
+    let opened = loop {
+        match open_document(slot, &doc).await {
+            Ok(opened) => break opened,
+            Err(e) => {
+                slot.kill(&e);
+                if attempt == WORKER_RETRIES {
+                    let _ = sender_tx
+                        .send(Err(FerrulesError::ParseNativeError.into()))
+                        .await;
+                    return;
+                }
+                attempt += 1;
+            }
+        }
+    };
+    let document = match opened {
+        Ok(document) => document,
+        Err(e) => {
+            tracing::error!("native worker failed parsing document: {e}");
+            let _ = sender_tx.send(Err(e)).await;
+            return;
+        }
+    };
+    let page_ids = match &doc.page_range {
+        Some(range) => range.page_ids(document.page_count),
+        None => Some((0..document.page_count).collect()),
+    };
+    let Some(page_ids) = page_ids else {
+        let _ = sender_tx
+            .send(Err(anyhow::anyhow!("{}", FerrulesError::ParseNativeError)))
+            .await;
+        return;
+    };
+    if let Some(document_tx) = document_tx {
+        let _ = document_tx.send(document);
+    }
+
+    let mut chunks = chunk_pages(&page_ids, workers).into_iter();
+    let first_chunk = chunks.next().unwrap_or_default();
+    let mut unqueued = Vec::new();
+    for chunk in chunks {
+        let job = NativeJob {
+            doc: Arc::clone(&doc),
+            page_ids: Some(chunk),
+            sender_tx: sender_tx.clone(),
+            document_tx: None,
+            cancellation: cancellation.clone(),
+            span: span.clone(),
+        };
+        // The pool is shutting down when its queue is gone
+        match jobs_tx.upgrade() {
+            Some(jobs_tx) => {
+                if let Err(SendError(job)) = jobs_tx.send(job) {
+                    unqueued.extend(job.page_ids);
+                }
+            }
+            None => unqueued.extend(job.page_ids),
+        }
+    }
+    parse_chunk(slot, &doc, first_chunk, &sender_tx).await;
+    for chunk in unqueued {
+        if cancellation.is_cancelled() {
+            break;
+        }
+        parse_chunk(slot, &doc, chunk, &sender_tx).await;
+    }
+}
+
+/// Forwards the pages parsed by the worker, removing them from `pending` as they arrive
+async fn forward_pages(
+    slot: &mut WorkerSlot,
+    doc: &NativeDoc,
+    pending: &mut Vec<PageID>,
+    sender_tx: &Sender<anyhow::Result<ParseNativePageResult>>,
+) -> anyhow::Result<()> {
+    let worker = slot.get()?;
+    worker.send(doc, pending).await?;
+    loop {
+        let page = match worker.recv().await? {
+            // Sent by the job that opened the document
+            WorkerResponse::Document(_) => continue,
+            WorkerResponse::Page(page) => {
+                let page_id = page.page_id as PageID;
+                pending.retain(|&id| id != page_id);
+                page.into_result()
+            }
+            WorkerResponse::PageError { page_id, message } => {
+                let page_id = page_id as PageID;
+                pending.retain(|&id| id != page_id);
+                Err(FerrulesError::NativePageError { page_id, message }.into())
+            }
+            WorkerResponse::Done => return Ok(()),
+            WorkerResponse::Error(e) => {
+                tracing::error!("native worker failed parsing document: {e}");
+                pending.clear();
+                let _ = sender_tx.send(Err(anyhow::anyhow!(e))).await;
+                return Ok(());
+            }
+            response @ (WorkerResponse::IncorrectPassword
+            | WorkerResponse::EncryptedDocument
+            | WorkerResponse::UnsupportedFormat(_)) => {
+                pending.clear();
+                if let Some(err) = response.document_error() {
+                    let _ = sender_tx.send(Err(err.into())).await;
+                }
+                return Ok(());
+            }
+        };
+        // The receiver might be gone, keep draining the worker output regardless
+        let _ = sender_tx.send(page).await;
+    }
+}
+
+/// Parses a chunk of pages. When the worker crashes, the pages it didn't send are retried on a
+/// respawned worker, then reported as failed pages.
+async fn parse_chunk(
+    slot: &mut WorkerSlot,
+    doc: &NativeDoc,
+    page_ids: Vec<PageID>,
+    sender_tx: &Sender<anyhow::Result<ParseNativePageResult>>,
+) {
+    if page_ids.is_empty() {
+        return;
+    }
+    let mut pending = page_ids;
+    for _ in 0..=WORKER_RETRIES {
+        match forward_pages(slot, doc, &mut pending, sender_tx).await {
+            Ok(_) => return,
+            Err(e) => slot.kill(&e),
+        }
+        if pending.is_empty() {
+            return;
+        }
+        tracing::warn!(
+            "native worker {} crashed with {} pages left",
+            slot.worker_id,
+            pending.len()
+        );
+    }
+    for page_id in pending {
+        let _ = sender_tx
+            .send(Err(FerrulesError::NativePageError {
+                page_id,
+                message: "native worker crashed".to_string(),
+            }
+            .into()))
+            .await;
+    }
+}
+
+async fn run_native_worker_task(
+    worker_id: usize,
+    workers: usize,
+    jobs_rx: Arc<Mutex<UnboundedReceiver<NativeJob>>>,
+    jobs_tx: WeakUnboundedSender<NativeJob>,
+) {
+    let mut slot = WorkerSlot {
+        worker_id,
+        worker: None,
+    };
+    loop {
+        let Some(mut job) = jobs_rx.lock().await.recv().await else {
+            break;
+        };
+        if job.cancellation.is_cancelled() {
+            let _ = job
+                .sender_tx
+                .send(Err(FerrulesError::Cancelled.into()))
+                .await;
+            continue;
+        }
+        let start = Instant::now();
+        let span = job.span.clone();
+        match job.page_ids.take() {
+            Some(page_ids) => {
+                parse_chunk(&mut slot, &job.doc, page_ids, &job.sender_tx)
+                    .instrument(span)
+                    .await
+            }
+            None => {
+                split_document(&mut slot, job, &jobs_tx, workers)
+                    .instrument(span)
+                    .await
+            }
+        }
+        tracing::debug!(
+            "native worker {worker_id} handled job in {:?}",
+            start.elapsed()
+        );
+    }
+}
+
+pub(crate) async fn start_native_process_pool(
+    workers: usize,
+    mut input_rx: Receiver<(ParseNativeRequest, Span)>,
+) {
+    // Documents and the chunks of their pages share a queue, pulled by the idle workers
+    let (jobs_tx, jobs_rx) = mpsc::unbounded_channel();
+    let jobs_rx = Arc::new(Mutex::new(jobs_rx));
+    let mut handles = Vec::with_capacity(workers);
+    for worker_id in 0..workers {
+        handles.push(tokio::spawn(run_native_worker_task(
+            worker_id,
+            workers,
+            Arc::clone(&jobs_rx),
+            jobs_tx.downgrade(),
+        )));
+    }
+    while let Some((req, parent_span)) = input_rx.recv().await {
+        let queue_duration = req.queue_time.elapsed();
+        tracing::debug!(parent: &parent_span, "Native request dequeued after {:?} in queue", queue_duration);
+        let _ = jobs_tx.send(NativeJob::new(req, parent_span));
+    }
+    // The workers exit once the queued jobs are done
+    drop(jobs_tx);
+    futures::future::join_all(handles).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::{DocumentInfo, FormFieldKind, OutlineEntry, TextDirection};
+
+    #[test]
+    fn test_worker_frame_roundtrip() -> anyhow::Result<()> {
+        let mut image = RgbaImage::new(4, 2);
+        image.put_pixel(1, 1, image::Rgba([255, 0, 0, 255]));
+        let page = ParseNativePageResult {
+            page_id: 3,
+            text_lines: vec![Line {
+                text: "hello".to_string(),
+                bbox: BBox {
+                    x0: 1.0,
+                    y0: 2.0,
+                    x1: 3.0,
+                    y1: 4.0,
+                },
+                rotation: 0.0,
+                spans: vec![],
+                confidence: None,
+                links: vec![],
+                direction: TextDirection::Ltr,
+            }],
+            paths: vec![],
+            form_fields: vec![FormField {
+                page_id: 3,
+                name: Some("applicant.name".to_string()),
+                kind: FormFieldKind::Text,
+                value: Some("Jane Doe".to_string()),
+                checked: None,
+                bbox: BBox {
+                    x0: 1.0,
+                    y0: 1.0,
+                    x1: 3.0,
+                    y1: 2.0,
+                },
+            }],
+            embedded_images: vec![],
+            page_bbox: BBox {
+                x0: 0.0,
+                y0: 0.0,
+                x1: 4.0,
+                y1: 2.0,
+            },
+            page_image: Arc::new(DynamicImage::ImageRgba8(image.clone())),
+            page_image_scale1: Some(DynamicImage::ImageRgba8(image)),
+            downscale_factor: 0.5,
+            rotation: PageRotation::Deg180,
+            page_rotation: PageRotation::Deg90,
+            structure_layout: vec![LayoutBBox {
+                id: 0,
+                bbox: BBox {
+                    x0: 0.0,
+                    y0: 0.0,
+                    x1: 4.0,
+                    y1: 1.0,
+                },
+                label: "Section-header".to_string(),
+                proba: 1.0,
+            }],
+            metadata: ParseNativeMetadata {
+                parse_native_duration_ms: 12.0,
+            },
+        };
+
+        let document = ParseNativeDocument {
+            outline: Some(vec![OutlineEntry {
+                title: "Introduction".to_string(),
+                level: 1,
+                page_id: Some(3),
+            }]),
+            info: DocumentInfo {
+                title: Some("Annual Report".to_string()),
+                ..Default::default()
+            },
+            page_count: 5,
+        };
+        let mut frames = encode_frame(&WorkerResponse::Document(document))?;
+        frames.extend(encode_frame(&WorkerResponse::Page(page.into()))?);
+        frames.extend(encode_frame(&WorkerResponse::PageError {
+            page_id: 4,
+            message: "invalid content stream".to_string(),
+        })?);
+        frames.extend(encode_frame(&WorkerResponse::Done)?);
+
+        let mut reader = std::io::Cursor::new(frames);
+        match read_frame_blocking::<WorkerResponse>(&mut reader)? {
+            Some(WorkerResponse::Document(document)) => {
+                assert_eq!(document.outline.unwrap()[0].title, "Introduction");
+                assert_eq!(document.info.title.as_deref(), Some("Annual Report"));
+                assert_eq!(document.page_count, 5);
+            }
+            _ => panic!("expected a document response"),
+        }
+        let decoded = match read_frame_blocking::<WorkerResponse>(&mut reader)? {
+            Some(WorkerResponse::Page(page)) => page.into_result()?,
+            _ => panic!("expected a page response"),
+        };
+        assert_eq!(decoded.page_id, 3);
+        assert_eq!(decoded.text_lines[0].text, "hello");
+        assert_eq!(decoded.rotation, PageRotation::Deg180);
+        assert_eq!(decoded.page_rotation, PageRotation::Deg90);
+        assert_eq!(decoded.form_fields[0].value.as_deref(), Some("Jane Doe"));
+        assert_eq!(decoded.structure_layout[0].label, "Section-header");
+        assert_eq!(
+            decoded.page_image.as_rgba8().unwrap().get_pixel(1, 1).0,
+            [255, 0, 0, 255]
+        );
+        match read_frame_blocking::<WorkerResponse>(&mut reader)? {
+            Some(WorkerResponse::PageError { page_id, message }) => {
+                assert_eq!(page_id, 4);
+                assert_eq!(message, "invalid content stream");
+            }
+            _ => panic!("expected a page error response"),
+        }
+        assert!(matches!(
+            read_frame_blocking::<WorkerResponse>(&mut reader)?,
+            Some(WorkerResponse::Done)
+        ));
+        assert!(read_frame_blocking::<WorkerResponse>(&mut reader)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_worker_frames() -> anyhow::Result<()> {
+        // A log line written on the worker stdout
+        let mut reader = std::io::Cursor::new(b"pdfium: warning, invalid xref table\n".to_vec());
+        assert!(read_frame_blocking::<WorkerResponse>(&mut reader).is_err());
+
+        // A frame cut short by a crashed worker
+        let frame = encode_frame(&WorkerResponse::Error("document error".to_string()))?;
+        let mut reader = std::io::Cursor::new(frame[..frame.len() - 4].to_vec());
+        assert!(read_frame_blocking::<WorkerResponse>(&mut reader).is_err());
+
+        // A garbage archive
+        let mut frame = 16u64.to_le_bytes().to_vec();
+        frame.extend([0xff; 16]);
+        let mut reader = std::io::Cursor::new(frame);
+        assert!(read_frame_blocking::<WorkerResponse>(&mut reader).is_err());
+
+        // A length over the limit isn't allocated
+        let mut reader = std::io::Cursor::new(u64::MAX.to_le_bytes().to_vec());
+        assert!(read_frame_blocking::<WorkerResponse>(&mut reader).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunk_pages() {
+        let page_ids: Vec<PageID> = (0..10).collect();
+        assert_eq!(
+            chunk_pages(&page_ids, 2),
+            vec![(0..5).collect::<Vec<_>>(), (5..10).collect()]
+        );
+        // Small documents aren't split below MIN_CHUNK_PAGES pages
+        assert_eq!(chunk_pages(&page_ids, 8).len(), 3);
+        assert_eq!(chunk_pages(&page_ids[..3], 4), vec![vec![0, 1, 2]]);
+        assert!(chunk_pages(&[], 4).is_empty());
+
+        assert_eq!(
+            page_ranges(&[1, 2, 3, 7, 9, 10]).ranges(),
+            &[1..4, 7..8, 9..11]
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::entities::Line;
+
+/// Cleans up the text extracted by pdfium before it is grouped into elements. Non-breaking
+/// spaces become regular spaces, zero-width and control characters are removed, the fields
+/// select the other transformations.
+///
+/// Normalization is applied per span, so the bboxes stay attached to their text even when a
+/// ligature expands to several characters.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    Archive,
+    RkyvDeserialize,
+    RkyvSerialize,
+)]
+#[archive(check_bytes)]
+#[serde(default)]
+pub struct TextNormalization {
+    /// Expands the Latin ligatures, e.g. `ﬁ` into `fi`
+    pub ligatures: bool,
+    /// Removes the soft hyphens marking the possible hyphenation points
+    pub soft_hyphens: bool,
+    /// Applies the full NFKC normalization, which also maps the superscript digits, fractions
+    /// and full-width forms to their plain equivalents
+    pub nfkc: bool,
+}
+
+impl Default for TextNormalization {
+    fn default() -> Self {
+        Self {
+            ligatures: true,
+            soft_hyphens: true,
+            nfkc: false,
+        }
+    }
+}
+
+const SOFT_HYPHEN: char = '\u{AD}';
+
+/// pdfium reports the hyphens ending a line with this control character
+const PDFIUM_LINE_END_HYPHEN: char = '\u{2}';
+
+/// Latin ligatures block, decomposed by NFKC
+fn is_ligature(c: char) -> bool {
+    ('\u{FB00}'..='\u{FB06}').contains(&c)
+}
+
+fn is_non_breaking_space(c: char) -> bool {
+    matches!(c, '\u{A0}' | '\u{2007}' | '\u{202F}')
+}
+
+fn is_zero_width(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}'
+    )
+}
+
+impl TextNormalization {
+    pub(crate) fn normalize(&self, text: &str) -> String {
+        let mut normalized = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                // Line breaks and tabs are kept, they separate the lines and words
+                '\n' | '\r' | '\t' => normalized.push(c),
+                PDFIUM_LINE_END_HYPHEN => normalized.push('-'),
+                c if c.is_control() || is_zero_width(c) => {}
+                SOFT_HYPHEN if self.soft_hyphens => {}
+                c if is_non_breaking_space(c) => normalized.push(' '),
+                c if self.ligatures && is_ligature(c) => normalized.extend(c.nfkc()),
+                c => normalized.push(c),
+            }
+        }
+        if self.nfkc {
+            normalized.nfkc().collect()
+        } else {
+            normalized
+        }
+    }
+
+    /// Normalizes the lines and their spans, dropping the spans left empty
+    pub(crate) fn normalize_lines(&self, lines: &mut [Line]) {
+        for line in lines.iter_mut() {
+            line.text = self.normalize(&line.text);
+            for span in line.spans.iter_mut() {
+                span.text = self.normalize(&span.text);
+            }
+            line.spans.retain(|span| !span.text.is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entities::{BBox, CharSpan},
+        parse::merge::MergeThresholds,
+    };
+
+    fn span(text: &str, x0: f32, x1: f32) -> CharSpan {
+        CharSpan {
+            bbox: BBox {
+                x0,
+                y0: 0.0,
+                x1,
+                y1: 10.0,
+            },
+            text: text.to_string(),
+            rotation: 0.0,
+            font_name: "Helvetica".to_string(),
+            font_size: 10.0,
+            font_weight: None,
+            bold: false,
+            italic: false,
+            script: None,
+            char_start_idx: 0,
+            char_end_idx: text.chars().count().saturating_sub(1),
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let normalization = TextNormalization::default();
+        assert_eq!(normalization.normalize("ﬁnd the ﬂow"), "find the flow");
+        assert_eq!(normalization.normalize("10\u{A0}km"), "10 km");
+        assert_eq!(
+            normalization.normalize("zero\u{200B}width\u{7}"),
+            "zerowidth"
+        );
+        assert_eq!(normalization.normalize("docu\u{2}"), "docu-");
+        assert_eq!(normalization.normalize("hyphen\u{AD}ation"), "hyphenation");
+        assert_eq!(normalization.normalize("x²\r\n"), "x²\r\n");
+
+        let nfkc = TextNormalization {
+            nfkc: true,
+            ..Default::default()
+        };
+        assert_eq!(nfkc.normalize("x² ＡＢ"), "x2 AB");
+
+        let faithful = TextNormalization {
+            ligatures: false,
+            soft_hyphens: false,
+            nfkc: false,
+        };
+        assert_eq!(faithful.normalize("ﬁ\u{AD}"), "ﬁ\u{AD}");
+    }
+
+    #[test]
+    fn test_normalize_lines_keeps_span_bboxes() {
+        let mut line = Line::new_from_span(span("ﬁnd", 0.0, 20.0));
+        let thresholds = MergeThresholds::default();
+        line.append(span(" \u{200B}", 20.0, 25.0), &thresholds)
+            .unwrap();
+        line.append(span("ﬂow", 25.0, 45.0), &thresholds).unwrap();
+        let mut lines = vec![line];
+        TextNormalization::default().normalize_lines(&mut lines);
+
+        let line = &lines[0];
+        assert_eq!(line.text, "find flow");
+        let spans: Vec<_> = line
+            .spans
+            .iter()
+            .map(|s| (s.text.as_str(), s.bbox.x0, s.bbox.x1))
+            .collect();
+        assert_eq!(
+            spans,
+            [("find", 0.0, 20.0), (" ", 20.0, 25.0), ("flow", 25.0, 45.0)]
+        );
+        // Word boxes are split from the expanded text, within the span bbox
+        let words = line.spans[0].words();
+        assert_eq!(words.len(), 1);
+        assert_eq!((words[0].bbox.x0, words[0].bbox.x1), (0.0, 20.0));
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use crate::{
+    blocks::TitleLevel,
+    entities::{Element, ElementID, OutlineEntry, PageID},
+};
+
+/// When a title contains the other, e.g. "1.2 Results" and "Results", the shorter one must
+/// cover this ratio of the longer one for them to match
+const MIN_TITLE_COVERAGE: f32 = 0.6;
+
+/// Lowercase alphanumeric words separated by single spaces
+fn normalize_title(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn titles_match(a: &str, b: &str) -> bool {
+    let (a, b) = (normalize_title(a), normalize_title(b));
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    !short.is_empty()
+        && long.contains(&short)
+        && short.len() as f32 >= long.len() as f32 * MIN_TITLE_COVERAGE
+}
+
+/// Replaces the levels guessed from the title heights by the depth of the outline entry
+/// pointing at the same page with the same text. Each entry is matched to a single title,
+/// entries pointing at pages outside of the parsed range never match. Unmatched titles keep
+/// their guessed level. Returns the number of matched titles.
+pub(crate) fn apply_outline_levels(
+    title_level: &mut HashMap<(PageID, ElementID), TitleLevel>,
+    titles: &[&Element],
+    outline: &[OutlineEntry],
+) -> usize {
+    let mut matched = vec![false; outline.len()];
+    for title in titles {
+        let entry = outline.iter().enumerate().find(|(idx, entry)| {
+            !matched[*idx]
+                && entry.page_id == Some(title.page_id)
+                && titles_match(&entry.title, &title.text_block.text)
+        });
+        if let Some((idx, entry)) = entry {
+            matched[idx] = true;
+            title_level.insert((title.page_id, title.id), entry.level);
+        }
+    }
+    matched.iter().filter(|&&m| m).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::{BBox, ElementText, ElementType};
+
+    fn title(id: ElementID, page_id: PageID, text: &str) -> Element {
+        Element {
+            id,
+            layout_block_id: id as i32,
+            text_block: ElementText {
+                text: text.to_string(),
+                links: vec![],
+                styles: vec![],
+                font_size: None,
+                lines: vec![],
+            },
+            kind: ElementType::Title,
+            page_id,
+            bbox: BBox::default(),
+            confidence: 1.0,
+        }
+    }
+
+    fn entry(title: &str, level: u8, page_id: Option<PageID>) -> OutlineEntry {
+        OutlineEntry {
+            title: title.to_string(),
+            level,
+            page_id,
+        }
+    }
+
+    #[test]
+    fn test_apply_outline_levels() {
+        let titles = [
+            title(0, 0, "Annual Report"),
+            title(1, 1, "1.2 Results"),
+            title(2, 1, "Outlook"),
+            // Same text as an entry but on another page
+            title(3, 2, "Annual report"),
+        ];
+        let outline = vec![
+            entry("ANNUAL REPORT", 1, Some(0)),
+            entry("Results", 2, Some(1)),
+            entry("Appendix", 1, Some(9)),
+            entry("Unresolved", 1, None),
+        ];
+        let mut title_level = HashMap::from([((1, 2), 4), ((2, 3), 5)]);
+
+        apply_outline_levels(
+            &mut title_level,
+            &titles.iter().collect::<Vec<_>>(),
+            &outline,
+        );
+
+        assert_eq!(title_level.get(&(0, 0)), Some(&1));
+        assert_eq!(title_level.get(&(1, 1)), Some(&2));
+        // Unmatched titles keep their guessed level
+        assert_eq!(title_level.get(&(1, 2)), Some(&4));
+        assert_eq!(title_level.get(&(2, 3)), Some(&5));
+    }
+
+    #[test]
+    fn test_titles_match() {
+        assert!(titles_match("2. Related Work", "Related work"));
+        assert!(!titles_match(
+            "Introduction",
+            "Introduction to the appendix tables"
+        ));
+        assert!(!titles_match("", ""));
+    }
+}
@@ -2,45 +2,116 @@ use std::{
     collections::HashMap,
     path::{Path, PathBuf},
     sync::Arc,
-    time::Instant,
+    time::{Duration, Instant},
 };
 use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 
 use image::DynamicImage;
 use tracing::instrument;
 
 use crate::{
     draw::{draw_blocks, draw_layout_bboxes, draw_text_lines},
-    entities::{Element, ElementType, Line, PDFPath, PageID, StructuredPage},
+    entities::{Element, ElementType, FormField, Line, PDFPath, PageID, StructuredPage},
     error::FerrulesError,
     layout::{
-        model::LayoutBBox, Metadata, ParseLayoutQueue, ParseLayoutRequest, ParseLayoutResponse,
+        model::LayoutBBox, LayoutClassFilter, LayoutMode, Metadata, ParseLayoutQueue,
+        ParseLayoutRequest, ParseLayoutResponse,
     },
     metrics::{OCRMetrics, PageMetrics, StepMetrics, TableMetrics},
-    ocr::{OCRMetadata, OCRQueue, ParseOCRRequest},
+    ocr::{
+        filter_ocr_lines, load_ocr_cache, store_ocr_cache, OCRMetadata, OCRQueue, OcrAccuracy,
+        OcrCacheKey, PageRotation, ParseOCRRequest, TextSource,
+    },
     parse::table::ParseTableQueue,
 };
 
 use super::{
-    merge::{merge_elements_into_blocks, merge_lines_layout, merge_remaining},
+    code::detect_code_elements,
+    formulas::detect_formula_elements,
+    merge::{
+        filter_layout_classes, heuristic_text_layout, merge_elements_into_blocks,
+        merge_lines_layout, merge_native_ocr_lines, merge_remaining, MergeThresholds,
+    },
     native::ParseNativePageResult,
+    page_cache::CachedPage,
 };
 
-/// This constant defines the minimum ratio between the area of text lines identified
-/// by the pdfium2 and the area of text regions detected through layout analysis.
-/// If this ratio falls below the threshold of 0.5 (or 50%), it indicates that the page
-/// may not have enough __native__ lines, and therefore should
-/// be considered for OCR to ensure accurate text extraction.
-const MIN_LAYOUT_COVERAGE_THRESHOLD: f32 = 0.5;
+/// Lines overlapping a form field widget by more than this fraction of their area are the
+/// widget value drawn by flattening, they are only kept in the form field block
+const MIN_FORM_FIELD_LINE_OVERLAP: f32 = 0.5;
 
-fn page_needs_ocr(text_boxes: &[&LayoutBBox], text_lines: &[Line]) -> bool {
-    let line_area = text_lines.iter().map(|l| l.bbox.area()).sum::<f32>();
-    let text_layoutbbox_area = text_boxes.iter().map(|l| l.bbox.area()).sum::<f32>();
+/// Per-page parsing options derived from the document `FerrulesParseConfig`
+#[derive(Debug, Clone)]
+pub(crate) struct PageParseConfig {
+    pub(crate) ocr_accuracy: OcrAccuracy,
+    pub(crate) text_source: TextSource,
+    pub(crate) ocr_cache_dir: Option<PathBuf>,
+    pub(crate) ocr_min_confidence: f32,
+    pub(crate) ocr_coverage_threshold: f32,
+    pub(crate) ocr_min_chars: usize,
+    pub(crate) force_ocr: bool,
+    pub(crate) layout_timeout: Option<Duration>,
+    pub(crate) layout_timeout_fallback: bool,
+    pub(crate) layout_mode: LayoutMode,
+    pub(crate) layout_class_filter: LayoutClassFilter,
+    pub(crate) cancellation: CancellationToken,
+    /// Keeps the page raster in the `StructuredPage`, it is dropped once the page is parsed
+    /// otherwise
+    pub(crate) keep_page_image: bool,
+    /// Segments the tagged pages from their structure tree instead of the layout model
+    pub(crate) prefer_tagged_structure: bool,
+}
 
-    if text_layoutbbox_area > 0f32 {
-        line_area / text_layoutbbox_area < MIN_LAYOUT_COVERAGE_THRESHOLD
-    } else {
-        true
+impl PageParseConfig {
+    /// Options changing the result of the page parsing, part of the page cache key. The OCR
+    /// cache, the layout timeout and the page raster don't change a cached page
+    pub(crate) fn cache_options(&self) -> String {
+        format!(
+            "{:?}|{:?}|{}|{}|{}|{}|{:?}|{:?}|{}",
+            self.ocr_accuracy,
+            self.text_source,
+            self.ocr_min_confidence,
+            self.ocr_coverage_threshold,
+            self.ocr_min_chars,
+            self.force_ocr,
+            self.layout_mode,
+            self.layout_class_filter,
+            self.prefer_tagged_structure,
+        )
+    }
+}
+
+/// How much of a page the native text layer covers, deciding whether the page is OCRed
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct TextLayerStats {
+    /// Ratio between the area of the native text lines identified by pdfium and the area of
+    /// the text regions of the layout, 0 without any text region
+    pub(crate) coverage: f32,
+    /// Non-whitespace characters of the native text lines
+    pub(crate) chars: usize,
+}
+
+impl TextLayerStats {
+    fn new(text_boxes: &[&LayoutBBox], text_lines: &[Line]) -> Self {
+        let line_area = text_lines.iter().map(|l| l.bbox.area()).sum::<f32>();
+        let text_layoutbbox_area = text_boxes.iter().map(|l| l.bbox.area()).sum::<f32>();
+        let coverage = if text_layoutbbox_area > 0f32 {
+            line_area / text_layoutbbox_area
+        } else {
+            0f32
+        };
+        let chars = text_lines
+            .iter()
+            .map(|l| l.text.chars().filter(|c| !c.is_whitespace()).count())
+            .sum();
+        Self { coverage, chars }
+    }
+
+    /// A page may not have enough __native__ lines when they cover less than
+    /// `coverage_threshold` of its text regions, or have less than `min_chars` characters
+    fn needs_ocr(&self, coverage_threshold: f32, min_chars: usize) -> bool {
+        self.coverage < coverage_threshold || self.chars < min_chars
     }
 }
 
@@ -48,9 +119,22 @@ fn page_needs_ocr(text_boxes: &[&LayoutBBox], text_lines: &[Line]) -> bool {
 fn build_page_elements(
     page_layout: &[LayoutBBox],
     text_lines: &[Line],
+    form_fields: &[FormField],
     page_idx: PageID,
 ) -> Result<Vec<Element>, FerrulesError> {
-    let mut elements = merge_lines_layout(page_layout, text_lines, page_idx)?;
+    let text_lines: Vec<Line> = text_lines
+        .iter()
+        .filter(|line| {
+            !form_fields.iter().any(|field| {
+                line.bbox.intersection(&field.bbox) / line.bbox.area() > MIN_FORM_FIELD_LINE_OVERLAP
+            })
+        })
+        .cloned()
+        .collect();
+    let mut elements = merge_lines_layout(page_layout, &text_lines, page_idx)?;
+    // Code listings are full of operators, they are detected before the formulas
+    detect_code_elements(&mut elements);
+    detect_formula_elements(&mut elements);
     let merged_layout_blocks_ids = elements
         .iter()
         .map(|e| e.layout_block_id)
@@ -61,9 +145,13 @@ fn build_page_elements(
         .collect();
 
     merge_remaining(&mut elements, &unmerged_layout_boxes, page_idx);
+    for form_field in form_fields {
+        elements.push(Element::from_form_field(elements.len() + 1, form_field));
+    }
     Ok(elements)
 }
 
+#[allow(clippy::too_many_arguments)]
 #[instrument(skip_all)]
 async fn parse_page_text(
     native_text_lines: Vec<Line>,
@@ -72,53 +160,169 @@ async fn parse_page_text(
     ocr_queue: OCRQueue,
     page_id: PageID,
     downscale_factor: f32,
-) -> Result<(Vec<Line>, Option<StepMetrics>, bool), FerrulesError> {
+    rotation: PageRotation,
+    page_config: PageParseConfig,
+) -> Result<(Vec<Line>, Option<StepMetrics>, bool, TextLayerStats), FerrulesError> {
     let text_layout_box: Vec<&LayoutBBox> =
         page_layout.iter().filter(|b| b.is_text_block()).collect();
-    let need_ocr = page_needs_ocr(&text_layout_box, &native_text_lines);
+    let text_layer = TextLayerStats::new(&text_layout_box, &native_text_lines);
+    tracing::debug!(
+        "Page {page_id} text layer: {:.0}% coverage, {} chars",
+        text_layer.coverage * 100.0,
+        text_layer.chars
+    );
+    let need_ocr = match page_config.text_source {
+        TextSource::NativeOnly => false,
+        TextSource::OcrOnly => true,
+        TextSource::PreferNative | TextSource::PreferOcr => {
+            page_config.force_ocr
+                || text_layer.needs_ocr(
+                    page_config.ocr_coverage_threshold,
+                    page_config.ocr_min_chars,
+                )
+        }
+    };
 
     let (ocr_result, ocr_metrics) = if need_ocr {
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        let req = ParseOCRRequest {
-            page_id,
-            page_image: Arc::clone(&page_image),
-            rescale_factor: downscale_factor,
-            metadata: OCRMetadata {
-                response_tx: tx,
-                queue_time: Instant::now(),
-            },
+        let ocr_image = if rotation == PageRotation::Deg0 {
+            Arc::clone(&page_image)
+        } else {
+            tracing::debug!(
+                "Rotating page {} by {} degrees before OCR",
+                page_id,
+                rotation.degrees()
+            );
+            Arc::new(rotation.apply(&page_image))
         };
-        ocr_queue.push(req).await?;
-        tracing::debug!("OCR request pushed to queue for page {}", page_id);
-
-        let res = rx
-            .await
-            .map_err(|e| {
-                tracing::error!("OCR channel receive error: {:?}", e);
-                FerrulesError::OcrError(format!("OCR channel error: {}", e))
-            })?
-            .map_err(|e| {
-                tracing::error!("OCR execution error: {:?}", e);
-                e
-            })?;
-
-        (Some(res.ocr_lines), Some(res.step_metrics))
+        let cache = page_config.ocr_cache_dir.as_deref().map(|cache_dir| {
+            let key = OcrCacheKey::new(&ocr_image, downscale_factor, page_config.ocr_accuracy);
+            (cache_dir, key)
+        });
+
+        match cache.and_then(|(cache_dir, key)| load_ocr_cache(cache_dir, &key)) {
+            Some(ocr_lines) => {
+                tracing::debug!("OCR cache hit for page {}", page_id);
+                (Some(ocr_lines), None)
+            }
+            None => {
+                let (tx, rx) = tokio::sync::oneshot::channel();
+                let req = ParseOCRRequest {
+                    page_id,
+                    page_image: ocr_image,
+                    rescale_factor: downscale_factor,
+                    accuracy: page_config.ocr_accuracy,
+                    metadata: OCRMetadata {
+                        response_tx: tx,
+                        queue_time: Instant::now(),
+                    },
+                };
+                ocr_queue.push(req).await?;
+                tracing::debug!("OCR request pushed to queue for page {}", page_id);
+
+                let res = rx
+                    .await
+                    .map_err(|e| {
+                        tracing::error!("OCR channel receive error: {:?}", e);
+                        FerrulesError::OcrError(format!("OCR channel error: {}", e))
+                    })?
+                    .map_err(|e| {
+                        tracing::error!("OCR execution error: {:?}", e);
+                        e
+                    })?;
+
+                if let Some((cache_dir, key)) = cache {
+                    store_ocr_cache(cache_dir, &key, &res.ocr_lines);
+                }
+                (Some(res.ocr_lines), Some(res.step_metrics))
+            }
+        }
     } else {
         (None, None)
     };
+    // The cache keeps every recognized line, low confidence ones are dropped afterwards
+    let ocr_result =
+        ocr_result.map(|lines| filter_ocr_lines(lines, page_config.ocr_min_confidence));
 
     let lines = if need_ocr && ocr_result.is_some() {
+        // OCR ran on the upright raster, map the lines back to the page coordinates
+        let page_width = page_image.width() as f32 * downscale_factor;
+        let page_height = page_image.height() as f32 * downscale_factor;
         let lines = ocr_result
             .as_ref()
             .unwrap()
             .iter()
-            .map(|ocr_line| ocr_line.to_line())
+            .map(|ocr_line| {
+                let mut line = ocr_line.to_line();
+                line.bbox = rotation.unrotate_bbox(&line.bbox, page_width, page_height);
+                for span in line.spans.iter_mut() {
+                    span.bbox = rotation.unrotate_bbox(&span.bbox, page_width, page_height);
+                }
+                line.rotation = rotation.text_rotation();
+                line
+            })
             .collect::<Vec<_>>();
-        lines
+        merge_native_ocr_lines(native_text_lines, lines, page_config.text_source)
     } else {
         native_text_lines
     };
-    Ok((lines, ocr_metrics, need_ocr))
+    Ok((lines, ocr_metrics, need_ocr, text_layer))
+}
+
+/// Gets the page layout from the layout model, along with the execution provider it ran on.
+/// Pages whose layout timed out are segmented from their text lines when
+/// `layout_timeout_fallback` is set, the returned provider is then `None`.
+async fn parse_page_layout(
+    page_id: PageID,
+    page_image: &Arc<DynamicImage>,
+    downscale_factor: f32,
+    text_lines: &[Line],
+    layout_queue: &ParseLayoutQueue,
+    page_config: &PageParseConfig,
+) -> Result<(Vec<LayoutBBox>, StepMetrics, Option<String>), FerrulesError> {
+    let (layout_tx, layout_rx) = tokio::sync::oneshot::channel();
+
+    let layout_req = ParseLayoutRequest {
+        page_id,
+        page_image: Arc::clone(page_image),
+        downscale_factor,
+        metadata: Metadata {
+            response_tx: layout_tx,
+            queue_time: Instant::now(),
+            timeout: page_config.layout_timeout,
+            cancellation: page_config.cancellation.clone(),
+        },
+    };
+    layout_queue.push(layout_req).await?;
+    tracing::debug!("Layout request pushed to queue");
+
+    let layout_response = layout_rx.await.map_err(|e| {
+        tracing::error!("Layout channel receive error: {:?}", e);
+        FerrulesError::LayoutParsingError
+    })?;
+    let layout_fallback = page_config.layout_timeout_fallback;
+    match layout_response {
+        Ok(ParseLayoutResponse {
+            _page_id: _, // TODO: remove page_id from ParseLayoutResponse
+            layout_bbox,
+            step_metrics,
+            provider,
+        }) => Ok((layout_bbox, step_metrics, Some(provider.to_string()))),
+        Err(e) => match e.downcast::<FerrulesError>() {
+            Ok(err @ FerrulesError::LayoutTimeout { .. }) if layout_fallback => {
+                tracing::warn!("{err}, falling back to lines-only segmentation");
+                Ok((
+                    heuristic_text_layout(text_lines),
+                    StepMetrics::default(),
+                    None,
+                ))
+            }
+            Ok(err) => Err(err),
+            Err(e) => {
+                tracing::error!("Layout model execution error: {:?}", e);
+                Err(FerrulesError::LayoutParsingError)
+            }
+        },
+    }
 }
 
 #[instrument(
@@ -140,6 +344,7 @@ pub async fn parse_page_full(
     layout_queue: ParseLayoutQueue,
     table_queue: ParseTableQueue,
     ocr_queue: OCRQueue,
+    page_config: PageParseConfig,
 ) -> Result<StructuredPage, FerrulesError> {
     let start_time = Instant::now();
     let span = tracing::Span::current();
@@ -147,53 +352,70 @@ pub async fn parse_page_full(
         page_id,
         text_lines,
         paths,
+        form_fields,
+        embedded_images,
         page_bbox,
         page_image,
         page_image_scale1,
         downscale_factor,
+        rotation,
+        page_rotation,
+        structure_layout,
         metadata: parse_native_metadata,
     } = parse_native_result;
-    let (layout_tx, layout_rx) = tokio::sync::oneshot::channel();
-
-    let layout_req = ParseLayoutRequest {
-        page_id,
-        page_image: Arc::clone(&page_image),
-        downscale_factor,
-        metadata: Metadata {
-            response_tx: layout_tx,
-            queue_time: Instant::now(),
-        },
+    // The structure tree of tagged pages gives the regions in their reading order
+    let tagged = page_config.prefer_tagged_structure && !structure_layout.is_empty();
+    // The heuristic segmentation is built from the lines of the page, set when it is used
+    let (page_layout, layout_step_metrics, layout_provider) = match page_config.layout_mode {
+        _ if tagged => (structure_layout, StepMetrics::default(), None),
+        LayoutMode::Skip => (
+            heuristic_text_layout(&text_lines),
+            StepMetrics::default(),
+            None,
+        ),
+        LayoutMode::Model => {
+            parse_page_layout(
+                page_id,
+                &page_image,
+                downscale_factor,
+                &text_lines,
+                &layout_queue,
+                &page_config,
+            )
+            .await?
+        }
     };
-    layout_queue.push(layout_req).await?;
-    tracing::debug!("Layout request pushed to queue");
-
-    let ParseLayoutResponse {
-        _page_id: _, // TODO: remove page_id from ParseLayoutResponse
-        layout_bbox: page_layout,
-        step_metrics: layout_step_metrics,
-    } = layout_rx
-        .await
-        // TODO: better unwrapping
-        .map_err(|e| {
-            tracing::error!("Layout channel receive error: {:?}", e);
-            FerrulesError::LayoutParsingError
-        })?
-        .map_err(|e| {
-            tracing::error!("Layout model execution error: {:?}", e);
-            FerrulesError::LayoutParsingError
-        })?;
     tracing::debug!("Layout response received");
+    let page_layout =
+        filter_layout_classes(page_layout, &page_config.layout_class_filter, &text_lines);
 
     let native_lines_captured = text_lines.clone();
-    let (text_lines_processed, ocr_step_metrics_inner, need_ocr) = parse_page_text(
+    let keep_page_image = page_config.keep_page_image;
+    // OCR runs on the sharpest of the layout input and the configured page raster
+    let (ocr_image, ocr_downscale_factor) = match &page_image_scale1 {
+        Some(raster) if page_bbox.width() / (raster.width() as f32) < downscale_factor => (
+            Arc::new(raster.clone()),
+            page_bbox.width() / raster.width() as f32,
+        ),
+        _ => (Arc::clone(&page_image), downscale_factor),
+    };
+    let (text_lines_processed, ocr_step_metrics_inner, need_ocr, text_layer) = parse_page_text(
         text_lines,
         &page_layout,
-        Arc::clone(&page_image),
+        ocr_image,
         ocr_queue,
         page_id,
-        downscale_factor,
+        ocr_downscale_factor,
+        rotation,
+        page_config,
     )
     .await?;
+    // OCR lines are only known now, the heuristic segmentation is recomputed to include them
+    let page_layout = if layout_provider.is_none() && need_ocr && !tagged {
+        heuristic_text_layout(&text_lines_processed)
+    } else {
+        page_layout
+    };
 
     let ocr_step_metrics = ocr_step_metrics_inner.map(|m| OCRMetrics {
         step_metrics: m,
@@ -201,7 +423,12 @@ pub async fn parse_page_full(
     });
 
     // Merging elements with layout
-    let mut elements = build_page_elements(&page_layout, &text_lines_processed, page_id)?;
+    let mut elements =
+        build_page_elements(&page_layout, &text_lines_processed, &form_fields, page_id)?;
+    if tagged {
+        // Region ids follow the structure tree, elements outside any region go last
+        elements.sort_by_key(|e| (e.layout_block_id < 0, e.layout_block_id));
+    }
     let text_lines_arc = Arc::new(text_lines_processed.clone());
     let paths_arc = Arc::new(paths);
 
@@ -242,10 +469,19 @@ pub async fn parse_page_full(
         }
     }
     if let Some(tmp_dir) = debug_dir {
+        // Debug images are drawn in PDF points
+        let debug_image = page_image_scale1
+            .as_ref()
+            .unwrap_or(&page_image)
+            .resize_exact(
+                page_bbox.width() as u32,
+                page_bbox.height() as u32,
+                image::imageops::FilterType::Triangle,
+            );
         debug_page(
             &tmp_dir,
             page_id,
-            &page_image_scale1,
+            &debug_image,
             &text_lines_processed,
             need_ocr,
             &page_layout,
@@ -261,6 +497,7 @@ pub async fn parse_page_full(
         total_duration_ms: start_time.elapsed().as_secs_f64() * 1000.0,
         native_step,
         layout_step: layout_step_metrics,
+        layout_provider,
         table_steps,
         ocr_step: ocr_step_metrics,
     };
@@ -272,10 +509,18 @@ pub async fn parse_page_full(
         id: page_id,
         width: page_bbox.width(),
         height: page_bbox.height(),
-        image: page_image_scale1,
+        rotation: page_rotation.degrees() as u16,
+        image: page_image_scale1
+            .filter(|_| keep_page_image)
+            .unwrap_or_default(),
         elements,
         paths: paths_arc.as_ref().clone(),
+        form_fields,
+        embedded_images,
         need_ocr,
+        text_coverage: text_layer.coverage,
+        native_chars: text_layer.chars,
+        tagged,
         native_lines: native_lines_captured,
         layout: page_layout,
         ocr_lines: if need_ocr {
@@ -289,6 +534,54 @@ pub async fn parse_page_full(
     Ok(structured_page)
 }
 
+/// Rebuilds a page from its native parsing and its cached layout, OCR and tables. Its metrics
+/// only have the native step, the other steps didn't run.
+pub(crate) fn page_from_cache(
+    parse_native_result: ParseNativePageResult,
+    cached: CachedPage,
+    keep_page_image: bool,
+) -> StructuredPage {
+    let ParseNativePageResult {
+        page_id,
+        text_lines,
+        paths,
+        form_fields,
+        embedded_images,
+        page_bbox,
+        page_image_scale1,
+        page_rotation,
+        metadata,
+        ..
+    } = parse_native_result;
+    let native_duration_ms = metadata.parse_native_duration_ms;
+    StructuredPage {
+        id: page_id,
+        width: page_bbox.width(),
+        height: page_bbox.height(),
+        rotation: page_rotation.degrees() as u16,
+        image: page_image_scale1
+            .filter(|_| keep_page_image)
+            .unwrap_or_default(),
+        elements: cached.elements,
+        paths,
+        form_fields,
+        embedded_images,
+        need_ocr: cached.need_ocr,
+        text_coverage: cached.text_coverage,
+        native_chars: cached.native_chars,
+        tagged: cached.tagged,
+        native_lines: text_lines,
+        layout: cached.layout,
+        ocr_lines: cached.ocr_lines,
+        metrics: PageMetrics {
+            page_id,
+            total_duration_ms: native_duration_ms,
+            native_step: StepMetrics::new(native_duration_ms),
+            ..Default::default()
+        },
+    }
+}
+
 #[allow(clippy::too_many_arguments, clippy::result_large_err)]
 fn debug_page(
     tmp_dir: &Path,
@@ -321,7 +614,11 @@ fn debug_page(
     })?;
     // Draw the final prediction -
     // TODO: Implement titles hashmap for titles in the page
-    let blocks = merge_elements_into_blocks(elements.to_vec(), HashMap::new())?;
+    let (blocks, _) = merge_elements_into_blocks(
+        elements.to_vec(),
+        HashMap::new(),
+        &MergeThresholds::default(),
+    );
     let final_img_buffer =
         draw_blocks(&blocks, page_image).map_err(|_| FerrulesError::DebugPageError {
             tmp_dir: tmp_dir.to_path_buf(),
@@ -352,3 +649,153 @@ fn debug_page(
             page_idx,
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entities::{BBox, DocumentMetadata, ParsedDocument, TextDirection},
+        metrics::ParsingMetrics,
+        ocr::{DEFAULT_OCR_COVERAGE_THRESHOLD, DEFAULT_OCR_MIN_CHARS},
+        parse::{
+            native::{parse_native_pages, RasterConfig},
+            normalize::TextNormalization,
+            reading_order::{sort_elements_reading_order, DEFAULT_COLUMN_GAP_THRESHOLD},
+        },
+        pdfium::load_pdfium,
+        render::markdown::to_markdown,
+    };
+
+    fn line(text: &str, x1: f32) -> Line {
+        Line {
+            text: text.to_string(),
+            bbox: BBox {
+                x0: 0.0,
+                y0: 0.0,
+                x1,
+                y1: 10.0,
+            },
+            rotation: 0.0,
+            spans: vec![],
+            confidence: None,
+            links: vec![],
+            direction: TextDirection::Ltr,
+        }
+    }
+
+    fn text_box() -> LayoutBBox {
+        LayoutBBox {
+            id: 0,
+            bbox: BBox {
+                x0: 0.0,
+                y0: 0.0,
+                x1: 100.0,
+                y1: 10.0,
+            },
+            label: "Text".to_string(),
+            proba: 0.9,
+        }
+    }
+
+    #[test]
+    fn test_text_layer_stats() {
+        let text_box = text_box();
+        let lines = [line("Quarterly revenue grew", 80.0)];
+        let stats = TextLayerStats::new(&[&text_box], &lines);
+        assert_eq!(stats.coverage, 0.8);
+        assert_eq!(stats.chars, 20);
+        assert!(!stats.needs_ocr(0.5, 10));
+        assert!(stats.needs_ocr(0.9, 10));
+        assert!(stats.needs_ocr(0.5, 30));
+
+        // Without text regions, only the characters count
+        let stats = TextLayerStats::new(&[], &lines);
+        assert_eq!(stats.coverage, 0.0);
+        assert!(!stats.needs_ocr(0.0, 10));
+    }
+
+    #[test]
+    fn test_junk_text_layer_needs_ocr() {
+        // A scanner text layer of a few characters stretched over the page
+        let text_box = text_box();
+        let lines = [line(" . ,  ", 100.0)];
+        let stats = TextLayerStats::new(&[&text_box], &lines);
+        assert_eq!(stats.coverage, 1.0);
+        assert!(stats.needs_ocr(DEFAULT_OCR_COVERAGE_THRESHOLD, DEFAULT_OCR_MIN_CHARS));
+    }
+
+    #[test]
+    fn test_two_columns_markdown_reading_order() -> anyhow::Result<()> {
+        let (pdfium, _) = load_pdfium()?;
+        let doc = std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/two_columns.pdf"
+        ))?;
+        let mut pages = Vec::new();
+        parse_native_pages(
+            &pdfium,
+            &doc,
+            None,
+            true,
+            None,
+            1024,
+            1024,
+            RasterConfig::default(),
+            Some(TextNormalization::default()),
+            MergeThresholds::default(),
+            |_| Ok(()),
+            |page| {
+                pages.push(page.unwrap());
+                Ok(())
+            },
+        )?;
+        let page = &pages[0];
+
+        // The paragraphs of the lines-only layout are ordered by their top, across columns
+        let layout = heuristic_text_layout(&page.text_lines);
+        let elements = build_page_elements(&layout, &page.text_lines, &[], page.page_id)?;
+        let elements = sort_elements_reading_order(
+            elements,
+            page.page_bbox.width(),
+            DEFAULT_COLUMN_GAP_THRESHOLD,
+        );
+        let doc = ParsedDocument {
+            doc_name: "two_columns".to_string(),
+            pages: vec![],
+            blocks: merge_elements_into_blocks(
+                elements,
+                HashMap::new(),
+                &MergeThresholds::default(),
+            )
+            .0,
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+        let markdown = to_markdown(&doc, "two_columns", None)?;
+        let markdown = markdown.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        let sentences = [
+            "Reading Order of Two Column Pages",
+            "The first column opens the paper with a sentence that runs over three lines of the left column.",
+            "A second paragraph ends the top of the left column.",
+            "The right column is only read once the left column is over and continues the same section.",
+            "Its last paragraph closes the first section of the page.",
+            "A wide paragraph spans both columns and separates the two sections of the page.",
+            "The second section starts in the left column again.",
+            "And it ends at the bottom of the right column.",
+        ];
+        let positions = sentences
+            .iter()
+            .map(|sentence| markdown.find(sentence))
+            .collect::<Option<Vec<_>>>()
+            .expect("sentences read contiguously");
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        Ok(())
+    }
+}
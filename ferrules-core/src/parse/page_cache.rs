@@ -0,0 +1,196 @@
+use std::path::PathBuf;
+
+use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
+
+use crate::{
+    cache::{load_entry, store_entry},
+    entities::{Element, Line, PageID, StructuredPage},
+    layout::model::LayoutBBox,
+    metrics::PageMetrics,
+};
+
+/// Default location of the on-disk page cache: `<user cache dir>/ferrules/pages`
+pub fn default_page_cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("ferrules")
+        .join("pages")
+}
+
+/// Identifies the parsed pages of a document.
+///
+/// The document bytes and every option changing the parsing of its pages, including the layout
+/// model settings, are part of the key, along with the ferrules version as the cached entries
+/// aren't versioned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PageCacheKey {
+    doc_hash: u64,
+    options_hash: u64,
+}
+
+impl PageCacheKey {
+    pub(crate) fn new(doc: &[u8], options: &str) -> Self {
+        let mut hasher = Xxh3::new();
+        hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.update(options.as_bytes());
+        Self {
+            doc_hash: xxh3_64(doc),
+            options_hash: hasher.digest(),
+        }
+    }
+
+    fn file_name(&self, page_id: PageID) -> String {
+        format!(
+            "{:016x}_{:016x}_{page_id}.ferr",
+            self.doc_hash, self.options_hash
+        )
+    }
+}
+
+/// Page cache of a document, the pages are stored as they finish parsing
+#[derive(Debug, Clone)]
+pub(crate) struct PageCache {
+    pub(crate) dir: PathBuf,
+    pub(crate) key: PageCacheKey,
+}
+
+/// Layout, OCR and table results of a parsed page. The rest of the page comes from its native
+/// parsing, which runs again on a cache hit.
+#[derive(Debug, Clone, Archive, RkyvSerialize, RkyvDeserialize)]
+#[archive(check_bytes)]
+pub(crate) struct CachedPage {
+    pub(crate) elements: Vec<Element>,
+    pub(crate) layout: Vec<LayoutBBox>,
+    pub(crate) ocr_lines: Vec<Line>,
+    pub(crate) need_ocr: bool,
+    pub(crate) text_coverage: f32,
+    pub(crate) native_chars: usize,
+    pub(crate) tagged: bool,
+    pub(crate) metrics: PageMetrics,
+}
+
+impl From<&StructuredPage> for CachedPage {
+    fn from(page: &StructuredPage) -> Self {
+        Self {
+            elements: page.elements.clone(),
+            layout: page.layout.clone(),
+            ocr_lines: page.ocr_lines.clone(),
+            need_ocr: page.need_ocr,
+            text_coverage: page.text_coverage,
+            native_chars: page.native_chars,
+            tagged: page.tagged,
+            metrics: page.metrics.clone(),
+        }
+    }
+}
+
+impl PageCache {
+    fn path(&self, page_id: PageID) -> PathBuf {
+        self.dir.join(self.key.file_name(page_id))
+    }
+
+    /// Returns the cached page, if any. Unreadable entries are treated as misses.
+    pub(crate) fn load(&self, page_id: PageID) -> Option<CachedPage> {
+        load_entry(&self.path(page_id))
+    }
+
+    /// Stores the parsed page. Failing to write the cache is not an error for the caller.
+    pub(crate) fn store(&self, page_id: PageID, page: &CachedPage) {
+        store_entry(&self.path(page_id), page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::model::ORTConfig;
+
+    fn cached_page() -> CachedPage {
+        CachedPage {
+            elements: vec![],
+            layout: vec![LayoutBBox {
+                id: 0,
+                bbox: Default::default(),
+                label: "Text".to_string(),
+                proba: 0.9,
+            }],
+            ocr_lines: vec![],
+            need_ocr: true,
+            text_coverage: 0.25,
+            native_chars: 12,
+            tagged: false,
+            metrics: PageMetrics::default(),
+        }
+    }
+
+    #[test]
+    fn test_page_cache_roundtrip() {
+        let cache = PageCache {
+            dir: std::env::temp_dir().join(format!("ferrules-pages-{}", uuid::Uuid::new_v4())),
+            key: PageCacheKey::new(b"%PDF-1.7", "options"),
+        };
+        assert!(cache.load(0).is_none());
+
+        cache.store(0, &cached_page());
+        let cached = cache.load(0).expect("cache hit");
+        assert!(cached.need_ocr);
+        assert_eq!(cached.native_chars, 12);
+        assert_eq!(cached.layout[0].label, "Text");
+        assert!(cache.load(1).is_none());
+
+        // A corrupted entry is a miss
+        let path = cache.path(0);
+        let mut entry = std::fs::read(&path).unwrap();
+        let last = entry.len() - 1;
+        entry[last] ^= 0xff;
+        std::fs::write(&path, &entry).unwrap();
+        assert!(cache.load(0).is_none());
+
+        // So is a truncated one
+        std::fs::write(&path, &entry[..entry.len() / 2]).unwrap();
+        assert!(cache.load(0).is_none());
+
+        let _ = std::fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn test_page_cache_key_invalidation() {
+        let key = PageCacheKey::new(b"%PDF-1.7", "options");
+        assert_eq!(key, PageCacheKey::new(b"%PDF-1.7", "options"));
+        assert_ne!(
+            key.file_name(0),
+            PageCacheKey::new(b"%PDF-1.6", "options").file_name(0)
+        );
+        assert_ne!(
+            key.file_name(0),
+            PageCacheKey::new(b"%PDF-1.7", "other options").file_name(0)
+        );
+        assert_ne!(key.file_name(0), key.file_name(1));
+    }
+
+    #[test]
+    fn test_page_cache_layout_model_miss() {
+        let dir = std::env::temp_dir().join(format!("ferrules-pages-{}", uuid::Uuid::new_v4()));
+        let cache = |config: &ORTConfig| PageCache {
+            dir: dir.clone(),
+            key: PageCacheKey::new(b"%PDF-1.7", &config.layout_fingerprint()),
+        };
+        let config = ORTConfig::default();
+        cache(&config).store(0, &cached_page());
+        assert!(cache(&config).load(0).is_some());
+
+        let other_threshold = ORTConfig {
+            conf_threshold: 0.5,
+            ..ORTConfig::default()
+        };
+        assert!(cache(&other_threshold).load(0).is_none());
+        let other_model = ORTConfig {
+            layout_model_path: Some(dir.join("layout.onnx")),
+            ..ORTConfig::default()
+        };
+        assert!(cache(&other_model).load(0).is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,147 @@
+use std::{ops::Range, str::FromStr};
+
+use crate::entities::PageID;
+
+/// Pages selected for parsing, written with 1-based inclusive page numbers as comma-separated
+/// segments in ascending order, e.g. `1,3,5-7,10-` where `10-` selects the pages from 10 to the
+/// end. Stored as sorted, non-overlapping 0-based ranges: open ranges end at `usize::MAX` until
+/// resolved against the document length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageRange {
+    ranges: Vec<Range<usize>>,
+}
+
+impl PageRange {
+    /// The 0-based ranges of the selection, the last one ends at `usize::MAX` when it's open
+    pub fn ranges(&self) -> &[Range<usize>] {
+        &self.ranges
+    }
+
+    /// 0-based ids of the selected pages of a document with `n_pages` pages, in order.
+    /// Returns `None` when a range ends after the last page, or an open range starts after it.
+    pub fn page_ids(&self, n_pages: usize) -> Option<Vec<PageID>> {
+        let mut page_ids = Vec::new();
+        for range in &self.ranges {
+            let end = if range.end == usize::MAX {
+                if range.start >= n_pages {
+                    return None;
+                }
+                n_pages
+            } else if range.end > n_pages {
+                return None;
+            } else {
+                range.end
+            };
+            page_ids.extend(range.start..end);
+        }
+        Some(page_ids)
+    }
+}
+
+impl FromIterator<Range<usize>> for PageRange {
+    fn from_iter<T: IntoIterator<Item = Range<usize>>>(iter: T) -> Self {
+        let mut ranges = iter
+            .into_iter()
+            .filter(|range| !range.is_empty())
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|range| range.start);
+
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        Self { ranges: merged }
+    }
+}
+
+impl From<Range<usize>> for PageRange {
+    fn from(range: Range<usize>) -> Self {
+        std::iter::once(range).collect()
+    }
+}
+
+fn parse_page_number(page: &str) -> anyhow::Result<usize> {
+    let page: usize = page.trim().parse()?;
+    if page == 0 {
+        anyhow::bail!("Page number must be greater than 0")
+    }
+    Ok(page)
+}
+
+impl FromStr for PageRange {
+    type Err = anyhow::Error;
+
+    fn from_str(range_str: &str) -> Result<Self, Self::Err> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for segment in range_str.split(',') {
+            let segment = segment.trim();
+            if segment.is_empty() {
+                anyhow::bail!("Invalid page range: empty segment in '{range_str}'")
+            }
+            let range = match segment.split_once('-') {
+                Some((start, end)) if end.trim().is_empty() => {
+                    parse_page_number(start)? - 1..usize::MAX
+                }
+                Some((start, end)) => {
+                    let (start, end) = (parse_page_number(start)?, parse_page_number(end)?);
+                    if end < start {
+                        anyhow::bail!("Invalid page range: end must be >= start in '{segment}'")
+                    }
+                    start - 1..end
+                }
+                // Single page
+                None => {
+                    let page = parse_page_number(segment)?;
+                    page - 1..page
+                }
+            };
+            if ranges
+                .last()
+                .is_some_and(|previous| range.start < previous.end)
+            {
+                anyhow::bail!(
+                    "Invalid page range: segment '{segment}' overlaps or comes before the previous one, segments must be in ascending order"
+                )
+            }
+            ranges.push(range);
+        }
+        Ok(ranges.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_page_range() {
+        let range: PageRange = "1,3,5-7,10-".parse().unwrap();
+        assert_eq!(range.ranges(), &[0..1, 2..3, 4..7, 9..usize::MAX]);
+        assert_eq!(range.page_ids(12), Some(vec![0, 2, 4, 5, 6, 9, 10, 11]));
+
+        // Adjacent segments are merged
+        let range: PageRange = "2, 3-6,7".parse().unwrap();
+        assert_eq!(range.ranges(), &[1..7]);
+
+        // Overlapping and descending segments are rejected
+        for invalid in [
+            "5-8,2", "3-6,5-8", "4,4", "8-,10", "", "0", "3-1", "1,,2", "-4", "a-b",
+        ] {
+            assert!(invalid.parse::<PageRange>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_page_ids_out_of_document() {
+        let range: PageRange = "2-4".parse().unwrap();
+        assert_eq!(range.page_ids(4), Some(vec![1, 2, 3]));
+        assert_eq!(range.page_ids(3), None);
+
+        let open: PageRange = "3-".parse().unwrap();
+        assert_eq!(open.page_ids(3), Some(vec![2]));
+        assert_eq!(open.page_ids(2), None);
+    }
+}
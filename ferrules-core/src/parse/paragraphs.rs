@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use tracing::instrument;
+
+use crate::{
+    blocks::{Block, BlockType},
+    entities::{Page, PageID},
+};
+
+/// Fraction of the page height, from the bottom for the first part and from the top for the
+/// second one, a paragraph must reach to be continued on the next page
+const PARAGRAPH_CONTINUATION_PAGE_MARGIN: f32 = 0.25;
+
+/// Punctuation ending a sentence, a paragraph ending with one of them is complete
+const SENTENCE_END: [char; 6] = ['.', '!', '?', ':', '…', '。'];
+
+/// Closing quotes and brackets allowed after the punctuation ending a sentence
+const SENTENCE_END_CLOSERS: [char; 7] = ['"', '\'', ')', ']', '”', '’', '»'];
+
+fn is_page_furniture(block: &Block) -> bool {
+    block.repeated || matches!(block.kind, BlockType::Header(_) | BlockType::Footer(_))
+}
+
+fn ends_sentence(text: &str) -> bool {
+    text.trim_end()
+        .trim_end_matches(SENTENCE_END_CLOSERS)
+        .ends_with(SENTENCE_END)
+}
+
+fn starts_lowercase(text: &str) -> bool {
+    text.trim_start()
+        .chars()
+        .next()
+        .is_some_and(char::is_lowercase)
+}
+
+fn block_text(block: &Block) -> Option<&str> {
+    match &block.kind {
+        BlockType::TextBlock(text) => Some(&text.text),
+        _ => None,
+    }
+}
+
+/// Whether the text block `next` continues the paragraph `text` on the following page: `text`
+/// ends in the bottom margin of its page without ending its sentence, `next` starts in the top
+/// margin of the next page with a lowercase letter
+fn continues_paragraph(text: &Block, next: &Block, page_heights: &HashMap<PageID, f32>) -> bool {
+    let (Some(last), Some(next_fragment)) = (
+        text.page_fragments().pop(),
+        next.page_fragments().into_iter().next(),
+    ) else {
+        return false;
+    };
+    if next_fragment.page_id != last.page_id + 1 {
+        return false;
+    }
+    let (Some(&last_height), Some(&next_height)) = (
+        page_heights.get(&last.page_id),
+        page_heights.get(&next_fragment.page_id),
+    ) else {
+        return false;
+    };
+    let ends_at_bottom = last.bbox.y1 >= last_height * (1.0 - PARAGRAPH_CONTINUATION_PAGE_MARGIN);
+    let starts_at_top = next_fragment.bbox.y0 <= next_height * PARAGRAPH_CONTINUATION_PAGE_MARGIN;
+    let (Some(text), Some(next_text)) = (block_text(text), block_text(next)) else {
+        return false;
+    };
+    ends_at_bottom && starts_at_top && !ends_sentence(text) && starts_lowercase(next_text)
+}
+
+/// Merges the paragraphs split by a page break: a text block ending at the bottom of a page
+/// in the middle of a sentence is continued by the text block starting the next page with a
+/// lowercase letter, when only headers, footers and repeated blocks are between them. Titles,
+/// lists, figures, tables or any other block in between keep them separate. The texts are
+/// joined with a space, the paragraph keeps the bbox of its first page, lists every page in
+/// `pages_id` and the bbox of each part in `fragments`.
+#[instrument(skip_all)]
+pub(crate) fn merge_paragraph_continuations(blocks: Vec<Block>, pages: &[Page]) -> Vec<Block> {
+    let page_heights: HashMap<PageID, f32> =
+        pages.iter().map(|page| (page.id, page.height)).collect();
+
+    let mut merged: Vec<Block> = Vec::with_capacity(blocks.len());
+    // Index in `merged` of the last text block, while only page furniture follows it
+    let mut open_text: Option<usize> = None;
+    for block in blocks {
+        if is_page_furniture(&block) {
+            merged.push(block);
+            continue;
+        }
+        if !matches!(block.kind, BlockType::TextBlock(_)) {
+            open_text = None;
+            merged.push(block);
+            continue;
+        }
+        if let Some(text_idx) =
+            open_text.filter(|&idx| continues_paragraph(&merged[idx], &block, &page_heights))
+        {
+            merged[text_idx].append_continuation(block);
+            continue;
+        }
+        open_text = Some(merged.len());
+        merged.push(block);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{List, ListItem, TextBlock},
+        entities::BBox,
+    };
+    use image::DynamicImage;
+
+    fn page(id: PageID) -> Page {
+        Page {
+            id,
+            width: 600.0,
+            height: 800.0,
+            image: DynamicImage::new_rgb8(1, 1),
+            rotation: 0,
+            need_ocr: false,
+            lines: vec![],
+            ocr_lines: vec![],
+            layout: vec![],
+            embedded_images: vec![],
+        }
+    }
+
+    fn block(id: usize, kind: BlockType, page: PageID, y0: f32, y1: f32) -> Block {
+        let bbox = BBox {
+            x0: 50.0,
+            y0,
+            x1: 550.0,
+            y1,
+        };
+        Block::for_test(id, kind, page, bbox)
+    }
+
+    fn text(text: &str) -> BlockType {
+        BlockType::TextBlock(TextBlock {
+            text: text.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_ends_sentence() {
+        assert!(ends_sentence("The end."));
+        assert!(ends_sentence("He said \"stop!\" "));
+        assert!(ends_sentence("As follows:"));
+        assert!(!ends_sentence("The paragraph goes on"));
+        assert!(!ends_sentence("a list of items,"));
+    }
+
+    #[test]
+    fn test_merge_paragraph_across_pages() {
+        let pages = [page(0), page(1), page(2)];
+        let footer = BlockType::Footer(TextBlock {
+            text: "Page 1".to_string(),
+        });
+        let mut header = block(2, text("Annual report"), 1, 20.0, 30.0);
+        header.repeated = true;
+        let blocks = vec![
+            block(0, text("The results of the year were"), 0, 500.0, 740.0),
+            block(1, footer, 0, 770.0, 780.0),
+            header,
+            block(3, text("better than expected, and the"), 1, 60.0, 780.0),
+            block(4, text("outlook is stable."), 2, 60.0, 120.0),
+            block(5, text("a new paragraph."), 2, 140.0, 160.0),
+        ];
+
+        let blocks = merge_paragraph_continuations(blocks, &pages);
+
+        assert_eq!(blocks.len(), 4);
+        let BlockType::TextBlock(ref paragraph) = blocks[0].kind else {
+            panic!("expected a text block");
+        };
+        assert_eq!(
+            paragraph.text,
+            "The results of the year were better than expected, and the outlook is stable."
+        );
+        assert_eq!(blocks[0].pages_id, vec![0, 1, 2]);
+        assert_eq!(blocks[0].bbox.y0, 500.0);
+        let fragments: Vec<_> = blocks[0]
+            .fragments
+            .iter()
+            .map(|f| (f.page_id, f.bbox.y0))
+            .collect();
+        assert_eq!(fragments, [(0, 500.0), (1, 60.0), (2, 60.0)]);
+        // The paragraph ending with a period isn't continued
+        assert!(matches!(blocks[3].kind, BlockType::TextBlock(_)));
+    }
+
+    #[test]
+    fn test_keep_separate_paragraphs() {
+        let pages = [page(0), page(1)];
+
+        // The first paragraph ends its sentence
+        let blocks = vec![
+            block(0, text("The first paragraph ends."), 0, 600.0, 740.0),
+            block(1, text("then a lowercase start"), 1, 60.0, 120.0),
+        ];
+        assert_eq!(merge_paragraph_continuations(blocks, &pages).len(), 2);
+
+        // The next page starts with an uppercase letter
+        let blocks = vec![
+            block(0, text("The first paragraph goes on"), 0, 600.0, 740.0),
+            block(1, text("Then a new sentence."), 1, 60.0, 120.0),
+        ];
+        assert_eq!(merge_paragraph_continuations(blocks, &pages).len(), 2);
+
+        // A list is between them
+        let list = BlockType::ListBlock(List {
+            items: vec![ListItem::new("- an item", BBox::default())],
+        });
+        let blocks = vec![
+            block(0, text("The first paragraph goes on"), 0, 600.0, 740.0),
+            block(1, list, 1, 40.0, 50.0),
+            block(2, text("and on."), 1, 60.0, 120.0),
+        ];
+        assert_eq!(merge_paragraph_continuations(blocks, &pages).len(), 3);
+
+        // The first paragraph ends mid-page
+        let blocks = vec![
+            block(0, text("The first paragraph goes on"), 0, 100.0, 200.0),
+            block(1, text("and on."), 1, 60.0, 120.0),
+        ];
+        assert_eq!(merge_paragraph_continuations(blocks, &pages).len(), 2);
+    }
+}
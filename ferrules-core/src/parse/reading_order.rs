@@ -0,0 +1,329 @@
+use serde::{Deserialize, Serialize};
+
+use crate::entities::{Element, ElementType};
+
+/// Default minimum horizontal gap (in page points) between two x-extents for them to be
+/// considered separate columns. Typical academic two-column layouts have a gutter of 15-25pt.
+pub const DEFAULT_COLUMN_GAP_THRESHOLD: f32 = 10.0;
+
+/// Elements wider than this ratio of the page width are considered full-width and are
+/// ignored when looking for column gutters.
+const FULL_WIDTH_RATIO: f32 = 0.6;
+
+/// A column gutter can be crossed by this ratio of the elements, e.g. a centered heading
+/// or a figure spanning two columns
+const MAX_GUTTER_CROSSING_RATIO: f32 = 0.2;
+
+/// How the elements of the pages are ordered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadingOrder {
+    /// Columns are detected on each page and read one after the other, the elements
+    /// spanning several columns are read between the columns above and below them
+    #[default]
+    Auto,
+    /// The elements are kept in the order of the layout, for the pages where the column
+    /// detection misfires, e.g. newspapers
+    SingleColumn,
+}
+
+/// Finds the x-positions of column separators on a page.
+///
+/// Builds a histogram of the number of non full-width elements covering each point of the
+/// page width and returns the center of every run of points wider than `gap_threshold`
+/// covered by at most `MAX_GUTTER_CROSSING_RATIO` of the elements. An empty result means
+/// that no clear column structure was found.
+fn find_column_separators(elements: &[Element], page_width: f32, gap_threshold: f32) -> Vec<f32> {
+    let extents: Vec<(f32, f32)> = elements
+        .iter()
+        .filter(|e| e.bbox.width() < page_width * FULL_WIDTH_RATIO)
+        .map(|e| (e.bbox.x0.max(0f32), e.bbox.x1.min(page_width)))
+        .collect();
+
+    if extents.len() < 2 {
+        return vec![];
+    }
+    // One point wide bins
+    let mut coverage = vec![0usize; page_width.ceil().max(0f32) as usize + 1];
+    for &(x0, x1) in &extents {
+        let end = (x1.ceil() as usize).min(coverage.len());
+        for count in coverage.iter_mut().take(end).skip(x0.floor() as usize) {
+            *count += 1;
+        }
+    }
+    let max_crossing = (extents.len() as f32 * MAX_GUTTER_CROSSING_RATIO) as usize;
+    let is_column = |count: &usize| *count > max_crossing;
+    let (Some(first), Some(last)) = (
+        coverage.iter().position(is_column),
+        coverage.iter().rposition(is_column),
+    ) else {
+        return vec![];
+    };
+
+    let mut separators = Vec::new();
+    let mut gap_start = None;
+    for (x, count) in coverage.iter().enumerate().take(last + 1).skip(first) {
+        match (is_column(count), gap_start) {
+            (false, None) => gap_start = Some(x),
+            (true, Some(start)) => {
+                if (x - start) as f32 > gap_threshold {
+                    separators.push((start + x) as f32 / 2f32);
+                }
+                gap_start = None;
+            }
+            _ => {}
+        }
+    }
+    separators
+}
+
+/// Whether the element crosses a column separator
+#[inline]
+fn spans_columns(element: &Element, separators: &[f32]) -> bool {
+    separators
+        .iter()
+        .any(|&s| element.bbox.x0 < s && element.bbox.x1 > s)
+}
+
+#[inline]
+fn column_index(element: &Element, separators: &[f32]) -> usize {
+    let (xc, _) = element.bbox.center();
+    separators.iter().take_while(|&&s| xc > s).count()
+}
+
+/// Reorders the elements of a single page in natural reading order.
+///
+/// Body elements are clustered into columns by their x-position and sorted top-to-bottom
+/// within each column, columns being read left to right. The elements spanning several
+/// columns, e.g. titles and wide figures, split the page into sections read one after the
+/// other. Page headers stay at the top and page footers at the bottom. When no clear column
+/// gap exists the original order is kept.
+pub(crate) fn sort_elements_reading_order(
+    elements: Vec<Element>,
+    page_width: f32,
+    gap_threshold: f32,
+) -> Vec<Element> {
+    let (mut headers, rest): (Vec<_>, Vec<_>) = elements
+        .into_iter()
+        .partition(|e| matches!(e.kind, ElementType::Header));
+    let (mut body, mut footers): (Vec<_>, Vec<_>) = rest
+        .into_iter()
+        .partition(|e| !matches!(e.kind, ElementType::Footer));
+
+    let separators = find_column_separators(&body, page_width, gap_threshold);
+    if !separators.is_empty() {
+        body.sort_by(|a, b| a.bbox.y0.total_cmp(&b.bbox.y0));
+        let mut ordered = Vec::with_capacity(body.len());
+        let mut section: Vec<Element> = Vec::new();
+        let flush = |section: &mut Vec<Element>, ordered: &mut Vec<Element>| {
+            // Stable sort, the elements of a column stay sorted top-to-bottom
+            section.sort_by_key(|e| column_index(e, &separators));
+            ordered.append(section);
+        };
+        for element in body {
+            if spans_columns(&element, &separators) {
+                flush(&mut section, &mut ordered);
+                ordered.push(element);
+            } else {
+                section.push(element);
+            }
+        }
+        flush(&mut section, &mut ordered);
+        body = ordered;
+    }
+
+    headers.append(&mut body);
+    headers.append(&mut footers);
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::{BBox, ElementText};
+
+    fn text_element(id: usize, text: &str, bbox: BBox) -> Element {
+        Element {
+            id,
+            layout_block_id: id as i32,
+            text_block: ElementText {
+                text: text.to_owned(),
+                links: vec![],
+                styles: vec![],
+                font_size: None,
+                lines: vec![],
+            },
+            kind: ElementType::Text,
+            page_id: 0,
+            bbox,
+            confidence: 1.0,
+        }
+    }
+
+    fn texts(elements: &[Element]) -> Vec<&str> {
+        elements
+            .iter()
+            .map(|e| e.text_block.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_two_columns_reading_order() {
+        // Native order interleaves the two columns line by line
+        let elements = vec![
+            text_element(
+                0,
+                "left-1",
+                BBox {
+                    x0: 50.0,
+                    y0: 100.0,
+                    x1: 280.0,
+                    y1: 200.0,
+                },
+            ),
+            text_element(
+                1,
+                "right-1",
+                BBox {
+                    x0: 320.0,
+                    y0: 100.0,
+                    x1: 550.0,
+                    y1: 200.0,
+                },
+            ),
+            text_element(
+                2,
+                "left-2",
+                BBox {
+                    x0: 50.0,
+                    y0: 220.0,
+                    x1: 280.0,
+                    y1: 400.0,
+                },
+            ),
+            text_element(
+                3,
+                "right-2",
+                BBox {
+                    x0: 320.0,
+                    y0: 220.0,
+                    x1: 550.0,
+                    y1: 400.0,
+                },
+            ),
+        ];
+
+        let ordered = sort_elements_reading_order(elements, 600.0, DEFAULT_COLUMN_GAP_THRESHOLD);
+        assert_eq!(
+            texts(&ordered),
+            vec!["left-1", "left-2", "right-1", "right-2"]
+        );
+    }
+
+    fn bbox(x0: f32, y0: f32, x1: f32, y1: f32) -> BBox {
+        BBox { x0, y0, x1, y1 }
+    }
+
+    #[test]
+    fn test_full_width_elements_split_columns() {
+        let elements = vec![
+            // Centered heading crossing the gutter
+            text_element(0, "title", bbox(200.0, 40.0, 400.0, 60.0)),
+            text_element(1, "left-1", bbox(50.0, 100.0, 280.0, 200.0)),
+            text_element(2, "right-1", bbox(320.0, 100.0, 550.0, 200.0)),
+            text_element(3, "left-2", bbox(50.0, 220.0, 280.0, 300.0)),
+            text_element(4, "right-2", bbox(320.0, 220.0, 550.0, 300.0)),
+            text_element(5, "figure", bbox(50.0, 320.0, 550.0, 500.0)),
+            text_element(6, "left-3", bbox(50.0, 520.0, 280.0, 700.0)),
+            text_element(7, "right-3", bbox(320.0, 520.0, 550.0, 700.0)),
+        ];
+
+        let ordered = sort_elements_reading_order(elements, 600.0, DEFAULT_COLUMN_GAP_THRESHOLD);
+        assert_eq!(
+            texts(&ordered),
+            vec!["title", "left-1", "left-2", "right-1", "right-2", "figure", "left-3", "right-3"]
+        );
+    }
+
+    #[test]
+    fn test_single_column_keeps_order() {
+        let elements = vec![
+            text_element(
+                0,
+                "first",
+                BBox {
+                    x0: 50.0,
+                    y0: 300.0,
+                    x1: 550.0,
+                    y1: 400.0,
+                },
+            ),
+            text_element(
+                1,
+                "second",
+                BBox {
+                    x0: 60.0,
+                    y0: 100.0,
+                    x1: 540.0,
+                    y1: 200.0,
+                },
+            ),
+        ];
+
+        let ordered = sort_elements_reading_order(elements, 600.0, DEFAULT_COLUMN_GAP_THRESHOLD);
+        assert_eq!(texts(&ordered), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn test_headers_and_footers_stay_in_place() {
+        let mut header = text_element(
+            0,
+            "header",
+            BBox {
+                x0: 320.0,
+                y0: 10.0,
+                x1: 550.0,
+                y1: 20.0,
+            },
+        );
+        header.kind = ElementType::Header;
+        let mut footer = text_element(
+            3,
+            "footer",
+            BBox {
+                x0: 50.0,
+                y0: 780.0,
+                x1: 100.0,
+                y1: 790.0,
+            },
+        );
+        footer.kind = ElementType::Footer;
+        let elements = vec![
+            header,
+            text_element(
+                1,
+                "right",
+                BBox {
+                    x0: 320.0,
+                    y0: 100.0,
+                    x1: 550.0,
+                    y1: 200.0,
+                },
+            ),
+            text_element(
+                2,
+                "left",
+                BBox {
+                    x0: 50.0,
+                    y0: 100.0,
+                    x1: 280.0,
+                    y1: 200.0,
+                },
+            ),
+            footer,
+        ];
+
+        let ordered = sort_elements_reading_order(elements, 600.0, DEFAULT_COLUMN_GAP_THRESHOLD);
+        assert_eq!(texts(&ordered), vec!["header", "left", "right", "footer"]);
+    }
+}
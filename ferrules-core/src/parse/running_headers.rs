@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use tracing::instrument;
+
+use crate::{
+    blocks::{Block, BlockType},
+    entities::{PageID, RunningHeader, RunningHeaderPosition},
+};
+
+/// Maximum difference, in page points, between the vertical centers of the occurrences of a
+/// running header
+const RUNNING_HEADER_Y_TOLERANCE: f32 = 15.0;
+
+/// Maximum difference, in page points, between the horizontal centers of the occurrences of
+/// a repeated text block
+const REPEATED_TEXT_X_TOLERANCE: f32 = 15.0;
+
+/// Fraction of the document pages a header or footer is repeated on to be a running header
+const RUNNING_HEADER_MIN_PAGE_RATIO: f32 = 0.5;
+
+/// Fraction of the document pages a text block is repeated on, e.g. a confidentiality notice,
+/// higher than for the headers as the layout model didn't classify it as page furniture
+const REPEATED_TEXT_MIN_PAGE_RATIO: f32 = 0.6;
+
+/// Page numbers and dates change from page to page: digit runs are replaced before comparing
+fn normalize_text(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len());
+    for word in text.split_whitespace() {
+        if !normalized.is_empty() {
+            normalized.push(' ');
+        }
+        let mut prev_digit = false;
+        for c in word.chars() {
+            if c.is_ascii_digit() {
+                if !prev_digit {
+                    normalized.push('#');
+                }
+                prev_digit = true;
+            } else {
+                normalized.extend(c.to_lowercase());
+                prev_digit = false;
+            }
+        }
+    }
+    normalized
+}
+
+fn running_position(block: &Block) -> Option<(RunningHeaderPosition, &str)> {
+    match &block.kind {
+        BlockType::Header(text) if block.pages_id.len() == 1 => {
+            Some((RunningHeaderPosition::Header, &text.text))
+        }
+        BlockType::Footer(text) if block.pages_id.len() == 1 => {
+            Some((RunningHeaderPosition::Footer, &text.text))
+        }
+        BlockType::TextBlock(text) if block.pages_id.len() == 1 => {
+            Some((RunningHeaderPosition::Body, &text.text))
+        }
+        _ => None,
+    }
+}
+
+/// Median of the values, `values` isn't empty
+fn median(mut values: Vec<f32>) -> f32 {
+    values.sort_by(f32::total_cmp);
+    values[values.len() / 2]
+}
+
+/// Flags the blocks repeated with the same text, up to their digits, at the same position
+/// across the `n_pages` pages as `repeated`, so the text outputs skip them. Header and footer
+/// blocks are repeated at the same vertical position on at least half of the pages, text
+/// blocks at the same position on at least 60% of the pages, and always on at least 2 pages.
+/// Returns one entry per running header.
+#[instrument(skip_all)]
+pub(crate) fn mark_running_headers(blocks: &mut [Block], n_pages: usize) -> Vec<RunningHeader> {
+    let min_pages = |ratio: f32| ((n_pages as f32 * ratio).ceil() as usize).max(2);
+
+    let mut groups: HashMap<(RunningHeaderPosition, String), Vec<usize>> = HashMap::new();
+    for (idx, block) in blocks.iter().enumerate() {
+        if let Some((position, text)) = running_position(block) {
+            let text = normalize_text(text);
+            if !text.is_empty() {
+                groups.entry((position, text)).or_default().push(idx);
+            }
+        }
+    }
+    let mut groups = groups.into_iter().collect::<Vec<_>>();
+    groups.sort_by_key(|(_, members)| members[0]);
+
+    let mut running_headers = Vec::new();
+    for ((position, _), members) in groups {
+        let center_x = median(
+            members
+                .iter()
+                .map(|&idx| blocks[idx].bbox.center().0)
+                .collect(),
+        );
+        let center_y = median(
+            members
+                .iter()
+                .map(|&idx| blocks[idx].bbox.center().1)
+                .collect(),
+        );
+        let (min_ratio, x_tolerance) = match position {
+            // Headers alternate between the left and right of the odd and even pages
+            RunningHeaderPosition::Header | RunningHeaderPosition::Footer => {
+                (RUNNING_HEADER_MIN_PAGE_RATIO, f32::INFINITY)
+            }
+            RunningHeaderPosition::Body => {
+                (REPEATED_TEXT_MIN_PAGE_RATIO, REPEATED_TEXT_X_TOLERANCE)
+            }
+        };
+
+        let members = members
+            .into_iter()
+            .filter(|&idx| {
+                let (x, y) = blocks[idx].bbox.center();
+                (x - center_x).abs() <= x_tolerance
+                    && (y - center_y).abs() <= RUNNING_HEADER_Y_TOLERANCE
+            })
+            .collect::<Vec<_>>();
+        let mut pages_id = members
+            .iter()
+            .flat_map(|&idx| blocks[idx].pages_id.iter().copied())
+            .collect::<Vec<PageID>>();
+        pages_id.sort_unstable();
+        pages_id.dedup();
+        if pages_id.len() < min_pages(min_ratio) {
+            continue;
+        }
+
+        let text = running_position(&blocks[members[0]])
+            .map(|(_, text)| text.trim().to_owned())
+            .unwrap_or_default();
+        for idx in members {
+            blocks[idx].repeated = true;
+        }
+        running_headers.push(RunningHeader {
+            position,
+            text,
+            pages_id,
+        });
+    }
+    running_headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{blocks::TextBlock, entities::BBox};
+
+    fn block(
+        id: usize,
+        kind: fn(TextBlock) -> BlockType,
+        text: &str,
+        page: PageID,
+        y0: f32,
+    ) -> Block {
+        let kind = kind(TextBlock {
+            text: text.to_string(),
+        });
+        let bbox = BBox {
+            x0: 50.0,
+            y0,
+            x1: 500.0,
+            y1: y0 + 12.0,
+        };
+        Block::for_test(id, kind, page, bbox)
+    }
+
+    #[test]
+    fn test_mark_running_headers() {
+        let mut blocks = Vec::new();
+        for page in 0..4 {
+            blocks.push(block(
+                blocks.len(),
+                BlockType::Header,
+                "Annual Report 2023",
+                page,
+                20.0,
+            ));
+            // Text repeated at the same position, the layout model missed the header
+            blocks.push(block(
+                blocks.len(),
+                BlockType::TextBlock,
+                "Confidential",
+                page,
+                400.0,
+            ));
+            // Repeated text moving down the pages is body text
+            blocks.push(block(
+                blocks.len(),
+                BlockType::TextBlock,
+                "See the notes",
+                page,
+                100.0 + 150.0 * page as f32,
+            ));
+            let page_number = format!("Page {} of 4", page + 1);
+            blocks.push(block(
+                blocks.len(),
+                BlockType::Footer,
+                &page_number,
+                page,
+                770.0,
+            ));
+        }
+        // Header of a single page
+        blocks.push(block(blocks.len(), BlockType::Header, "Appendix", 3, 40.0));
+        // Same text as the running header but in the middle of the page
+        blocks.push(block(
+            blocks.len(),
+            BlockType::Header,
+            "Annual Report 2023",
+            2,
+            300.0,
+        ));
+
+        let running_headers = mark_running_headers(&mut blocks, 4);
+
+        assert_eq!(running_headers.len(), 3);
+        assert_eq!(running_headers[0].position, RunningHeaderPosition::Header);
+        assert_eq!(running_headers[0].text, "Annual Report 2023");
+        assert_eq!(running_headers[0].pages_id, vec![0, 1, 2, 3]);
+        assert_eq!(running_headers[1].position, RunningHeaderPosition::Body);
+        assert_eq!(running_headers[1].text, "Confidential");
+        assert_eq!(running_headers[2].position, RunningHeaderPosition::Footer);
+        assert_eq!(running_headers[2].text, "Page 1 of 4");
+
+        // The repeated blocks are kept in the document, flagged
+        assert_eq!(blocks.len(), 18);
+        let kept = blocks
+            .iter()
+            .filter(|b| !b.repeated)
+            .map(|b| b.id)
+            .collect::<Vec<_>>();
+        assert_eq!(kept, vec![2, 6, 10, 14, 16, 17]);
+    }
+
+    #[test]
+    fn test_keep_headers_on_few_pages() {
+        let mut blocks = vec![
+            block(0, BlockType::Header, "Chapter 1", 0, 20.0),
+            block(1, BlockType::Header, "Chapter 1", 1, 20.0),
+            block(2, BlockType::TextBlock, "Draft", 0, 400.0),
+            block(3, BlockType::TextBlock, "Draft", 1, 400.0),
+        ];
+
+        let running_headers = mark_running_headers(&mut blocks, 10);
+        assert!(running_headers.is_empty());
+        assert!(blocks.iter().all(|b| !b.repeated));
+
+        // Half of the pages is enough for the headers, not for the text
+        let running_headers = mark_running_headers(&mut blocks, 4);
+        assert_eq!(running_headers.len(), 1);
+        assert_eq!(running_headers[0].position, RunningHeaderPosition::Header);
+        let repeated = blocks.iter().map(|b| b.repeated).collect::<Vec<_>>();
+        assert_eq!(repeated, vec![true, true, false, false]);
+    }
+}
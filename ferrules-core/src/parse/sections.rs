@@ -0,0 +1,161 @@
+use crate::{
+    blocks::{Block, BlockType},
+    entities::Section,
+};
+
+/// Nests the blocks under their titles by title level. The first section is the implicit root,
+/// at level 0 without a title, holding the blocks before the first title and the top-level
+/// sections. A title closes the open sections of the same or a deeper level: a level 3 title
+/// before any level 1 title is a child of the root. Headers, footers and repeated blocks
+/// aren't section content.
+pub(crate) fn build_sections(blocks: &[Block]) -> Vec<Section> {
+    let mut sections = vec![Section::default()];
+    // Ids of the open sections, from the root to the innermost
+    let mut open: Vec<usize> = vec![0];
+    for block in blocks {
+        if block.repeated || matches!(block.kind, BlockType::Header(_) | BlockType::Footer(_)) {
+            continue;
+        }
+        let BlockType::Title(title) = &block.kind else {
+            let current = *open.last().expect("the root section is always open");
+            sections[current].blocks.push(block.id);
+            continue;
+        };
+        // Unassigned levels are 0, below the root
+        let level = title.level.max(1);
+        while let Some(&last) = open.last() {
+            if sections[last].level < level {
+                break;
+            }
+            open.pop();
+        }
+        let parent = *open.last().expect("the root section is always open");
+        let id = sections.len();
+        sections[parent].children.push(id);
+        sections.push(Section {
+            id,
+            title_block_id: Some(block.id),
+            level,
+            ..Default::default()
+        });
+        open.push(id);
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{TextBlock, Title},
+        entities::{BBox, DocumentMetadata, ParsedDocument, ParsingMetrics},
+    };
+    use std::time::Duration;
+
+    fn block(id: usize, kind: BlockType) -> Block {
+        Block::for_test(id, kind, 0, BBox::default())
+    }
+
+    fn title(id: usize, level: u8) -> Block {
+        block(
+            id,
+            BlockType::Title(Title {
+                level,
+                text: format!("Title {id}"),
+            }),
+        )
+    }
+
+    fn text(id: usize) -> Block {
+        block(
+            id,
+            BlockType::TextBlock(TextBlock {
+                text: format!("Paragraph {id}"),
+            }),
+        )
+    }
+
+    #[test]
+    fn test_build_sections() {
+        let blocks = vec![
+            text(0),
+            title(1, 1),
+            text(2),
+            title(3, 2),
+            text(4),
+            block(
+                5,
+                BlockType::Footer(TextBlock {
+                    text: "1".to_owned(),
+                }),
+            ),
+            title(6, 2),
+            title(7, 1),
+            text(8),
+        ];
+        let sections = build_sections(&blocks);
+
+        let tree: Vec<_> = sections
+            .iter()
+            .map(|s| {
+                (
+                    s.title_block_id,
+                    s.level,
+                    s.children.clone(),
+                    s.blocks.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            tree,
+            [
+                (None, 0, vec![1, 4], vec![0]),
+                (Some(1), 1, vec![2, 3], vec![2]),
+                (Some(3), 2, vec![], vec![4]),
+                (Some(6), 2, vec![], vec![]),
+                (Some(7), 1, vec![], vec![8]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_sections_without_titles() {
+        let sections = build_sections(&[text(0), text(1)]);
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].title_block_id, None);
+        assert_eq!(sections[0].blocks, [0, 1]);
+
+        assert_eq!(build_sections(&[]).len(), 1);
+    }
+
+    #[test]
+    fn test_build_sections_deep_title_first() {
+        let sections = build_sections(&[title(0, 3), text(1), title(2, 1), title(3, 2)]);
+        let parents: Vec<_> = sections.iter().map(|s| s.children.clone()).collect();
+        assert_eq!(parents, [vec![1, 2], vec![], vec![3], vec![]]);
+        assert_eq!(sections[1].blocks, [1]);
+    }
+
+    #[test]
+    fn test_section_iter_blocks() {
+        let blocks = vec![title(0, 1), text(1), title(2, 2), text(3), title(4, 1)];
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![],
+            sections: build_sections(&blocks),
+            blocks,
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+        };
+
+        let ids: Vec<_> = doc.sections()[1].iter_blocks(&doc).map(|b| b.id).collect();
+        assert_eq!(ids, [0, 1, 2, 3]);
+        let ids: Vec<_> = doc.sections()[0].iter_blocks(&doc).map(|b| b.id).collect();
+        assert_eq!(ids, [0, 1, 2, 3, 4]);
+    }
+}
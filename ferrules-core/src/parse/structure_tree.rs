@@ -0,0 +1,273 @@
+use std::{collections::HashMap, ffi::c_void};
+
+use pdfium_render::prelude::*;
+
+use crate::{
+    blocks::{Block, BlockSource},
+    entities::{BBox, PageID},
+    layout::model::LayoutBBox,
+};
+
+/// Bounds the structure tree traversal, pdfium doesn't protect against cyclic trees
+const MAX_STRUCTURE_ELEMENTS: usize = 10_000;
+
+/// `FPDF_PAGEOBJ_TEXT` and `FPDF_PAGEOBJ_IMAGE` page object types
+const FPDF_PAGEOBJ_TEXT: i32 = 1;
+const FPDF_PAGEOBJ_IMAGE: i32 = 3;
+
+/// How a structure element is turned into layout regions
+#[derive(Debug, PartialEq)]
+enum StructureNode {
+    /// A region with the layout class of the element, covering all its descendants
+    Region(&'static str),
+    /// Grouping element, its children are visited
+    Group,
+}
+
+/// Layout class of the standard structure types of ISO 32000, matching the layout model
+/// labels. Elements grouping other elements (`Document`, `Sect`, `L`...) and the unknown
+/// custom types are traversed.
+fn structure_node(tag: &str) -> StructureNode {
+    match tag {
+        "Title" => StructureNode::Region("Title"),
+        "H" | "H1" | "H2" | "H3" | "H4" | "H5" | "H6" => StructureNode::Region("Section-header"),
+        "P" | "BlockQuote" => StructureNode::Region("Text"),
+        "Code" => StructureNode::Region("Code"),
+        "LI" => StructureNode::Region("List-item"),
+        "Table" => StructureNode::Region("Table"),
+        "Figure" => StructureNode::Region("Picture"),
+        "Caption" => StructureNode::Region("Caption"),
+        "Note" | "FENote" => StructureNode::Region("Footnote"),
+        "Formula" => StructureNode::Region("Formula"),
+        _ => StructureNode::Group,
+    }
+}
+
+/// Decodes the UTF-16LE, NUL terminated strings returned by pdfium
+fn utf16le_string(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Bounds of the text and image objects of the page, by marked content ID, in top-down page
+/// coordinates
+fn marked_content_bounds(
+    bindings: &dyn PdfiumLibraryBindings,
+    page: FPDF_PAGE,
+    page_height: f32,
+) -> HashMap<i32, BBox> {
+    let mut bounds: HashMap<i32, BBox> = HashMap::new();
+    for index in 0..bindings.FPDFPage_CountObjects(page) {
+        let object = bindings.FPDFPage_GetObject(page, index);
+        if object.is_null()
+            || !matches!(
+                bindings.FPDFPageObj_GetType(object),
+                FPDF_PAGEOBJ_TEXT | FPDF_PAGEOBJ_IMAGE
+            )
+        {
+            continue;
+        }
+        let mcid = bindings.FPDFPageObj_GetMarkedContentID(object);
+        if mcid < 0 {
+            continue;
+        }
+        let (mut left, mut bottom, mut right, mut top) = (0f32, 0f32, 0f32, 0f32);
+        if bindings.FPDFPageObj_GetBounds(object, &mut left, &mut bottom, &mut right, &mut top) == 0
+        {
+            continue;
+        }
+        // PDF coordinates are bottom-up, convert to top-down
+        let bbox = BBox {
+            x0: left,
+            y0: page_height - top,
+            x1: right,
+            y1: page_height - bottom,
+        };
+        bounds
+            .entry(mcid)
+            .and_modify(|merged| merged.merge(&bbox))
+            .or_insert(bbox);
+    }
+    bounds
+}
+
+fn element_type(bindings: &dyn PdfiumLibraryBindings, element: FPDF_STRUCTELEMENT) -> String {
+    let len = bindings.FPDF_StructElement_GetType(element, std::ptr::null_mut(), 0);
+    if len == 0 {
+        return String::new();
+    }
+    let mut buffer = vec![0u8; len as usize];
+    bindings.FPDF_StructElement_GetType(element, buffer.as_mut_ptr() as *mut c_void, len);
+    utf16le_string(&buffer)
+}
+
+fn element_children(
+    bindings: &dyn PdfiumLibraryBindings,
+    element: FPDF_STRUCTELEMENT,
+) -> Vec<FPDF_STRUCTELEMENT> {
+    (0..bindings.FPDF_StructElement_CountChildren(element))
+        .map(|index| bindings.FPDF_StructElement_GetChildAtIndex(element, index))
+        // Marked content references aren't elements
+        .filter(|child| !child.is_null())
+        .collect()
+}
+
+/// Union of the bounds of the marked content of `element` and its descendants
+fn element_bounds(
+    bindings: &dyn PdfiumLibraryBindings,
+    element: FPDF_STRUCTELEMENT,
+    content_bounds: &HashMap<i32, BBox>,
+    visited: &mut usize,
+) -> Option<BBox> {
+    let mut bbox: Option<BBox> = None;
+    let mut stack = vec![element];
+    while let Some(element) = stack.pop() {
+        *visited += 1;
+        if *visited > MAX_STRUCTURE_ELEMENTS {
+            break;
+        }
+        for index in 0..bindings
+            .FPDF_StructElement_GetMarkedContentIdCount(element)
+            .max(0)
+        {
+            let mcid = bindings.FPDF_StructElement_GetMarkedContentIdAtIndex(element, index);
+            if let Some(content) = content_bounds.get(&mcid) {
+                match bbox.as_mut() {
+                    Some(bbox) => bbox.merge(content),
+                    None => bbox = Some(content.clone()),
+                }
+            }
+        }
+        stack.extend(element_children(bindings, element).into_iter().rev());
+    }
+    bbox
+}
+
+/// Regions of the structure tree of a tagged page, in the tree order, with their layout class
+/// and the union of the bounds of their text and images. Empty when the page isn't tagged.
+///
+/// The bboxes are in the top-down coordinates of the unrotated page.
+pub(crate) fn extract_structure_layout(page: &PdfPage, page_height: f32) -> Vec<LayoutBBox> {
+    let bindings = page.bindings();
+    let page_handle = bindings.get_handle_from_page(page);
+    let tree = bindings.FPDF_StructTree_GetForPage(page_handle);
+    if tree.is_null() {
+        return Vec::new();
+    }
+    let roots: Vec<_> = (0..bindings.FPDF_StructTree_CountChildren(tree))
+        .map(|index| bindings.FPDF_StructTree_GetChildAtIndex(tree, index))
+        .filter(|element| !element.is_null())
+        .collect();
+
+    let mut regions = Vec::new();
+    if !roots.is_empty() {
+        let content_bounds = marked_content_bounds(bindings, page_handle, page_height);
+        let mut visited = 0;
+        // Depth-first, the children are visited before the next sibling
+        let mut stack: Vec<_> = roots.into_iter().rev().collect();
+        while let Some(element) = stack.pop() {
+            if visited > MAX_STRUCTURE_ELEMENTS {
+                tracing::warn!("structure tree truncated to {MAX_STRUCTURE_ELEMENTS} elements");
+                break;
+            }
+            visited += 1;
+            match structure_node(&element_type(bindings, element)) {
+                StructureNode::Region(label) => {
+                    let bbox = element_bounds(bindings, element, &content_bounds, &mut visited);
+                    if let Some(bbox) = bbox {
+                        regions.push(LayoutBBox {
+                            id: regions.len() as i32,
+                            bbox,
+                            label: label.to_owned(),
+                            proba: 1.0,
+                        });
+                    }
+                }
+                StructureNode::Group => {
+                    stack.extend(element_children(bindings, element).into_iter().rev())
+                }
+            }
+        }
+    }
+    bindings.FPDF_StructTree_Close(tree);
+    regions
+}
+
+/// Marks the blocks starting on a tagged page as segmented from its structure tree
+pub(crate) fn mark_structure_tree_blocks(blocks: &mut [Block], tagged_pages: &[PageID]) {
+    for block in blocks.iter_mut() {
+        if block
+            .pages_id
+            .first()
+            .is_some_and(|page_id| tagged_pages.contains(page_id))
+        {
+            block.source = BlockSource::StructureTree;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{BlockType, TextBlock};
+
+    fn block(id: usize, pages_id: Vec<PageID>) -> Block {
+        let kind = BlockType::TextBlock(TextBlock {
+            text: format!("block {id}"),
+        });
+        let bbox = BBox {
+            x0: 0.0,
+            y0: 0.0,
+            x1: 100.0,
+            y1: 10.0,
+        };
+        Block {
+            pages_id,
+            ..Block::for_test(id, kind, 0, bbox)
+        }
+    }
+
+    #[test]
+    fn test_structure_node() {
+        assert_eq!(
+            structure_node("H2"),
+            StructureNode::Region("Section-header")
+        );
+        assert_eq!(structure_node("P"), StructureNode::Region("Text"));
+        assert_eq!(structure_node("LI"), StructureNode::Region("List-item"));
+        assert_eq!(structure_node("Figure"), StructureNode::Region("Picture"));
+        // Lists and sections are traversed to their items and paragraphs
+        assert_eq!(structure_node("L"), StructureNode::Group);
+        assert_eq!(structure_node("Sect"), StructureNode::Group);
+        assert_eq!(structure_node("CustomTag"), StructureNode::Group);
+    }
+
+    #[test]
+    fn test_utf16le_string() {
+        let bytes: Vec<u8> = "H1\0"
+            .encode_utf16()
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect();
+        assert_eq!(utf16le_string(&bytes), "H1");
+        assert_eq!(utf16le_string(&[]), "");
+    }
+
+    #[test]
+    fn test_mark_structure_tree_blocks() {
+        let mut blocks = vec![block(0, vec![0]), block(1, vec![1, 2]), block(2, vec![2])];
+        mark_structure_tree_blocks(&mut blocks, &[1]);
+        let sources: Vec<_> = blocks.iter().map(|b| b.source).collect();
+        assert_eq!(
+            sources,
+            [
+                BlockSource::Layout,
+                BlockSource::StructureTree,
+                BlockSource::Layout
+            ]
+        );
+    }
+}
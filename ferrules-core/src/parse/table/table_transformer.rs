@@ -5,9 +5,6 @@ use std::time::Duration;
 use image::imageops::FilterType;
 use image::{DynamicImage, GenericImageView};
 use ndarray::{s, stack, Array4, ArrayD, Axis};
-use ort::execution_providers::{
-    CPUExecutionProvider, CUDAExecutionProvider, CoreMLExecutionProvider, TensorRTExecutionProvider,
-};
 use ort::session::builder::GraphOptimizationLevel;
 use ort::session::Session;
 use tokio::sync::{mpsc, oneshot};
@@ -17,7 +14,7 @@ use tracing::Instrument;
 use crate::blocks::{TableAlgorithm, TableBlock};
 use crate::entities::BBox;
 use crate::error::FerrulesError;
-use crate::layout::model::{nms, LayoutBBox};
+use crate::layout::model::{nms, provider_dispatch, LayoutBBox};
 
 pub const TABLE_MODEL_BYTES: &[u8] =
     include_bytes!("../../../../models/table-transformer-structure-recognition_fp16.onnx");
@@ -237,42 +234,12 @@ impl TableTransformerStandard {
     }
 
     pub fn new(config: &crate::layout::model::ORTConfig) -> Result<Self, FerrulesError> {
-        let mut execution_providers = Vec::new();
-
-        // Get providers sorted by priority: accelerators first
-        let providers = config.get_sorted_providers();
-
-        // Providers
-        for provider in providers {
-            match provider {
-                crate::layout::model::OrtExecutionProvider::Trt(device_id) => {
-                    execution_providers.push(
-                        TensorRTExecutionProvider::default()
-                            .with_device_id(device_id)
-                            .build(),
-                    );
-                }
-                crate::layout::model::OrtExecutionProvider::CUDA(device_id) => {
-                    execution_providers.push(
-                        CUDAExecutionProvider::default()
-                            .with_device_id(device_id)
-                            .build(),
-                    );
-                }
-                crate::layout::model::OrtExecutionProvider::CoreML { ane_only } => {
-                    let provider = CoreMLExecutionProvider::default();
-                    let provider = if ane_only {
-                        provider.with_ane_only().build()
-                    } else {
-                        provider.build()
-                    };
-                    execution_providers.push(provider)
-                }
-                crate::layout::model::OrtExecutionProvider::CPU => {
-                    execution_providers.push(CPUExecutionProvider::default().build());
-                }
-            }
-        }
+        // Providers sorted by priority: accelerators first
+        let execution_providers = config
+            .get_sorted_providers()
+            .iter()
+            .filter_map(provider_dispatch)
+            .collect::<Vec<_>>();
 
         let opt_lvl = match config.opt_level {
             Some(crate::layout::model::ORTGraphOptimizationLevel::Level1) => {
@@ -656,39 +623,12 @@ impl TableTransformer {
     const CONFIDENCE_THRESHOLD: f32 = 0.6;
 
     pub fn new(config: &crate::layout::model::ORTConfig) -> Result<Self, FerrulesError> {
-        let mut execution_providers = Vec::new();
-        let providers = config.get_sorted_providers();
-
-        for provider in providers {
-            match provider {
-                crate::layout::model::OrtExecutionProvider::Trt(device_id) => {
-                    execution_providers.push(
-                        TensorRTExecutionProvider::default()
-                            .with_device_id(device_id)
-                            .build(),
-                    );
-                }
-                crate::layout::model::OrtExecutionProvider::CUDA(device_id) => {
-                    execution_providers.push(
-                        CUDAExecutionProvider::default()
-                            .with_device_id(device_id)
-                            .build(),
-                    );
-                }
-                crate::layout::model::OrtExecutionProvider::CoreML { ane_only } => {
-                    let provider = CoreMLExecutionProvider::default();
-                    let provider = if ane_only {
-                        provider.with_ane_only().build()
-                    } else {
-                        provider.build()
-                    };
-                    execution_providers.push(provider)
-                }
-                crate::layout::model::OrtExecutionProvider::CPU => {
-                    execution_providers.push(CPUExecutionProvider::default().build());
-                }
-            }
-        }
+        // Providers sorted by priority: accelerators first
+        let execution_providers = config
+            .get_sorted_providers()
+            .iter()
+            .filter_map(provider_dispatch)
+            .collect::<Vec<_>>();
 
         let opt_lvl = match config.opt_level {
             Some(crate::layout::model::ORTGraphOptimizationLevel::Level1) => {
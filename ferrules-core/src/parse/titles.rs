@@ -1,30 +1,205 @@
 #![allow(incomplete_features)]
 use std::collections::HashMap;
 
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
 use itertools::Itertools;
-use rand::seq::SliceRandom;
-use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     blocks::TitleLevel,
-    entities::{Element, ElementID, PageID},
+    entities::{
+        BBox, Element, ElementID, ElementType, OutlineEntry, PageID, TitleLevels, TitleSizeLevel,
+    },
+    parse::outline::apply_outline_levels,
 };
 
 /// Minimum gap between headings to consider them in separate buckets
 const TITLE_MERGE_THRESHOLD: f32 = 0.7;
 
+/// Text elements set in a font this much larger than the body text are headings
+const TITLE_FONT_SIZE_RATIO: f32 = 1.3;
+
+/// Text elements with more words are paragraphs whatever their font size
+const MAX_TITLE_WORDS: usize = 16;
+
+/// Text elements with less letters, e.g. numbers or drop caps, are paragraphs whatever their
+/// font size
+const MIN_TITLE_LETTERS: usize = 2;
+
+/// Font size of most of the text of `elements`, `None` when none has a font size
+fn body_font_size(elements: &[Element]) -> Option<f32> {
+    let mut text_len_by_size: HashMap<u32, usize> = HashMap::new();
+    for element in elements
+        .iter()
+        .filter(|e| matches!(e.kind, ElementType::Text))
+    {
+        if let Some(font_size) = element.text_block.font_size {
+            // Half point buckets
+            *text_len_by_size
+                .entry((font_size * 2.0).round() as u32)
+                .or_default() += element.text_block.text.len();
+        }
+    }
+    text_len_by_size
+        .into_iter()
+        .max_by_key(|&(size, text_len)| (text_len, size))
+        .map(|(size, _)| size as f32 / 2.0)
+}
+
+/// Turns into subtitles the short text elements in a font noticeably larger than the body
+/// text, the headings the layout model read as paragraphs. The text within a figure and the
+/// text without a word, e.g. a large number or a drop cap, stay paragraphs
+pub(crate) fn promote_large_text_titles(elements: &mut [Element]) {
+    let Some(body_font_size) = body_font_size(elements) else {
+        return;
+    };
+    let figures: Vec<(PageID, BBox)> = elements
+        .iter()
+        .filter(|element| matches!(element.kind, ElementType::Image))
+        .map(|element| (element.page_id, element.bbox.clone()))
+        .collect();
+    for element in elements.iter_mut() {
+        let text = &element.text_block.text;
+        let is_heading = matches!(element.kind, ElementType::Text)
+            && element
+                .text_block
+                .font_size
+                .is_some_and(|font_size| font_size >= body_font_size * TITLE_FONT_SIZE_RATIO)
+            && text.split_whitespace().count() <= MAX_TITLE_WORDS
+            && text.chars().filter(|c| c.is_alphabetic()).count() >= MIN_TITLE_LETTERS
+            && !figures.iter().any(|(page_id, bbox)| {
+                *page_id == element.page_id
+                    && bbox.intersection(&element.bbox) > element.bbox.area() / 2.0
+            });
+        if is_heading {
+            element.kind = ElementType::Subtitle;
+        }
+    }
+}
+
+/// Whether most of the text of the element is set in bold
+fn is_bold(element: &Element) -> bool {
+    let visible_len = |text: &str| text.chars().filter(|c| !c.is_whitespace()).count();
+    let bold_len: usize = element
+        .text_block
+        .styles
+        .iter()
+        .filter(|style| style.bold)
+        .map(|style| visible_len(&style.text))
+        .sum();
+    bold_len > 0 && bold_len * 2 >= visible_len(&element.text_block.text)
+}
+
+/// Largest number of title levels of the default strategy
+pub const DEFAULT_TITLE_CLUSTERS: usize = 6;
+
+/// How the levels of the titles are assigned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum TitleLevelStrategy {
+    /// The font sizes are clustered in at most `k` levels, the close clusters share a level
+    #[serde(rename = "kmeans")]
+    KMeans { k: usize },
+    /// One level per font size rounded to the half point, for the documents with a few
+    /// consistent heading styles
+    FontSizeBuckets,
+    /// The depth of the PDF outline entry of the title, the titles missing from the outline
+    /// or the documents without one fall back to the clustering in
+    /// [`DEFAULT_TITLE_CLUSTERS`] levels
+    #[default]
+    OutlineFirst,
+}
+
+impl fmt::Display for TitleLevelStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TitleLevelStrategy::KMeans { k } => write!(f, "kmeans:{k}"),
+            TitleLevelStrategy::FontSizeBuckets => write!(f, "font-size"),
+            TitleLevelStrategy::OutlineFirst => write!(f, "outline"),
+        }
+    }
+}
+
+impl FromStr for TitleLevelStrategy {
+    type Err = anyhow::Error;
+
+    /// Parses `outline`, `font-size`, `kmeans` or `kmeans:<k>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, k) = match s.split_once(':') {
+            Some((name, k)) => (name, Some(k)),
+            None => (s, None),
+        };
+        match (name.to_ascii_lowercase().as_str(), k) {
+            ("outline", None) => Ok(TitleLevelStrategy::OutlineFirst),
+            ("font-size", None) => Ok(TitleLevelStrategy::FontSizeBuckets),
+            ("kmeans", None) => Ok(TitleLevelStrategy::KMeans {
+                k: DEFAULT_TITLE_CLUSTERS,
+            }),
+            ("kmeans", Some(k)) => match k.parse::<usize>() {
+                Ok(0) | Err(_) => bail!("`{k}` isn't a positive number of title levels"),
+                Ok(k) => Ok(TitleLevelStrategy::KMeans { k }),
+            },
+            _ => bail!(
+                "unknown title level strategy `{s}`, expected outline, font-size or kmeans[:<k>]"
+            ),
+        }
+    }
+}
+
+/// Sizes the titles are ranked by: their font sizes, or their bbox heights when some titles
+/// have no font size. The flag is set for the heights
+fn title_sizes(titles: &[&Element]) -> (Vec<f32>, bool) {
+    match titles
+        .iter()
+        .map(|e| e.text_block.font_size)
+        .collect::<Option<Vec<_>>>()
+    {
+        Some(font_sizes) => (font_sizes, false),
+        None => (titles.iter().map(|e| e.bbox.height()).collect(), true),
+    }
+}
+
+/// Half point bucket of a size
+fn size_bucket(size: f32) -> u32 {
+    (size * 2.0).round() as u32
+}
+
+/// Levels of the titles from the rank of their size, 0 for the largest: by size rank, then
+/// the bold titles before the regular ones
+fn levels_from_size_ranks(
+    titles: &[&Element],
+    size_ranks: &[usize],
+) -> HashMap<(PageID, ElementID), TitleLevel> {
+    let keys: Vec<(usize, bool)> = titles
+        .iter()
+        .zip(size_ranks)
+        .map(|(el, &rank)| (rank, !is_bold(el)))
+        .collect();
+    let levels: Vec<(usize, bool)> = keys.iter().copied().sorted().dedup().collect();
+    titles
+        .iter()
+        .zip(keys.iter())
+        .map(|(el, key)| {
+            let level = levels.binary_search(key).expect("key of a title") + 1;
+            ((el.page_id, el.id), level as TitleLevel)
+        })
+        .collect()
+}
+
+/// Assigns a level to each title by clustering their sizes, largest first. Within a size
+/// cluster, the bold titles rank above the regular ones
 pub fn title_levels_kmeans(
     titles: &[&Element],
     title_buckets: usize,
 ) -> HashMap<(PageID, ElementID), TitleLevel> {
-    let mut title_level = HashMap::new();
-
-    let samples: Vec<f32> = titles.iter().map(|e| e.bbox.height()).collect();
-    let sample_len = samples.len();
+    let (samples, _) = title_sizes(titles);
 
     // TODO: Check this heuristic
-    if sample_len <= title_buckets {
-        return title_level;
+    if samples.len() <= title_buckets {
+        return HashMap::new();
     }
 
     let (centroids, assignments) = run_kmeans_1d(&samples, title_buckets, 100);
@@ -36,10 +211,10 @@ pub fn title_levels_kmeans(
         .sorted_by(|(_, c1), (_, c2)| c2.total_cmp(c1))
         .collect();
 
-    let mut centroid_mapping = vec![-1i8; centroids_sorted.len()];
+    let mut centroid_mapping = vec![0; centroids_sorted.len()];
 
-    // Map sorted centroids to levels, merging close ones
-    let mut prev_centroid = (1, centroids_sorted[0].1);
+    // Map sorted centroids to ranks, merging close ones
+    let mut prev_centroid = (0, centroids_sorted[0].1);
     for (c_idx, c_val) in centroids_sorted.iter() {
         if *c_val < prev_centroid.1 * TITLE_MERGE_THRESHOLD {
             prev_centroid.0 += 1;
@@ -48,15 +223,104 @@ pub fn title_levels_kmeans(
         prev_centroid.1 = *c_val;
     }
 
-    for (el, assignment) in titles.iter().zip(assignments.iter()) {
-        assert!(centroid_mapping[*assignment] >= 0);
-        title_level.insert((el.page_id, el.id), centroid_mapping[*assignment] as u8);
-    }
+    let size_ranks: Vec<usize> = assignments.iter().map(|&a| centroid_mapping[a]).collect();
+    levels_from_size_ranks(titles, &size_ranks)
+}
+
+/// Assigns a level to each distinct title size, rounded to the half point, largest first.
+/// Within a size, the bold titles rank above the regular ones
+pub fn title_levels_font_size(titles: &[&Element]) -> HashMap<(PageID, ElementID), TitleLevel> {
+    let (samples, _) = title_sizes(titles);
+    let buckets: Vec<u32> = samples
+        .iter()
+        .map(|&size| size_bucket(size))
+        .sorted_by(|a, b| b.cmp(a))
+        .dedup()
+        .collect();
+    let size_ranks: Vec<usize> = samples
+        .iter()
+        .map(|&size| {
+            buckets
+                .iter()
+                .position(|&b| b == size_bucket(size))
+                .expect("bucket of a title")
+        })
+        .collect();
+    levels_from_size_ranks(titles, &size_ranks)
+}
+
+/// Assigns the title levels with `strategy`, along with the size to level mapping recorded in
+/// the document metadata
+pub(crate) fn assign_title_levels(
+    titles: &[&Element],
+    strategy: TitleLevelStrategy,
+    outline: Option<&[OutlineEntry]>,
+) -> (HashMap<(PageID, ElementID), TitleLevel>, TitleLevels) {
+    let mut title_level = match strategy {
+        TitleLevelStrategy::KMeans { k } => title_levels_kmeans(titles, k),
+        TitleLevelStrategy::FontSizeBuckets => title_levels_font_size(titles),
+        TitleLevelStrategy::OutlineFirst => title_levels_kmeans(titles, DEFAULT_TITLE_CLUSTERS),
+    };
+
+    let (samples, by_height) = title_sizes(titles);
+    let mut sizes: Vec<TitleSizeLevel> = titles
+        .iter()
+        .zip(samples)
+        .filter_map(|(title, size)| {
+            let level = *title_level.get(&(title.page_id, title.id))?;
+            Some(TitleSizeLevel {
+                size: size_bucket(size) as f32 / 2.0,
+                bold: is_bold(title),
+                level,
+            })
+        })
+        .collect();
+    sizes.sort_by(|a, b| {
+        b.size
+            .total_cmp(&a.size)
+            .then(a.bold.cmp(&b.bold).reverse())
+    });
+    sizes.dedup_by(|a, b| a.size == b.size && a.bold == b.bold);
+
+    let outline_titles = match (strategy, outline) {
+        (TitleLevelStrategy::OutlineFirst, Some(outline)) => {
+            apply_outline_levels(&mut title_level, titles, outline)
+        }
+        _ => 0,
+    };
+
+    let levels = TitleLevels {
+        strategy,
+        by_height,
+        sizes,
+        outline_titles,
+    };
+    (title_level, levels)
+}
 
-    title_level
+/// The sample furthest from the centroids, `None` when every sample is on a centroid
+fn furthest_sample(samples: &[f32], centroids: &[f32]) -> Option<f32> {
+    samples
+        .iter()
+        .map(|&x| {
+            let dist = centroids
+                .iter()
+                .map(|&c| (x - c).abs())
+                .fold(f32::INFINITY, f32::min);
+            (x, dist)
+        })
+        .filter(|&(_, dist)| dist > f32::EPSILON)
+        // The first sample wins ties, the initialization doesn't depend on the iteration order
+        .fold(None, |best: Option<(f32, f32)>, (x, dist)| match best {
+            Some((_, best_dist)) if best_dist >= dist => best,
+            _ => Some((x, dist)),
+        })
+        .map(|(x, _)| x)
 }
 
-/// Simple 1D K-Means implementation with K-Means++ initialization
+/// Simple 1D K-Means implementation. The initialization is deterministic so a document always
+/// gets the same levels: the first centroid is the largest sample, each next one the sample
+/// furthest from the centroids already chosen
 fn run_kmeans_1d(samples: &[f32], k: usize, max_iters: usize) -> (Vec<f32>, Vec<usize>) {
     let n = samples.len();
     if n == 0 || k == 0 {
@@ -68,57 +332,19 @@ fn run_kmeans_1d(samples: &[f32], k: usize, max_iters: usize) -> (Vec<f32>, Vec<
         return (samples.to_vec(), assignments);
     }
 
-    let mut rng = rand::rngs::StdRng::seed_from_u64(42);
     let mut centroids = Vec::with_capacity(k);
-
-    // K-Means++ Initialization
-    // 1. Choose one center uniformly at random from among the data points.
-    if let Some(&first) = samples.choose(&mut rng) {
-        centroids.push(first);
-    } else {
-        return (vec![0.0; k], vec![0; n]); // Should not happen given check above
-    }
-
-    // 2. For each data point x, compute D(x), the distance between x and the nearest center that has already been chosen.
-    // 3. Choose one new data point at random as a new center, using a weighted probability distribution where a point x is chosen with probability proportional to D(x)^2.
-    // 4. Repeat Steps 2 and 3 until k centers have been chosen.
-    for _ in 1..k {
-        let mut dists_sq = Vec::with_capacity(n);
-        let mut sum_dist_sq = 0.0;
-
-        for &x in samples {
-            let min_dist_sq = centroids
-                .iter()
-                .map(|&c| (x - c).powi(2))
-                .fold(f32::INFINITY, |a, b| a.min(b));
-            dists_sq.push(min_dist_sq);
-            sum_dist_sq += min_dist_sq;
-        }
-
-        if sum_dist_sq <= f32::EPSILON {
-            // All points are on top of existing centroids, pick random unpicked point or just random
-            if let Some(&next) = samples.choose(&mut rng) {
-                centroids.push(next);
-            }
-        } else {
-            let target = rng.gen::<f32>() * sum_dist_sq;
-            let mut current_sum = 0.0;
-            let mut chosen = samples[n - 1]; // Fallback
-            for (i, &d) in dists_sq.iter().enumerate() {
-                current_sum += d;
-                if current_sum >= target {
-                    chosen = samples[i];
-                    break;
-                }
-            }
-            centroids.push(chosen);
+    centroids.push(samples.iter().copied().fold(f32::NEG_INFINITY, f32::max));
+    while centroids.len() < k {
+        match furthest_sample(samples, &centroids) {
+            Some(next) => centroids.push(next),
+            // Fewer distinct samples than clusters, the extra clusters stay empty
+            None => centroids.push(centroids[0]),
         }
     }
 
     let mut assignments = vec![0; n];
-    let mut old_assignments = vec![0; n];
 
-    for _iter in 0..max_iters {
+    for iter in 0..max_iters {
         // Assignment step
         let mut changed = false;
         for (i, &x) in samples.iter().enumerate() {
@@ -137,7 +363,7 @@ fn run_kmeans_1d(samples: &[f32], k: usize, max_iters: usize) -> (Vec<f32>, Vec<
             }
         }
 
-        if !changed && _iter > 0 {
+        if !changed && iter > 0 {
             break;
         }
 
@@ -153,17 +379,228 @@ fn run_kmeans_1d(samples: &[f32], k: usize, max_iters: usize) -> (Vec<f32>, Vec<
         for j in 0..k {
             if counts[j] > 0 {
                 centroids[j] = new_centroids[j] / counts[j] as f32;
-            } else {
-                // If a cluster becomes empty, reinitialize it to a random point (or keep old, or pick point furthest from any centroid)
-                // For simplicity, just pick a random point to restart it
-                if let Some(&p) = samples.choose(&mut rng) {
-                    centroids[j] = p;
-                }
+            } else if let Some(p) = furthest_sample(samples, &centroids) {
+                // Restart the empty cluster on the worst fitted sample
+                centroids[j] = p;
             }
         }
-
-        old_assignments.copy_from_slice(&assignments);
     }
 
     (centroids, assignments)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::{ElementText, StyleSpan};
+
+    fn text_element(id: usize, text: &str, font_size: f32) -> Element {
+        Element {
+            id,
+            layout_block_id: 0,
+            text_block: ElementText {
+                text: text.to_string(),
+                links: vec![],
+                styles: vec![],
+                font_size: Some(font_size),
+                lines: vec![],
+            },
+            kind: ElementType::Text,
+            page_id: 0,
+            bbox: BBox::default(),
+            confidence: 1.0,
+        }
+    }
+
+    fn title(id: usize, font_size: f32, bold: bool) -> Element {
+        let text = format!("Title {id}");
+        let styles = if bold {
+            vec![StyleSpan {
+                text: text.clone(),
+                bold: true,
+                italic: false,
+                script: None,
+            }]
+        } else {
+            vec![]
+        };
+        Element {
+            kind: ElementType::Title,
+            text_block: ElementText {
+                styles,
+                ..text_element(id, &text, font_size).text_block
+            },
+            ..text_element(id, &text, font_size)
+        }
+    }
+
+    fn levels(titles: &[Element]) -> Vec<TitleLevel> {
+        let title_level = title_levels_kmeans(&titles.iter().collect::<Vec<_>>(), 6);
+        titles
+            .iter()
+            .map(|title| title_level[&(title.page_id, title.id)])
+            .collect()
+    }
+
+    #[test]
+    fn test_title_levels_font_sizes() {
+        let sizes = [24.0, 16.0, 11.0, 16.0, 11.0, 11.0, 24.0, 16.0, 11.0];
+        let titles: Vec<_> = sizes
+            .iter()
+            .enumerate()
+            .map(|(id, &size)| title(id, size, false))
+            .collect();
+
+        assert_eq!(levels(&titles), [1, 2, 3, 2, 3, 3, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_title_levels_bold_ranks_first() {
+        let titles: Vec<_> = [
+            (24.0, true),
+            (14.0, true),
+            (14.0, false),
+            (14.0, true),
+            (14.0, false),
+            (14.0, false),
+            (24.0, true),
+            (14.0, true),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(id, &(size, bold))| title(id, size, bold))
+        .collect();
+
+        assert_eq!(levels(&titles), [1, 2, 3, 2, 3, 3, 1, 2]);
+    }
+
+    #[test]
+    fn test_title_levels_font_size_buckets() {
+        // Too few titles to cluster, a level per font size and weight
+        let titles = [
+            title(0, 18.2, true),
+            title(1, 12.0, true),
+            title(2, 12.0, false),
+            title(3, 18.0, true),
+        ];
+        let titles: Vec<_> = titles.iter().collect();
+        assert!(title_levels_kmeans(&titles, DEFAULT_TITLE_CLUSTERS).is_empty());
+
+        let title_level = title_levels_font_size(&titles);
+        let levels: Vec<_> = titles.iter().map(|t| title_level[&(0, t.id)]).collect();
+        assert_eq!(levels, [1, 2, 3, 1]);
+    }
+
+    #[test]
+    fn test_assign_title_levels() {
+        let sizes = [24.0, 16.0, 11.0, 16.0, 11.0, 11.0, 24.0, 16.0];
+        let titles: Vec<_> = sizes
+            .iter()
+            .enumerate()
+            .map(|(id, &size)| title(id, size, false))
+            .collect();
+        let titles: Vec<_> = titles.iter().collect();
+        let outline = [OutlineEntry {
+            title: "Title 1".to_string(),
+            level: 1,
+            page_id: Some(0),
+        }];
+
+        let strategy = TitleLevelStrategy::KMeans { k: 4 };
+        let (title_level, levels) = assign_title_levels(&titles, strategy, Some(&outline));
+        // The same document always gets the same levels
+        assert_eq!(title_levels_kmeans(&titles, 4), title_level);
+        assert_eq!(title_level[&(0, 1)], 2);
+        assert_eq!(levels.strategy, strategy);
+        assert_eq!(levels.outline_titles, 0);
+        let sizes: Vec<_> = levels.sizes.iter().map(|s| (s.size, s.level)).collect();
+        assert_eq!(sizes, [(24.0, 1), (16.0, 2), (11.0, 3)]);
+
+        // The outline depth wins over the font size
+        let strategy = TitleLevelStrategy::OutlineFirst;
+        let (title_level, levels) = assign_title_levels(&titles, strategy, Some(&outline));
+        assert_eq!(title_level[&(0, 1)], 1);
+        assert_eq!(title_level[&(0, 3)], 2);
+        assert_eq!(levels.outline_titles, 1);
+    }
+
+    #[test]
+    fn test_title_level_strategy_from_str() {
+        assert_eq!(
+            "outline".parse::<TitleLevelStrategy>().unwrap(),
+            TitleLevelStrategy::OutlineFirst
+        );
+        assert_eq!(
+            "kmeans".parse::<TitleLevelStrategy>().unwrap(),
+            TitleLevelStrategy::KMeans { k: 6 }
+        );
+        assert_eq!(
+            "kmeans:3".parse::<TitleLevelStrategy>().unwrap(),
+            TitleLevelStrategy::KMeans { k: 3 }
+        );
+        assert_eq!(
+            "font-size".parse::<TitleLevelStrategy>().unwrap(),
+            TitleLevelStrategy::FontSizeBuckets
+        );
+        assert!("kmeans:0".parse::<TitleLevelStrategy>().is_err());
+        assert!("outline:2".parse::<TitleLevelStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_promote_large_text_titles() {
+        let paragraph = "The body of the document is set in a regular eleven point font and is long enough to be the most common size";
+        let mut elements = vec![
+            text_element(0, "Introduction", 16.0),
+            text_element(1, paragraph, 11.0),
+            text_element(2, paragraph, 11.0),
+            // Slightly larger text isn't a heading
+            text_element(3, "Note", 12.0),
+            // Large paragraph, e.g. a pull quote
+            text_element(4, paragraph, 16.0),
+        ];
+
+        promote_large_text_titles(&mut elements);
+
+        let kinds = elements
+            .iter()
+            .map(|e| matches!(e.kind, ElementType::Subtitle))
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, vec![true, false, false, false, false]);
+    }
+
+    #[test]
+    fn test_promote_large_text_titles_skips_figures_and_numbers() {
+        let paragraph = "The body of the document is set in a regular eleven point font and is long enough to be the most common size";
+        let figure_bbox = BBox {
+            x0: 50.0,
+            y0: 100.0,
+            x1: 300.0,
+            y1: 300.0,
+        };
+        let mut figure = text_element(0, "", 11.0);
+        figure.kind = ElementType::Image;
+        figure.bbox = figure_bbox;
+        // Figure label set in a large font
+        let mut label = text_element(1, "Revenue growth", 18.0);
+        label.bbox = BBox {
+            x0: 100.0,
+            y0: 150.0,
+            x1: 200.0,
+            y1: 170.0,
+        };
+        let mut elements = vec![
+            figure,
+            label,
+            text_element(2, paragraph, 11.0),
+            // KPI number and drop cap
+            text_element(3, "42%", 24.0),
+            text_element(4, "T", 32.0),
+        ];
+
+        promote_large_text_titles(&mut elements);
+
+        assert!(elements
+            .iter()
+            .all(|e| !matches!(e.kind, ElementType::Subtitle)));
+    }
+}
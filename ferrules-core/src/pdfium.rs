@@ -0,0 +1,115 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use pdfium_render::prelude::*;
+
+use crate::error::FerrulesError;
+
+/// Environment variable pointing to the pdfium library loaded at runtime, either the library
+/// file itself or the directory containing it
+pub const PDFIUM_PATH_ENV: &str = "FERRULES_PDFIUM_PATH";
+
+/// pdfium library the bindings were loaded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PdfiumBinding {
+    /// Library loaded from the path of `FERRULES_PDFIUM_PATH`
+    Path(PathBuf),
+    /// Library found by the system loader
+    System,
+    /// pdfium statically linked in the binary, requires the `pdfium-static` feature
+    Static,
+}
+
+impl fmt::Display for PdfiumBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PdfiumBinding::Path(path) => write!(f, "dynamic ({})", path.display()),
+            PdfiumBinding::System => write!(f, "system library"),
+            PdfiumBinding::Static => write!(f, "statically linked"),
+        }
+    }
+}
+
+/// Path of the pdfium library file: `path` itself, or the platform library name inside it
+/// when `path` is a directory
+fn library_path(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        Pdfium::pdfium_platform_library_name_at_path(path)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Falls back to the statically linked pdfium when no system library was found
+#[cfg(feature = "pdfium-static")]
+fn bind_static(
+    system_err: PdfiumError,
+) -> Result<(Box<dyn PdfiumLibraryBindings>, PdfiumBinding), FerrulesError> {
+    Pdfium::bind_to_statically_linked_library()
+        .map(|bindings| (bindings, PdfiumBinding::Static))
+        .map_err(|e| {
+            FerrulesError::PdfiumLoad(format!(
+                "no system library ({system_err:?}), statically linked library: {e:?}"
+            ))
+        })
+}
+
+#[cfg(not(feature = "pdfium-static"))]
+fn bind_static(
+    system_err: PdfiumError,
+) -> Result<(Box<dyn PdfiumLibraryBindings>, PdfiumBinding), FerrulesError> {
+    Err(FerrulesError::PdfiumLoad(format!(
+        "no system library ({system_err:?}) and ferrules was built without the `pdfium-static` feature, set {PDFIUM_PATH_ENV}"
+    )))
+}
+
+/// Binds pdfium from `path`, then the system library, then the statically linked one.
+/// An explicit path that can't be loaded is an error, it never falls back to another library.
+pub fn bind_pdfium(
+    path: Option<&Path>,
+) -> Result<(Box<dyn PdfiumLibraryBindings>, PdfiumBinding), FerrulesError> {
+    if let Some(path) = path {
+        let path = library_path(path);
+        return Pdfium::bind_to_library(&path)
+            .map(|bindings| (bindings, PdfiumBinding::Path(path.clone())))
+            .map_err(|e| FerrulesError::PdfiumLoad(format!("{}: {e:?}", path.display())));
+    }
+    match Pdfium::bind_to_system_library() {
+        Ok(bindings) => Ok((bindings, PdfiumBinding::System)),
+        Err(system_err) => bind_static(system_err),
+    }
+}
+
+/// Loads pdfium from the library of `FERRULES_PDFIUM_PATH` when it is set, otherwise from the
+/// system library or the statically linked one. Returns the binding that was used.
+pub fn load_pdfium() -> Result<(Pdfium, PdfiumBinding), FerrulesError> {
+    let path = std::env::var_os(PDFIUM_PATH_ENV).map(PathBuf::from);
+    let (bindings, binding) = bind_pdfium(path.as_deref())?;
+    tracing::debug!("pdfium bound to the {binding}");
+    Ok((Pdfium::new(bindings), binding))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_missing_library() {
+        let err = bind_pdfium(Some(Path::new("/nonexistent/libpdfium.so")))
+            .err()
+            .expect("missing library must not be bound");
+        assert!(matches!(err, FerrulesError::PdfiumLoad(_)));
+        assert!(err.to_string().contains("/nonexistent/libpdfium.so"));
+    }
+
+    #[test]
+    fn test_binding_display() {
+        assert_eq!(
+            PdfiumBinding::Path(PathBuf::from("/opt/pdfium/libpdfium.so")).to_string(),
+            "dynamic (/opt/pdfium/libpdfium.so)"
+        );
+        assert_eq!(PdfiumBinding::Static.to_string(), "statically linked");
+    }
+}
@@ -0,0 +1,347 @@
+use std::{io::Cursor, path::PathBuf};
+
+use docx_rs::{
+    AbstractNumbering, Docx, IndentLevel, Level, LevelJc, LevelText, NumberFormat, Numbering,
+    NumberingId, Paragraph, Pic, Run, RunFonts, SpecialIndentType, Start, Style, StyleType, Table,
+    TableCell, TableRow,
+};
+use regex::Regex;
+
+use crate::blocks::{Block, BlockType, ListMarker, TableBlock};
+
+use super::{html::LIST_BULLET_PATTERN, Render, Renderer};
+
+const BULLET_NUMBERING_ID: usize = 1;
+const DECIMAL_NUMBERING_ID: usize = 2;
+
+/// Nesting levels of the list numberings, the deeper items use the last one
+const LIST_LEVELS: u8 = 9;
+
+/// Font of the code listings
+const CODE_FONT: &str = "Courier New";
+
+/// EMU (English Metric Units) per pixel at 96 DPI
+const EMU_PER_PX: u32 = 9525;
+
+/// Images are scaled down to fit the text width of a letter page (6 inches)
+const MAX_IMAGE_WIDTH_EMU: u32 = 6 * 914_400;
+
+fn heading_style_id(level: u8) -> String {
+    format!("Heading{}", level.clamp(1, 6))
+}
+
+/// Font size in half-points of each heading level
+fn heading_size(level: u8) -> usize {
+    match level {
+        1 => 36,
+        2 => 32,
+        3 => 28,
+        4 => 26,
+        5 => 24,
+        _ => 22,
+    }
+}
+
+fn text_paragraph(text: &str) -> Paragraph {
+    // Merged blocks keep the line breaks of the page, a paragraph flows its text
+    let text = text.lines().collect::<Vec<_>>().join(" ");
+    Paragraph::new().add_run(Run::new().add_text(text))
+}
+
+/// List numbering indented by half an inch per level, `text` is the marker of each level
+fn list_numbering(id: usize, format: &str, text: impl Fn(usize) -> String) -> AbstractNumbering {
+    (0..LIST_LEVELS as usize).fold(AbstractNumbering::new(id), |numbering, level| {
+        numbering.add_level(
+            Level::new(
+                level,
+                Start::new(1),
+                NumberFormat::new(format),
+                LevelText::new(text(level)),
+                LevelJc::new("left"),
+            )
+            .indent(
+                Some(720 * (level as i32 + 1)),
+                Some(SpecialIndentType::Hanging(360)),
+                None,
+                None,
+            ),
+        )
+    })
+}
+
+#[derive(Debug)]
+enum DocxChild {
+    Paragraph(Paragraph),
+    Table(Table),
+}
+
+#[derive(Debug)]
+pub struct DocxRenderer {
+    children: Vec<DocxChild>,
+    img_src_path: Option<PathBuf>,
+    list_regex: Regex,
+}
+
+impl DocxRenderer {
+    pub(crate) fn new(img_src_path: Option<PathBuf>) -> Self {
+        let list_regex = Regex::new(LIST_BULLET_PATTERN).unwrap();
+
+        Self {
+            children: Vec::new(),
+            img_src_path,
+            list_regex,
+        }
+    }
+
+    pub fn finalize(self) -> anyhow::Result<Vec<u8>> {
+        let mut docx = Docx::new()
+            .add_abstract_numbering(list_numbering(BULLET_NUMBERING_ID, "bullet", |_| {
+                "•".to_owned()
+            }))
+            .add_abstract_numbering(list_numbering(DECIMAL_NUMBERING_ID, "decimal", |level| {
+                format!("%{}.", level + 1)
+            }))
+            .add_numbering(Numbering::new(BULLET_NUMBERING_ID, BULLET_NUMBERING_ID))
+            .add_numbering(Numbering::new(DECIMAL_NUMBERING_ID, DECIMAL_NUMBERING_ID));
+
+        for level in 1..=6 {
+            docx = docx.add_style(
+                Style::new(heading_style_id(level), StyleType::Paragraph)
+                    .name(format!("heading {level}"))
+                    .size(heading_size(level))
+                    .bold(),
+            );
+        }
+        for child in self.children {
+            docx = match child {
+                DocxChild::Paragraph(paragraph) => docx.add_paragraph(paragraph),
+                DocxChild::Table(table) => docx.add_table(table),
+            };
+        }
+
+        let mut buffer = Cursor::new(Vec::new());
+        docx.build().pack(&mut buffer)?;
+        Ok(buffer.into_inner())
+    }
+
+    fn image_paragraph(&self, path: &std::path::Path) -> anyhow::Result<Paragraph> {
+        let bytes = std::fs::read(path)?;
+        let (width, height) = image::image_dimensions(path)?;
+        let (mut width_emu, mut height_emu) = (width * EMU_PER_PX, height * EMU_PER_PX);
+        if width_emu > MAX_IMAGE_WIDTH_EMU {
+            height_emu = (height_emu as f64 * MAX_IMAGE_WIDTH_EMU as f64 / width_emu as f64) as u32;
+            width_emu = MAX_IMAGE_WIDTH_EMU;
+        }
+        let pic = Pic::new(&bytes).size(width_emu, height_emu);
+        Ok(Paragraph::new().add_run(Run::new().add_image(pic)))
+    }
+
+    fn table(table: &TableBlock) -> Table {
+        let rows = table
+            .rows
+            .iter()
+            .map(|row| {
+                let cells = row
+                    .cells
+                    .iter()
+                    .map(|cell| {
+                        let mut run = Run::new().add_text(cell.text.as_str());
+                        if row.is_header {
+                            run = run.bold();
+                        }
+                        let docx_cell =
+                            TableCell::new().add_paragraph(Paragraph::new().add_run(run));
+                        if cell.col_span > 1 {
+                            docx_cell.grid_span(cell.col_span as usize)
+                        } else {
+                            docx_cell
+                        }
+                    })
+                    .collect();
+                TableRow::new(cells)
+            })
+            .collect();
+        Table::new(rows)
+    }
+}
+
+impl Renderer for DocxRenderer {
+    type Ok = ();
+
+    fn render_block(&mut self, block: &Block) -> anyhow::Result<Self::Ok> {
+        match &block.kind {
+            BlockType::Title(title) => {
+                self.children.push(DocxChild::Paragraph(
+                    Paragraph::new()
+                        .add_run(Run::new().add_text(title.text.as_str()))
+                        .style(&heading_style_id(title.level)),
+                ));
+            }
+            BlockType::Header(text_block)
+            | BlockType::Footer(text_block)
+            | BlockType::TextBlock(text_block) => {
+                self.children
+                    .push(DocxChild::Paragraph(text_paragraph(&text_block.text)));
+            }
+            BlockType::FormField(form_field) => {
+                self.children
+                    .push(DocxChild::Paragraph(text_paragraph(&form_field.text())));
+            }
+            // A paragraph per line of the listing, keeping its indentation
+            BlockType::Code(code) => {
+                for line in code.text.lines() {
+                    let run = Run::new()
+                        .add_text(line)
+                        .fonts(RunFonts::new().ascii(CODE_FONT).hi_ansi(CODE_FONT));
+                    self.children
+                        .push(DocxChild::Paragraph(Paragraph::new().add_run(run)));
+                }
+            }
+            BlockType::ListBlock(list) => {
+                for item in &list.items {
+                    let clean_text = self.list_regex.replace(item.content(), "");
+                    let numbering_id = match item.marker {
+                        ListMarker::Ordered => DECIMAL_NUMBERING_ID,
+                        ListMarker::Unordered => BULLET_NUMBERING_ID,
+                    };
+                    let level = item.level.min(LIST_LEVELS - 1) as usize;
+                    self.children.push(DocxChild::Paragraph(
+                        text_paragraph(&clean_text)
+                            .numbering(NumberingId::new(numbering_id), IndentLevel::new(level)),
+                    ));
+                }
+            }
+            BlockType::Image(image_block) => {
+                if let Some(img_src_path) = &self.img_src_path {
+                    let img_path = img_src_path.join(image_block.path());
+                    match self.image_paragraph(&img_path) {
+                        Ok(paragraph) => self.children.push(DocxChild::Paragraph(paragraph)),
+                        Err(e) => {
+                            tracing::warn!("can't embed image {} in docx: {e}", img_path.display())
+                        }
+                    }
+                    if let Some(caption) = &image_block.caption {
+                        self.children.push(DocxChild::Paragraph(
+                            Paragraph::new()
+                                .add_run(Run::new().add_text(caption.as_str()).italic()),
+                        ));
+                    }
+                }
+            }
+            BlockType::Formula(formula) => {
+                if let Some(img_src_path) = &self.img_src_path {
+                    let img_path = img_src_path.join(formula.path());
+                    match self.image_paragraph(&img_path) {
+                        Ok(paragraph) => self.children.push(DocxChild::Paragraph(paragraph)),
+                        Err(e) => {
+                            tracing::warn!(
+                                "can't embed formula {} in docx: {e}",
+                                img_path.display()
+                            )
+                        }
+                    }
+                }
+            }
+            BlockType::Table(table) => {
+                if table.rows.is_empty() {
+                    tracing::warn!(
+                        "skipping table block {} without structure in docx",
+                        block.id
+                    );
+                } else {
+                    self.children.push(DocxChild::Table(Self::table(table)));
+                    if let Some(caption) = &table.caption {
+                        self.children.push(DocxChild::Paragraph(
+                            Paragraph::new()
+                                .add_run(Run::new().add_text(caption.as_str()).italic()),
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders the document blocks as a DOCX file. Images are embedded from the files written in
+/// `img_src_path` and skipped when it is `None`.
+#[tracing::instrument(skip_all)]
+pub fn to_docx<R: Render>(blocks: R, img_src_path: Option<PathBuf>) -> anyhow::Result<Vec<u8>> {
+    let mut docx_renderer = DocxRenderer::new(img_src_path);
+    blocks.render(&mut docx_renderer)?;
+    docx_renderer.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{List, ListItem, TextBlock, Title},
+        entities::{BBox, DocumentMetadata, ParsedDocument},
+        metrics::ParsingMetrics,
+    };
+    use std::time::Duration;
+
+    fn block(id: usize, kind: BlockType) -> Block {
+        let bbox = BBox {
+            x0: 0.0,
+            y0: 0.0,
+            x1: 100.0,
+            y1: 10.0,
+        };
+        Block::for_test(id, kind, 0, bbox)
+    }
+
+    #[test]
+    fn test_docx_blocks() -> anyhow::Result<()> {
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![],
+            blocks: vec![
+                block(
+                    0,
+                    BlockType::Title(Title {
+                        level: 2,
+                        text: "Introduction".to_string(),
+                    }),
+                ),
+                block(
+                    1,
+                    BlockType::TextBlock(TextBlock {
+                        text: "first line\nsecond line".to_string(),
+                    }),
+                ),
+                block(
+                    2,
+                    BlockType::ListBlock(List {
+                        items: vec![
+                            ListItem::new("• apples", BBox::default()),
+                            ListItem::new("• pears", BBox::default()),
+                        ],
+                    }),
+                ),
+                // Unstructured table: skipped instead of failing the export
+                block(3, BlockType::Table(TableBlock::default())),
+            ],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let bytes = to_docx(&doc, None)?;
+        assert!(bytes.starts_with(b"PK"));
+
+        let json = docx_rs::read_docx(&bytes)?.json();
+        assert!(json.contains("Heading2"));
+        assert!(json.contains("Introduction"));
+        assert!(json.contains("first line second line"));
+        assert!(json.contains("pears"));
+        assert!(!json.contains("• pears"));
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt::Write;
+
+use crate::entities::{BBox, Page, ParsedDocument, TextDirection};
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[inline]
+fn hocr_bbox(bbox: &BBox) -> String {
+    format!(
+        "bbox {} {} {} {}",
+        bbox.x0.round() as i64,
+        bbox.y0.round() as i64,
+        bbox.x1.round() as i64,
+        bbox.y1.round() as i64
+    )
+}
+
+fn write_page(out: &mut String, page: &Page) -> std::fmt::Result {
+    let page_num = page.id + 1;
+    writeln!(
+        out,
+        "  <div class=\"ocr_page\" id=\"page_{page_num}\" title=\"{}; ppageno {}\">",
+        hocr_bbox(&BBox {
+            x0: 0f32,
+            y0: 0f32,
+            x1: page.width,
+            y1: page.height,
+        }),
+        page.id
+    )?;
+    for (line_idx, line) in page
+        .lines
+        .iter()
+        .filter(|l| !l.text.trim().is_empty())
+        .enumerate()
+    {
+        let line_id = format!("{page_num}_{}", line_idx + 1);
+        let dir = match line.direction {
+            TextDirection::Ltr => "",
+            TextDirection::Rtl => " dir=\"rtl\"",
+        };
+        write!(
+            out,
+            "   <span class=\"ocr_line\" id=\"line_{line_id}\"{dir} title=\"{}\">",
+            hocr_bbox(&line.bbox)
+        )?;
+        for (word_idx, word) in line.words().iter().enumerate() {
+            if word_idx > 0 {
+                out.push(' ');
+            }
+            write!(
+                out,
+                "<span class=\"ocrx_word\" id=\"word_{line_id}_{}\" title=\"{}\">{}</span>",
+                word_idx + 1,
+                hocr_bbox(&word.bbox),
+                escape(&word.text)
+            )?;
+        }
+        writeln!(out, "</span>")?;
+    }
+    writeln!(out, "  </div>")
+}
+
+/// Renders the document text lines in the hOCR format, with one `ocr_page` per page and
+/// `ocr_line`/`ocrx_word` elements carrying their bbox in page coordinates. Right-to-left lines
+/// are marked with `dir="rtl"`.
+#[tracing::instrument(skip_all)]
+pub fn to_hocr(doc: &ParsedDocument, page_title: &str) -> anyhow::Result<String> {
+    let mut out = String::new();
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(
+        out,
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\">"
+    )?;
+    writeln!(
+        out,
+        "<html xmlns=\"http://www.w3.org/1999/xhtml\" xml:lang=\"en\" lang=\"en\">"
+    )?;
+    writeln!(out, " <head>")?;
+    writeln!(out, "  <title>{}</title>", escape(page_title))?;
+    writeln!(
+        out,
+        "  <meta http-equiv=\"Content-Type\" content=\"text/html;charset=utf-8\"/>"
+    )?;
+    writeln!(out, "  <meta name=\"ocr-system\" content=\"ferrules\"/>")?;
+    writeln!(
+        out,
+        "  <meta name=\"ocr-capabilities\" content=\"ocr_page ocr_line ocrx_word\"/>"
+    )?;
+    writeln!(out, " </head>")?;
+    writeln!(out, " <body>")?;
+    for page in &doc.pages {
+        write_page(&mut out, page)?;
+    }
+    writeln!(out, " </body>")?;
+    writeln!(out, "</html>")?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entities::{DocumentMetadata, Line},
+        metrics::ParsingMetrics,
+    };
+    use image::DynamicImage;
+    use std::time::Duration;
+
+    #[test]
+    fn test_hocr_page_lines_words() -> anyhow::Result<()> {
+        let line = Line {
+            text: "Hello <world>".to_string(),
+            bbox: BBox {
+                x0: 10.0,
+                y0: 20.0,
+                x1: 140.0,
+                y1: 30.0,
+            },
+            rotation: 0.0,
+            spans: vec![],
+            confidence: None,
+            links: vec![],
+            direction: TextDirection::Ltr,
+        };
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![Page {
+                id: 0,
+                width: 600.0,
+                height: 800.0,
+                image: DynamicImage::new_rgb8(1, 1),
+                rotation: 0,
+                need_ocr: true,
+                lines: vec![line],
+                ocr_lines: vec![],
+                layout: vec![],
+                embedded_images: vec![],
+            }],
+            blocks: vec![],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let hocr = to_hocr(&doc, "doc")?;
+        assert!(hocr.contains(
+            "<div class=\"ocr_page\" id=\"page_1\" title=\"bbox 0 0 600 800; ppageno 0\">"
+        ));
+        assert!(
+            hocr.contains("<span class=\"ocr_line\" id=\"line_1_1\" title=\"bbox 10 20 140 30\">")
+        );
+        // 13 chars of 10pt each: "Hello" covers the first 5
+        assert!(hocr.contains(
+            "<span class=\"ocrx_word\" id=\"word_1_1_1\" title=\"bbox 10 20 60 30\">Hello</span>"
+        ));
+        assert!(hocr.contains(
+            "<span class=\"ocrx_word\" id=\"word_1_1_2\" title=\"bbox 70 20 140 30\">&lt;world&gt;</span>"
+        ));
+        Ok(())
+    }
+}
@@ -1,18 +1,129 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, io::Cursor, path::PathBuf};
 
+use base64::{engine::general_purpose::STANDARD, Engine};
 use build_html::{Html, HtmlChild, HtmlContainer, HtmlElement, HtmlPage, HtmlTag};
 use regex::Regex;
 
-use crate::blocks::{Block, BlockType};
+use crate::{
+    blocks::{Block, BlockType, ListItem, ListMarker},
+    entities::{LinkSpan, LinkTarget, ParsedDocument, ScriptPosition, StyleSpan},
+    utils::block_image,
+};
 
 use super::{Render, Renderer};
 
-static LIST_BULLET_PATTERN: &str = r"(^|[\n ]|<[^>]*>)[•●○ഠ ം◦■▪▫–—-]( )";
+pub(super) static LIST_BULLET_PATTERN: &str = r"(^|[\n ]|<[^>]*>)[•●○ഠ ം◦■▪▫–—-]( )";
+
+/// Wraps the first occurrence of each link text, after the previous link, in an `<a>` tag.
+/// Links to pages of the document have no anchor in the html and are kept as plain text
+fn with_links(text: &str, links: &[LinkSpan]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    for link in links {
+        let LinkTarget::Uri(uri) = &link.target else {
+            continue;
+        };
+        let Some(start) = rest.find(link.text.as_str()) else {
+            continue;
+        };
+        let end = start + link.text.len();
+        out.push_str(&rest[..start]);
+        out.push_str(&format!(
+            "<a href=\"{}\">{}</a>",
+            uri.replace('"', "%22"),
+            &rest[start..end]
+        ));
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// First occurrence of a superscript or subscript run in `text` that isn't part of a number,
+/// so that a footnote reference `1` isn't matched in a year like `2019`
+fn find_script(text: &str, script: &str) -> Option<usize> {
+    let is_digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+    text.match_indices(script).map(|(idx, _)| idx).find(|&idx| {
+        !is_digit(text[..idx].chars().next_back())
+            && !is_digit(text[idx + script.len()..].chars().next())
+    })
+}
+
+/// Wraps the first occurrence of each styled run, after the previous run, in `<strong>`,
+/// `<em>`, `<sup>` and `<sub>` tags
+fn with_styles(text: &str, styles: &[StyleSpan]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    for style in styles {
+        let start = match style.script {
+            Some(_) => find_script(rest, &style.text),
+            None => rest.find(style.text.as_str()),
+        };
+        let Some(start) = start else {
+            continue;
+        };
+        let end = start + style.text.len();
+        let mut styled = rest[start..end].to_owned();
+        match style.script {
+            Some(ScriptPosition::Superscript) => styled = format!("<sup>{styled}</sup>"),
+            Some(ScriptPosition::Subscript) => styled = format!("<sub>{styled}</sub>"),
+            None => {}
+        }
+        if style.italic {
+            styled = format!("<em>{styled}</em>");
+        }
+        if style.bold {
+            styled = format!("<strong>{styled}</strong>");
+        }
+        out.push_str(&rest[..start]);
+        out.push_str(&styled);
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Escapes the characters of a code listing read as markup, its line breaks are kept
+fn escape_code(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// `<ol>` or `<ul>` of the items, following the marker of the first one. The items deeper than
+/// the previous one are a nested list inside its `<li>`
+fn nested_list(items: &[ListItem], item_html: &impl Fn(&ListItem) -> String) -> HtmlElement {
+    let tag = match items.first().map(|item| item.marker) {
+        Some(ListMarker::Ordered) => HtmlTag::OrderedList,
+        _ => HtmlTag::UnorderedList,
+    };
+    let mut list = HtmlElement::new(tag);
+    let mut idx = 0;
+    while idx < items.len() {
+        let item = &items[idx];
+        let mut li =
+            HtmlElement::new(HtmlTag::ListElement).with_child(item_html(item).as_str().into());
+        let end = items[idx + 1..]
+            .iter()
+            .position(|next| next.level <= item.level)
+            .map_or(items.len(), |offset| idx + 1 + offset);
+        if end > idx + 1 {
+            li.add_child(nested_list(&items[idx + 1..end], item_html).into());
+        }
+        list.add_child(li.into());
+        idx = end;
+    }
+    list
+}
 
 #[derive(Debug)]
 pub struct HTMLRenderer {
     root_element: HtmlElement,
     img_src_path: Option<PathBuf>,
+    /// `data:` URIs of the images embedded in the page, by image or formula block id
+    inline_images: HashMap<usize, String>,
+    /// Writes the image captions in a `<figcaption>`, besides the `alt` text of the image
+    figcaptions: bool,
     list_regex: Regex,
 }
 
@@ -25,9 +136,29 @@ impl HTMLRenderer {
         Self {
             root_element: root,
             img_src_path,
+            inline_images: HashMap::new(),
+            figcaptions: true,
             list_regex,
         }
     }
+
+    pub(crate) fn with_inline_images(mut self, inline_images: HashMap<usize, String>) -> Self {
+        self.inline_images = inline_images;
+        self
+    }
+
+    /// Only keeps the image captions in their `alt` text
+    pub(super) fn without_figcaptions(mut self) -> Self {
+        self.figcaptions = false;
+        self
+    }
+
+    /// Adds an image caption as an emphasized paragraph
+    pub(super) fn push_caption_paragraph(&mut self, caption: &str) {
+        self.root_element
+            .add_child(HtmlChild::Raw(format!("<p><em>{caption}</em></p>")));
+    }
+
     pub fn finalize(self, page_title: &str) -> String {
         HtmlPage::new()
             .with_title(page_title)
@@ -39,6 +170,8 @@ impl HTMLRenderer {
         block: &Block,
         container: &mut HtmlElement,
         img_src_path: Option<&PathBuf>,
+        inline_images: &HashMap<usize, String>,
+        figcaptions: bool,
         list_regex: &Regex,
     ) -> anyhow::Result<()> {
         match &block.kind {
@@ -53,51 +186,61 @@ impl HTMLRenderer {
                     _ => HtmlTag::Heading6,
                 };
                 let el = HtmlElement::new(tag)
-                    .with_child(title.text.as_str().into())
+                    .with_child(with_links(&title.text, &block.links).as_str().into())
                     .into();
                 container.add_child(el);
             }
             BlockType::Header(text_block) => {
                 let el = HtmlElement::new(HtmlTag::Header)
-                    .with_child(text_block.text.as_str().into())
+                    .with_child(with_links(&text_block.text, &block.links).as_str().into())
                     .into();
                 container.add_child(el);
             }
             BlockType::Footer(text_block) => {
                 let el = HtmlElement::new(HtmlTag::Footer)
-                    .with_child(text_block.text.as_str().into())
+                    .with_child(with_links(&text_block.text, &block.links).as_str().into())
                     .into();
                 container.add_child(el);
             }
             BlockType::ListBlock(list) => {
-                let mut ul = HtmlElement::new(HtmlTag::UnorderedList);
-                for item in &list.items {
-                    let clean_text = list_regex.replace(item, "").into_owned();
-                    let li = HtmlElement::new(HtmlTag::ListElement)
-                        .with_child(clean_text.as_str().into())
-                        .into();
-                    ul.add_child(li);
-                }
-                container.add_child(ul.into());
+                let item_html = |item: &ListItem| {
+                    let clean_text = list_regex.replace(item.content(), "");
+                    with_links(&with_styles(&clean_text, &block.styles), &block.links)
+                };
+                container.add_child(nested_list(&list.items, &item_html).into());
             }
             BlockType::TextBlock(text_block) => {
                 let el = HtmlElement::new(HtmlTag::ParagraphText)
-                    .with_child(text_block.text.as_str().into())
+                    .with_child(
+                        with_links(&with_styles(&text_block.text, &block.styles), &block.links)
+                            .as_str()
+                            .into(),
+                    )
                     .into();
                 container.add_child(el);
             }
             BlockType::Image(image_block) => {
-                if let Some(img_src_path) = img_src_path {
+                let img_src = match inline_images.get(&block.id) {
+                    Some(data_uri) => Some(data_uri.clone()),
+                    None => img_src_path.map(|img_src_path| {
+                        img_src_path
+                            .join(image_block.path())
+                            .to_str()
+                            .unwrap()
+                            .to_owned()
+                    }),
+                };
+                if let Some(img_src) = img_src {
                     let mut figure = HtmlElement::new(HtmlTag::Figure);
-                    let img_src = img_src_path
-                        .join(image_block.path())
-                        .to_str()
-                        .unwrap()
-                        .to_owned();
-                    let img = HtmlElement::new(HtmlTag::Image).with_image(img_src, "");
+                    let alt = image_block
+                        .caption
+                        .as_deref()
+                        .unwrap_or_default()
+                        .replace('"', "&quot;");
+                    let img = HtmlElement::new(HtmlTag::Image).with_image(img_src, alt);
                     figure.add_child(img.into());
 
-                    if let Some(caption) = &image_block.caption {
+                    if let Some(caption) = image_block.caption.as_ref().filter(|_| figcaptions) {
                         let figcaption = HtmlElement::new(HtmlTag::Figcaption)
                             .with_child(caption.as_str().into())
                             .into();
@@ -136,6 +279,44 @@ impl HTMLRenderer {
                 table_html.push_str("</table>");
                 container.add_child(HtmlChild::Raw(table_html));
             }
+            BlockType::FormField(form_field) => {
+                let el = HtmlElement::new(HtmlTag::ParagraphText)
+                    .with_child(form_field.text().as_str().into())
+                    .into();
+                container.add_child(el);
+            }
+            // The garbled text of the equation is only kept in the json output
+            BlockType::Formula(formula) => {
+                let img_src = match inline_images.get(&block.id) {
+                    Some(data_uri) => Some(data_uri.clone()),
+                    None => img_src_path.map(|img_src_path| {
+                        img_src_path
+                            .join(formula.path())
+                            .to_str()
+                            .unwrap()
+                            .to_owned()
+                    }),
+                };
+                if let Some(img_src) = img_src {
+                    let img = HtmlElement::new(HtmlTag::Image).with_image(img_src, "formula");
+                    container.add_child(
+                        HtmlElement::new(HtmlTag::Figure)
+                            .with_child(img.into())
+                            .into(),
+                    );
+                }
+            }
+            BlockType::Code(code) => {
+                let class = code
+                    .language
+                    .as_ref()
+                    .map(|language| format!(" class=\"language-{}\"", language.replace('"', "")))
+                    .unwrap_or_default();
+                container.add_child(HtmlChild::Raw(format!(
+                    "<pre><code{class}>{}</code></pre>",
+                    escape_code(&code.text)
+                )));
+            }
         }
         Ok(())
     }
@@ -149,6 +330,8 @@ impl Renderer for HTMLRenderer {
             block,
             &mut self.root_element,
             self.img_src_path.as_ref(),
+            &self.inline_images,
+            self.figcaptions,
             &self.list_regex,
         )
     }
@@ -164,3 +347,177 @@ pub fn to_html<R: Render>(
     blocks.render(&mut html_renderer)?;
     Ok(html_renderer.finalize(page_title))
 }
+
+/// Renders the document as a single self-contained html page: the image and formula blocks are
+/// cropped from the page rasters, with `image_padding` points around them, and embedded as
+/// base64 PNG `data:` URIs.
+#[tracing::instrument(skip_all)]
+pub fn to_html_inline(
+    doc: &ParsedDocument,
+    page_title: &str,
+    image_padding: u32,
+) -> anyhow::Result<String> {
+    let mut inline_images = HashMap::new();
+    for block in &doc.blocks {
+        if !matches!(block.kind, BlockType::Image(_) | BlockType::Formula(_)) {
+            continue;
+        }
+        if let Some(crop) = block_image(doc, block, image_padding) {
+            let mut png = Cursor::new(Vec::new());
+            crop.write_to(&mut png, image::ImageFormat::Png)?;
+            let data_uri = format!("data:image/png;base64,{}", STANDARD.encode(png.get_ref()));
+            inline_images.insert(block.id, data_uri);
+        }
+    }
+
+    let mut html_renderer = HTMLRenderer::new(None).with_inline_images(inline_images);
+    doc.render(&mut html_renderer)?;
+    Ok(html_renderer.finalize(page_title))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::ImageBlock,
+        entities::{BBox, DocumentMetadata, Page},
+        metrics::ParsingMetrics,
+        utils::DEFAULT_IMAGE_PADDING,
+    };
+    use image::{DynamicImage, Rgb, RgbImage};
+    use std::time::Duration;
+
+    #[test]
+    fn test_html_inline_images() -> anyhow::Result<()> {
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![Page {
+                id: 0,
+                width: 100.0,
+                height: 100.0,
+                image: DynamicImage::ImageRgb8(RgbImage::from_pixel(100, 100, Rgb([255, 0, 0]))),
+                rotation: 0,
+                need_ocr: false,
+                lines: vec![],
+                ocr_lines: vec![],
+                layout: vec![],
+                embedded_images: vec![],
+            }],
+            blocks: vec![Block::for_test(
+                0,
+                BlockType::Image(ImageBlock {
+                    id: 3,
+                    caption: Some("figure".to_string()),
+                    embedded: None,
+                }),
+                0,
+                BBox {
+                    x0: 20.0,
+                    y0: 20.0,
+                    x1: 60.0,
+                    y1: 40.0,
+                },
+            )],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let html = to_html_inline(&doc, "doc", DEFAULT_IMAGE_PADDING)?;
+        assert!(!html.contains("img_3.png"));
+        let start = html.find("data:image/png;base64,").expect("inline image") + 22;
+        let end = start + html[start..].find('"').unwrap();
+        let img = image::load_from_memory(&STANDARD.decode(&html[start..end])?)?;
+        // The bbox plus the crop padding on each side
+        assert_eq!((img.width(), img.height()), (50, 30));
+
+        // The multi-file mode still references the image files
+        let html = to_html(&doc, "doc", Some(PathBuf::from("figures")))?;
+        assert!(html.contains("figures/img_3.png"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_list() {
+        let item = |text: &str, level| ListItem {
+            level,
+            ..ListItem::new(text, BBox::default())
+        };
+        let items = vec![
+            item("• Fruits", 0),
+            item("1. apples", 1),
+            item("2. pears", 1),
+            item("◦ green", 2),
+            item("• Vegetables", 0),
+        ];
+        let list = nested_list(&items, &|item: &ListItem| item.content().to_owned());
+        assert_eq!(
+            list.to_html_string(),
+            "<ul><li>Fruits<ol><li>apples</li><li>pears<ul><li>green</li></ul></li></ol></li>\
+             <li>Vegetables</li></ul>"
+        );
+    }
+
+    #[test]
+    fn test_with_links() {
+        let links = vec![
+            LinkSpan {
+                text: "here".to_string(),
+                target: LinkTarget::Uri("https://example.com".to_string()),
+            },
+            LinkSpan {
+                text: "page 2".to_string(),
+                target: LinkTarget::PageId(1),
+            },
+            LinkSpan {
+                text: "here".to_string(),
+                target: LinkTarget::Uri("https://example.org".to_string()),
+            },
+        ];
+        assert_eq!(
+            with_links("Click here, see page 2 or here.", &links),
+            "Click <a href=\"https://example.com\">here</a>, see page 2 or \
+             <a href=\"https://example.org\">here</a>."
+        );
+        assert_eq!(with_links("no links", &[]), "no links");
+    }
+
+    #[test]
+    fn test_with_styles() {
+        let style = |text: &str, bold, italic| StyleSpan {
+            text: text.to_string(),
+            bold,
+            italic,
+            script: None,
+        };
+        let styles = vec![
+            style("Note:", true, false),
+            style("not", false, true),
+            style("both", true, true),
+        ];
+        assert_eq!(
+            with_styles("Note: do not mix both styles.", &styles),
+            "<strong>Note:</strong> do <em>not</em> mix <strong><em>both</em></strong> styles."
+        );
+
+        let script = |text: &str, script| StyleSpan {
+            text: text.to_string(),
+            bold: false,
+            italic: false,
+            script: Some(script),
+        };
+        let styles = vec![
+            script("1", ScriptPosition::Superscript),
+            script("2", ScriptPosition::Subscript),
+        ];
+        assert_eq!(
+            with_styles("Founded in 2019, the lab1 studies H2O.", &styles),
+            "Founded in 2019, the lab<sup>1</sup> studies H<sub>2</sub>O."
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::{
+    blocks::{Block, BlockType},
+    entities::{BBox, PageID},
+};
+
+use super::{Render, Renderer};
+
+/// One line of the JSON Lines output
+#[derive(Debug, Serialize)]
+struct BlockRecord<'a> {
+    doc_name: &'a str,
+    block_id: usize,
+    kind: &'static str,
+    pages_id: &'a [PageID],
+    bbox: &'a BBox,
+    confidence: f32,
+    text: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    repeated: bool,
+}
+
+fn block_kind(kind: &BlockType) -> &'static str {
+    // Same names as the `block_type` tag of the json output
+    match kind {
+        BlockType::Header(_) => "Header",
+        BlockType::Footer(_) => "Footer",
+        BlockType::Title(_) => "Title",
+        BlockType::ListBlock(_) => "ListBlock",
+        BlockType::TextBlock(_) => "TextBlock",
+        BlockType::Image(_) => "Image",
+        BlockType::Table(_) => "Table",
+        BlockType::FormField(_) => "FormField",
+        BlockType::Formula(_) => "Formula",
+        BlockType::Code(_) => "Code",
+    }
+}
+
+fn block_text(kind: &BlockType) -> String {
+    match kind {
+        BlockType::Header(text_block)
+        | BlockType::Footer(text_block)
+        | BlockType::TextBlock(text_block) => text_block.text.clone(),
+        BlockType::Title(title) => title.text.clone(),
+        BlockType::ListBlock(list) => list.text(),
+        BlockType::Image(image_block) => image_block.caption.clone().unwrap_or_default(),
+        BlockType::Table(table) => table
+            .rows
+            .iter()
+            .map(|row| {
+                row.cells
+                    .iter()
+                    .map(|cell| cell.text.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\t")
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        BlockType::FormField(form_field) => form_field.text(),
+        BlockType::Formula(formula) => formula.raw_text.clone(),
+        BlockType::Code(code) => code.text.clone(),
+    }
+}
+
+/// Writes each block as a single line JSON object
+#[derive(Debug)]
+pub struct JsonlRenderer<'a, W: Write> {
+    doc_name: &'a str,
+    writer: W,
+}
+
+impl<'a, W: Write> JsonlRenderer<'a, W> {
+    pub fn new(doc_name: &'a str, writer: W) -> Self {
+        Self { doc_name, writer }
+    }
+
+    pub fn finalize(mut self) -> anyhow::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Renderer for JsonlRenderer<'_, W> {
+    type Ok = ();
+
+    fn render_block(&mut self, block: &Block) -> anyhow::Result<Self::Ok> {
+        let record = BlockRecord {
+            doc_name: self.doc_name,
+            block_id: block.id,
+            kind: block_kind(&block.kind),
+            pages_id: &block.pages_id,
+            bbox: &block.bbox,
+            confidence: block.confidence,
+            text: block_text(&block.kind),
+            repeated: block.repeated,
+        };
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn render_repeated(&self) -> bool {
+        true
+    }
+}
+
+/// Writes the document blocks in the JSON Lines format, one block per line
+#[tracing::instrument(skip_all)]
+pub fn to_jsonl<R: Render, W: Write>(blocks: R, doc_name: &str, writer: W) -> anyhow::Result<W> {
+    let mut jsonl_renderer = JsonlRenderer::new(doc_name, writer);
+    blocks.render(&mut jsonl_renderer)?;
+    jsonl_renderer.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{List, ListItem, TextBlock, Title},
+        entities::{DocumentMetadata, ParsedDocument},
+        metrics::ParsingMetrics,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn test_jsonl_block_per_line() -> anyhow::Result<()> {
+        let bbox = BBox {
+            x0: 10.0,
+            y0: 20.0,
+            x1: 200.0,
+            y1: 40.0,
+        };
+        let doc = ParsedDocument {
+            doc_name: "report".to_string(),
+            pages: vec![],
+            blocks: vec![
+                Block::for_test(
+                    0,
+                    BlockType::Title(Title {
+                        level: 1,
+                        text: "Summary".to_string(),
+                    }),
+                    0,
+                    bbox.clone(),
+                ),
+                Block {
+                    pages_id: vec![0, 1],
+                    ..Block::for_test(
+                        1,
+                        BlockType::TextBlock(TextBlock {
+                            text: "first\nsecond".to_string(),
+                        }),
+                        0,
+                        bbox.clone(),
+                    )
+                },
+                Block::for_test(
+                    2,
+                    BlockType::ListBlock(List {
+                        items: vec![
+                            ListItem::new("a", BBox::default()),
+                            ListItem::new("b", BBox::default()),
+                        ],
+                    }),
+                    1,
+                    bbox,
+                ),
+            ],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let out = String::from_utf8(to_jsonl(&doc, &doc.doc_name, Vec::new())?)?;
+        let records = out
+            .lines()
+            .map(serde_json::from_str::<serde_json::Value>)
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0]["doc_name"], "report");
+        assert_eq!(records[0]["kind"], "Title");
+        assert_eq!(records[0]["text"], "Summary");
+        assert_eq!(records[1]["block_id"], 1);
+        assert_eq!(records[1]["pages_id"], serde_json::json!([0, 1]));
+        assert_eq!(records[1]["text"], "first\nsecond");
+        assert_eq!(records[1]["bbox"]["x1"], 200.0);
+        assert_eq!(records[1]["confidence"], 1.0);
+        assert_eq!(records[2]["kind"], "ListBlock");
+        assert_eq!(records[2]["text"], "a\nb");
+        Ok(())
+    }
+}
@@ -1,8 +1,13 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
-use html2md::parse_html;
+use html2md::{
+    parse_html_custom, Handle, NodeData, StructuredPrinter, TagHandler, TagHandlerFactory,
+};
 
-use crate::blocks::Block;
+use crate::{
+    blocks::{Block, BlockType, TableBlock, TableCell, TableRow},
+    entities::{DocumentInfo, PageID, ParsedDocument},
+};
 
 use super::{html::HTMLRenderer, Render, Renderer};
 
@@ -13,12 +18,122 @@ pub struct MarkdownRender {
 
 impl MarkdownRender {
     pub(crate) fn new(img_src_path: Option<PathBuf>) -> Self {
-        let html_renderer = HTMLRenderer::new(img_src_path);
+        let html_renderer = HTMLRenderer::new(img_src_path).without_figcaptions();
         Self { html_renderer }
     }
+    /// Italic paragraph listing the next pages of a block spanning several pages, numbered
+    /// from 1
+    fn push_continuation_note(&mut self, next_pages_id: &[PageID]) {
+        let pages = next_pages_id
+            .iter()
+            .map(|page_id| (page_id + 1).to_string())
+            .collect::<Vec<_>>();
+        let note = if pages.len() == 1 {
+            format!("Continues on page {}", pages[0])
+        } else {
+            format!("Continues on pages {}", pages.join(", "))
+        };
+        self.html_renderer.push_caption_paragraph(&note);
+    }
+
     pub fn finalize(self, page_title: &str) -> String {
         let page = self.html_renderer.finalize(page_title);
-        parse_html(&page)
+        let mut handlers: HashMap<String, Box<dyn TagHandlerFactory>> = HashMap::new();
+        handlers.insert("pre".to_owned(), Box::new(FencedCodeFactory));
+        parse_html_custom(&page, &handlers)
+    }
+}
+
+/// Language of the `language-x` class of the `<code>` element of a `<pre>` block
+fn code_language(pre: &Handle) -> Option<String> {
+    pre.children
+        .borrow()
+        .iter()
+        .find_map(|child| match &child.data {
+            NodeData::Element { name, attrs, .. } if &*name.local == "code" => {
+                attrs.borrow().iter().find_map(|attr| {
+                    (&*attr.name.local == "class")
+                        .then(|| attr.value.strip_prefix("language-").map(str::to_owned))
+                        .flatten()
+                })
+            }
+            _ => None,
+        })
+}
+
+/// Writes the `<pre>` blocks as fenced code blocks tagged with their language, html2md leaves
+/// the fence untagged
+struct FencedCodeFactory;
+
+impl TagHandlerFactory for FencedCodeFactory {
+    fn instantiate(&self) -> Box<dyn TagHandler> {
+        Box::new(FencedCodeHandler)
+    }
+}
+
+struct FencedCodeHandler;
+
+impl TagHandler for FencedCodeHandler {
+    fn handle(&mut self, tag: &Handle, printer: &mut StructuredPrinter) {
+        let language = code_language(tag).unwrap_or_default();
+        printer.insert_newline();
+        printer.append_str(&format!("\n```{language}\n"));
+    }
+
+    fn after_handle(&mut self, printer: &mut StructuredPrinter) {
+        printer.append_str("\n```\n");
+        printer.insert_newline();
+    }
+}
+
+/// Copy of the table where the cells spanning several rows or columns are followed by empty
+/// cells, markdown tables have no merged cells. Spans past the last row are cut
+fn expand_table_spans(table: &TableBlock) -> TableBlock {
+    let row_count = table.rows.len();
+    let mut grid: Vec<Vec<Option<TableCell>>> = vec![Vec::new(); row_count];
+    for (row_idx, row) in table.rows.iter().enumerate() {
+        let mut col = 0;
+        for cell in &row.cells {
+            // Skip the positions covered by the cells of the rows above
+            while grid[row_idx].get(col).is_some_and(Option::is_some) {
+                col += 1;
+            }
+            let (row_span, col_span) =
+                (cell.row_span.max(1) as usize, cell.col_span.max(1) as usize);
+            for (r, grid_row) in grid.iter_mut().enumerate().skip(row_idx).take(row_span) {
+                if grid_row.len() < col + col_span {
+                    grid_row.resize(col + col_span, None);
+                }
+                for (c, slot) in grid_row[col..col + col_span].iter_mut().enumerate() {
+                    let text = if r == row_idx && c == 0 {
+                        cell.text.clone()
+                    } else {
+                        String::new()
+                    };
+                    *slot = Some(TableCell {
+                        text,
+                        row_span: 1,
+                        col_span: 1,
+                        ..cell.clone()
+                    });
+                }
+            }
+            col += col_span;
+        }
+    }
+    debug_assert_eq!(grid.len(), row_count);
+    TableBlock {
+        caption: None,
+        rows: table
+            .rows
+            .iter()
+            .zip(grid)
+            .map(|(row, cells)| TableRow {
+                cells: cells.into_iter().map(Option::unwrap_or_default).collect(),
+                ..row.clone()
+            })
+            .collect(),
+        ..table.clone()
     }
 }
 
@@ -26,7 +141,29 @@ impl Renderer for MarkdownRender {
     type Ok = ();
 
     fn render_block(&mut self, block: &Block) -> anyhow::Result<Self::Ok> {
-        self.html_renderer.render_block(block)
+        match &block.kind {
+            // Markdown has no figure caption: the caption is the alt text of the image and is
+            // written in italic under it
+            BlockType::Image(image_block) => {
+                self.html_renderer.render_block(block)?;
+                if let Some(caption) = &image_block.caption {
+                    self.html_renderer.push_caption_paragraph(caption);
+                }
+                Ok(())
+            }
+            // The caption of a table is lost in the conversion to markdown, it is written in
+            // italic under the table
+            BlockType::Table(table) => {
+                let mut expanded = block.clone();
+                expanded.kind = BlockType::Table(expand_table_spans(table));
+                self.html_renderer.render_block(&expanded)?;
+                if let Some(caption) = &table.caption {
+                    self.html_renderer.push_caption_paragraph(caption);
+                }
+                Ok(())
+            }
+            _ => self.html_renderer.render_block(block),
+        }
     }
 }
 
@@ -40,3 +177,455 @@ pub fn to_markdown<R: Render>(
     blocks.render(&mut html_renderer)?;
     Ok(html_renderer.finalize(page_title))
 }
+
+/// YAML front matter with the entries of the PDF information dictionary, empty if it has none.
+/// Values are written as JSON strings, which are valid YAML double-quoted scalars.
+fn front_matter(info: &DocumentInfo) -> anyhow::Result<String> {
+    let entries = [
+        ("title", &info.title),
+        ("author", &info.author),
+        ("subject", &info.subject),
+        ("keywords", &info.keywords),
+        ("creator", &info.creator),
+        ("producer", &info.producer),
+        ("creation_date", &info.creation_date),
+        ("modification_date", &info.modification_date),
+    ];
+    let mut out = String::new();
+    for (key, value) in entries {
+        if let Some(value) = value {
+            out.push_str(&format!("{key}: {}\n", serde_json::to_string(value)?));
+        }
+    }
+    if out.is_empty() {
+        return Ok(out);
+    }
+    Ok(format!("---\n{out}---\n\n"))
+}
+
+/// Renders the document as markdown, preceded by its metadata as a YAML front matter
+pub fn to_markdown_document(
+    doc: &ParsedDocument,
+    img_src_path: Option<PathBuf>,
+) -> anyhow::Result<String> {
+    let markdown = to_markdown(doc, &doc.doc_name, img_src_path)?;
+    Ok(front_matter(&doc.metadata.info)? + &markdown)
+}
+
+/// Renders the blocks of each page as a separate markdown document, for page aligned chunks,
+/// sorted by page id. A block spanning several pages is rendered with its first page, followed
+/// by a note listing the pages it continues on. The parsed pages without blocks get an empty
+/// document
+pub fn to_markdown_pages(
+    doc: &ParsedDocument,
+    img_src_path: Option<PathBuf>,
+) -> anyhow::Result<Vec<(PageID, String)>> {
+    let mut page_ids: Vec<PageID> = doc.pages.iter().map(|page| page.id).collect();
+    page_ids.extend(
+        doc.blocks
+            .iter()
+            .filter_map(|b| b.pages_id.first().copied()),
+    );
+    page_ids.sort_unstable();
+    page_ids.dedup();
+
+    page_ids
+        .into_iter()
+        .map(|page_id| {
+            let mut renderer = MarkdownRender::new(img_src_path.clone());
+            for block in doc
+                .blocks
+                .iter()
+                .filter(|b| b.pages_id.first() == Some(&page_id))
+            {
+                if block.repeated && !renderer.render_repeated() {
+                    continue;
+                }
+                renderer.render_block(block)?;
+                if block.pages_id.len() > 1 {
+                    renderer.push_continuation_note(&block.pages_id[1..]);
+                }
+            }
+            let page_title = format!("{} - page {}", doc.doc_name, page_id + 1);
+            Ok((page_id, renderer.finalize(&page_title)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{CodeBlock, FormulaBlock, ImageBlock, List, ListItem, TableAlgorithm, TextBlock},
+        entities::{BBox, DocumentMetadata, LinkSpan, LinkTarget, StyleSpan},
+        metrics::ParsingMetrics,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn test_markdown_image_caption() -> anyhow::Result<()> {
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![],
+            blocks: vec![Block::for_test(
+                0,
+                BlockType::Image(ImageBlock {
+                    id: 0,
+                    caption: Some("Figure 1: revenue".to_string()),
+                    embedded: None,
+                }),
+                0,
+                BBox::default(),
+            )],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let md = to_markdown(&doc, "doc", Some(PathBuf::from("imgs")))?;
+        assert!(md.contains("![Figure 1: revenue](imgs/img_0.png)"));
+        assert!(md.contains("*Figure 1: revenue*"));
+        assert!(md.find("img_0.png") < md.find("*Figure 1"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_formula_image() -> anyhow::Result<()> {
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![],
+            blocks: vec![Block::for_test(
+                0,
+                BlockType::Formula(FormulaBlock {
+                    id: 1,
+                    page_id: 2,
+                    raw_text: "∑ 𝑥ᵢ ≤ ∫".to_string(),
+                }),
+                2,
+                BBox::default(),
+            )],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let md = to_markdown(&doc, "doc", Some(PathBuf::from("imgs")))?;
+        assert!(md.contains("![formula](imgs/formula_2_1.png)"));
+        assert!(!md.contains("∑"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_code_fence() -> anyhow::Result<()> {
+        let code_block = |id: usize, language: Option<&str>| {
+            Block::for_test(
+                id,
+                BlockType::Code(CodeBlock {
+                    text: "if a < b {\n    *x = 1;\n\n    return;\n}".to_string(),
+                    language: language.map(str::to_owned),
+                }),
+                0,
+                BBox::default(),
+            )
+        };
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![],
+            blocks: vec![code_block(0, Some("rust")), code_block(1, None)],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let md = to_markdown(&doc, "doc", None)?;
+        assert!(md.contains("```rust\nif a < b {\n    *x = 1;\n\n    return;\n}\n```"));
+        assert!(md.contains("```\nif a < b {"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_skips_repeated_blocks() -> anyhow::Result<()> {
+        let text_block = |id: usize, text: &str, repeated: bool| Block {
+            repeated,
+            ..Block::for_test(
+                id,
+                BlockType::TextBlock(TextBlock {
+                    text: text.to_string(),
+                }),
+                0,
+                BBox::default(),
+            )
+        };
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![],
+            blocks: vec![
+                text_block(0, "ACME Corp - Confidential", true),
+                text_block(1, "Quarterly results", false),
+            ],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let md = to_markdown(&doc, "doc", None)?;
+        assert!(md.contains("Quarterly results"));
+        assert!(!md.contains("Confidential"));
+        Ok(())
+    }
+
+    fn cell(text: &str, row_span: u8, col_span: u8) -> TableCell {
+        TableCell {
+            text: text.to_string(),
+            row_span,
+            col_span,
+            ..Default::default()
+        }
+    }
+
+    fn row(cells: Vec<TableCell>, is_header: bool) -> TableRow {
+        TableRow {
+            cells,
+            is_header,
+            bbox: BBox::default(),
+        }
+    }
+
+    #[test]
+    fn test_markdown_table_merged_header() -> anyhow::Result<()> {
+        let table = TableBlock {
+            id: 0,
+            caption: Some("Table 2: revenue by region".to_string()),
+            rows: vec![
+                row(vec![cell("Region", 2, 1), cell("Revenue", 1, 2)], true),
+                row(vec![cell("2023", 1, 1), cell("2024", 1, 1)], true),
+                row(
+                    vec![cell("EMEA", 1, 1), cell("10", 1, 1), cell("12", 1, 1)],
+                    false,
+                ),
+                // A span past the last row doesn't panic
+                row(vec![cell("APAC", 3, 1), cell("8", 1, 5)], false),
+            ],
+            has_borders: true,
+            algorithm: TableAlgorithm::Lattice,
+        };
+        let expanded = expand_table_spans(&table);
+        let texts: Vec<Vec<&str>> = expanded
+            .rows
+            .iter()
+            .map(|row| row.cells.iter().map(|cell| cell.text.as_str()).collect())
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                vec!["Region", "Revenue", ""],
+                vec!["", "2023", "2024"],
+                vec!["EMEA", "10", "12"],
+                vec!["APAC", "8", "", "", "", ""],
+            ]
+        );
+
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![],
+            blocks: vec![Block::for_test(
+                0,
+                BlockType::Table(table),
+                0,
+                BBox::default(),
+            )],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+        let md = to_markdown(&doc, "doc", None)?;
+        let lines: Vec<&str> = md.lines().filter(|line| line.starts_with('|')).collect();
+        let caption = md.find("*Table 2: revenue by region*").unwrap();
+        assert!(md.find(lines[4]).is_some_and(|last_row| last_row < caption));
+        assert_eq!(lines.len(), 5);
+        assert!(lines[3].contains("EMEA") && lines[3].matches('|').count() == 7);
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_links_and_styles() -> anyhow::Result<()> {
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![],
+            blocks: vec![Block {
+                links: vec![LinkSpan {
+                    text: "here".to_string(),
+                    target: LinkTarget::Uri("https://example.com/report".to_string()),
+                }],
+                styles: vec![StyleSpan {
+                    text: "full report".to_string(),
+                    bold: true,
+                    italic: false,
+                    script: None,
+                }],
+                ..Block::for_test(
+                    0,
+                    BlockType::TextBlock(TextBlock {
+                        text: "The full report is available here.".to_string(),
+                    }),
+                    0,
+                    BBox::default(),
+                )
+            }],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let md = to_markdown(&doc, "doc", None)?;
+        assert!(md.contains("The **full report** is available [here](https://example.com/report)."));
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_nested_list() -> anyhow::Result<()> {
+        let item = |text: &str, level| ListItem {
+            level,
+            ..ListItem::new(text, BBox::default())
+        };
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![],
+            blocks: vec![Block::for_test(
+                0,
+                BlockType::ListBlock(List {
+                    items: vec![
+                        item("• Fruits", 0),
+                        item("1. apples", 1),
+                        item("◦ green", 2),
+                        item("• Vegetables", 0),
+                    ],
+                }),
+                0,
+                BBox::default(),
+            )],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let md = to_markdown(&doc, "doc", None)?;
+        let indent = |text: &str| {
+            let line = md.lines().find(|line| line.contains(text)).unwrap();
+            line.len() - line.trim_start().len()
+        };
+        assert!(md.contains("* Fruits") && md.contains("* Vegetables"));
+        assert!(md.contains("1. apples"));
+        assert_eq!(indent("Fruits"), indent("Vegetables"));
+        assert!(indent("Fruits") < indent("apples") && indent("apples") < indent("green"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_pages() -> anyhow::Result<()> {
+        let text_block = |id: usize, text: &str, pages_id: Vec<PageID>| Block {
+            pages_id,
+            ..Block::for_test(
+                id,
+                BlockType::TextBlock(TextBlock {
+                    text: text.to_string(),
+                }),
+                0,
+                BBox::default(),
+            )
+        };
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![],
+            blocks: vec![
+                text_block(0, "Introduction", vec![0]),
+                text_block(1, "A paragraph split by the page break", vec![0, 1]),
+                text_block(2, "Conclusion", vec![2]),
+            ],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let pages = to_markdown_pages(&doc, None)?;
+
+        let page_ids: Vec<_> = pages.iter().map(|(page_id, _)| *page_id).collect();
+        assert_eq!(page_ids, [0, 2]);
+        let (_, first_page) = &pages[0];
+        assert!(first_page.contains("Introduction"));
+        assert!(first_page.contains("A paragraph split by the page break"));
+        assert!(first_page.contains("*Continues on page 2*"));
+        assert!(!first_page.contains("Conclusion"));
+        assert!(pages[1].1.contains("Conclusion"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_front_matter() -> anyhow::Result<()> {
+        let mut doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![],
+            blocks: vec![],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+        assert!(!to_markdown_document(&doc, None)?.starts_with("---"));
+
+        doc.metadata.info = DocumentInfo {
+            title: Some("Report: \"Q3\"".to_string()),
+            creation_date: Some("2023-04-15T09:30:12+02:00".to_string()),
+            ..Default::default()
+        };
+        let md = to_markdown_document(&doc, None)?;
+        assert!(md.starts_with(
+            "---\ntitle: \"Report: \\\"Q3\\\"\"\ncreation_date: \"2023-04-15T09:30:12+02:00\"\n---\n\n"
+        ));
+        Ok(())
+    }
+}
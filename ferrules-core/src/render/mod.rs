@@ -2,8 +2,12 @@ use anyhow::Context;
 
 use crate::{blocks::Block, entities::ParsedDocument};
 
+pub mod docx;
+pub mod hocr;
 pub mod html;
+pub mod jsonl;
 pub mod markdown;
+pub mod txt;
 
 pub trait Render {
     type Output;
@@ -14,6 +18,11 @@ pub trait Renderer {
     type Ok;
 
     fn render_block(&mut self, block: &Block) -> anyhow::Result<Self::Ok>;
+
+    /// Renders the blocks flagged `repeated`, e.g. running headers, which the text outputs skip
+    fn render_repeated(&self) -> bool {
+        false
+    }
 }
 
 impl Render for &ParsedDocument {
@@ -21,6 +30,9 @@ impl Render for &ParsedDocument {
 
     fn render<R: Renderer>(&self, renderer: &mut R) -> anyhow::Result<()> {
         for block in &self.blocks {
+            if block.repeated && !renderer.render_repeated() {
+                continue;
+            }
             renderer.render_block(block).context("can't render block")?;
         }
         Ok(())
@@ -0,0 +1,173 @@
+use crate::{
+    blocks::{Block, BlockType},
+    entities::PageID,
+};
+
+use super::{Render, Renderer};
+
+/// Default marker written between pages: a form feed, like `pdftotext`
+pub const DEFAULT_PAGE_BREAK: &str = "\x0c";
+
+const IMAGE_PLACEHOLDER: &str = "[image]";
+const TABLE_PLACEHOLDER: &str = "[table]";
+const FORMULA_PLACEHOLDER: &str = "[formula]";
+
+fn with_caption(placeholder: &str, caption: Option<&String>) -> String {
+    match caption {
+        Some(caption) => format!("{placeholder} {caption}"),
+        None => placeholder.to_owned(),
+    }
+}
+
+/// Concatenates the text of the blocks in order, separated by blank lines
+#[derive(Debug)]
+pub struct TextRenderer<'a> {
+    out: String,
+    page_break: &'a str,
+    last_page: Option<PageID>,
+}
+
+impl<'a> TextRenderer<'a> {
+    pub fn new(page_break: &'a str) -> Self {
+        Self {
+            out: String::new(),
+            page_break,
+            last_page: None,
+        }
+    }
+
+    pub fn finalize(mut self) -> String {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        self.out
+    }
+}
+
+impl Renderer for TextRenderer<'_> {
+    type Ok = ();
+
+    fn render_block(&mut self, block: &Block) -> anyhow::Result<Self::Ok> {
+        let text = match &block.kind {
+            BlockType::Header(text_block)
+            | BlockType::Footer(text_block)
+            | BlockType::TextBlock(text_block) => text_block.text.clone(),
+            BlockType::Title(title) => title.text.clone(),
+            BlockType::ListBlock(list) => list.text(),
+            BlockType::Image(image_block) => {
+                with_caption(IMAGE_PLACEHOLDER, image_block.caption.as_ref())
+            }
+            BlockType::Table(table) => with_caption(TABLE_PLACEHOLDER, table.caption.as_ref()),
+            BlockType::FormField(form_field) => form_field.text(),
+            BlockType::Formula(_) => FORMULA_PLACEHOLDER.to_owned(),
+            BlockType::Code(code) => code.text.clone(),
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let first_page = block.pages_id.iter().min().copied();
+        if let Some(last_page) = self.last_page {
+            if first_page.is_some_and(|first_page| first_page > last_page) {
+                self.out.push_str("\n\n");
+                self.out.push_str(self.page_break);
+            }
+            self.out.push_str("\n\n");
+        }
+        self.out.push_str(text);
+        self.last_page = block.pages_id.iter().max().copied().or(self.last_page);
+        Ok(())
+    }
+}
+
+/// Renders the document blocks as plain text, with `page_break` written between pages
+#[tracing::instrument(skip_all)]
+pub fn to_txt<R: Render>(blocks: R, page_break: &str) -> anyhow::Result<String> {
+    let mut text_renderer = TextRenderer::new(page_break);
+    blocks.render(&mut text_renderer)?;
+    Ok(text_renderer.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{ImageBlock, List, ListItem, TextBlock, Title},
+        entities::{BBox, DocumentMetadata, ParsedDocument},
+        metrics::ParsingMetrics,
+    };
+    use std::time::Duration;
+
+    fn block(id: usize, kind: BlockType, pages_id: Vec<PageID>) -> Block {
+        let bbox = BBox {
+            x0: 0.0,
+            y0: 0.0,
+            x1: 100.0,
+            y1: 10.0,
+        };
+        Block {
+            pages_id,
+            ..Block::for_test(id, kind, 0, bbox)
+        }
+    }
+
+    #[test]
+    fn test_txt_blocks_and_page_breaks() -> anyhow::Result<()> {
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![],
+            blocks: vec![
+                block(
+                    0,
+                    BlockType::Title(Title {
+                        level: 1,
+                        text: "Title".to_string(),
+                    }),
+                    vec![0],
+                ),
+                block(
+                    1,
+                    BlockType::TextBlock(TextBlock {
+                        text: "spans two pages".to_string(),
+                    }),
+                    vec![0, 1],
+                ),
+                block(
+                    2,
+                    BlockType::ListBlock(List {
+                        items: vec![
+                            ListItem::new("- a", BBox::default()),
+                            ListItem::new("- b", BBox::default()),
+                        ],
+                    }),
+                    vec![1],
+                ),
+                block(
+                    3,
+                    BlockType::Image(ImageBlock {
+                        id: 0,
+                        caption: None,
+                        embedded: None,
+                    }),
+                    vec![2],
+                ),
+            ],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let txt = to_txt(&doc, "---")?;
+        assert_eq!(
+            txt,
+            "Title\n\nspans two pages\n\n- a\n- b\n\n---\n\n[image]\n"
+        );
+        Ok(())
+    }
+}
@@ -1,42 +1,74 @@
 use crate::{
     blocks,
-    entities::ParsedDocument,
-    render::{html::to_html, markdown::to_markdown},
+    entities::{Page, PageID, ParsedDocument},
+    error::FerrulesError,
+    parse::{native::document_load_error, page_range::PageRange},
+    pdfium::load_pdfium,
+    render::{
+        docx::to_docx,
+        hocr::to_hocr,
+        html::{to_html, to_html_inline},
+        jsonl::to_jsonl,
+        markdown::{to_markdown_document, to_markdown_pages},
+        txt::to_txt,
+    },
 };
-
-const IMAGE_PADDING: u32 = 5;
 use anyhow::Context;
 use colored::*;
-use pdfium_render::prelude::Pdfium;
+use image::DynamicImage;
 use std::{
+    collections::HashMap,
     fs::{create_dir, File},
     io::{BufWriter, Write},
-    ops::Range,
     path::{Path, PathBuf},
     str::FromStr,
 };
 
+/// Default margin, in page points, kept around the image and table crops
+pub const DEFAULT_IMAGE_PADDING: u32 = 5;
+
 pub fn get_doc_length<P: AsRef<Path>>(
     path: P,
     password: Option<&str>,
-    page_range: Option<Range<usize>>,
+    page_range: Option<&PageRange>,
 ) -> anyhow::Result<usize> {
-    // TODO : This panic ! should be handlered
-    let pdfium = Pdfium::new(Pdfium::bind_to_statically_linked_library().unwrap());
-    let document = pdfium.load_pdf_from_file(&path, password).unwrap();
-    let pages: Vec<_> = document.pages().iter().enumerate().collect();
-    match page_range {
-        Some(range) => {
-            if range.end > pages.len() {
-                anyhow::bail!(
-                    "Page range end ({}) exceeds document length ({})",
-                    range.end,
-                    pages.len()
-                );
+    let (pdfium, _) = load_pdfium()?;
+    let document =
+        pdfium.load_pdf_from_file(&path, password).map_err(|e| {
+            match document_load_error(&e, password) {
+                Some(err) => anyhow::Error::from(err),
+                None => anyhow::Error::from(e).context(format!(
+                    "can't open the document {}",
+                    path.as_ref().display()
+                )),
             }
-            Ok(range.len())
-        }
-        None => Ok(pages.len()),
+        })?;
+    page_range_length(document.pages().len() as usize, page_range)
+}
+
+/// Same as [`get_doc_length`] for a document already in memory, e.g. fetched from a URL
+pub fn get_doc_length_from_bytes(
+    doc: &[u8],
+    password: Option<&str>,
+    page_range: Option<&PageRange>,
+) -> anyhow::Result<usize> {
+    let (pdfium, _) = load_pdfium()?;
+    let document = pdfium
+        .load_pdf_from_byte_slice(doc, password)
+        .map_err(|e| match document_load_error(&e, password) {
+            Some(err) => anyhow::Error::from(err),
+            None => anyhow::Error::from(e).context("can't open the document"),
+        })?;
+    page_range_length(document.pages().len() as usize, page_range)
+}
+
+fn page_range_length(n_pages: usize, page_range: Option<&PageRange>) -> anyhow::Result<usize> {
+    match page_range {
+        Some(range) => match range.page_ids(n_pages) {
+            Some(page_ids) => Ok(page_ids.len()),
+            None => anyhow::bail!("Page range exceeds document length ({n_pages})"),
+        },
+        None => Ok(n_pages),
     }
 }
 
@@ -55,54 +87,81 @@ fn sanitize_doc_name(doc_name: &str) -> String {
         .collect::<String>()
 }
 
-fn save_doc_images(imgs_dir: &Path, doc: &ParsedDocument) -> anyhow::Result<()> {
+/// Crops the region of `block` from the raster of its first page, with some padding.
+/// Crops the block from its page raster with `padding` points around it, clamped to the page.
+pub(crate) fn crop_block_image(
+    doc: &ParsedDocument,
+    block: &blocks::Block,
+    padding: u32,
+) -> Option<DynamicImage> {
+    let page_id = block.pages_id.first()?;
+    let page = doc.pages.iter().find(|&p| p.id == *page_id)?;
+    // Page rasters are dropped without `FerrulesParseConfig::keep_page_images`
+    if page.image.width() == 0 {
+        return None;
+    }
+    assert!(page.height as u32 > 0);
+    assert!(page.width as u32 > 0);
+
+    let padding = padding as f32;
+    let x0 = (block.bbox.x0 - padding).clamp(0.0, page.width - 1.0);
+    let y0 = (block.bbox.y0 - padding).clamp(0.0, page.height - 1.0);
+    let x1 = (block.bbox.x1 + padding).min(page.width);
+    let y1 = (block.bbox.y1 + padding).min(page.height);
+    // The raster resolution is set by `FerrulesParseConfig::raster_dpi`, bboxes are in points
+    let scale = page.image.width() as f32 / page.width;
+    let width = ((x1 - x0) * scale).max(1.0) as u32;
+    let height = ((y1 - y0) * scale).max(1.0) as u32;
+
+    Some(
+        page.image
+            .crop_imm((x0 * scale) as u32, (y0 * scale) as u32, width, height),
+    )
+}
+
+/// Image of `block`: the bitmap embedded in the PDF at its original resolution when the block
+/// was matched to an image object, the crop of the page raster otherwise.
+pub(crate) fn block_image(
+    doc: &ParsedDocument,
+    block: &blocks::Block,
+    padding: u32,
+) -> Option<DynamicImage> {
+    if let blocks::BlockType::Image(blocks::ImageBlock {
+        embedded: Some(embedded),
+        ..
+    }) = &block.kind
+    {
+        let embedded_image = block
+            .pages_id
+            .first()
+            .and_then(|page_id| doc.pages.iter().find(|p| p.id == *page_id))
+            .and_then(|page| page.embedded_images.get(embedded.index));
+        if let Some(embedded_image) = embedded_image {
+            return Some(embedded_image.image.clone());
+        }
+    }
+    crop_block_image(doc, block, padding)
+}
+
+fn save_doc_images(imgs_dir: &Path, doc: &ParsedDocument, padding: u32) -> anyhow::Result<()> {
     for block in doc.blocks.iter() {
+        let file_name = match &block.kind {
+            blocks::BlockType::Image(img_block) => img_block.path(),
+            blocks::BlockType::Table(table_block) => table_block.path(),
+            blocks::BlockType::Formula(formula_block) => formula_block.path(),
+            _ => continue,
+        };
+        let Some(image) = block_image(doc, block, padding) else {
+            continue;
+        };
         match &block.kind {
-            blocks::BlockType::Image(img_block) => {
-                let page_id = block.pages_id.first().unwrap();
-                match doc.pages.iter().find(|&p| p.id == *page_id) {
-                    Some(page) => {
-                        assert!(page.height as u32 > 0);
-                        assert!(page.width as u32 > 0);
-
-                        let x = (block.bbox.x0 - IMAGE_PADDING as f32) as u32;
-                        let y = (block.bbox.y0 - IMAGE_PADDING as f32) as u32;
-                        let width = (block.bbox.width().max(1.0) as u32 + 2 * IMAGE_PADDING)
-                            .min(page.width as u32);
-                        let height = (block.bbox.height().max(1.0) as u32 + 2 * IMAGE_PADDING)
-                            .min(page.height as u32);
-
-                        let crop = page.image.clone().crop(x, y, width, height);
-
-                        let output_file = imgs_dir.join(img_block.path());
-                        crop.save(output_file)?;
-                    }
-                    None => continue,
-                }
-            }
-            blocks::BlockType::Table(table_block) => {
-                let page_id = block.pages_id.first().unwrap();
-                match doc.pages.iter().find(|&p| p.id == *page_id) {
-                    Some(page) => {
-                        assert!(page.height as u32 > 0);
-                        assert!(page.width as u32 > 0);
-
-                        let x = (block.bbox.x0 - IMAGE_PADDING as f32) as u32;
-                        let y = (block.bbox.y0 - IMAGE_PADDING as f32) as u32;
-                        let width = (block.bbox.width().max(1.0) as u32 + 2 * IMAGE_PADDING)
-                            .min(page.width as u32);
-                        let height = (block.bbox.height().max(1.0) as u32 + 2 * IMAGE_PADDING)
-                            .min(page.height as u32);
-
-                        let crop = page.image.clone().crop(x, y, width, height);
-
-                        let output_file = imgs_dir.join(table_block.path());
-                        crop.save(output_file)?;
-                    }
-                    None => continue,
-                }
+            // The JPEG encoder has no alpha channel
+            blocks::BlockType::Image(img_block)
+                if img_block.format() == blocks::EmbeddedImageFormat::Jpeg =>
+            {
+                DynamicImage::ImageRgb8(image.to_rgb8()).save(imgs_dir.join(file_name))?
             }
-            _ => continue,
+            _ => image.save(imgs_dir.join(file_name))?,
         }
     }
     Ok(())
@@ -147,59 +206,586 @@ pub fn create_dirs<P: AsRef<Path>>(
     Ok((res_dir_path, debug_path))
 }
 
+/// Output formats written by [`save_parsed_document`] next to the json document
+#[derive(Debug, Clone)]
+pub struct SaveOptions {
+    pub save_imgs: bool,
+    pub save_html: bool,
+    /// Writes a single html file with the images embedded instead of the figures directory
+    pub html_inline: bool,
+    pub save_markdown: bool,
+    /// Writes the markdown of each page to its own `page_0001.md`, `page_0002.md`... file
+    pub save_markdown_pages: bool,
+    pub save_hocr: bool,
+    pub save_docx: bool,
+    /// Writes the blocks as JSON Lines instead of the json document
+    pub save_jsonl: bool,
+    /// Writes the plain text output with this marker between pages
+    pub txt_page_break: Option<String>,
+    /// Margin, in page points, kept around the image and table crops
+    pub image_padding: u32,
+    /// Don't report the results and debug output paths on stderr
+    pub quiet: bool,
+    /// Divides the bboxes of the json and JSON Lines outputs by their page width and height,
+    /// giving coordinates in [0, 1]. The pages keep their dimensions in points
+    pub normalize_bboxes: bool,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            save_imgs: false,
+            save_html: false,
+            html_inline: false,
+            save_markdown: false,
+            save_markdown_pages: false,
+            save_hocr: false,
+            save_docx: false,
+            save_jsonl: false,
+            txt_page_break: None,
+            image_padding: DEFAULT_IMAGE_PADDING,
+            quiet: false,
+            normalize_bboxes: false,
+        }
+    }
+}
+
+/// Writes the document as json
+pub fn write_json<W: Write>(doc: &ParsedDocument, mut writer: W) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut writer, doc)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the document as markdown with its YAML front matter. Images link to
+/// `img_src_path` when provided
+pub fn write_markdown<W: Write>(
+    doc: &ParsedDocument,
+    mut writer: W,
+    img_src_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    writer.write_all(to_markdown_document(doc, img_src_path)?.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the markdown of each page to `page_0001.md`, `page_0002.md`... in `dir`, the pages
+/// being numbered from 1. Images link to `img_src_path` when provided
+pub fn write_markdown_pages(
+    doc: &ParsedDocument,
+    dir: &Path,
+    img_src_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    for (page_id, markdown) in to_markdown_pages(doc, img_src_path)? {
+        let mut writer = BufWriter::new(File::create(
+            dir.join(format!("page_{:04}.md", page_id + 1)),
+        )?);
+        writer.write_all(markdown.as_bytes())?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Writes the document as an html page. Images link to `img_src_path` when provided
+pub fn write_html<W: Write>(
+    doc: &ParsedDocument,
+    mut writer: W,
+    img_src_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    writer.write_all(to_html(doc, &doc.doc_name, img_src_path)?.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the document as a single html page with its images embedded
+pub fn write_html_inline<W: Write>(
+    doc: &ParsedDocument,
+    mut writer: W,
+    image_padding: u32,
+) -> anyhow::Result<()> {
+    writer.write_all(to_html_inline(doc, &doc.doc_name, image_padding)?.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the text of the document with `page_break` between its pages
+pub fn write_txt<W: Write>(
+    doc: &ParsedDocument,
+    mut writer: W,
+    page_break: &str,
+) -> anyhow::Result<()> {
+    writer.write_all(to_txt(doc, page_break)?.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the document as hOCR
+pub fn write_hocr<W: Write>(doc: &ParsedDocument, mut writer: W) -> anyhow::Result<()> {
+    writer.write_all(to_hocr(doc, &doc.doc_name)?.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the document as a docx file. Images are read from `img_src_path` when provided
+pub fn write_docx<W: Write>(
+    doc: &ParsedDocument,
+    mut writer: W,
+    img_src_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    writer.write_all(&to_docx(doc, img_src_path)?)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Creates the `{doc_name}.{extension}` output file in the results directory
+fn create_output_file(
+    res_dir_path: &Path,
+    doc_name: &str,
+    extension: &str,
+) -> anyhow::Result<BufWriter<File>> {
+    let file = File::create(res_dir_path.join(format!("{doc_name}.{extension}")))?;
+    Ok(BufWriter::new(file))
+}
+
 pub fn save_parsed_document(
     doc: &ParsedDocument,
     res_dir_path: PathBuf,
-    save_imgs: bool,
-    save_html: bool,
-    save_markdown: bool,
+    options: &SaveOptions,
 ) -> anyhow::Result<()> {
+    let SaveOptions {
+        save_imgs,
+        save_html,
+        html_inline,
+        save_markdown,
+        save_markdown_pages,
+        save_hocr,
+        save_docx,
+        save_jsonl,
+        ref txt_page_break,
+        image_padding,
+        quiet,
+        normalize_bboxes,
+    } = *options;
     let sanitized_doc_name = sanitize_doc_name(&doc.doc_name);
-    // Save json
-    let file_out = res_dir_path.join(format!("{}.json", &sanitized_doc_name));
-    let file = File::create(&file_out)?;
-    let mut writer = BufWriter::new(file);
-    let doc_json = serde_json::to_string(&doc)?;
-    writer.write_all(doc_json.as_bytes())?;
+    let output_file =
+        |extension: &str| create_output_file(&res_dir_path, &sanitized_doc_name, extension);
+    // Only the serialized outputs are normalized, the images are cropped in page coordinates
+    let normalized_doc = normalize_bboxes.then(|| normalized_bboxes(doc));
+    let serialized_doc = normalized_doc.as_ref().unwrap_or(doc);
+    if save_jsonl {
+        // One block per line instead of the whole document json
+        to_jsonl(serialized_doc, &doc.doc_name, output_file("jsonl")?)?;
+    } else {
+        write_json(serialized_doc, output_file("json")?)?;
+    }
     // TODO: this is shit, refac
     let fig_path = PathBuf::from_str("figures").unwrap();
 
     if save_imgs {
-        save_doc_images(&res_dir_path.join(&fig_path), doc).context("can't save the doc images")?;
+        save_doc_images(&res_dir_path.join(&fig_path), doc, image_padding)
+            .context("can't save the doc images")?;
     }
 
-    if let Some(dbg_path) = &doc.debug_path {
-        println!(
+    if let Some(dbg_path) = doc.debug_path.as_ref().filter(|_| !quiet) {
+        eprintln!(
             "{} Debug output saved in: {}",
             "ℹ".yellow().bold(),
             dbg_path.display().to_string().yellow().underline()
         );
     }
 
-    if save_html {
+    if save_html && html_inline {
+        write_html_inline(doc, output_file("html")?, image_padding)?;
+    } else if save_html {
         if !save_imgs {
-            save_doc_images(&res_dir_path.join(&fig_path), doc)
+            save_doc_images(&res_dir_path.join(&fig_path), doc, image_padding)
                 .context("can't save the doc images")?;
         }
-        let html_content = to_html(doc, &doc.doc_name, Some(fig_path.clone())).unwrap();
-        let html_file_out = res_dir_path.join(format!("{}.html", sanitized_doc_name));
-        let file = File::create(&html_file_out)?;
-        let mut writer = BufWriter::new(file);
-        writer.write_all(html_content.as_bytes())?;
+        write_html(doc, output_file("html")?, Some(fig_path.clone()))?;
     }
 
     if save_markdown {
-        let md_content = to_markdown(doc, &doc.doc_name, Some(fig_path.clone())).unwrap();
-        let html_file_out = res_dir_path.join(format!("{}.md", sanitized_doc_name));
-        let file = File::create(&html_file_out)?;
-        let mut writer = BufWriter::new(file);
-        writer.write_all(md_content.as_bytes())?;
-    }
-    println!(
-        "{} Results saved in: {}",
-        "✓".green().bold(),
-        res_dir_path.display().to_string().cyan().underline()
-    );
+        write_markdown(doc, output_file("md")?, Some(fig_path.clone()))?;
+    }
+
+    if save_markdown_pages {
+        write_markdown_pages(doc, &res_dir_path, Some(fig_path.clone()))?;
+    }
+
+    if save_hocr {
+        write_hocr(doc, output_file("hocr")?)?;
+    }
+
+    if save_docx {
+        if !save_imgs && (!save_html || html_inline) {
+            save_doc_images(&res_dir_path.join(&fig_path), doc, image_padding)
+                .context("can't save the doc images")?;
+        }
+        write_docx(
+            doc,
+            output_file("docx")?,
+            Some(res_dir_path.join(&fig_path)),
+        )?;
+    }
+
+    if let Some(page_break) = txt_page_break.as_deref() {
+        write_txt(doc, output_file("txt")?, page_break)?;
+    }
+    if !quiet {
+        eprintln!(
+            "{} Results saved in: {}",
+            "✓".green().bold(),
+            res_dir_path.display().to_string().cyan().underline()
+        );
+    }
 
     Ok(())
 }
+
+/// Copy of the serialized parts of `doc` with every bbox divided by the dimensions of its page.
+/// Blocks spanning several pages are normalized by their first page. The page images and lines,
+/// which aren't serialized, are left out
+fn normalized_bboxes(doc: &ParsedDocument) -> ParsedDocument {
+    let page_sizes: HashMap<PageID, (f32, f32)> = doc
+        .pages
+        .iter()
+        .filter(|page| page.width > 0.0 && page.height > 0.0)
+        .map(|page| (page.id, (page.width, page.height)))
+        .collect();
+    let page_size = |page_id: &PageID| page_sizes.get(page_id).copied();
+
+    let pages = doc
+        .pages
+        .iter()
+        .map(|page| {
+            let mut ocr_lines = page.ocr_lines.clone();
+            let mut layout = page.layout.clone();
+            if let Some((width, height)) = page_size(&page.id) {
+                for line in &mut ocr_lines {
+                    line.bbox.normalize(width, height);
+                    for word in &mut line.words {
+                        word.bbox.normalize(width, height);
+                    }
+                }
+                for layout_bbox in &mut layout {
+                    layout_bbox.bbox.normalize(width, height);
+                }
+            }
+            Page {
+                id: page.id,
+                width: page.width,
+                height: page.height,
+                image: DynamicImage::default(),
+                rotation: page.rotation,
+                need_ocr: page.need_ocr,
+                lines: vec![],
+                ocr_lines,
+                layout,
+                embedded_images: vec![],
+            }
+        })
+        .collect();
+
+    let mut blocks = doc.blocks.clone();
+    for block in &mut blocks {
+        let Some((width, height)) = block.pages_id.first().and_then(page_size) else {
+            continue;
+        };
+        block.bbox.normalize(width, height);
+        if let blocks::BlockType::Table(table) = &mut block.kind {
+            for row in &mut table.rows {
+                row.bbox.normalize(width, height);
+                for cell in &mut row.cells {
+                    cell.bbox.normalize(width, height);
+                }
+            }
+        }
+    }
+
+    let mut form_fields = doc.form_fields.clone();
+    for field in &mut form_fields {
+        if let Some((width, height)) = page_size(&field.page_id) {
+            field.bbox.normalize(width, height);
+        }
+    }
+
+    ParsedDocument {
+        doc_name: doc.doc_name.clone(),
+        pages,
+        blocks,
+        debug_path: doc.debug_path.clone(),
+        metadata: doc.metadata.clone(),
+        metrics: doc.metrics.clone(),
+        form_fields,
+        outline: doc.outline.clone(),
+        failed_pages: doc.failed_pages.clone(),
+        warnings: doc.warnings.clone(),
+        sections: doc.sections.clone(),
+    }
+}
+
+/// Writes the document to `writer` in a single format without creating any file: the first
+/// enabled of markdown, html, plain text, hOCR and JSON Lines, or the json document. Images are
+/// only kept by the inline html output.
+pub fn write_parsed_document<W: Write>(
+    doc: &ParsedDocument,
+    mut writer: W,
+    options: &SaveOptions,
+) -> anyhow::Result<()> {
+    if options.save_markdown {
+        write_markdown(doc, writer, None)
+    } else if options.save_html && options.html_inline {
+        write_html_inline(doc, writer, options.image_padding)
+    } else if options.save_html {
+        write_html(doc, writer, None)
+    } else if let Some(page_break) = options.txt_page_break.as_deref() {
+        write_txt(doc, writer, page_break)
+    } else if options.save_hocr {
+        write_hocr(doc, writer)
+    } else if options.save_jsonl {
+        if options.normalize_bboxes {
+            to_jsonl(&normalized_bboxes(doc), &doc.doc_name, &mut writer)?;
+        } else {
+            to_jsonl(doc, &doc.doc_name, &mut writer)?;
+        }
+        Ok(())
+    } else if options.save_docx {
+        anyhow::bail!("the docx output can only be saved to a file");
+    } else if options.save_markdown_pages {
+        anyhow::bail!("the markdown pages can only be saved to files");
+    } else if options.normalize_bboxes {
+        write_json(&normalized_bboxes(doc), writer)
+    } else {
+        write_json(doc, writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_doc_length_invalid_document() {
+        let path = std::env::temp_dir().join(format!("ferrules-{}.pdf", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"not a pdf").unwrap();
+
+        let err = get_doc_length(&path, None, None).unwrap_err();
+        assert!(err.to_string().contains("can't open the document"));
+        assert!(get_doc_length(path.with_extension("missing"), None, None).is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_doc_length_encrypted_document() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/encrypted.pdf");
+
+        assert_eq!(get_doc_length(&path, Some("secret"), None).unwrap(), 1);
+        let err = get_doc_length(&path, Some("wrong"), None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<FerrulesError>(),
+            Some(FerrulesError::IncorrectPassword)
+        ));
+        let err = get_doc_length(&path, None, None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<FerrulesError>(),
+            Some(FerrulesError::EncryptedDocument)
+        ));
+    }
+
+    #[test]
+    fn test_write_parsed_document_single_format() -> anyhow::Result<()> {
+        use crate::{
+            blocks::{Block, BlockType, TextBlock},
+            entities::{BBox, DocumentMetadata},
+            metrics::ParsingMetrics,
+        };
+        use std::time::Duration;
+
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![],
+            blocks: vec![Block::for_test(
+                0,
+                BlockType::TextBlock(TextBlock {
+                    text: "hello".to_string(),
+                }),
+                0,
+                BBox::default(),
+            )],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+        let write = |options: SaveOptions| -> anyhow::Result<String> {
+            let mut out = Vec::new();
+            write_parsed_document(&doc, &mut out, &options)?;
+            Ok(String::from_utf8(out)?)
+        };
+
+        let txt = write(SaveOptions {
+            txt_page_break: Some(String::new()),
+            save_hocr: true,
+            ..Default::default()
+        })?;
+        assert_eq!(txt, "hello\n");
+        let json = write(SaveOptions::default())?;
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json)?["doc_name"],
+            "doc"
+        );
+        assert!(write(SaveOptions {
+            save_docx: true,
+            ..Default::default()
+        })
+        .is_err());
+
+        // Every format can be written to memory
+        let mut html = Vec::new();
+        write_html(&doc, &mut html, None)?;
+        assert!(String::from_utf8(html)?.contains("hello"));
+        let mut markdown = Vec::new();
+        write_markdown(&doc, &mut markdown, None)?;
+        assert!(String::from_utf8(markdown)?.contains("hello"));
+        let mut docx = Vec::new();
+        write_docx(&doc, &mut docx, None)?;
+        // docx files are zip archives
+        assert!(docx.starts_with(b"PK"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalized_bboxes() -> anyhow::Result<()> {
+        use crate::{
+            blocks::{Block, BlockType, TextBlock},
+            entities::{BBox, DocumentMetadata, Page},
+            metrics::ParsingMetrics,
+        };
+        use std::time::Duration;
+
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![Page {
+                id: 0,
+                width: 200.0,
+                height: 400.0,
+                image: DynamicImage::new_rgb8(1, 1),
+                rotation: 0,
+                need_ocr: false,
+                lines: vec![],
+                ocr_lines: vec![],
+                layout: vec![],
+                embedded_images: vec![],
+            }],
+            blocks: vec![Block::for_test(
+                0,
+                BlockType::TextBlock(TextBlock {
+                    text: "hello".to_string(),
+                }),
+                0,
+                BBox {
+                    x0: 20.0,
+                    y0: 100.0,
+                    x1: 200.0,
+                    y1: 200.0,
+                },
+            )],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+
+        let mut out = Vec::new();
+        write_parsed_document(
+            &doc,
+            &mut out,
+            &SaveOptions {
+                normalize_bboxes: true,
+                ..Default::default()
+            },
+        )?;
+        let json = serde_json::from_slice::<serde_json::Value>(&out)?;
+        assert_eq!(
+            json["blocks"][0]["bbox"],
+            serde_json::json!({"x0": 0.1, "y0": 0.25, "x1": 1.0, "y1": 0.5})
+        );
+        assert_eq!(json["pages"][0]["width"], 200.0);
+        // The document itself keeps its page coordinates
+        assert_eq!(doc.blocks[0].bbox.x0, 20.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_crop_block_image_padding() {
+        use crate::{
+            blocks::{Block, BlockType, ImageBlock},
+            entities::{BBox, DocumentMetadata, Page},
+            metrics::ParsingMetrics,
+        };
+        use std::time::Duration;
+
+        let block_at = |x0: f32, y0: f32, x1: f32, y1: f32| {
+            Block::for_test(
+                0,
+                BlockType::Image(ImageBlock {
+                    id: 0,
+                    caption: None,
+                    embedded: None,
+                }),
+                0,
+                BBox { x0, y0, x1, y1 },
+            )
+        };
+        let doc = ParsedDocument {
+            doc_name: "doc".to_string(),
+            pages: vec![Page {
+                id: 0,
+                width: 100.0,
+                height: 80.0,
+                image: DynamicImage::new_rgb8(100, 80),
+                rotation: 0,
+                need_ocr: false,
+                lines: vec![],
+                ocr_lines: vec![],
+                layout: vec![],
+                embedded_images: vec![],
+            }],
+            blocks: vec![],
+            debug_path: None,
+            metadata: DocumentMetadata::new(Duration::from_millis(1)),
+            metrics: ParsingMetrics::default(),
+            form_fields: vec![],
+            outline: None,
+            failed_pages: vec![],
+            warnings: vec![],
+            sections: vec![],
+        };
+        let crop_size = |block: &Block, padding: u32| {
+            let crop = crop_block_image(&doc, block, padding).unwrap();
+            (crop.width(), crop.height())
+        };
+
+        let block = block_at(20.0, 20.0, 60.0, 40.0);
+        assert_eq!(crop_size(&block, 0), (40, 20));
+        assert_eq!(crop_size(&block, DEFAULT_IMAGE_PADDING), (50, 30));
+        // Padding past the page edges is clamped to the page
+        assert_eq!(crop_size(&block, 50), (100, 80));
+        let corner = block_at(90.0, 70.0, 100.0, 80.0);
+        assert_eq!(crop_size(&corner, 20), (30, 30));
+
+        // Without the page raster there is nothing to crop
+        let mut doc = doc;
+        doc.pages[0].image = DynamicImage::default();
+        assert!(crop_block_image(&doc, &block, 0).is_none());
+    }
+}
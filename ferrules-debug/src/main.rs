@@ -273,6 +273,15 @@ impl FerrulesDebug {
                         .size(theme::TEXT_SIZE_MD)
                         .color(theme::TEXT)
                         .font(theme::FONT_MEDIUM),
+                    // Calibrates the OCR coverage and characters thresholds
+                    text(format!(
+                        "Text layer: {:.0}% coverage, {} chars{}",
+                        current_page.text_coverage * 100.0,
+                        current_page.native_chars,
+                        if current_page.need_ocr { " (OCR)" } else { "" }
+                    ))
+                    .size(theme::TEXT_SIZE_SM)
+                    .color(theme::SUBTEXT0),
                     widgets::v_space(30.0),
                     button(
                         text("Open .ferr")
@@ -582,7 +582,14 @@ impl<'a> PagePainter<'a> {
                         ArchivedBlockType::Header(h) => h.text.to_string(),
                         ArchivedBlockType::Footer(f) => f.text.to_string(),
                         ArchivedBlockType::Title(t) => t.text.to_string(),
-                        ArchivedBlockType::ListBlock(l) => l.items.join("\n"),
+                        ArchivedBlockType::ListBlock(l) => l
+                            .items
+                            .iter()
+                            .map(|item| item.text.as_str())
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                        ArchivedBlockType::Formula(f) => f.raw_text.to_string(),
+                        ArchivedBlockType::Code(c) => c.text.to_string(),
                         _ => String::new(),
                     };
                     let block_kind = match &block.kind {
@@ -593,6 +600,9 @@ impl<'a> PagePainter<'a> {
                         ArchivedBlockType::TextBlock(_) => "Text",
                         ArchivedBlockType::Image(_) => "Image",
                         ArchivedBlockType::Table(_) => "Table",
+                        ArchivedBlockType::FormField(_) => "FormField",
+                        ArchivedBlockType::Formula(_) => "Formula",
+                        ArchivedBlockType::Code(_) => "Code",
                     };
 
                     let mut table_details = None;
@@ -684,6 +694,9 @@ impl<'a> PagePainter<'a> {
                         ArchivedElementType::Caption => "Caption",
                         ArchivedElementType::Image => "Image",
                         ArchivedElementType::Table(_) => "Table",
+                        ArchivedElementType::FormField(_) => "FormField",
+                        ArchivedElementType::Formula => "Formula",
+                        ArchivedElementType::Code => "Code",
                     };
                     hovered_element = Some(InspectorElement {
                         id: element.id as usize,